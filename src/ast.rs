@@ -1,9 +1,11 @@
 extern crate pest;
 
+use std::collections::HashMap;
+
+use pest::error::ErrorVariant;
 use pest::iterators::Pair;
 use pest::Parser;
 
-use self::pest::iterators::Pairs;
 use Node::*;
 
 // *** Rules ***
@@ -11,6 +13,7 @@ use Node::*;
 enum RuleType {
     Parser(ParserRule),
     Token(TokenRule),
+    Extern(Vec<String>),
 }
 
 impl From<ParserRule> for RuleType {
@@ -25,27 +28,60 @@ impl From<TokenRule> for RuleType {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ParserRule {
     pub name: String,
+    // Only templates have parameters
+    pub params: Vec<String>,
+    // Target language declarations of the rule's arguments and return values
+    pub args: Vec<String>,
+    pub returns: Vec<String>,
+    // `##` doc comment lines
+    pub doc: Vec<String>,
     pub node: Node,
 }
 
 impl Comment for ParserRule {
     fn comment(&self) -> String {
-        format!("{}: {}", &self.name, self.node.comment())
+        let mut decl = self.name.to_string();
+        if !self.params.is_empty() {
+            decl += &format!("<{}>", self.params.join(", "));
+        }
+        if !self.args.is_empty() {
+            decl += &format!("[{}]", self.args.join(", "));
+        }
+        if !self.returns.is_empty() {
+            decl += &format!(" returns [{}]", self.returns.join(", "));
+        }
+
+        format!("{}: {}", decl, self.node.comment())
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct TokenRule {
     pub name: String,
-    pub literal: Node,
+    // `##` doc comment lines
+    pub doc: Vec<String>,
+    // `##` doc comment lines starting with '@', which annotate the token for tools instead of documenting it (ex:
+    // `## @scope keyword.control` -> `("scope", "keyword.control")`) - flags have an empty value (ex: `## @skip`)
+    pub annotations: Vec<(String, String)>,
+    // A single `TokenLit` or a pattern
+    pub node: Node,
+}
+
+impl TokenRule {
+    pub fn annotation(&self, name: &str) -> Option<&str> {
+        self.annotations
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
 }
 
 impl Comment for TokenRule {
     fn comment(&self) -> String {
-        format!("{}: {}", &self.name, self.literal.comment())
+        format!("{}: {}", &self.name, self.node.comment())
     }
 }
 
@@ -53,10 +89,12 @@ pub trait Comment {
     fn comment(&self) -> String;
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Node {
+    // rule_part (`name = ...` or, when accumulating into a list, `name += ...`)
     Binding {
         name: String,
+        list: bool,
         node: Box<Node>,
     },
 
@@ -64,6 +102,11 @@ pub enum Node {
     Alternatives {
         nodes: Vec<Node>,
     },
+    // rule_piece (only when followed by '# Label')
+    Labeled {
+        label: String,
+        node: Box<Node>,
+    },
     // rule_piece
     MultipartBody {
         nodes: Vec<Node>,
@@ -81,10 +124,43 @@ pub enum Node {
         node: Box<Node>,
         brackets: bool,
     },
+    // rule_part (one or more, use `ZeroOrOne` for zero or more)
+    SeparatedList {
+        node: Box<Node>,
+        separator: Box<Node>,
+        trailing: bool,
+    },
+    // rule_part ('&' rule_elem)
+    PositiveLookahead {
+        node: Box<Node>,
+    },
+    // rule_part ('!' rule_elem)
+    NegativeLookahead {
+        node: Box<Node>,
+    },
+    // rule_part (action) - code for a single target language
+    Action {
+        lang: String,
+        code: String,
+        predicate: bool,
+    },
 
-    // RULE_NAME
+    // Empty alternative (or '(' ')') - always matches without consuming anything
+    Empty,
+
+    // RULE_NAME ('[' arg (',' arg)* ']')?
     RuleRef {
         name: String,
+        args: Vec<String>,
+    },
+    // RULE_NAME '<' rule_body (',' rule_body)* '>'
+    TemplateRef {
+        name: String,
+        args: Vec<Node>,
+    },
+    // '[' ... ']' or '\p{...}' (only in token rules)
+    CharClass {
+        class: String,
     },
     // TOKEN_NAME
     TokenRef {
@@ -94,19 +170,56 @@ pub enum Node {
     // TOKEN_LIT
     TokenLit {
         literal: String,
+        case_insensitive: bool,
     },
 }
 
+impl Node {
+    pub fn children(&self) -> Vec<&Node> {
+        match self {
+            Alternatives { nodes } | MultipartBody { nodes } => nodes.iter().collect(),
+            TemplateRef { args, .. } => args.iter().collect(),
+            SeparatedList {
+                node, separator, ..
+            } => vec![node, separator],
+            Binding { node, .. }
+            | Labeled { node, .. }
+            | ZeroOrMore { node }
+            | OneOrMore { node }
+            | ZeroOrOne { node, .. }
+            | PositiveLookahead { node }
+            | NegativeLookahead { node } => vec![node],
+            Action { .. }
+            | Empty
+            | RuleRef { .. }
+            | TokenRef { .. }
+            | TokenLit { .. }
+            | CharClass { .. } => vec![],
+        }
+    }
+}
+
 impl Comment for Node {
     fn comment(&self) -> String {
         match self {
-            Binding { name, node } => format!("{}={}", name, node.comment()),
+            Binding { name, list, node } => {
+                let op = if *list { "+=" } else { "=" };
+                match node.as_ref() {
+                    // Suffixes bind tighter than the binding (ex: `x=a*`), so only groups need parentheses
+                    Alternatives { .. } | MultipartBody { .. } => {
+                        format!("{}{}({})", name, op, node.comment())
+                    }
+                    _ => format!("{}{}{}", name, op, node.comment()),
+                }
+            }
 
             Alternatives { nodes } => {
                 let comments: Vec<String> = nodes.iter().map(|node| node.comment()).collect();
                 comments.join(" | ")
             }
 
+            Labeled { label, node } => format!("{} @{}", node.comment(), label),
+
             MultipartBody { nodes } => {
                 let comments: Vec<String> = nodes
                     .iter()
@@ -120,7 +233,7 @@ impl Comment for Node {
 
             ZeroOrMore { node } => match node.as_ref() {
                 // Regular Nodes
-                RuleRef { .. } | TokenRef { .. } | TokenLit { .. } => {
+                Empty | RuleRef { .. } | TokenRef { .. } | TokenLit { .. } | CharClass { .. } => {
                     format!("{}*", node.comment())
                 }
                 // Containers
@@ -129,7 +242,7 @@ impl Comment for Node {
 
             OneOrMore { node } => match node.as_ref() {
                 // Regular Nodes
-                RuleRef { .. } | TokenRef { .. } | TokenLit { .. } => {
+                Empty | RuleRef { .. } | TokenRef { .. } | TokenLit { .. } | CharClass { .. } => {
                     format!("{}+", node.comment())
                 }
                 // Containers
@@ -142,7 +255,11 @@ impl Comment for Node {
                 } else {
                     match node.as_ref() {
                         // Regular Nodes
-                        RuleRef { .. } | TokenRef { .. } | TokenLit { .. } => {
+                        Empty
+                        | RuleRef { .. }
+                        | TokenRef { .. }
+                        | TokenLit { .. }
+                        | CharClass { .. } => {
                             format!("{}?", node.comment())
                         }
                         // Containers
@@ -151,7 +268,44 @@ impl Comment for Node {
                 }
             }
 
-            RuleRef { name } => name.to_owned(),
+            SeparatedList {
+                node,
+                separator,
+                trailing,
+            } => {
+                let op = if *trailing { "%%" } else { "%" };
+                format!(
+                    "{} {} {}",
+                    elem_comment(node.as_ref()),
+                    op,
+                    elem_comment(separator.as_ref())
+                )
+            }
+
+            PositiveLookahead { node } => format!("&{}", elem_comment(node)),
+
+            NegativeLookahead { node } => format!("!{}", elem_comment(node)),
+
+            Action {
+                lang,
+                code,
+                predicate,
+            } => format!(
+                "{{{}: {}}}{}",
+                lang,
+                code,
+                if *predicate { "?" } else { "" }
+            ),
+
+            Empty => "()".to_string(),
+
+            RuleRef { name, args } if args.is_empty() => name.to_owned(),
+            RuleRef { name, args } => format!("{}[{}]", name, args.join(", ")),
+
+            TemplateRef { name, args } => {
+                let comments: Vec<String> = args.iter().map(|arg| arg.comment()).collect();
+                format!("{}<{}>", name, comments.join(", "))
+            }
 
             TokenRef { name, replaced_lit } => (match replaced_lit {
                 Some(lit) => lit,
@@ -159,57 +313,215 @@ impl Comment for Node {
             })
             .to_owned(),
 
-            TokenLit { literal } => format!("\"{}\"", literal),
+            TokenLit {
+                literal,
+                case_insensitive,
+            } => format!(
+                "\"{}{}\"",
+                literal,
+                if *case_insensitive { "i" } else { "" }
+            ),
+
+            CharClass { class } => class.to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GrammarKind {
+    // grammar X; (or no declaration at all)
+    Combined,
+    // lexer grammar X;
+    Lexer,
+    // parser grammar X;
+    Parser,
+}
+
+fn elem_comment(node: &Node) -> String {
+    match node {
+        // Regular Nodes
+        Empty | RuleRef { .. } | TokenRef { .. } | TokenLit { .. } | CharClass { .. } => {
+            node.comment()
         }
+        // Containers
+        _ => format!("({})", node.comment()),
     }
 }
 
 // top_level
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Grammar {
+    pub name: Option<String>,
+    pub kind: GrammarKind,
+    pub options: HashMap<String, String>,
     pub parser_rules: Vec<ParserRule>,
     pub token_rules: Vec<TokenRule>,
+    // Tokens declared with `extern`, matched by a user supplied scanner
+    pub extern_tokens: Vec<String>,
+}
+
+impl Grammar {
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(|value| value.as_str())
+    }
 }
 
 #[derive(pest_derive::Parser)]
 #[grammar = "HRPG.pest"]
 struct HRPGParser;
 
-pub fn parse_hrpg(data: &str) -> Result<Grammar, pest::error::Error<Rule>> {
-    let nodes: Vec<RuleType> = HRPGParser::parse(Rule::top_level, data)?
-        .next()
-        .unwrap()
-        .into_inner()
-        .filter(|p| p.as_rule() == Rule::entry)
-        .map(parse_rule_type)
-        .collect();
+pub fn parse_hrpg(data: &str) -> Result<Grammar, Box<pest::error::Error<Rule>>> {
+    let pairs = HRPGParser::parse(Rule::top_level, data).map_err(Box::new)?;
+
+    // An ANTLR style label parses as a comment, so it's an error instead of being silently dropped
+    if let Some(label) = pairs
+        .clone()
+        .flatten()
+        .find(|pair| pair.as_rule() == Rule::antlr_label)
+    {
+        let span = label.as_span();
+        let name = label.into_inner().next().unwrap().as_str();
+        return Err(Box::new(pest::error::Error::new_from_span(
+            ErrorVariant::CustomError {
+                message: format!(
+                    "Alternative labels are written as '@{}' ('#' starts a comment)",
+                    name
+                ),
+            },
+            span,
+        )));
+    }
 
+    let pairs = pairs.into_iter().next().unwrap().into_inner();
+
+    let mut name: Option<String> = None;
+    let mut kind = GrammarKind::Combined;
+    let mut options: HashMap<String, String> = HashMap::new();
     let mut parser_rules: Vec<ParserRule> = vec![];
     let mut token_rules: Vec<TokenRule> = vec![];
-
-    for node in nodes {
-        match node {
-            RuleType::Parser(rule) => parser_rules.push(rule),
-            RuleType::Token(rule) => token_rules.push(rule),
+    let mut extern_tokens: Vec<String> = vec![];
+
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::grammar_decl => {
+                for inner in pair.into_inner() {
+                    match inner.as_rule() {
+                        Rule::grammar_kind => {
+                            kind = match inner.as_str() {
+                                "lexer" => GrammarKind::Lexer,
+                                _ => GrammarKind::Parser,
+                            }
+                        }
+                        Rule::grammar_name => name = Some(inner.as_str().to_owned()),
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            Rule::grammar_options => {
+                for option in pair.into_inner() {
+                    let mut inner = option.into_inner();
+                    let option_name = inner.next().unwrap().as_str().to_owned();
+                    // Quoted values are stored without their quotes
+                    let option_value = inner.next().unwrap().as_str().trim_matches('\'');
+                    options.insert(option_name, option_value.to_owned());
+                }
+            }
+            Rule::entry => match parse_rule_type(pair) {
+                RuleType::Parser(rule) => parser_rules.push(rule),
+                RuleType::Token(rule) => token_rules.push(rule),
+                RuleType::Extern(names) => extern_tokens.extend(names),
+            },
+            Rule::EOI => (),
+            _ => unreachable!(),
         }
     }
 
     Ok(Grammar {
+        name,
+        kind,
+        options,
         parser_rules,
         token_rules,
+        extern_tokens,
     })
 }
 
 fn parse_rule_type(pair: pest::iterators::Pair<Rule>) -> RuleType {
     match pair.as_rule() {
-        Rule::entry => parse_rule_type(pair.into_inner().next().unwrap()),
+        Rule::entry => {
+            let mut doc = vec![];
+            for inner in pair.into_inner() {
+                match inner.as_rule() {
+                    Rule::doc_comment => doc.push(parse_doc_comment(inner)),
+                    _ => {
+                        return match parse_rule_type(inner) {
+                            RuleType::Parser(rule) => ParserRule { doc, ..rule }.into(),
+                            RuleType::Token(rule) => {
+                                let (annotations, doc) = split_annotations(doc);
+                                TokenRule {
+                                    doc,
+                                    annotations,
+                                    ..rule
+                                }
+                                .into()
+                            }
+                            // Nothing to document
+                            extern_decl => extern_decl,
+                        };
+                    }
+                }
+            }
+            unreachable!()
+        }
+
+        Rule::extern_decl => RuleType::Extern(
+            pair.into_inner()
+                .map(|name| name.as_str().to_owned())
+                .collect(),
+        ),
 
         Rule::parse_rule => {
             let mut inner_rules = pair.into_inner();
             let rule_name = inner_rules.next().unwrap().as_str().to_owned();
-            let rule_body = parse_node(inner_rules.next().unwrap());
+            let mut next_inner = inner_rules.next().unwrap();
+
+            let params = match next_inner.as_rule() {
+                Rule::rule_params => {
+                    let params = next_inner
+                        .into_inner()
+                        .map(|param| param.as_str().to_owned())
+                        .collect();
+                    next_inner = inner_rules.next().unwrap();
+                    params
+                }
+                _ => vec![],
+            };
+
+            let args = match next_inner.as_rule() {
+                Rule::rule_args => {
+                    let args = parse_arg_list(next_inner.into_inner().next().unwrap());
+                    next_inner = inner_rules.next().unwrap();
+                    args
+                }
+                _ => vec![],
+            };
+
+            let returns = match next_inner.as_rule() {
+                Rule::rule_returns => {
+                    let returns = parse_arg_list(next_inner.into_inner().next().unwrap());
+                    next_inner = inner_rules.next().unwrap();
+                    returns
+                }
+                _ => vec![],
+            };
+
+            let rule_body = parse_node(next_inner);
             ParserRule {
                 name: rule_name,
+                params,
+                args,
+                returns,
+                doc: vec![],
                 node: rule_body,
             }
             .into()
@@ -218,10 +530,12 @@ fn parse_rule_type(pair: pest::iterators::Pair<Rule>) -> RuleType {
         Rule::token_rule => {
             let mut inner = pair.into_inner();
             let token_name = inner.next().unwrap().as_str().to_owned();
-            let token_lit = parse_node(inner.next().unwrap());
+            let token_body = parse_node(inner.next().unwrap());
             TokenRule {
                 name: token_name,
-                literal: token_lit,
+                doc: vec![],
+                annotations: vec![],
+                node: token_body,
             }
             .into()
         }
@@ -230,76 +544,318 @@ fn parse_rule_type(pair: pest::iterators::Pair<Rule>) -> RuleType {
     }
 }
 
+// A single leading space is part of the `## ` prefix
+fn parse_doc_comment(pair: Pair<Rule>) -> String {
+    let text = pair.into_inner().next().unwrap().as_str().trim_end();
+    text.strip_prefix(' ').unwrap_or(text).to_owned()
+}
+
+pub(crate) fn split_annotations(doc: Vec<String>) -> (Vec<(String, String)>, Vec<String>) {
+    let mut annotations = vec![];
+    let mut lines = vec![];
+
+    for line in doc {
+        match line.strip_prefix('@') {
+            Some(annotation) => {
+                let (name, value) = annotation.split_once(' ').unwrap_or((annotation, ""));
+                annotations.push((name.to_string(), value.trim().to_string()));
+            }
+            None => lines.push(line),
+        }
+    }
+    (annotations, lines)
+}
+
+fn parse_arg_list(pair: Pair<Rule>) -> Vec<String> {
+    pair.into_inner()
+        .map(|arg| arg.as_str().trim().to_owned())
+        .collect()
+}
+
 fn parse_node(pair: Pair<Rule>) -> Node {
     match pair.as_rule() {
-        Rule::rule_body => {
+        // The ';' and newline terminated forms only differ in how whitespace is skipped
+        Rule::rule_body | Rule::nl_rule_body => {
             let mut nodes: Vec<Node> = pair.into_inner().map(parse_node).collect();
             match nodes.len() {
                 1 => nodes.remove(0),
                 _ => Alternatives { nodes },
             }
         }
-        Rule::rule_piece => {
+        Rule::rule_piece | Rule::nl_rule_piece => {
             let mut inner_rules = pair.into_inner();
-            // Make a copy of our iterator in case there is no binding
-            let saved_inner_rules = inner_rules.clone();
-            let first_inner = inner_rules.next().unwrap();
-
-            fn process_rules(inner_rules: Pairs<Rule>) -> Node {
-                let mut nodes: Vec<Node> = inner_rules.map(parse_node).collect();
-
-                match nodes.len() {
-                    1 => nodes.remove(0),
-                    _ => MultipartBody { nodes },
-                }
+            let node = match inner_rules.next() {
+                Some(rule_parts) => parse_node(rule_parts),
+                None => Empty,
+            };
+
+            // Is this alternative labeled?
+            match inner_rules.next() {
+                Some(alt_label) => Labeled {
+                    label: alt_label.into_inner().next().unwrap().as_str().to_owned(),
+                    node: Box::new(node),
+                },
+                None => node,
             }
+        }
+        Rule::rule_parts | Rule::nl_rule_parts => {
+            let mut nodes: Vec<Node> = pair.into_inner().map(parse_node).collect();
 
-            // Do we have a binding and rules or just rules?
-            match first_inner.as_rule() {
-                Rule::rule_name => Binding {
-                    name: first_inner.as_str().to_owned(),
-                    node: Box::new(process_rules(inner_rules)),
-                },
-                _ => process_rules(saved_inner_rules),
+            match nodes.len() {
+                1 => nodes.remove(0),
+                _ => MultipartBody { nodes },
             }
         }
         Rule::rule_part => {
-            let mut inner_rules = pair.clone().into_inner();
-            let first_inner = inner_rules.next().unwrap();
-            let node = parse_node(first_inner.clone());
-
-            match first_inner.as_rule() {
-                Rule::rule_elem => match pair.as_str().chars().last() {
-                    Some('+') => OneOrMore {
-                        node: Box::new(node),
-                    },
-                    Some('*') => ZeroOrMore {
-                        node: Box::new(node),
-                    },
-                    Some('?') => ZeroOrOne {
-                        node: Box::new(node),
-                        brackets: false,
-                    },
-                    _ => node,
-                },
+            let mut inner_rules = pair.into_inner();
+            let mut first_inner = inner_rules.next().unwrap();
+
+            if first_inner.as_rule() == Rule::action {
+                let mut inner = first_inner.into_inner();
+                let mut block = inner.next().unwrap().into_inner();
+                let lang = block.next().unwrap().as_str().to_owned();
+                let code = block.next().unwrap().as_str().trim().to_owned();
+
+                return Action {
+                    lang,
+                    code,
+                    predicate: inner.next().is_some(),
+                };
+            }
+
+            // Lookahead takes the place of a binding/suffix
+            if first_inner.as_rule() == Rule::lookahead {
+                let node = Box::new(parse_node(inner_rules.next().unwrap()));
+
+                return match first_inner.as_str() {
+                    "&" => PositiveLookahead { node },
+                    _ => NegativeLookahead { node },
+                };
+            }
+
+            // Do we have a binding?
+            let binding = match first_inner.as_rule() {
+                Rule::binding => {
+                    let mut binding_rules = first_inner.into_inner();
+                    let name = binding_rules.next().unwrap().as_str().to_owned();
+                    let list = binding_rules.next().unwrap().as_str() == "+=";
+                    first_inner = inner_rules.next().unwrap();
+                    Some((name, list))
+                }
+                _ => None,
+            };
+
+            let node = match first_inner.as_rule() {
+                Rule::rule_elem => {
+                    let node = parse_node(first_inner);
+
+                    match inner_rules.next() {
+                        Some(suffix) if suffix.as_rule() == Rule::suffix => match suffix.as_str() {
+                            "+" => OneOrMore {
+                                node: Box::new(node),
+                            },
+                            "*" => ZeroOrMore {
+                                node: Box::new(node),
+                            },
+                            _ => ZeroOrOne {
+                                node: Box::new(node),
+                                brackets: false,
+                            },
+                        },
+                        Some(separator) => SeparatedList {
+                            node: Box::new(node),
+                            separator: Box::new(parse_node(inner_rules.next().unwrap())),
+                            trailing: separator.as_str() == "%%",
+                        },
+                        None => node,
+                    }
+                }
                 Rule::rule_body => ZeroOrOne {
-                    node: Box::new(node),
+                    node: Box::new(parse_node(first_inner)),
                     brackets: true,
                 },
                 _ => unreachable!(),
+            };
+
+            match binding {
+                Some((name, list)) => Binding {
+                    name,
+                    list,
+                    node: Box::new(node),
+                },
+                None => node,
             }
         }
         Rule::rule_elem => parse_node(pair.into_inner().next().unwrap()),
+        Rule::template_ref => {
+            let mut inner_rules = pair.into_inner();
+            TemplateRef {
+                name: inner_rules.next().unwrap().as_str().to_owned(),
+                args: inner_rules.map(parse_node).collect(),
+            }
+        }
+        Rule::rule_call => {
+            let mut inner_rules = pair.into_inner();
+            RuleRef {
+                name: inner_rules.next().unwrap().as_str().to_owned(),
+                args: parse_arg_list(inner_rules.next().unwrap()),
+            }
+        }
         Rule::rule_name => RuleRef {
             name: pair.as_str().to_owned(),
+            args: vec![],
         },
         Rule::token_name => TokenRef {
             name: pair.as_str().to_owned(),
             replaced_lit: None,
         },
-        Rule::token_lit => TokenLit {
-            literal: pair.as_str().to_owned(),
+        Rule::token_body | Rule::nl_token_body => {
+            let mut nodes: Vec<Node> = pair.into_inner().map(parse_node).collect();
+            match nodes.len() {
+                1 => nodes.remove(0),
+                _ => Alternatives { nodes },
+            }
+        }
+        Rule::token_seq | Rule::nl_token_seq => {
+            let mut nodes: Vec<Node> = pair.into_inner().map(parse_node).collect();
+            match nodes.len() {
+                1 => nodes.remove(0),
+                _ => MultipartBody { nodes },
+            }
+        }
+        Rule::token_part => {
+            let mut inner_rules = pair.into_inner();
+            let node = parse_node(inner_rules.next().unwrap());
+
+            match inner_rules.next().map(|suffix| suffix.as_str()) {
+                Some("+") => OneOrMore {
+                    node: Box::new(node),
+                },
+                Some("*") => ZeroOrMore {
+                    node: Box::new(node),
+                },
+                Some("?") => ZeroOrOne {
+                    node: Box::new(node),
+                    brackets: false,
+                },
+                _ => node,
+            }
+        }
+        Rule::token_atom => parse_node(pair.into_inner().next().unwrap()),
+        Rule::token_lit => {
+            let literal = pair.as_str();
+            match literal.strip_suffix('i') {
+                Some(literal) => TokenLit {
+                    literal: literal.to_owned(),
+                    case_insensitive: true,
+                },
+                None => TokenLit {
+                    literal: literal.to_owned(),
+                    case_insensitive: false,
+                },
+            }
+        }
+        Rule::char_class | Rule::unicode_class => CharClass {
+            class: pair.as_str().to_owned(),
         },
         _ => unreachable!(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule_comment(data: &str) -> String {
+        let grammar = parse_hrpg(data).unwrap();
+        grammar.parser_rules[0].node.comment()
+    }
+
+    #[test]
+    fn annotations_are_not_doc() {
+        let grammar =
+            parse_hrpg("## Keywords\n## @scope keyword.control\n## @skip\nIF: 'if';\n").unwrap();
+        let rule = &grammar.token_rules[0];
+        assert_eq!(rule.doc, vec!["Keywords"]);
+        assert_eq!(rule.annotation("scope"), Some("keyword.control"));
+        assert_eq!(rule.annotation("skip"), Some(""));
+        assert_eq!(rule.annotation("other"), None);
+    }
+
+    // Only token rules have annotations
+    #[test]
+    fn parser_rule_doc_keeps_at_lines() {
+        let grammar = parse_hrpg("## @see b\na: 'x';\n").unwrap();
+        assert_eq!(grammar.parser_rules[0].doc, vec!["@see b"]);
+    }
+
+    // Each block is its own action, so only the last one is a predicate
+    #[test]
+    fn action_blocks() {
+        assert_eq!(
+            rule_comment("a: {rust: self.a()} {rust: self.b()}? c;"),
+            "{rust: self.a()} {rust: self.b()}? c"
+        );
+
+        let grammar = parse_hrpg("a: {rust: self.a()} {python: self.a()}? c;").unwrap();
+        match &grammar.parser_rules[0].node {
+            MultipartBody { nodes } => assert!(matches!(
+                &nodes[..2],
+                [
+                    Action { lang, predicate: false, .. },
+                    Action { predicate: true, .. },
+                ] if lang == "rust"
+            )),
+            node => panic!("{:?}", node),
+        }
+    }
+
+    #[test]
+    fn comment_is_not_label() {
+        assert_eq!(rule_comment("a: b  # TODO\n | c;"), "b | c");
+        assert_eq!(rule_comment("a: b  # TODO\n | c\n"), "b | c");
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(
+            rule_comment("a: b @First | c @Second;"),
+            "b @First | c @Second"
+        );
+        assert_eq!(
+            rule_comment("a: b @First\n | c @Second\n"),
+            "b @First | c @Second"
+        );
+    }
+
+    #[test]
+    fn comment_and_label_on_alternative() {
+        let data = "a: b @First # TODO\n | c @Second /* note */ # Done\n;";
+        assert_eq!(rule_comment(data), "b @First | c @Second");
+
+        let data = "a: b @First # TODO\n | c @Second # Done\n";
+        assert_eq!(rule_comment(data), "b @First | c @Second");
+    }
+
+    #[test]
+    fn comments_starting_with_uppercase() {
+        assert_eq!(rule_comment("a: b #TODO: fix\n | c;"), "b | c");
+        assert_eq!(rule_comment("a: b # Done\n | c\n"), "b | c");
+        assert!(parse_hrpg("a: B; #Note this\nB: 'b';\n").is_ok());
+    }
+
+    #[test]
+    fn antlr_label_is_error() {
+        let err = parse_hrpg("expr: expr '*' expr # Mul | INT # Int;").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Alternative labels are written as '@Mul' ('#' starts a comment)"));
+        assert!(parse_hrpg("a: b #B\n | c #C\n;").is_ok());
+        assert!(parse_hrpg("a: b #B |\n c\n").is_err());
+    }
+
+    #[test]
+    fn label_only_ends_alternative() {
+        assert!(parse_hrpg("a: b @First c | d @Second;").is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use crate::ast::{Comment, Grammar, Node, ParserRule};
+use crate::parser_gen::LangConfig;
+
+use convert_case::Casing;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FieldKind {
+    Once,
+    Optional,
+    List,
+}
+
+impl FieldKind {
+    fn to_optional(self) -> FieldKind {
+        match self {
+            FieldKind::Once => FieldKind::Optional,
+            kind => kind,
+        }
+    }
+
+    fn merge(self, other: FieldKind) -> FieldKind {
+        match (self, other) {
+            (FieldKind::List, _) | (_, FieldKind::List) => FieldKind::List,
+            (FieldKind::Optional, _) | (_, FieldKind::Optional) => FieldKind::Optional,
+            _ => FieldKind::Once,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FieldType {
+    Token,
+    Node(String),
+}
+
+#[derive(Debug)]
+pub struct Field {
+    pub name: String,
+    pub field_type: FieldType,
+    pub kind: FieldKind,
+}
+
+#[derive(Debug)]
+pub struct Variant {
+    pub name: String,
+    pub comment: String,
+    pub fields: Vec<Field>,
+}
+
+// The language agnostic description of a generated AST node type
+#[derive(Debug)]
+pub enum AstType {
+    // Rules without top level alternatives (and bindings of containers) - one field per binding
+    Struct {
+        name: String,
+        // Doc comment lines of the rule - types made for bindings have none
+        doc: Vec<String>,
+        comment: String,
+        fields: Vec<Field>,
+    },
+    // Rules with top level alternatives - one variant per alternative
+    Enum {
+        name: String,
+        doc: Vec<String>,
+        comment: String,
+        variants: Vec<Variant>,
+    },
+}
+
+pub struct AstGen<'l, L> {
+    types: Vec<AstType>,
+    lang_config: &'l L,
+}
+
+impl<'l, L: LangConfig> AstGen<'l, L> {
+    pub fn new(config: &'l L) -> Self {
+        AstGen {
+            types: vec![],
+            lang_config: config,
+        }
+    }
+
+    pub fn generate(mut self, grammar: &Grammar) -> Vec<AstType> {
+        for rule in &grammar.parser_rules {
+            log::trace!("Starting AST type: {}", &rule.name);
+            self.make_rule_type(rule);
+            log::trace!("Ending AST type: {}", &rule.name);
+        }
+
+        self.types
+    }
+
+    fn make_rule_type(&mut self, rule: &ParserRule) {
+        let name = rule.name.to_case(self.lang_config.class_case());
+
+        let ast_type = match &rule.node {
+            Node::Alternatives { nodes } => {
+                let mut variants: Vec<Variant> = vec![];
+
+                for (idx, node) in nodes.iter().enumerate() {
+                    let mut variant = self.make_variant(&rule.name, idx, node);
+                    // Unlabeled alternatives can derive the same name (ex: `a b | a c`)
+                    if variants.iter().any(|v| v.name == variant.name) {
+                        variant.name = format!("{}{}", variant.name, idx + 1);
+                    }
+                    variants.push(variant);
+                }
+
+                AstType::Enum {
+                    name,
+                    doc: rule.doc.clone(),
+                    comment: rule.comment(),
+                    variants,
+                }
+            }
+            node => AstType::Struct {
+                name,
+                doc: rule.doc.clone(),
+                comment: rule.comment(),
+                fields: self.make_fields(&rule.name, node),
+            },
+        };
+
+        self.types.push(ast_type);
+    }
+
+    fn make_variant(&mut self, base: &str, idx: usize, node: &Node) -> Variant {
+        // Labels name the variant, otherwise we try and derive a name from the alternative
+        let (name, node) = match node {
+            Node::Labeled { label, node } => (label.to_string(), node.as_ref()),
+            Node::RuleRef { name, .. } | Node::TokenRef { name, .. } => (name.to_string(), node),
+            Node::Empty => ("empty".to_string(), node),
+            _ => (format!("alt{}", idx + 1), node),
+        };
+
+        let mut fields = self.make_fields(base, node);
+
+        // An alternative of just a single rule/token still needs to hold what it matched
+        if fields.is_empty() {
+            match unwrap_repetition(node, FieldKind::Once) {
+                (Node::RuleRef { name, .. }, kind) => fields.push(Field {
+                    name: name.to_case(self.lang_config.var_case()),
+                    field_type: FieldType::Node(name.to_case(self.lang_config.class_case())),
+                    kind,
+                }),
+                (Node::TokenRef { name, .. }, kind) => fields.push(Field {
+                    name: name.to_case(self.lang_config.var_case()),
+                    field_type: FieldType::Token,
+                    kind,
+                }),
+                _ => (),
+            }
+        }
+
+        Variant {
+            name: name.to_case(self.lang_config.class_case()),
+            comment: node.comment(),
+            fields,
+        }
+    }
+
+    fn make_fields(&mut self, base: &str, node: &Node) -> Vec<Field> {
+        let mut fields = vec![];
+        self.collect_fields(base, node, FieldKind::Once, &mut fields);
+        fields
+    }
+
+    fn collect_fields(
+        &mut self,
+        base: &str,
+        node: &Node,
+        kind: FieldKind,
+        fields: &mut Vec<Field>,
+    ) {
+        match node {
+            Node::Binding {
+                name,
+                list,
+                node: bound_node,
+            } => {
+                // Repetition changes how many values are bound, not their type
+                let (bound_node, kind) = unwrap_repetition(bound_node, kind);
+                let kind = if *list { FieldKind::List } else { kind };
+
+                // A binding can be used more than once (ex: `+=`) - `Transform` ensures they are all the same type
+                let field_name = name.to_case(self.lang_config.var_case());
+                if let Some(field) = fields.iter_mut().find(|field| field.name == field_name) {
+                    field.kind = field.kind.merge(kind);
+                    return;
+                }
+
+                let field_type = match bound_node {
+                    Node::RuleRef { name, .. } => {
+                        FieldType::Node(name.to_case(self.lang_config.class_case()))
+                    }
+                    Node::TokenRef { .. } => FieldType::Token,
+                    // Containers get their own type named after the binding
+                    _ => {
+                        let sub_base = format!("{}_{}", base, name);
+                        let sub_fields = self.make_fields(&sub_base, bound_node);
+                        let type_name = sub_base.to_case(self.lang_config.class_case());
+
+                        self.types.push(AstType::Struct {
+                            name: type_name.clone(),
+                            doc: vec![],
+                            comment: node.comment(),
+                            fields: sub_fields,
+                        });
+                        FieldType::Node(type_name)
+                    }
+                };
+
+                fields.push(Field {
+                    name: field_name,
+                    field_type,
+                    kind,
+                });
+            }
+            // Any one alternative might not match
+            Node::Alternatives { nodes } => nodes
+                .iter()
+                .for_each(|node| self.collect_fields(base, node, kind.to_optional(), fields)),
+            Node::Labeled { node, .. } => self.collect_fields(base, node, kind, fields),
+            Node::MultipartBody { nodes } => nodes
+                .iter()
+                .for_each(|node| self.collect_fields(base, node, kind, fields)),
+            Node::ZeroOrMore { node } | Node::OneOrMore { node } => {
+                self.collect_fields(base, node, FieldKind::List, fields)
+            }
+            Node::SeparatedList {
+                node, separator, ..
+            } => {
+                self.collect_fields(base, node, FieldKind::List, fields);
+                self.collect_fields(base, separator, FieldKind::List, fields);
+            }
+            Node::ZeroOrOne { node, .. } => {
+                self.collect_fields(base, node, kind.to_optional(), fields)
+            }
+            // Lookahead never consumes anything, so there is nothing to store
+            Node::PositiveLookahead { .. } | Node::NegativeLookahead { .. } => (),
+            Node::Action { .. } | Node::Empty => (),
+            Node::RuleRef { .. }
+            | Node::TemplateRef { .. }
+            | Node::TokenRef { .. }
+            | Node::TokenLit { .. }
+            | Node::CharClass { .. } => (),
+        }
+    }
+}
+
+fn unwrap_repetition(node: &Node, kind: FieldKind) -> (&Node, FieldKind) {
+    match node {
+        Node::ZeroOrMore { node } | Node::OneOrMore { node } | Node::SeparatedList { node, .. } => {
+            unwrap_repetition(node, FieldKind::List)
+        }
+        Node::ZeroOrOne { node, .. } => unwrap_repetition(node, kind.to_optional()),
+        _ => (node, kind),
+    }
+}
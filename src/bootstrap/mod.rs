@@ -0,0 +1,606 @@
+// The grammar file parser generated by the Rust backend from `examples/hrpg/hrpg.hrpg` - regenerate it with
+// `cargo run -- build examples/hrpg/hrpg.hrpg -o src/bootstrap/parser.rs`
+#[rustfmt::skip]
+mod parser;
+
+use std::collections::HashMap;
+
+use crate::ast::Node::*;
+use crate::ast::{split_annotations, Grammar, GrammarKind, Node, ParserRule, TokenRule};
+use parser::{ExternalScanner, Parser, RuleKind, TokenKind, Tree};
+
+pub use parser::ParseError;
+
+// Matches the tokens of `hrpg.hrpg` that depend on context
+struct HrpgScanner;
+
+impl ExternalScanner for HrpgScanner {
+    // The end of a line (or the input), after any spaces and block comments - a comment also ends the line, unless it
+    // is a doc comment or an alternative label
+    fn scan_nl(&mut self, input: &str, pos: usize) -> Option<usize> {
+        let pos = skip_inline(input, pos);
+        let rest = &input[pos..];
+
+        if rest.is_empty() {
+            return Some(pos);
+        }
+        if rest.starts_with(['\n', '\r']) {
+            return Some(pos + 1);
+        }
+
+        let mut chars = rest.chars();
+        if chars.next() != Some('#') {
+            return None;
+        }
+        match chars.next() {
+            Some('#') if chars.next() != Some('#') => None,
+            Some('A'..='Z') => None,
+            // Comments need a newline to end them
+            _ => rest.find('\n').map(|idx| pos + idx + 1),
+        }
+    }
+
+    // Target language code up to the ',' or bracket ending it - commas only separate arguments outside of brackets
+    fn scan_arg(&mut self, input: &str, pos: usize) -> Option<usize> {
+        let mut closers = vec![];
+
+        for (idx, ch) in input[pos..].char_indices() {
+            match ch {
+                '(' => closers.push(')'),
+                '[' => closers.push(']'),
+                ')' | ']' if closers.last() == Some(&ch) => {
+                    closers.pop();
+                }
+                ')' | ']' if !closers.is_empty() => return None,
+                ')' | ']' | ',' if closers.is_empty() => return (idx > 0).then_some(pos + idx),
+                _ => (),
+            }
+        }
+        None
+    }
+
+    // Target language code up to the '}' ending its block - braces in code must be balanced
+    fn scan_action_code(&mut self, input: &str, pos: usize) -> Option<usize> {
+        let mut depth = 0;
+
+        for (idx, ch) in input[pos..].char_indices() {
+            match ch {
+                '{' => depth += 1,
+                '}' if depth == 0 => return Some(pos + idx),
+                '}' => depth -= 1,
+                _ => (),
+            }
+        }
+        (depth == 0).then_some(input.len())
+    }
+
+    fn skip(&mut self, input: &str, pos: usize) -> usize {
+        block_comment(input, pos).unwrap_or(pos)
+    }
+}
+
+fn skip_inline(input: &str, mut pos: usize) -> usize {
+    loop {
+        if input[pos..].starts_with([' ', '\t']) {
+            pos += 1;
+        } else if let Some(end) = block_comment(input, pos) {
+            pos = end;
+        } else {
+            return pos;
+        }
+    }
+}
+
+// Block comments nest (ex: `/* a /* b */ c */`)
+fn block_comment(input: &str, pos: usize) -> Option<usize> {
+    if !input[pos..].starts_with("/*") {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut idx = pos;
+    while let Some(ch) = input[idx..].chars().next() {
+        if input[idx..].starts_with("/*") {
+            depth += 1;
+            idx += 2;
+        } else if input[idx..].starts_with("*/") {
+            depth -= 1;
+            idx += 2;
+            if depth == 0 {
+                return Some(idx);
+            }
+        } else {
+            idx += ch.len_utf8();
+        }
+    }
+    None
+}
+
+// Parses a grammar file without pest - the result is the same as `ast::parse_hrpg`
+pub fn parse_grammar(data: &str) -> Result<Grammar, ParseError> {
+    let tree = Parser::new(data, HrpgScanner).parse()?;
+    let top_level = children(&tree);
+
+    let mut kind = GrammarKind::Combined;
+    let mut name = None;
+    if let Some(decl) = rule(top_level, RuleKind::GrammarDecl) {
+        if let Some(grammar_kind) = rule(decl, RuleKind::GrammarKind) {
+            kind = match token(grammar_kind, TokenKind::Lexer) {
+                Some(_) => GrammarKind::Lexer,
+                None => GrammarKind::Parser,
+            };
+        }
+        name = rule(decl, RuleKind::GrammarName).map(|name| text(name).to_owned());
+    }
+
+    let mut options = HashMap::new();
+    for option in rules(top_level, RuleKind::GrammarOptions)
+        .flat_map(|grammar_options| rules(grammar_options, RuleKind::GrammarOption))
+    {
+        let option_name = rule(option, RuleKind::GrammarName).map_or("", text);
+        // Quoted values are stored without their quotes
+        let option_value = rule(option, RuleKind::OptionValue).map_or("", text);
+        options.insert(
+            option_name.to_owned(),
+            option_value.trim_matches('\'').to_owned(),
+        );
+    }
+
+    let mut parser_rules = vec![];
+    let mut token_rules = vec![];
+    let mut extern_tokens = vec![];
+
+    for entry in rules(top_level, RuleKind::Entry) {
+        let doc: Vec<String> = tokens(entry, TokenKind::DocComment)
+            .map(doc_comment)
+            .collect();
+
+        if let Some(extern_decl) = rule(entry, RuleKind::ExternDecl) {
+            // Nothing to document
+            extern_tokens.extend(tokens(extern_decl, TokenKind::TokenName).map(str::to_owned));
+        } else if let Some(parse_rule) = rule(entry, RuleKind::Rule) {
+            parser_rules.push(ParserRule {
+                name: token(parse_rule, TokenKind::RuleName)
+                    .unwrap_or_default()
+                    .to_owned(),
+                params: rule(parse_rule, RuleKind::RuleParams)
+                    .map(|params| {
+                        tokens(params, TokenKind::RuleName)
+                            .map(str::to_owned)
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                args: rule(parse_rule, RuleKind::RuleArgs)
+                    .map(arg_list)
+                    .unwrap_or_default(),
+                returns: rule(parse_rule, RuleKind::RuleReturns)
+                    .map(arg_list)
+                    .unwrap_or_default(),
+                doc,
+                node: rule(parse_rule, RuleKind::RuleBody)
+                    .or_else(|| rule(parse_rule, RuleKind::NlRuleBody))
+                    .map_or(Empty, rule_body),
+            });
+        } else if let Some(token_rule) = rule(entry, RuleKind::TokenRule) {
+            let (annotations, doc) = split_annotations(doc);
+            token_rules.push(TokenRule {
+                name: token(token_rule, TokenKind::TokenName)
+                    .unwrap_or_default()
+                    .to_owned(),
+                doc,
+                annotations,
+                node: rule(token_rule, RuleKind::TokenBody)
+                    .or_else(|| rule(token_rule, RuleKind::NlTokenBody))
+                    .map_or(Empty, token_body),
+            });
+        }
+    }
+
+    Ok(Grammar {
+        name,
+        kind,
+        options,
+        parser_rules,
+        token_rules,
+        extern_tokens,
+    })
+}
+
+// A single leading space is part of the `## ` prefix
+fn doc_comment(comment: &str) -> String {
+    let text = comment.trim_start_matches("##").trim_end();
+    text.strip_prefix(' ').unwrap_or(text).to_owned()
+}
+
+fn arg_list(decl: &[Tree]) -> Vec<String> {
+    rule(decl, RuleKind::ArgList)
+        .map(|args| {
+            tokens(args, TokenKind::Arg)
+                .map(|arg| arg.trim().to_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// `rule_body` and `nl_rule_body` only differ in how whitespace is skipped
+fn rule_body(body: &[Tree]) -> Node {
+    // An alternative after a '|' can be missing from newline terminated bodies (ex: `a: b |`)
+    let mut nodes: Vec<Node> = body
+        .split(|tree| is_token(tree, TokenKind::Pipe))
+        .map(|alternative| {
+            rule(alternative, RuleKind::RulePiece)
+                .or_else(|| rule(alternative, RuleKind::NlRulePiece))
+                .map_or(Empty, rule_piece)
+        })
+        .collect();
+
+    match nodes.len() {
+        1 => nodes.remove(0),
+        _ => Alternatives { nodes },
+    }
+}
+
+fn rule_piece(piece: &[Tree]) -> Node {
+    let node = match rule(piece, RuleKind::RuleParts).or_else(|| rule(piece, RuleKind::NlRuleParts))
+    {
+        Some(parts) => {
+            let mut nodes: Vec<Node> = rules(parts, RuleKind::RulePart).map(rule_part).collect();
+            match nodes.len() {
+                1 => nodes.remove(0),
+                _ => MultipartBody { nodes },
+            }
+        }
+        None => Empty,
+    };
+
+    // Is this alternative labeled?
+    match token(piece, TokenKind::AltLabel) {
+        Some(label) => Labeled {
+            label: label.trim_start_matches('@').to_owned(),
+            node: Box::new(node),
+        },
+        None => node,
+    }
+}
+
+fn rule_part(part: &[Tree]) -> Node {
+    if let Some(action) = rule(part, RuleKind::Action) {
+        let block = rule(action, RuleKind::ActionBlock).unwrap_or_default();
+        let lang = rule(block, RuleKind::GrammarName).map_or("", text);
+        let code = token(block, TokenKind::ActionCode).unwrap_or_default();
+
+        return Action {
+            lang: lang.to_owned(),
+            code: code.trim().to_owned(),
+            predicate: token(action, TokenKind::QuestMark).is_some(),
+        };
+    }
+
+    let elems: Vec<&[Tree]> = rules(part, RuleKind::RuleElem).collect();
+
+    // Lookahead takes the place of a binding/suffix
+    if let Some(lookahead) = rule(part, RuleKind::Lookahead) {
+        let node = Box::new(rule_elem(elems[0]));
+
+        return match text(lookahead) {
+            "&" => PositiveLookahead { node },
+            _ => NegativeLookahead { node },
+        };
+    }
+
+    let node = match elems.first() {
+        Some(elem) => {
+            let node = Box::new(rule_elem(elem));
+
+            if let Some(separator) = rule(part, RuleKind::Separator) {
+                SeparatedList {
+                    node,
+                    separator: Box::new(rule_elem(elems[1])),
+                    trailing: text(separator) == "%%",
+                }
+            } else {
+                match rule(part, RuleKind::Suffix).map(text) {
+                    Some("+") => OneOrMore { node },
+                    Some("*") => ZeroOrMore { node },
+                    Some(_) => ZeroOrOne {
+                        node,
+                        brackets: false,
+                    },
+                    None => *node,
+                }
+            }
+        }
+        None => ZeroOrOne {
+            node: Box::new(rule(part, RuleKind::RuleBody).map_or(Empty, rule_body)),
+            brackets: true,
+        },
+    };
+
+    match rule(part, RuleKind::Binding) {
+        Some(binding) => Binding {
+            name: token(binding, TokenKind::RuleName)
+                .unwrap_or_default()
+                .to_owned(),
+            list: token(binding, TokenKind::PlusEquals).is_some(),
+            node: Box::new(node),
+        },
+        None => node,
+    }
+}
+
+fn rule_elem(elem: &[Tree]) -> Node {
+    if let Some(body) = rule(elem, RuleKind::RuleBody) {
+        rule_body(body)
+    } else if let Some(template_ref) = rule(elem, RuleKind::TemplateRef) {
+        TemplateRef {
+            name: token(template_ref, TokenKind::RuleName)
+                .unwrap_or_default()
+                .to_owned(),
+            args: rules(template_ref, RuleKind::RuleBody)
+                .map(rule_body)
+                .collect(),
+        }
+    } else if let Some(rule_call) = rule(elem, RuleKind::RuleCall) {
+        RuleRef {
+            name: token(rule_call, TokenKind::RuleCall)
+                .unwrap_or_default()
+                .trim_end_matches('[')
+                .to_owned(),
+            args: arg_list(rule_call),
+        }
+    } else if let Some(name) = token(elem, TokenKind::RuleName) {
+        RuleRef {
+            name: name.to_owned(),
+            args: vec![],
+        }
+    } else if let Some(name) = token(elem, TokenKind::TokenName) {
+        TokenRef {
+            name: name.to_owned(),
+            replaced_lit: None,
+        }
+    } else {
+        token_lit(token(elem, TokenKind::TokenLit).unwrap_or_default())
+    }
+}
+
+// `token_body` and `nl_token_body` only differ in how whitespace is skipped
+fn token_body(body: &[Tree]) -> Node {
+    let mut nodes: Vec<Node> = body
+        .iter()
+        .filter(|tree| is_rule(tree, RuleKind::TokenSeq) || is_rule(tree, RuleKind::NlTokenSeq))
+        .map(|seq| {
+            let mut nodes: Vec<Node> = rules(children(seq), RuleKind::TokenPart)
+                .map(token_part)
+                .collect();
+            match nodes.len() {
+                1 => nodes.remove(0),
+                _ => MultipartBody { nodes },
+            }
+        })
+        .collect();
+
+    match nodes.len() {
+        1 => nodes.remove(0),
+        _ => Alternatives { nodes },
+    }
+}
+
+fn token_part(part: &[Tree]) -> Node {
+    let atom = rule(part, RuleKind::TokenAtom).unwrap_or_default();
+    let node = if let Some(body) = rule(atom, RuleKind::TokenBody) {
+        token_body(body)
+    } else if let Some(literal) = token(atom, TokenKind::TokenLit) {
+        token_lit(literal)
+    } else {
+        CharClass {
+            class: text(atom).to_owned(),
+        }
+    };
+
+    match rule(part, RuleKind::Suffix).map(text) {
+        Some("+") => OneOrMore {
+            node: Box::new(node),
+        },
+        Some("*") => ZeroOrMore {
+            node: Box::new(node),
+        },
+        Some(_) => ZeroOrOne {
+            node: Box::new(node),
+            brackets: false,
+        },
+        None => node,
+    }
+}
+
+fn token_lit(literal: &str) -> Node {
+    match literal.strip_suffix('i') {
+        Some(literal) => TokenLit {
+            literal: literal.to_owned(),
+            case_insensitive: true,
+        },
+        None => TokenLit {
+            literal: literal.to_owned(),
+            case_insensitive: false,
+        },
+    }
+}
+
+// *** Tree access ***
+
+fn children<'t, 'i>(tree: &'t Tree<'i>) -> &'t [Tree<'i>] {
+    match tree {
+        Tree::Rule { children, .. } => children,
+        Tree::Token(_) => &[],
+    }
+}
+
+fn is_rule(tree: &Tree, kind: RuleKind) -> bool {
+    matches!(tree, Tree::Rule { kind: rule_kind, .. } if *rule_kind == kind)
+}
+
+fn is_token(tree: &Tree, kind: TokenKind) -> bool {
+    matches!(tree, Tree::Token(token) if token.kind == kind)
+}
+
+// Children of each child rule of the kind
+fn rules<'t, 'i>(trees: &'t [Tree<'i>], kind: RuleKind) -> impl Iterator<Item = &'t [Tree<'i>]> {
+    trees
+        .iter()
+        .filter(move |tree| is_rule(tree, kind))
+        .map(children)
+}
+
+fn rule<'t, 'i>(trees: &'t [Tree<'i>], kind: RuleKind) -> Option<&'t [Tree<'i>]> {
+    rules(trees, kind).next()
+}
+
+fn tokens<'t, 'i>(trees: &'t [Tree<'i>], kind: TokenKind) -> impl Iterator<Item = &'i str> + 't {
+    trees.iter().filter_map(move |tree| match tree {
+        Tree::Token(token) if token.kind == kind => Some(token.text),
+        _ => None,
+    })
+}
+
+fn token<'i>(trees: &[Tree<'i>], kind: TokenKind) -> Option<&'i str> {
+    tokens(trees, kind).next()
+}
+
+// The text of the first token within the trees (ex: the name of a `grammar_name`)
+fn text<'i>(trees: &[Tree<'i>]) -> &'i str {
+    trees
+        .iter()
+        .find_map(|tree| match tree {
+            Tree::Rule { children, .. } => Some(text(children)).filter(|text| !text.is_empty()),
+            Tree::Token(token) => Some(token.text),
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use super::*;
+    use crate::ast::parse_hrpg;
+    use crate::lang::rust::{write_rust, RustConfig};
+    use crate::lexer_gen::LexerGen;
+    use crate::parser_gen::ParserGen;
+    use crate::transform::Transform;
+
+    const HRPG: &str = include_str!("../../examples/hrpg/hrpg.hrpg");
+
+    fn build(g: &Grammar) -> String {
+        let (g2, transform) = Transform::process(g);
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+
+        let vocab = transform.vocab();
+        let lexer_spec = LexerGen::new(RustConfig).generate(&g2, &vocab);
+        let spec = ParserGen::new(RustConfig).generate(&g2);
+        write_rust(&lexer_spec, &spec, &vocab, "hrpg.hrpg").unwrap()
+    }
+
+    fn assert_same(data: &str) {
+        assert_eq!(
+            parse_grammar(data).unwrap(),
+            parse_hrpg(data).unwrap(),
+            "{}",
+            data
+        );
+    }
+
+    #[test]
+    fn parser_is_up_to_date() {
+        assert_eq!(build(&parse_hrpg(HRPG).unwrap()), include_str!("parser.rs"));
+    }
+
+    // The checked in parser generates itself
+    #[test]
+    fn regenerating_is_fixed_point() {
+        assert_eq!(
+            build(&parse_grammar(HRPG).unwrap()),
+            include_str!("parser.rs")
+        );
+    }
+
+    #[test]
+    fn examples_match_pest() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let mut count = 0;
+
+        for dir in fs::read_dir(examples).unwrap() {
+            for file in fs::read_dir(dir.unwrap().path()).unwrap() {
+                let path = file.unwrap().path();
+                if path.extension().is_some_and(|ext| ext == "hrpg") {
+                    assert_same(&fs::read_to_string(&path).unwrap());
+                    count += 1;
+                }
+            }
+        }
+        assert!(count > 1);
+    }
+
+    #[test]
+    fn declaration_and_options() {
+        assert_same("lexer grammar Lex;\nA: 'a';\n");
+        assert_same("parser grammar calc;\noptions { tokenVocab = Lex; ws = ' '; }\na: A;\n");
+        assert_same("grammar G; options {} a: 'x';");
+    }
+
+    #[test]
+    fn doc_comments_and_annotations() {
+        assert_same("## Keywords\n## @scope keyword.control\n## @skip\nIF: 'if';\n");
+        assert_same("##\n## A rule\n### Heading ###\n# comment\na: 'x'\n");
+        assert_same("## @see b\na: 'x';\n");
+    }
+
+    #[test]
+    fn newline_terminated_rules() {
+        assert_same("a: b c\n  | d\nb: 'b' | 'c' |\n| 'd'\n");
+        assert_same("a: b  # TODO\n | c\n");
+        assert_same("a: b @First # TODO\n | c @Second /* note */ # Done\n");
+        assert_same("a: b /* a /* nested */ comment */ c\nd: e");
+        assert_same("A: 'a' | 'b'\n | 'c'\nB: [a-z]+ 'x'?\n");
+    }
+
+    #[test]
+    fn semicolon_terminated_rules() {
+        assert_same("a: b\n  c\n  | d;\nb: ;\nc: 'c' | ;");
+        assert_same("a: b @First | c @Second | () @Third;");
+        assert_same("a: b  # TODO\n | c;");
+        assert_same("A: 'a'\n 'b'*\n | ('c' | 'd')+;");
+    }
+
+    #[test]
+    fn rule_parts() {
+        assert_same("a: x=b ys+=c* &d !'e' (f | g)? [h i] j % ',' k %% ';'\n");
+        assert_same("a: {rust: self.depth += 1} {python: self.depth += 1} b {rust: self.ok()}?\n");
+        assert_same("a: {rust: if x { y } else { z }} b\n");
+        assert_same("a: {rust: self.a()} {rust: self.b()}? c\n");
+        assert_same("A: 'select'i '\\'' [^'\\\\] \\p{XID_Start} \\P{L}\n");
+    }
+
+    #[test]
+    fn args_returns_and_templates() {
+        assert_same("stmt[in_loop: bool, depth: (u32, u32)] returns [node: Vec<Node>]: expr[true, f(a, b)] stmt [x]\n");
+        assert_same("list<item, sep>: item (sep item)*\na: list<b | c, ','>\n");
+    }
+
+    #[test]
+    fn externs() {
+        assert_same("extern A, B;\nextern C\na: A B C\n");
+        assert_same("a: b\nextern C\n");
+    }
+
+    // Same position as pest
+    #[test]
+    fn error_position() {
+        let data = "a: b;\nc: (d;\n";
+        let err = parse_grammar(data).unwrap_err();
+        assert_eq!((err.line, err.column), (2, 6));
+        assert!(err.expected.contains(&TokenKind::Rparen));
+
+        let pest_err = parse_hrpg(data).unwrap_err();
+        assert_eq!(pest_err.line_col, pest::error::LineColLocation::Pos((2, 6)));
+    }
+}
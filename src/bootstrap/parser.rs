@@ -0,0 +1,1964 @@
+// Generated by hrpg from hrpg.hrpg - do not edit
+
+#![allow(dead_code, unused_variables)]
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Eof = 0,
+    Illegal = 1,
+    Nl = 2,
+    Arg = 3,
+    ActionCode = 4,
+    Whitespace = 5,
+    Comment = 6,
+    DocComment = 7,
+    RuleName = 8,
+    RuleCall = 9,
+    TokenName = 10,
+    OptionValue = 11,
+    TokenLit = 12,
+    CharClass = 13,
+    UnicodeClass = 14,
+    AltLabel = 15,
+    Colon = 16,
+    Pipe = 17,
+    Equals = 18,
+    PlusEquals = 19,
+    Lbracket = 20,
+    Rbracket = 21,
+    Lparen = 22,
+    Rparen = 23,
+    Lbrace = 24,
+    Rbrace = 25,
+    Langle = 26,
+    Rangle = 27,
+    Comma = 28,
+    Plus = 29,
+    Star = 30,
+    QuestMark = 31,
+    Percent = 32,
+    DoublePercent = 33,
+    Ampersand = 34,
+    Bang = 35,
+    Returns = 36,
+    Extern = 37,
+    Grammar = 38,
+    Lexer = 39,
+    Parser = 40,
+    Options = 41,
+    Semi = 42,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Token<'i> {
+    pub kind: TokenKind,
+    pub text: &'i str,
+    // Byte offset of the token in the input
+    pub start: usize,
+}
+
+// Where the parser is in the input - a byte offset
+pub type Pos = usize;
+
+// Matches `extern` tokens and anything else the generated lexer can't
+pub trait ExternalScanner {
+    // NL
+    fn scan_nl(&mut self, input: &str, pos: usize) -> Option<usize>;
+    // ARG
+    fn scan_arg(&mut self, input: &str, pos: usize) -> Option<usize>;
+    // ACTION_CODE
+    fn scan_action_code(&mut self, input: &str, pos: usize) -> Option<usize>;
+    // Skips what `@skip` tokens can't match (ex: nested comments)
+    fn skip(&mut self, _input: &str, pos: usize) -> usize {
+        pos
+    }
+}
+
+// WHITESPACE: [ \t\r\n]+
+fn match_whitespace(input: &str, pos: usize) -> Option<usize> {
+    class(input, pos, &[('\t', '\n'), ('\r', '\r'), (' ', ' ')], false).map(|pos| repeat(pos, |pos| class(input, pos, &[('\t', '\n'), ('\r', '\r'), (' ', ' ')], false)))
+}
+
+/// Exactly two '#' start a doc comment instead
+// COMMENT: "'#'" ("'##'" [^\n]* | [^#\n] [^\n]*)? "'\n'"
+fn match_comment(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "#").map(|pos| literal(input, pos, "##").map(|pos| repeat(pos, |pos| class(input, pos, &[('\n', '\n')], true))).or_else(|| class(input, pos, &[('\n', '\n'), ('#', '#')], true).map(|pos| repeat(pos, |pos| class(input, pos, &[('\n', '\n')], true)))).unwrap_or(pos)).and_then(|pos| literal(input, pos, "\n"))
+}
+
+/// `## text` - exactly two '#' so `### Heading ###` style comments are still plain comments
+// DOC_COMMENT: "'##'" ([^#\n] [^\n]*)?
+fn match_doc_comment(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "##").map(|pos| class(input, pos, &[('\n', '\n'), ('#', '#')], true).map(|pos| repeat(pos, |pos| class(input, pos, &[('\n', '\n')], true))).unwrap_or(pos))
+}
+
+// RULE_NAME: [a-z] [a-zA-Z0-9_]*
+fn match_rule_name(input: &str, pos: usize) -> Option<usize> {
+    class(input, pos, &[('a', 'z')], false).map(|pos| repeat(pos, |pos| class(input, pos, &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')], false)))
+}
+
+// RULE_CALL: [a-z] [a-zA-Z0-9_]* "'['"
+fn match_rule_call(input: &str, pos: usize) -> Option<usize> {
+    class(input, pos, &[('a', 'z')], false).map(|pos| repeat(pos, |pos| class(input, pos, &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')], false))).and_then(|pos| literal(input, pos, "["))
+}
+
+// TOKEN_NAME: [A-Z] [a-zA-Z0-9_]*
+fn match_token_name(input: &str, pos: usize) -> Option<usize> {
+    class(input, pos, &[('A', 'Z')], false).map(|pos| repeat(pos, |pos| class(input, pos, &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')], false)))
+}
+
+// OPTION_VALUE: [a-zA-Z0-9_]+
+fn match_option_value(input: &str, pos: usize) -> Option<usize> {
+    class(input, pos, &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')], false).map(|pos| repeat(pos, |pos| class(input, pos, &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')], false)))
+}
+
+/// A trailing 'i' ignores case when matching (ex: `'select'i`)
+// TOKEN_LIT: "'\''" ("'\\'" [^\n] | [^'\\\n])* "'\''" "'i'"?
+fn match_token_lit(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "'").map(|pos| repeat(pos, |pos| literal(input, pos, "\\").and_then(|pos| class(input, pos, &[('\n', '\n')], true)).or_else(|| class(input, pos, &[('\n', '\n'), ('\'', '\''), ('\\', '\\')], true)))).and_then(|pos| literal(input, pos, "'")).map(|pos| literal(input, pos, "i").unwrap_or(pos))
+}
+
+// CHAR_CLASS: "'['" ("'\\'" [^\n] | [^\]\\\n])+ "']'"
+fn match_char_class(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "[").and_then(|pos| literal(input, pos, "\\").and_then(|pos| class(input, pos, &[('\n', '\n')], true)).or_else(|| class(input, pos, &[('\n', '\n'), ('\\', ']')], true)).map(|pos| repeat(pos, |pos| literal(input, pos, "\\").and_then(|pos| class(input, pos, &[('\n', '\n')], true)).or_else(|| class(input, pos, &[('\n', '\n'), ('\\', ']')], true))))).and_then(|pos| literal(input, pos, "]"))
+}
+
+/// Unicode property or general category outside of a character class (ex: `\p{XID_Start}`, `\P{L}`)
+// UNICODE_CLASS: "'\\'" [pP] "'{'" [^}]+ "'}'"
+fn match_unicode_class(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "\\").and_then(|pos| class(input, pos, &[('P', 'P'), ('p', 'p')], false)).and_then(|pos| literal(input, pos, "{")).and_then(|pos| class(input, pos, &[('}', '}')], true).map(|pos| repeat(pos, |pos| class(input, pos, &[('}', '}')], true)))).and_then(|pos| literal(input, pos, "}"))
+}
+
+// ALT_LABEL: "'@'" [A-Z] [a-zA-Z0-9_]*
+fn match_alt_label(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "@").and_then(|pos| class(input, pos, &[('A', 'Z')], false)).map(|pos| repeat(pos, |pos| class(input, pos, &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')], false)))
+}
+
+// COLON: "':'"
+fn match_colon(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, ":")
+}
+
+// PIPE: "'|'"
+fn match_pipe(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "|")
+}
+
+// EQUALS: "'='"
+fn match_equals(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "=")
+}
+
+// PLUS_EQUALS: "'+='"
+fn match_plus_equals(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "+=")
+}
+
+// LBRACKET: "'['"
+fn match_lbracket(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "[")
+}
+
+// RBRACKET: "']'"
+fn match_rbracket(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "]")
+}
+
+// LPAREN: "'('"
+fn match_lparen(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "(")
+}
+
+// RPAREN: "')'"
+fn match_rparen(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, ")")
+}
+
+// LBRACE: "'{'"
+fn match_lbrace(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "{")
+}
+
+// RBRACE: "'}'"
+fn match_rbrace(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "}")
+}
+
+// LANGLE: "'<'"
+fn match_langle(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "<")
+}
+
+// RANGLE: "'>'"
+fn match_rangle(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, ">")
+}
+
+// COMMA: "','"
+fn match_comma(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, ",")
+}
+
+// PLUS: "'+'"
+fn match_plus(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "+")
+}
+
+// STAR: "'*'"
+fn match_star(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "*")
+}
+
+// QUEST_MARK: "'?'"
+fn match_quest_mark(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "?")
+}
+
+// PERCENT: "'%'"
+fn match_percent(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "%")
+}
+
+// DOUBLE_PERCENT: "'%%'"
+fn match_double_percent(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "%%")
+}
+
+// AMPERSAND: "'&'"
+fn match_ampersand(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "&")
+}
+
+// BANG: "'!'"
+fn match_bang(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "!")
+}
+
+// RETURNS: "'returns'"
+fn match_returns(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "returns").filter(|&end| word_end(input, end))
+}
+
+// EXTERN: "'extern'"
+fn match_extern(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "extern").filter(|&end| word_end(input, end))
+}
+
+// GRAMMAR: "'grammar'"
+fn match_grammar(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "grammar").filter(|&end| word_end(input, end))
+}
+
+// LEXER: "'lexer'"
+fn match_lexer(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "lexer").filter(|&end| word_end(input, end))
+}
+
+// PARSER: "'parser'"
+fn match_parser(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "parser").filter(|&end| word_end(input, end))
+}
+
+// OPTIONS: "'options'"
+fn match_options(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, "options").filter(|&end| word_end(input, end))
+}
+
+// SEMI: "';'"
+fn match_semi(input: &str, pos: usize) -> Option<usize> {
+    literal(input, pos, ";")
+}
+
+fn literal(input: &str, pos: usize, text: &str) -> Option<usize> {
+    input[pos..].starts_with(text).then_some(pos + text.len())
+}
+
+fn literal_ignore_case(input: &str, pos: usize, text: &str) -> Option<usize> {
+    let mut end = pos;
+    let mut chars = input[pos..].chars();
+    for expected in text.chars() {
+        let ch = chars.next()?;
+        if !ch.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+        end += ch.len_utf8();
+    }
+    Some(end)
+}
+
+// `ranges` are sorted and don't overlap
+fn class(input: &str, pos: usize, ranges: &[(char, char)], negated: bool) -> Option<usize> {
+    let ch = input[pos..].chars().next()?;
+    let found = ranges
+        .binary_search_by(|&(start, end)| {
+            if end < ch {
+                std::cmp::Ordering::Less
+            } else if start > ch {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok();
+    (found != negated).then_some(pos + ch.len_utf8())
+}
+
+fn repeat(mut pos: usize, pattern: impl Fn(usize) -> Option<usize>) -> usize {
+    while let Some(end) = pattern(pos) {
+        if end == pos {
+            break;
+        }
+        pos = end;
+    }
+    pos
+}
+
+// Keywords can't be followed by anything that would continue them as an identifier
+fn word_end(input: &str, end: usize) -> bool {
+    !input[end..].starts_with(|ch: char| ch.is_alphanumeric() || ch == '_')
+}
+
+pub struct Lexer<'i, S> {
+    input: &'i str,
+    scanner: S,
+}
+
+impl<'i, S: ExternalScanner> Lexer<'i, S> {
+    pub fn new(input: &'i str, scanner: S) -> Self {
+        Lexer { input, scanner }
+    }
+
+    // Skips `@skip` tokens (and whatever the scanner skips) until neither match
+    fn skip(&mut self, mut pos: usize) -> usize {
+        loop {
+            let start = pos;
+            pos = self.scanner.skip(self.input, pos);
+            if let Some(end) = match_whitespace(self.input, pos) {
+                pos = end;
+            }
+            if let Some(end) = match_comment(self.input, pos) {
+                pos = end;
+            }
+            if pos == start {
+                return pos;
+            }
+        }
+    }
+
+    // Matches a `kind` token at `pos`, after any skipped tokens - or returns where it was expected
+    pub fn token(&mut self, kind: TokenKind, pos: Pos) -> Result<(Token<'i>, Pos), usize> {
+        // Extern tokens are matched where the last token ended, so their hooks can match what would otherwise be skipped
+        let start = match kind {
+            TokenKind::Nl | TokenKind::Arg | TokenKind::ActionCode => pos,
+            _ => self.skip(pos),
+        };
+        let end = self.token_end(kind, start).ok_or(start)?;
+        Ok((
+            Token {
+                kind,
+                text: &self.input[start..end],
+                start,
+            },
+            end,
+        ))
+    }
+
+    // Why no token matches at `pos`, when it isn't just the wrong token there (ex: an inconsistent dedent)
+    pub fn error(&self, pos: usize) -> Option<&str> {
+        None
+    }
+
+    // Where a `kind` token at `start` ends, if it matches there
+    fn token_end(&mut self, kind: TokenKind, start: usize) -> Option<usize> {
+        let input = self.input;
+        match kind {
+            TokenKind::Eof => (start == input.len()).then_some(start),
+            TokenKind::Nl => self.scanner.scan_nl(input, start),
+            TokenKind::Arg => self.scanner.scan_arg(input, start),
+            TokenKind::ActionCode => self.scanner.scan_action_code(input, start),
+            TokenKind::Whitespace => match_whitespace(input, start),
+            TokenKind::Comment => match_comment(input, start),
+            TokenKind::DocComment => match_doc_comment(input, start),
+            TokenKind::RuleName => match_rule_name(input, start),
+            TokenKind::RuleCall => match_rule_call(input, start),
+            TokenKind::TokenName => match_token_name(input, start),
+            TokenKind::OptionValue => match_option_value(input, start),
+            TokenKind::TokenLit => match_token_lit(input, start),
+            TokenKind::CharClass => match_char_class(input, start),
+            TokenKind::UnicodeClass => match_unicode_class(input, start),
+            TokenKind::AltLabel => match_alt_label(input, start),
+            TokenKind::Colon => match_colon(input, start),
+            TokenKind::Pipe => match_pipe(input, start),
+            TokenKind::Equals => match_equals(input, start),
+            TokenKind::PlusEquals => match_plus_equals(input, start),
+            TokenKind::Lbracket => match_lbracket(input, start),
+            TokenKind::Rbracket => match_rbracket(input, start),
+            TokenKind::Lparen => match_lparen(input, start),
+            TokenKind::Rparen => match_rparen(input, start),
+            TokenKind::Lbrace => match_lbrace(input, start),
+            TokenKind::Rbrace => match_rbrace(input, start),
+            TokenKind::Langle => match_langle(input, start),
+            TokenKind::Rangle => match_rangle(input, start),
+            TokenKind::Comma => match_comma(input, start),
+            TokenKind::Plus => match_plus(input, start),
+            TokenKind::Star => match_star(input, start),
+            TokenKind::QuestMark => match_quest_mark(input, start),
+            TokenKind::Percent => match_percent(input, start),
+            TokenKind::DoublePercent => match_double_percent(input, start),
+            TokenKind::Ampersand => match_ampersand(input, start),
+            TokenKind::Bang => match_bang(input, start),
+            TokenKind::Returns => match_returns(input, start),
+            TokenKind::Extern => match_extern(input, start),
+            TokenKind::Grammar => match_grammar(input, start),
+            TokenKind::Lexer => match_lexer(input, start),
+            TokenKind::Parser => match_parser(input, start),
+            TokenKind::Options => match_options(input, start),
+            TokenKind::Semi => match_semi(input, start),
+            TokenKind::Illegal => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleKind {
+    Grammar,
+    GrammarDecl,
+    GrammarKind,
+    GrammarName,
+    GrammarOptions,
+    GrammarOption,
+    OptionValue,
+    Entry,
+    ExternDecl,
+    Rule,
+    RuleParams,
+    RuleArgs,
+    RuleReturns,
+    ArgList,
+    RuleBody,
+    RulePiece,
+    RuleParts,
+    NlRuleBody,
+    NlRulePiece,
+    NlRuleParts,
+    RulePart,
+    Action,
+    ActionBlock,
+    Lookahead,
+    Suffix,
+    Separator,
+    Binding,
+    RuleElem,
+    RuleCall,
+    TemplateRef,
+    TokenRule,
+    TokenBody,
+    TokenSeq,
+    NlTokenBody,
+    NlTokenSeq,
+    TokenPart,
+    TokenAtom,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tree<'i> {
+    Rule { kind: RuleKind, children: Vec<Tree<'i>> },
+    Token(Token<'i>),
+}
+
+// Where parsing got the furthest before failing, and the tokens it expected there
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub expected: Vec<TokenKind>,
+    // Why the lexer stopped matching tokens there, if it did (ex: an inconsistent dedent)
+    pub lexer_error: Option<String>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(error) = &self.lexer_error {
+            return write!(f, "{}:{}: {}", self.line, self.column, error);
+        }
+        let expected: Vec<String> = self.expected.iter().map(|kind| format!("{:?}", kind)).collect();
+        write!(f, "{}:{}: expected {}", self.line, self.column, expected.join(" or "))
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub struct Parser<'i, S> {
+    input: &'i str,
+    pos: Pos,
+    lexer: Lexer<'i, S>,
+    // Lookahead nesting - tokens that don't match within lookahead aren't expected
+    silent: usize,
+    // The furthest position a token didn't match at, and the tokens expected there
+    fail_pos: usize,
+    expected: Vec<TokenKind>,
+}
+
+impl<'i, S: ExternalScanner> Parser<'i, S> {
+    pub fn new(input: &'i str, scanner: S) -> Self {
+        Parser {
+            input,
+            pos: Pos::default(),
+            lexer: Lexer::new(input, scanner),
+            silent: 0,
+            fail_pos: 0,
+            expected: vec![],
+        }
+    }
+
+    // Parsing starts with the first rule, which has to end with `EOF` to match all of the input
+    pub fn parse(mut self) -> Result<Tree<'i>, ParseError> {
+        match self.parse_grammar() {
+            Some(mut trees) => Ok(trees.remove(0)),
+            None => Err(self.error()),
+        }
+    }
+
+    fn token(&mut self, kind: TokenKind) -> Option<Vec<Tree<'i>>> {
+        match self.lexer.token(kind, self.pos) {
+            Ok((token, end)) => {
+                self.pos = end;
+                Some(vec![Tree::Token(token)])
+            }
+            Err(start) => {
+                self.fail(start, kind);
+                None
+            }
+        }
+    }
+
+    fn fail(&mut self, pos: usize, kind: TokenKind) {
+        if self.silent > 0 || pos < self.fail_pos {
+            return;
+        }
+        if pos > self.fail_pos {
+            self.fail_pos = pos;
+            self.expected.clear();
+        }
+        if !self.expected.contains(&kind) {
+            self.expected.push(kind);
+        }
+    }
+
+    fn error(&self) -> ParseError {
+        let before = &self.input[..self.fail_pos];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        ParseError {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            expected: self.expected.clone(),
+            lexer_error: self.lexer.error(self.fail_pos).map(str::to_string),
+        }
+    }
+
+    /// A grammar is an optional declaration and options, followed by rules, token rules and extern declarations
+    // grammar: grammar_decl? grammar_options? entry* EOF
+    pub fn parse_grammar(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // grammar_decl?
+        let start = self.pos;
+        match self.parse_grammar_decl() {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        // grammar_options?
+        let start = self.pos;
+        match self.parse_grammar_options() {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        // entry*
+        loop {
+            let start = self.pos;
+            match self.parse_entry() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        // EOF
+        children.extend(self.token(TokenKind::Eof)?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::Grammar,
+            children,
+        }])
+    }
+
+    // grammar_decl: grammar_kind? 'grammar' grammar_name ';'
+    pub fn parse_grammar_decl(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // grammar_kind?
+        let start = self.pos;
+        match self.parse_grammar_kind() {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        // 'grammar'
+        children.extend(self.token(TokenKind::Grammar)?);
+        // grammar_name
+        children.extend(self.parse_grammar_name()?);
+        // ';'
+        children.extend(self.token(TokenKind::Semi)?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::GrammarDecl,
+            children,
+        }])
+    }
+
+    // grammar_kind: 'lexer' | 'parser'
+    pub fn parse_grammar_kind(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // 'lexer'
+        if let Some(children) = self.token(TokenKind::Lexer) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::GrammarKind,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // 'parser'
+        if let Some(children) = self.token(TokenKind::Parser) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::GrammarKind,
+                children,
+            }]);
+        }
+        self.pos = start;
+        None
+    }
+
+    // grammar_name: RULE_NAME | TOKEN_NAME
+    pub fn parse_grammar_name(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // RULE_NAME
+        if let Some(children) = self.token(TokenKind::RuleName) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::GrammarName,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // TOKEN_NAME
+        if let Some(children) = self.token(TokenKind::TokenName) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::GrammarName,
+                children,
+            }]);
+        }
+        self.pos = start;
+        None
+    }
+
+    // grammar_options: 'options' '{' grammar_option* '}'
+    pub fn parse_grammar_options(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // 'options'
+        children.extend(self.token(TokenKind::Options)?);
+        // '{'
+        children.extend(self.token(TokenKind::Lbrace)?);
+        // grammar_option*
+        loop {
+            let start = self.pos;
+            match self.parse_grammar_option() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        // '}'
+        children.extend(self.token(TokenKind::Rbrace)?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::GrammarOptions,
+            children,
+        }])
+    }
+
+    // grammar_option: grammar_name '=' option_value ';'
+    pub fn parse_grammar_option(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // grammar_name
+        children.extend(self.parse_grammar_name()?);
+        // '='
+        children.extend(self.token(TokenKind::Equals)?);
+        // option_value
+        children.extend(self.parse_option_value()?);
+        // ';'
+        children.extend(self.token(TokenKind::Semi)?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::GrammarOption,
+            children,
+        }])
+    }
+
+    // option_value: TOKEN_LIT | OPTION_VALUE
+    pub fn parse_option_value(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // TOKEN_LIT
+        if let Some(children) = self.token(TokenKind::TokenLit) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::OptionValue,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // OPTION_VALUE
+        if let Some(children) = self.token(TokenKind::OptionValue) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::OptionValue,
+                children,
+            }]);
+        }
+        self.pos = start;
+        None
+    }
+
+    // extern_decl | rule | token_rule
+    fn parse_entry_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // extern_decl
+        if let Some(children) = self.parse_extern_decl() {
+            return Some(children);
+        }
+        self.pos = start;
+        // rule
+        if let Some(children) = self.parse_rule() {
+            return Some(children);
+        }
+        self.pos = start;
+        // token_rule
+        if let Some(children) = self.parse_token_rule() {
+            return Some(children);
+        }
+        self.pos = start;
+        None
+    }
+
+    /// Doc comments attach to the entry that follows them
+    // entry: DOC_COMMENT* (extern_decl | rule | token_rule)
+    pub fn parse_entry(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // DOC_COMMENT*
+        loop {
+            let start = self.pos;
+            match self.token(TokenKind::DocComment) {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        // extern_decl | rule | token_rule
+        children.extend(self.parse_entry_sub_1()?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::Entry,
+            children,
+        }])
+    }
+
+    // !NL ',' TOKEN_NAME
+    fn parse_extern_decl_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // !NL
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.token(TokenKind::Nl).is_some();
+            self.silent -= 1;
+            self.pos = start;
+            (!matched).then(Vec::new)
+        };
+        children.extend(matched?);
+        // ','
+        children.extend(self.token(TokenKind::Comma)?);
+        // TOKEN_NAME
+        children.extend(self.token(TokenKind::TokenName)?);
+        Some(children)
+    }
+
+    // !NL ';'
+    fn parse_extern_decl_sub_3(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // !NL
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.token(TokenKind::Nl).is_some();
+            self.silent -= 1;
+            self.pos = start;
+            (!matched).then(Vec::new)
+        };
+        children.extend(matched?);
+        // ';'
+        children.extend(self.token(TokenKind::Semi)?);
+        Some(children)
+    }
+
+    // !NL ';' | &NL
+    fn parse_extern_decl_sub_2(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // !NL ';'
+        if let Some(children) = self.parse_extern_decl_sub_3() {
+            return Some(children);
+        }
+        self.pos = start;
+        // &NL
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.token(TokenKind::Nl).is_some();
+            self.silent -= 1;
+            self.pos = start;
+            matched.then(Vec::new)
+        };
+        if let Some(children) = matched {
+            return Some(children);
+        }
+        self.pos = start;
+        None
+    }
+
+    /// Tokens matched by hand-written code instead of a token rule (ex: `extern HEREDOC, RAW_STRING;`)
+    // extern_decl: 'extern' !NL TOKEN_NAME (!NL ',' TOKEN_NAME)* (!NL ';' | &NL)
+    pub fn parse_extern_decl(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // 'extern'
+        children.extend(self.token(TokenKind::Extern)?);
+        // !NL
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.token(TokenKind::Nl).is_some();
+            self.silent -= 1;
+            self.pos = start;
+            (!matched).then(Vec::new)
+        };
+        children.extend(matched?);
+        // TOKEN_NAME
+        children.extend(self.token(TokenKind::TokenName)?);
+        // (!NL ',' TOKEN_NAME)*
+        loop {
+            let start = self.pos;
+            match self.parse_extern_decl_sub_1() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        // !NL ';' | &NL
+        children.extend(self.parse_extern_decl_sub_2()?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::ExternDecl,
+            children,
+        }])
+    }
+
+    // rule_body ';'
+    fn parse_rule_sub_2(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // rule_body
+        children.extend(self.parse_rule_body()?);
+        // ';'
+        children.extend(self.token(TokenKind::Semi)?);
+        Some(children)
+    }
+
+    // rule_body ';' | nl_rule_body
+    fn parse_rule_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // rule_body ';'
+        if let Some(children) = self.parse_rule_sub_2() {
+            return Some(children);
+        }
+        self.pos = start;
+        // nl_rule_body
+        if let Some(children) = self.parse_nl_rule_body() {
+            return Some(children);
+        }
+        self.pos = start;
+        None
+    }
+
+    /// Rules end with either a ';' or the end of the line - the ';' form is tried first so its bodies can span lines
+    /// freely, while newline terminated bodies only continue onto lines starting with '|'
+    // rule: RULE_NAME rule_params? rule_args? rule_returns? ':' (rule_body ';' | nl_rule_body)
+    pub fn parse_rule(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // RULE_NAME
+        children.extend(self.token(TokenKind::RuleName)?);
+        // rule_params?
+        let start = self.pos;
+        match self.parse_rule_params() {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        // rule_args?
+        let start = self.pos;
+        match self.parse_rule_args() {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        // rule_returns?
+        let start = self.pos;
+        match self.parse_rule_returns() {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        // ':'
+        children.extend(self.token(TokenKind::Colon)?);
+        // rule_body ';' | nl_rule_body
+        children.extend(self.parse_rule_sub_1()?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::Rule,
+            children,
+        }])
+    }
+
+    // ',' RULE_NAME
+    fn parse_rule_params_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // ','
+        children.extend(self.token(TokenKind::Comma)?);
+        // RULE_NAME
+        children.extend(self.token(TokenKind::RuleName)?);
+        Some(children)
+    }
+
+    /// Rules with parameters are templates, instantiated with arguments at each use (ex: `comma_list<value>`)
+    // rule_params: '<' RULE_NAME (',' RULE_NAME)* '>'
+    pub fn parse_rule_params(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // '<'
+        children.extend(self.token(TokenKind::Langle)?);
+        // RULE_NAME
+        children.extend(self.token(TokenKind::RuleName)?);
+        // (',' RULE_NAME)*
+        loop {
+            let start = self.pos;
+            match self.parse_rule_params_sub_1() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        // '>'
+        children.extend(self.token(TokenKind::Rangle)?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::RuleParams,
+            children,
+        }])
+    }
+
+    /// Arguments and return values are declared in the target language (ex: `stmt[in_loop: bool] returns [node: Node]`
+    /// for Rust) and are checked when generating the parser
+    // rule_args: '[' arg_list ']'
+    pub fn parse_rule_args(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // '['
+        children.extend(self.token(TokenKind::Lbracket)?);
+        // arg_list
+        children.extend(self.parse_arg_list()?);
+        // ']'
+        children.extend(self.token(TokenKind::Rbracket)?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::RuleArgs,
+            children,
+        }])
+    }
+
+    // rule_returns: 'returns' '[' arg_list ']'
+    pub fn parse_rule_returns(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // 'returns'
+        children.extend(self.token(TokenKind::Returns)?);
+        // '['
+        children.extend(self.token(TokenKind::Lbracket)?);
+        // arg_list
+        children.extend(self.parse_arg_list()?);
+        // ']'
+        children.extend(self.token(TokenKind::Rbracket)?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::RuleReturns,
+            children,
+        }])
+    }
+
+    // ',' ARG
+    fn parse_arg_list_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // ','
+        children.extend(self.token(TokenKind::Comma)?);
+        // ARG
+        children.extend(self.token(TokenKind::Arg)?);
+        Some(children)
+    }
+
+    // arg_list: ARG (',' ARG)*
+    pub fn parse_arg_list(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // ARG
+        children.extend(self.token(TokenKind::Arg)?);
+        // (',' ARG)*
+        loop {
+            let start = self.pos;
+            match self.parse_arg_list_sub_1() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        Some(vec![Tree::Rule {
+            kind: RuleKind::ArgList,
+            children,
+        }])
+    }
+
+    // '|' rule_piece
+    fn parse_rule_body_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // '|'
+        children.extend(self.token(TokenKind::Pipe)?);
+        // rule_piece
+        children.extend(self.parse_rule_piece()?);
+        Some(children)
+    }
+
+    /// An alternative can be empty (ex: `opt_tail: ',' item | ;`) - `()` is the same, but can be labeled
+    // rule_body: rule_piece ('|' rule_piece)*
+    pub fn parse_rule_body(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // rule_piece
+        children.extend(self.parse_rule_piece()?);
+        // ('|' rule_piece)*
+        loop {
+            let start = self.pos;
+            match self.parse_rule_body_sub_1() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        Some(vec![Tree::Rule {
+            kind: RuleKind::RuleBody,
+            children,
+        }])
+    }
+
+    // !NL ALT_LABEL
+    fn parse_rule_piece_sub_2(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // !NL
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.token(TokenKind::Nl).is_some();
+            self.silent -= 1;
+            self.pos = start;
+            (!matched).then(Vec::new)
+        };
+        children.extend(matched?);
+        // ALT_LABEL
+        children.extend(self.token(TokenKind::AltLabel)?);
+        Some(children)
+    }
+
+    // rule_parts (!NL ALT_LABEL)?
+    fn parse_rule_piece_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // rule_parts
+        children.extend(self.parse_rule_parts()?);
+        // (!NL ALT_LABEL)?
+        let start = self.pos;
+        match self.parse_rule_piece_sub_2() {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        Some(children)
+    }
+
+    /// Alternative labels must be on the same line as the end of their alternative
+    // rule_piece: rule_parts (!NL ALT_LABEL)? | ()
+    pub fn parse_rule_piece(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // rule_parts (!NL ALT_LABEL)?
+        if let Some(children) = self.parse_rule_piece_sub_1() {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::RulePiece,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // ()
+        if let Some(children) = Some(Vec::new()) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::RulePiece,
+                children,
+            }]);
+        }
+        self.pos = start;
+        None
+    }
+
+    // !extern_decl rule_part
+    fn parse_rule_parts_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // !extern_decl
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.parse_extern_decl().is_some();
+            self.silent -= 1;
+            self.pos = start;
+            (!matched).then(Vec::new)
+        };
+        children.extend(matched?);
+        // rule_part
+        children.extend(self.parse_rule_part()?);
+        Some(children)
+    }
+
+    /// An `extern` declaration can't continue a rule (otherwise it would be part of a preceding newline terminated rule)
+    // rule_parts: rule_part (!extern_decl rule_part)*
+    pub fn parse_rule_parts(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // rule_part
+        children.extend(self.parse_rule_part()?);
+        // (!extern_decl rule_part)*
+        loop {
+            let start = self.pos;
+            match self.parse_rule_parts_sub_1() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        Some(vec![Tree::Rule {
+            kind: RuleKind::RuleParts,
+            children,
+        }])
+    }
+
+    // !NL nl_rule_piece
+    fn parse_nl_rule_body_sub_2(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // !NL
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.token(TokenKind::Nl).is_some();
+            self.silent -= 1;
+            self.pos = start;
+            (!matched).then(Vec::new)
+        };
+        children.extend(matched?);
+        // nl_rule_piece
+        children.extend(self.parse_nl_rule_piece()?);
+        Some(children)
+    }
+
+    // '|' (!NL nl_rule_piece)?
+    fn parse_nl_rule_body_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // '|'
+        children.extend(self.token(TokenKind::Pipe)?);
+        // (!NL nl_rule_piece)?
+        let start = self.pos;
+        match self.parse_nl_rule_body_sub_2() {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        Some(children)
+    }
+
+    /// A '|' on the same line or starting a following line separates alternatives
+    // nl_rule_body: nl_rule_piece ('|' (!NL nl_rule_piece)?)* &NL
+    pub fn parse_nl_rule_body(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // nl_rule_piece
+        children.extend(self.parse_nl_rule_piece()?);
+        // ('|' (!NL nl_rule_piece)?)*
+        loop {
+            let start = self.pos;
+            match self.parse_nl_rule_body_sub_1() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        // &NL
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.token(TokenKind::Nl).is_some();
+            self.silent -= 1;
+            self.pos = start;
+            matched.then(Vec::new)
+        };
+        children.extend(matched?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::NlRuleBody,
+            children,
+        }])
+    }
+
+    // !NL ALT_LABEL
+    fn parse_nl_rule_piece_sub_2(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // !NL
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.token(TokenKind::Nl).is_some();
+            self.silent -= 1;
+            self.pos = start;
+            (!matched).then(Vec::new)
+        };
+        children.extend(matched?);
+        // ALT_LABEL
+        children.extend(self.token(TokenKind::AltLabel)?);
+        Some(children)
+    }
+
+    // nl_rule_parts (!NL ALT_LABEL)?
+    fn parse_nl_rule_piece_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // nl_rule_parts
+        children.extend(self.parse_nl_rule_parts()?);
+        // (!NL ALT_LABEL)?
+        let start = self.pos;
+        match self.parse_nl_rule_piece_sub_2() {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        Some(children)
+    }
+
+    // nl_rule_piece: nl_rule_parts (!NL ALT_LABEL)? | ()
+    pub fn parse_nl_rule_piece(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // nl_rule_parts (!NL ALT_LABEL)?
+        if let Some(children) = self.parse_nl_rule_piece_sub_1() {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::NlRulePiece,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // ()
+        if let Some(children) = Some(Vec::new()) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::NlRulePiece,
+                children,
+            }]);
+        }
+        self.pos = start;
+        None
+    }
+
+    // !NL rule_part
+    fn parse_nl_rule_parts_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // !NL
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.token(TokenKind::Nl).is_some();
+            self.silent -= 1;
+            self.pos = start;
+            (!matched).then(Vec::new)
+        };
+        children.extend(matched?);
+        // rule_part
+        children.extend(self.parse_rule_part()?);
+        Some(children)
+    }
+
+    // nl_rule_parts: rule_part (!NL rule_part)*
+    pub fn parse_nl_rule_parts(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // rule_part
+        children.extend(self.parse_rule_part()?);
+        // (!NL rule_part)*
+        loop {
+            let start = self.pos;
+            match self.parse_nl_rule_parts_sub_1() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        Some(vec![Tree::Rule {
+            kind: RuleKind::NlRuleParts,
+            children,
+        }])
+    }
+
+    // lookahead rule_elem
+    fn parse_rule_part_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // lookahead
+        children.extend(self.parse_lookahead()?);
+        // rule_elem
+        children.extend(self.parse_rule_elem()?);
+        Some(children)
+    }
+
+    // separator rule_elem
+    fn parse_rule_part_sub_6(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // separator
+        children.extend(self.parse_separator()?);
+        // rule_elem
+        children.extend(self.parse_rule_elem()?);
+        Some(children)
+    }
+
+    // separator rule_elem | suffix
+    fn parse_rule_part_sub_5(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // separator rule_elem
+        if let Some(children) = self.parse_rule_part_sub_6() {
+            return Some(children);
+        }
+        self.pos = start;
+        // suffix
+        if let Some(children) = self.parse_suffix() {
+            return Some(children);
+        }
+        self.pos = start;
+        None
+    }
+
+    // rule_elem (separator rule_elem | suffix)?
+    fn parse_rule_part_sub_4(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // rule_elem
+        children.extend(self.parse_rule_elem()?);
+        // (separator rule_elem | suffix)?
+        let start = self.pos;
+        match self.parse_rule_part_sub_5() {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        Some(children)
+    }
+
+    // '[' rule_body ']'
+    fn parse_rule_part_sub_7(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // '['
+        children.extend(self.token(TokenKind::Lbracket)?);
+        // rule_body
+        children.extend(self.parse_rule_body()?);
+        // ']'
+        children.extend(self.token(TokenKind::Rbracket)?);
+        Some(children)
+    }
+
+    // rule_elem (separator rule_elem | suffix)? | '[' rule_body ']'
+    fn parse_rule_part_sub_3(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // rule_elem (separator rule_elem | suffix)?
+        if let Some(children) = self.parse_rule_part_sub_4() {
+            return Some(children);
+        }
+        self.pos = start;
+        // '[' rule_body ']'
+        if let Some(children) = self.parse_rule_part_sub_7() {
+            return Some(children);
+        }
+        self.pos = start;
+        None
+    }
+
+    // binding? (rule_elem (separator rule_elem | suffix)? | '[' rule_body ']')
+    fn parse_rule_part_sub_2(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // binding?
+        let start = self.pos;
+        match self.parse_binding() {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        // rule_elem (separator rule_elem | suffix)? | '[' rule_body ']'
+        children.extend(self.parse_rule_part_sub_3()?);
+        Some(children)
+    }
+
+    // rule_part: action | lookahead rule_elem | binding? (rule_elem (separator rule_elem | suffix)? | '[' rule_body ']')
+    pub fn parse_rule_part(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // action
+        if let Some(children) = self.parse_action() {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::RulePart,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // lookahead rule_elem
+        if let Some(children) = self.parse_rule_part_sub_1() {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::RulePart,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // binding? (rule_elem (separator rule_elem | suffix)? | '[' rule_body ']')
+        if let Some(children) = self.parse_rule_part_sub_2() {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::RulePart,
+                children,
+            }]);
+        }
+        self.pos = start;
+        None
+    }
+
+    /// Target language code (ex: `{rust: self.depth += 1}`), ignored by other languages - a trailing '?' makes it a
+    /// predicate that must be true for parsing to continue
+    // action: action_block '?'?
+    pub fn parse_action(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // action_block
+        children.extend(self.parse_action_block()?);
+        // '?'?
+        let start = self.pos;
+        match self.token(TokenKind::QuestMark) {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        Some(vec![Tree::Rule {
+            kind: RuleKind::Action,
+            children,
+        }])
+    }
+
+    // action_block: '{' grammar_name ':' ACTION_CODE '}'
+    pub fn parse_action_block(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // '{'
+        children.extend(self.token(TokenKind::Lbrace)?);
+        // grammar_name
+        children.extend(self.parse_grammar_name()?);
+        // ':'
+        children.extend(self.token(TokenKind::Colon)?);
+        // ACTION_CODE
+        children.extend(self.token(TokenKind::ActionCode)?);
+        // '}'
+        children.extend(self.token(TokenKind::Rbrace)?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::ActionBlock,
+            children,
+        }])
+    }
+
+    /// `&x` matches if `x` is next and `!x` matches if it is not - neither consumes any input
+    // lookahead: '&' | '!'
+    pub fn parse_lookahead(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // '&'
+        if let Some(children) = self.token(TokenKind::Ampersand) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::Lookahead,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // '!'
+        if let Some(children) = self.token(TokenKind::Bang) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::Lookahead,
+                children,
+            }]);
+        }
+        self.pos = start;
+        None
+    }
+
+    // suffix: '+' | '*' | '?'
+    pub fn parse_suffix(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // '+'
+        if let Some(children) = self.token(TokenKind::Plus) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::Suffix,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // '*'
+        if let Some(children) = self.token(TokenKind::Star) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::Suffix,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // '?'
+        if let Some(children) = self.token(TokenKind::QuestMark) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::Suffix,
+                children,
+            }]);
+        }
+        self.pos = start;
+        None
+    }
+
+    /// `item % sep` is one or more items separated by `sep`, `item %% sep` also allows a trailing `sep`
+    // separator: '%%' | '%'
+    pub fn parse_separator(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // '%%'
+        if let Some(children) = self.token(TokenKind::DoublePercent) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::Separator,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // '%'
+        if let Some(children) = self.token(TokenKind::Percent) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::Separator,
+                children,
+            }]);
+        }
+        self.pos = start;
+        None
+    }
+
+    // '+=' | '='
+    fn parse_binding_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // '+='
+        if let Some(children) = self.token(TokenKind::PlusEquals) {
+            return Some(children);
+        }
+        self.pos = start;
+        // '='
+        if let Some(children) = self.token(TokenKind::Equals) {
+            return Some(children);
+        }
+        self.pos = start;
+        None
+    }
+
+    // binding: RULE_NAME ('+=' | '=')
+    pub fn parse_binding(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // RULE_NAME
+        children.extend(self.token(TokenKind::RuleName)?);
+        // '+=' | '='
+        children.extend(self.parse_binding_sub_1()?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::Binding,
+            children,
+        }])
+    }
+
+    // '(' rule_body ')'
+    fn parse_rule_elem_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // '('
+        children.extend(self.token(TokenKind::Lparen)?);
+        // rule_body
+        children.extend(self.parse_rule_body()?);
+        // ')'
+        children.extend(self.token(TokenKind::Rparen)?);
+        Some(children)
+    }
+
+    // rule_elem: '(' rule_body ')' | template_ref | rule_call | RULE_NAME | TOKEN_NAME | TOKEN_LIT
+    pub fn parse_rule_elem(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // '(' rule_body ')'
+        if let Some(children) = self.parse_rule_elem_sub_1() {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::RuleElem,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // template_ref
+        if let Some(children) = self.parse_template_ref() {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::RuleElem,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // rule_call
+        if let Some(children) = self.parse_rule_call() {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::RuleElem,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // RULE_NAME
+        if let Some(children) = self.token(TokenKind::RuleName) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::RuleElem,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // TOKEN_NAME
+        if let Some(children) = self.token(TokenKind::TokenName) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::RuleElem,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // TOKEN_LIT
+        if let Some(children) = self.token(TokenKind::TokenLit) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::RuleElem,
+                children,
+            }]);
+        }
+        self.pos = start;
+        None
+    }
+
+    /// Arguments must directly follow the rule name, otherwise it is an optional part (ex: `stmt[true]` vs `stmt [x]`)
+    // rule_call: RULE_CALL arg_list ']'
+    pub fn parse_rule_call(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // RULE_CALL
+        children.extend(self.token(TokenKind::RuleCall)?);
+        // arg_list
+        children.extend(self.parse_arg_list()?);
+        // ']'
+        children.extend(self.token(TokenKind::Rbracket)?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::RuleCall,
+            children,
+        }])
+    }
+
+    // ',' rule_body
+    fn parse_template_ref_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // ','
+        children.extend(self.token(TokenKind::Comma)?);
+        // rule_body
+        children.extend(self.parse_rule_body()?);
+        Some(children)
+    }
+
+    // template_ref: RULE_NAME '<' rule_body (',' rule_body)* '>'
+    pub fn parse_template_ref(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // RULE_NAME
+        children.extend(self.token(TokenKind::RuleName)?);
+        // '<'
+        children.extend(self.token(TokenKind::Langle)?);
+        // rule_body
+        children.extend(self.parse_rule_body()?);
+        // (',' rule_body)*
+        loop {
+            let start = self.pos;
+            match self.parse_template_ref_sub_1() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        // '>'
+        children.extend(self.token(TokenKind::Rangle)?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::TemplateRef,
+            children,
+        }])
+    }
+
+    // token_body ';'
+    fn parse_token_rule_sub_2(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // token_body
+        children.extend(self.parse_token_body()?);
+        // ';'
+        children.extend(self.token(TokenKind::Semi)?);
+        Some(children)
+    }
+
+    // token_body ';' | nl_token_body
+    fn parse_token_rule_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // token_body ';'
+        if let Some(children) = self.parse_token_rule_sub_2() {
+            return Some(children);
+        }
+        self.pos = start;
+        // nl_token_body
+        if let Some(children) = self.parse_nl_token_body() {
+            return Some(children);
+        }
+        self.pos = start;
+        None
+    }
+
+    // token_rule: TOKEN_NAME ':' (token_body ';' | nl_token_body)
+    pub fn parse_token_rule(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // TOKEN_NAME
+        children.extend(self.token(TokenKind::TokenName)?);
+        // ':'
+        children.extend(self.token(TokenKind::Colon)?);
+        // token_body ';' | nl_token_body
+        children.extend(self.parse_token_rule_sub_1()?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::TokenRule,
+            children,
+        }])
+    }
+
+    // '|' token_seq
+    fn parse_token_body_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // '|'
+        children.extend(self.token(TokenKind::Pipe)?);
+        // token_seq
+        children.extend(self.parse_token_seq()?);
+        Some(children)
+    }
+
+    /// Token rules that aren't a single literal are patterns (ex: `IDENT: [a-zA-Z_] [a-zA-Z0-9_]*;`)
+    // token_body: token_seq ('|' token_seq)*
+    pub fn parse_token_body(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // token_seq
+        children.extend(self.parse_token_seq()?);
+        // ('|' token_seq)*
+        loop {
+            let start = self.pos;
+            match self.parse_token_body_sub_1() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        Some(vec![Tree::Rule {
+            kind: RuleKind::TokenBody,
+            children,
+        }])
+    }
+
+    // token_seq: token_part+
+    pub fn parse_token_seq(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // token_seq: token_part+
+        let matched = match self.parse_token_part() {
+            Some(mut items) => loop {
+                let start = self.pos;
+                match self.parse_token_part() {
+                    Some(matched) if self.pos > start => items.extend(matched),
+                    _ => {
+                        self.pos = start;
+                        break Some(items);
+                    }
+                }
+            },
+            None => None,
+        };
+        if let Some(children) = matched {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::TokenSeq,
+                children,
+            }]);
+        }
+        self.pos = start;
+        None
+    }
+
+    // '|' !NL nl_token_seq
+    fn parse_nl_token_body_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // '|'
+        children.extend(self.token(TokenKind::Pipe)?);
+        // !NL
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.token(TokenKind::Nl).is_some();
+            self.silent -= 1;
+            self.pos = start;
+            (!matched).then(Vec::new)
+        };
+        children.extend(matched?);
+        // nl_token_seq
+        children.extend(self.parse_nl_token_seq()?);
+        Some(children)
+    }
+
+    // nl_token_body: nl_token_seq ('|' !NL nl_token_seq)* &NL
+    pub fn parse_nl_token_body(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // nl_token_seq
+        children.extend(self.parse_nl_token_seq()?);
+        // ('|' !NL nl_token_seq)*
+        loop {
+            let start = self.pos;
+            match self.parse_nl_token_body_sub_1() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        // &NL
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.token(TokenKind::Nl).is_some();
+            self.silent -= 1;
+            self.pos = start;
+            matched.then(Vec::new)
+        };
+        children.extend(matched?);
+        Some(vec![Tree::Rule {
+            kind: RuleKind::NlTokenBody,
+            children,
+        }])
+    }
+
+    // !NL token_part
+    fn parse_nl_token_seq_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // !NL
+        let matched = {
+            let start = self.pos;
+            self.silent += 1;
+            let matched = self.token(TokenKind::Nl).is_some();
+            self.silent -= 1;
+            self.pos = start;
+            (!matched).then(Vec::new)
+        };
+        children.extend(matched?);
+        // token_part
+        children.extend(self.parse_token_part()?);
+        Some(children)
+    }
+
+    // nl_token_seq: token_part (!NL token_part)*
+    pub fn parse_nl_token_seq(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // token_part
+        children.extend(self.parse_token_part()?);
+        // (!NL token_part)*
+        loop {
+            let start = self.pos;
+            match self.parse_nl_token_seq_sub_1() {
+                Some(matched) if self.pos > start => children.extend(matched),
+                _ => {
+                    self.pos = start;
+                    break;
+                }
+            }
+        }
+        Some(vec![Tree::Rule {
+            kind: RuleKind::NlTokenSeq,
+            children,
+        }])
+    }
+
+    // token_part: token_atom suffix?
+    pub fn parse_token_part(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // token_atom
+        children.extend(self.parse_token_atom()?);
+        // suffix?
+        let start = self.pos;
+        match self.parse_suffix() {
+            Some(matched) => children.extend(matched),
+            None => self.pos = start,
+        }
+        Some(vec![Tree::Rule {
+            kind: RuleKind::TokenPart,
+            children,
+        }])
+    }
+
+    // '(' token_body ')'
+    fn parse_token_atom_sub_1(&mut self) -> Option<Vec<Tree<'i>>> {
+        let mut children = Vec::new();
+        // '('
+        children.extend(self.token(TokenKind::Lparen)?);
+        // token_body
+        children.extend(self.parse_token_body()?);
+        // ')'
+        children.extend(self.token(TokenKind::Rparen)?);
+        Some(children)
+    }
+
+    // token_atom: '(' token_body ')' | TOKEN_LIT | CHAR_CLASS | UNICODE_CLASS
+    pub fn parse_token_atom(&mut self) -> Option<Vec<Tree<'i>>> {
+        let start = self.pos;
+        // '(' token_body ')'
+        if let Some(children) = self.parse_token_atom_sub_1() {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::TokenAtom,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // TOKEN_LIT
+        if let Some(children) = self.token(TokenKind::TokenLit) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::TokenAtom,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // CHAR_CLASS
+        if let Some(children) = self.token(TokenKind::CharClass) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::TokenAtom,
+                children,
+            }]);
+        }
+        self.pos = start;
+        // UNICODE_CLASS
+        if let Some(children) = self.token(TokenKind::UnicodeClass) {
+            return Some(vec![Tree::Rule {
+                kind: RuleKind::TokenAtom,
+                children,
+            }]);
+        }
+        self.pos = start;
+        None
+    }
+}
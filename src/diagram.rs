@@ -1,4 +1,5 @@
-use crate::ast::{Grammar, Node, ParserRule, TokenRule};
+use crate::ast::{Comment, Grammar, Node, ParserRule, TokenRule};
+use crate::escape::{display_literal, unescape_literal};
 use railroad::{Diagram, RailroadNode};
 
 pub fn draw_diagram(grammar: &Grammar) -> Diagram<Box<dyn RailroadNode>> {
@@ -15,24 +16,34 @@ pub fn draw_diagram(grammar: &Grammar) -> Diagram<Box<dyn RailroadNode>> {
 
 #[inline]
 fn make_parser_rule(rule: &ParserRule) -> Box<dyn RailroadNode> {
-    make_rule(&rule.name, &rule.node)
+    make_rule(&rule.name, &rule.doc, &rule.node)
 }
 
 #[inline]
 fn make_token_rule(rule: &TokenRule) -> Box<dyn RailroadNode> {
-    make_rule(&rule.name, &rule.literal)
+    make_rule(&rule.name, &rule.doc, &rule.node)
 }
 
-fn make_rule(name: &str, node: &Node) -> Box<dyn RailroadNode> {
+fn make_rule(name: &str, doc: &[String], node: &Node) -> Box<dyn RailroadNode> {
     let comment = Box::new(railroad::Comment::new(name.into()));
     let start = Box::new(railroad::Start);
     let end = Box::new(railroad::End);
-    Box::new(railroad::Sequence::new(vec![
+    let rule = Box::new(railroad::Sequence::new(vec![
         start,
         comment,
         make_node(node),
         end,
-    ]))
+    ]));
+
+    // Doc comments label the whole rule
+    if doc.is_empty() {
+        rule
+    } else {
+        Box::new(railroad::LabeledBox::new(
+            rule,
+            railroad::Comment::new(doc.join(" ")),
+        ))
+    }
 }
 
 fn make_node(node: &Node) -> Box<dyn RailroadNode> {
@@ -40,20 +51,74 @@ fn make_node(node: &Node) -> Box<dyn RailroadNode> {
     use Node::*;
 
     match node {
-        Binding { name: _, node } => make_node(node),
+        Binding { node, .. } => make_node(node),
         Alternatives { nodes } => Box::new(Choice::new(nodes.iter().map(make_node).collect())),
+        Labeled { label, node } => Box::new(LabeledBox::new(
+            make_node(node),
+            railroad::Comment::new(label.into()),
+        )),
         MultipartBody { nodes } => Box::new(Sequence::new(nodes.iter().map(make_node).collect())),
         ZeroOrMore { node } => {
-            let repeat = Box::new(Repeat::new(make_node(node), Empty));
+            let repeat = Box::new(Repeat::new(make_node(node), railroad::Empty));
             Box::new(Optional::new(repeat))
         }
-        OneOrMore { node } => Box::new(Repeat::new(make_node(node), Empty)),
+        OneOrMore { node } => Box::new(Repeat::new(make_node(node), railroad::Empty)),
         ZeroOrOne { node, .. } => Box::new(Optional::new(make_node(node))),
-        RuleRef { name } => Box::new(NonTerminal::new(name.into())),
+        // Separator goes on the back edge, a trailing separator is optional after the last item
+        SeparatedList {
+            node,
+            separator,
+            trailing,
+        } => {
+            let repeat = Box::new(Repeat::new(make_node(node), make_node(separator)));
+            if *trailing {
+                Box::new(Sequence::new(vec![
+                    repeat,
+                    Box::new(Optional::new(make_node(separator))),
+                ]))
+            } else {
+                repeat
+            }
+        }
+        PositiveLookahead { node } => Box::new(LabeledBox::new(
+            make_node(node),
+            railroad::Comment::new("followed by".into()),
+        )),
+        NegativeLookahead { node } => Box::new(LabeledBox::new(
+            make_node(node),
+            railroad::Comment::new("not followed by".into()),
+        )),
+        // Only predicates affect what is matched, other actions are left out
+        Action {
+            predicate: true, ..
+        } => Box::new(railroad::Comment::new(node.comment())),
+        Action { .. } => Box::new(railroad::Empty),
+        Node::Empty => Box::new(railroad::Empty),
+        RuleRef { name, .. } => Box::new(NonTerminal::new(name.into())),
+        TemplateRef { .. } => Box::new(NonTerminal::new(node.comment())),
         TokenRef { name, replaced_lit } => match replaced_lit {
-            Some(literal) => Box::new(Terminal::new(literal.into())),
+            Some(literal) => Box::new(Terminal::new(literal_label(literal))),
             None => Box::new(NonTerminal::new(name.into())),
         },
-        TokenLit { literal } => Box::new(Terminal::new(literal.into())),
+        TokenLit {
+            literal,
+            case_insensitive,
+        } => {
+            let label = literal_label(literal);
+            Box::new(Terminal::new(if *case_insensitive {
+                label + "i"
+            } else {
+                label
+            }))
+        }
+        CharClass { class } => Box::new(Terminal::new(class.into())),
+    }
+}
+
+// Invalid escapes were already reported by `Transform`, so they are shown as written
+fn literal_label(literal: &str) -> String {
+    match unescape_literal(literal) {
+        Ok(value) => display_literal(&value),
+        Err(_) => literal.into(),
     }
 }
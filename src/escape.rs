@@ -0,0 +1,301 @@
+use regex_syntax::hir::{Class, HirKind};
+
+// Escape sequences allowed in literals and character classes: \n \r \t \0 \\ \' \" \u{7FFF}
+// Character classes can also escape their own syntax (\[ \] \- \^) and contain Unicode properties (\p{L} \P{L})
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CharClass {
+    pub negated: bool,
+    // Sorted, non-overlapping inclusive ranges
+    pub ranges: Vec<(char, char)>,
+}
+
+// Unescapes a quoted literal (ex: `'\''` -> `'`)
+pub fn unescape_literal(literal: &str) -> Result<String, String> {
+    let value = literal
+        .strip_prefix('\'')
+        .and_then(|lit| lit.strip_suffix('\''))
+        .ok_or_else(|| format!("Literal {} is not quoted", literal))?;
+
+    let mut chars = value.chars();
+    let mut result = String::with_capacity(value.len());
+
+    while let Some(ch) = chars.next() {
+        let ch = match ch {
+            '\\' => unescape_char(&mut chars, "")
+                .map_err(|err| format!("{} in literal {}", err, literal))?,
+            ch => ch,
+        };
+        result.push(ch);
+    }
+
+    Ok(result)
+}
+
+// Parses a bracketed character class (ex: `[^a-z_\]]`) or a single Unicode property (ex: `\p{L}`) - ranges are
+// sorted and merged so large classes stay compact
+pub fn parse_char_class(class: &str) -> Result<CharClass, String> {
+    let body = match class.strip_prefix('[') {
+        Some(body) => body
+            .strip_suffix(']')
+            .ok_or_else(|| format!("Character class {} is not bracketed", class))?,
+        None if class.starts_with("\\p") || class.starts_with("\\P") => class,
+        None => return Err(format!("Character class {} is not bracketed", class)),
+    };
+    let (negated, body) = match body.strip_prefix('^') {
+        Some(body) if class.starts_with('[') => (true, body),
+        _ => (false, body),
+    };
+
+    let mut chars = body.chars();
+    let mut items: Vec<ClassItem> = vec![];
+
+    // Unescape first so an escaped '-' isn't seen as a range
+    while let Some(ch) = chars.next() {
+        items.push(match ch {
+            '\\' if chars.as_str().starts_with(['p', 'P']) => ClassItem::Property(
+                unicode_property(&mut chars)
+                    .map_err(|err| format!("{} in character class {}", err, class))?,
+            ),
+            '\\' => ClassItem::Char(
+                unescape_char(&mut chars, "[]-^")
+                    .map_err(|err| format!("{} in character class {}", err, class))?,
+            ),
+            '-' => ClassItem::Dash,
+            ch => ClassItem::Char(ch),
+        });
+    }
+
+    let mut ranges = vec![];
+    let mut idx = 0;
+
+    while idx < items.len() {
+        // A '-' is only a range when it is between two characters
+        match &items[idx..] {
+            [ClassItem::Char(start), ClassItem::Dash, ClassItem::Char(end), ..] => {
+                if start > end {
+                    return Err(format!(
+                        "Range '{}-{}' is out of order in character class {}",
+                        start.escape_default(),
+                        end.escape_default(),
+                        class
+                    ));
+                }
+                ranges.push((*start, *end));
+                idx += 3;
+                continue;
+            }
+            [ClassItem::Char(ch), ..] => ranges.push((*ch, *ch)),
+            [ClassItem::Dash, ..] => ranges.push(('-', '-')),
+            [ClassItem::Property(property_ranges), ..] => ranges.extend(property_ranges),
+            [] => unreachable!(),
+        }
+        idx += 1;
+    }
+
+    if ranges.is_empty() {
+        return Err(format!("Character class {} is empty", class));
+    }
+
+    Ok(CharClass {
+        negated,
+        ranges: merge_ranges(ranges),
+    })
+}
+
+enum ClassItem {
+    Char(char),
+    Dash,
+    Property(Vec<(char, char)>),
+}
+
+// Looks up a Unicode property or general category (ex: `p{L}`, `P{XID_Start}`) - the backslash has been consumed
+fn unicode_property(chars: &mut std::str::Chars) -> Result<Vec<(char, char)>, String> {
+    let rest = chars.as_str();
+    let (name, _) = rest[1..]
+        .strip_prefix('{')
+        .and_then(|rest| rest.split_once('}'))
+        .ok_or("Invalid Unicode property (expected '\\p{...}')")?;
+    let property = &rest[..name.len() + 3];
+
+    let hir = regex_syntax::ParserBuilder::new()
+        .build()
+        .parse(&format!("\\{}", property))
+        .map_err(|_| format!("Unknown Unicode property '\\{}'", property))?;
+
+    let ranges = match hir.kind() {
+        HirKind::Class(Class::Unicode(class)) => class
+            .iter()
+            .map(|range| (range.start(), range.end()))
+            .collect(),
+        _ => return Err(format!("Unknown Unicode property '\\{}'", property)),
+    };
+
+    *chars = rest[property.len()..].chars();
+    Ok(ranges)
+}
+
+fn merge_ranges(mut ranges: Vec<(char, char)>) -> Vec<(char, char)> {
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(char, char)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            // Overlapping or adjacent
+            Some((_, last_end)) if start as u32 <= *last_end as u32 + 1 => {
+                *last_end = (*last_end).max(end);
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+// Escapes a literal value so it can be quoted again (the reverse of `unescape_literal`, without quotes)
+pub fn escape_literal(value: &str) -> String {
+    value
+        .chars()
+        .map(|ch| match ch {
+            '\'' => "\\'".to_string(),
+            '\\' => "\\\\".to_string(),
+            ch if ch.is_control() => ch.escape_default().to_string(),
+            ch => ch.to_string(),
+        })
+        .collect()
+}
+
+// Quotes a literal value for display - only characters that can't be seen are escaped
+pub fn display_literal(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .map(|ch| match ch {
+            ch if ch.is_control() => ch.escape_default().to_string(),
+            ch => ch.to_string(),
+        })
+        .collect();
+    format!("'{}'", value)
+}
+
+fn unescape_char(chars: &mut std::str::Chars, extra: &str) -> Result<char, String> {
+    match chars.next() {
+        Some('n') => Ok('\n'),
+        Some('r') => Ok('\r'),
+        Some('t') => Ok('\t'),
+        Some('0') => Ok('\0'),
+        Some(ch @ ('\\' | '\'' | '"')) => Ok(ch),
+        Some(ch) if extra.contains(ch) => Ok(ch),
+        Some('u') => {
+            let rest = chars.as_str();
+            let hex = rest
+                .strip_prefix('{')
+                .and_then(|rest| rest.split_once('}'))
+                .map(|(hex, _)| hex)
+                .ok_or("Invalid unicode escape (expected '\\u{...}')")?;
+
+            let ch = match hex.len() {
+                1..=6 => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                _ => None,
+            }
+            .ok_or_else(|| format!("Invalid unicode escape '\\u{{{}}}'", hex))?;
+
+            // Skip past the braces and hex digits
+            *chars = rest[hex.len() + 2..].chars();
+            Ok(ch)
+        }
+        Some(ch) => Err(format!("Invalid escape sequence '\\{}'", ch)),
+        None => Err("Incomplete escape sequence".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescape_simple() {
+        assert_eq!(unescape_literal("'abc'").unwrap(), "abc");
+        assert_eq!(unescape_literal("''").unwrap(), "");
+        assert_eq!(
+            unescape_literal(r#"'\n\r\t\0\\\'\"'"#).unwrap(),
+            "\n\r\t\0\\'\""
+        );
+    }
+
+    #[test]
+    fn unescape_unicode() {
+        assert_eq!(unescape_literal(r"'\u{27}'").unwrap(), "'");
+        assert_eq!(unescape_literal(r"'a\u{1F600}b'").unwrap(), "a\u{1F600}b");
+        assert!(unescape_literal(r"'\u{00000041}'").is_err());
+        assert!(unescape_literal(r"'\u{}'").is_err());
+        assert!(unescape_literal(r"'\u{D800}'").is_err());
+        assert!(unescape_literal(r"'\u{110000}'").is_err());
+        assert!(unescape_literal(r"'\u{zz}'").is_err());
+        assert!(unescape_literal(r"'\u27'").is_err());
+        assert!(unescape_literal(r"'\u{27'").is_err());
+    }
+
+    #[test]
+    fn unescape_invalid() {
+        assert!(unescape_literal(r"'\q'").is_err());
+        assert!(unescape_literal(r"'\'").is_err());
+        assert!(unescape_literal("abc").is_err());
+        assert!(unescape_literal("'abc").is_err());
+        // Class syntax can only be escaped in classes
+        assert!(unescape_literal(r"'\['").is_err());
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        for value in ["abc", "'", "\\", "a\n\tb", "\0", "\u{1F600}", "\\'"] {
+            let literal = format!("'{}'", escape_literal(value));
+            assert_eq!(unescape_literal(&literal).unwrap(), value);
+        }
+        assert_eq!(display_literal("a\nb'"), "'a\\nb''");
+    }
+
+    fn ranges(class: &str) -> Vec<(char, char)> {
+        parse_char_class(class).unwrap().ranges
+    }
+
+    #[test]
+    fn class_ranges() {
+        assert_eq!(ranges("[a-z_]"), [('_', '_'), ('a', 'z')]);
+        // Overlapping and adjacent ranges are merged
+        assert_eq!(ranges("[d-fa-cb]"), [('a', 'f')]);
+        assert_eq!(ranges("[x]"), [('x', 'x')]);
+        assert!(!parse_char_class("[a]").unwrap().negated);
+        assert!(parse_char_class("[^a]").unwrap().negated);
+    }
+
+    #[test]
+    fn class_dashes() {
+        // A '-' that isn't between two characters is itself
+        assert_eq!(ranges("[a-]"), [('-', '-'), ('a', 'a')]);
+        assert_eq!(ranges("[-a]"), [('-', '-'), ('a', 'a')]);
+        assert_eq!(ranges(r"[a\-z]"), [('-', '-'), ('a', 'a'), ('z', 'z')]);
+        assert!(parse_char_class("[z-a]").is_err());
+    }
+
+    #[test]
+    fn class_escapes() {
+        assert_eq!(ranges(r"[\]]"), [(']', ']')]);
+        assert_eq!(ranges(r"[\^]"), [('^', '^')]);
+        assert_eq!(ranges(r"[\n\t]"), [('\t', '\n')]);
+        assert_eq!(ranges(r"[\u{41}-\u{43}]"), [('A', 'C')]);
+        assert!(parse_char_class(r"[\q]").is_err());
+        assert!(parse_char_class("[]").is_err());
+        assert!(parse_char_class("a-z").is_err());
+    }
+
+    #[test]
+    fn class_properties() {
+        let digits = ranges(r"\p{Nd}");
+        assert!(digits.contains(&('0', '9')));
+        assert!(parse_char_class(r"\P{Nd}").is_ok());
+
+        let class = ranges(r"[\p{Nd}_]");
+        assert!(class.contains(&('_', '_')) && class.contains(&('0', '9')));
+        assert!(parse_char_class(r"\p{NotAProperty}").is_err());
+        assert!(parse_char_class(r"[\p{L]").is_err());
+    }
+}
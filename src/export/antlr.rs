@@ -0,0 +1,547 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::ast::{Grammar, GrammarKind, Node, ParserRule, TokenRule};
+use crate::escape::unescape_literal;
+use crate::export::{group, skip_tokens, Export, Format, Prec, Writer};
+use crate::lexer_gen::CASE_INSENSITIVE;
+use crate::transform::EOF;
+use crate::vocab::TOKEN_VOCAB;
+
+// Names ANTLR reserves, which can't be used as rule names or labels
+const KEYWORDS: [&str; 14] = [
+    "catch", "channels", "finally", "fragment", "grammar", "import", "lexer", "locals", "mode",
+    "options", "parser", "returns", "throws", "tokens",
+];
+
+// Writes a transformed grammar as an ANTLR4 grammar (.g4) - ANTLR requires the grammar's name to match its file name
+pub fn write_antlr(grammar: &Grammar, name: &str) -> Export {
+    let mut writer = AntlrWriter {
+        writer: Writer::new(grammar),
+        rule_names: grammar
+            .parser_rules
+            .iter()
+            .map(|rule| rule.name.to_string())
+            .collect(),
+    };
+    let mut out = String::new();
+
+    let kind = match grammar.kind {
+        GrammarKind::Combined => "",
+        GrammarKind::Lexer => "lexer ",
+        GrammarKind::Parser => "parser ",
+    };
+    writeln!(out, "{}grammar {};\n", kind, name).unwrap();
+
+    writer.write_options(&mut out);
+    writer.write_tokens(&mut out);
+
+    for rule in &grammar.parser_rules {
+        writer.write_parser_rule(&mut out, rule);
+        out.push('\n');
+    }
+
+    // HRPG matches literals ahead of patterns (ex: a keyword that is also an identifier), while ANTLR matches whichever
+    // rule comes first
+    let (literals, patterns): (Vec<&TokenRule>, Vec<&TokenRule>) = grammar
+        .token_rules
+        .iter()
+        .partition(|rule| matches!(rule.node, Node::TokenLit { .. }));
+
+    // Token rules are usually one line each, so only multi-line ones are set apart
+    let skipped = skip_tokens(grammar);
+    for rule in literals.into_iter().chain(patterns) {
+        let multi_line = matches!(rule.node, Node::Alternatives { .. });
+        if (multi_line || !rule.doc.is_empty()) && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        writer.write_token_rule(&mut out, rule, skipped.contains(&rule.name));
+        if multi_line {
+            out.push('\n');
+        }
+    }
+
+    writer.writer.finish(out)
+}
+
+fn write_doc(out: &mut String, doc: &[String]) {
+    match doc {
+        [] => (),
+        [line] => writeln!(out, "/** {} */", line).unwrap(),
+        lines => {
+            out.push_str("/**\n");
+            for line in lines {
+                writeln!(out, " * {}", line).unwrap();
+            }
+            out.push_str(" */\n");
+        }
+    }
+}
+
+struct AntlrWriter<'g> {
+    writer: Writer<'g>,
+    rule_names: HashSet<String>,
+}
+
+impl<'g> AntlrWriter<'g> {
+    // ANTLR only has a case insensitive option for lexers
+    fn write_options(&mut self, out: &mut String) {
+        // Sorted so the output doesn't depend on hash order
+        let grammar = self.writer.grammar;
+        let mut options: Vec<_> = grammar.options.iter().collect();
+        options.sort();
+
+        let mut written = vec![];
+        for (name, value) in options {
+            match name.as_str() {
+                TOKEN_VOCAB => written.push(format!("{} = {};", name, value)),
+                CASE_INSENSITIVE if grammar.kind != GrammarKind::Parser => {
+                    written.push(format!("{} = {};", name, value))
+                }
+                CASE_INSENSITIVE => (),
+                _ => self.writer.warn(format!(
+                    "Option '{}' has no ANTLR equivalent and was dropped",
+                    name
+                )),
+            }
+        }
+
+        if !written.is_empty() {
+            out.push_str("options {\n");
+            for option in written {
+                writeln!(out, "    {}", option).unwrap();
+            }
+            out.push_str("}\n\n");
+        }
+    }
+
+    // Extern tokens, and tokens used without a rule (ex: INDENT), have to come from a custom lexer - parser grammars
+    // get all of their tokens from the token vocabulary instead, and `EOF` is built into ANTLR
+    fn write_tokens(&mut self, out: &mut String) {
+        let grammar = self.writer.grammar;
+        if grammar.kind == GrammarKind::Parser {
+            return;
+        }
+
+        let mut names = grammar.extern_tokens.clone();
+        for rule in &grammar.parser_rules {
+            collect_token_names(&rule.node, &mut names);
+        }
+        names.retain(|name| {
+            name != EOF && !grammar.token_rules.iter().any(|rule| &rule.name == name)
+        });
+
+        if !names.is_empty() {
+            writeln!(out, "tokens {{ {} }}\n", names.join(", ")).unwrap();
+        }
+    }
+
+    fn rule_name(&mut self, name: &str) -> String {
+        match KEYWORDS.contains(&name) {
+            true => {
+                self.writer.warn(format!(
+                    "Rule '{}' is an ANTLR keyword and was renamed '{}_'",
+                    name, name
+                ));
+                format!("{}_", name)
+            }
+            false => name.to_string(),
+        }
+    }
+
+    fn write_parser_rule(&mut self, out: &mut String, rule: &ParserRule) {
+        write_doc(out, &rule.doc);
+
+        let mut decl = self.rule_name(&rule.name);
+        if !rule.args.is_empty() {
+            write!(decl, "[{}]", rule.args.join(", ")).unwrap();
+        }
+        if !rule.returns.is_empty() {
+            write!(decl, " returns [{}]", rule.returns.join(", ")).unwrap();
+        }
+
+        // Only the alternatives of a rule can be labeled in ANTLR
+        let alts: Vec<String> = match &rule.node {
+            Node::Alternatives { nodes } => {
+                nodes.iter().map(|node| self.alternative(node)).collect()
+            }
+            node => vec![self.alternative(node)],
+        };
+        write_rule(out, &decl, &alts);
+    }
+
+    fn alternative(&mut self, node: &Node) -> String {
+        match node {
+            Node::Labeled { label, node } => {
+                let text = self.write_text(node);
+                let mut label = label.to_string();
+
+                // ANTLR names the context classes of both after them (ex: `expr` and `Expr` are both `ExprContext`)
+                if self.rule_names.iter().any(|name| capitalize(name) == label) {
+                    self.writer.warn(format!(
+                        "Label '{}' conflicts with a rule in ANTLR and was renamed '{}Alt'",
+                        label, label
+                    ));
+                    label += "Alt";
+                }
+                format!("{} # {}", text, label).trim_start().to_string()
+            }
+            node => self.write_text(node),
+        }
+    }
+
+    // Nodes that match nothing (ex: actions) are left out
+    fn write_text(&mut self, node: &Node) -> String {
+        self.write_node(node)
+            .map(|(text, _)| text)
+            .unwrap_or_default()
+    }
+
+    fn write_token_rule(&mut self, out: &mut String, rule: &TokenRule, skipped: bool) {
+        write_doc(out, &rule.doc);
+
+        let mut alts: Vec<String> = match &rule.node {
+            Node::Alternatives { nodes } => {
+                nodes.iter().map(|node| self.write_text(node)).collect()
+            }
+            node => vec![self.write_text(node)],
+        };
+        // Lexer commands only apply to the alternative they follow
+        if skipped {
+            for alt in &mut alts {
+                *alt += " -> skip";
+            }
+        }
+        write_rule(out, &rule.name, &alts);
+    }
+}
+
+impl<'g> Format<'g> for AntlrWriter<'g> {
+    const NAME: &'static str = "ANTLR";
+    const RULE_ARGS: bool = true;
+
+    fn writer(&mut self) -> &mut Writer<'g> {
+        &mut self.writer
+    }
+
+    // ANTLR labels can only name a single element or a set of tokens (ex: `op=('+' | '-')`) - a list binding of a
+    // separated list labels each element (ex: `xs+=x (',' xs+=x)*`)
+    fn binding(&mut self, name: &str, list: bool, node: &Node) -> Option<(String, Prec)> {
+        let op = if list { "+=" } else { "=" };
+        let mut label = name.to_string();
+        if self.rule_names.contains(name) || KEYWORDS.contains(&name) {
+            self.writer.warn(format!(
+                "Binding '{}' conflicts with a rule or keyword in ANTLR and was renamed '{}_'",
+                name, name
+            ));
+            label += "_";
+        }
+
+        let labeled = |item: (String, Prec)| {
+            (
+                format!("{}{}{}", label, op, group(item, Prec::Primary)),
+                Prec::Term,
+            )
+        };
+        match node {
+            Node::SeparatedList {
+                node,
+                separator,
+                trailing,
+            } if is_labelable(node) => {
+                let item = labeled(self.write_node(node)?);
+                let separator = self.write_node(separator);
+                Some(self.separated(item, separator, *trailing))
+            }
+            Node::ZeroOrMore { node: inner }
+            | Node::OneOrMore { node: inner }
+            | Node::ZeroOrOne { node: inner, .. }
+                if is_labelable(inner) =>
+            {
+                let (text, _) = self.write_node(node)?;
+                Some((format!("{}{}{}", label, op, text), Prec::Term))
+            }
+            node if is_labelable(node) => Some(labeled(self.write_node(node)?)),
+            node => {
+                self.writer.warn(
+                    "Bindings of groups that aren't a set of tokens can't be ANTLR labels and were dropped",
+                );
+                self.write_node(node)
+            }
+        }
+    }
+
+    // An empty alternative is written as nothing (ex: `a | `)
+    fn choice(&mut self, alts: Vec<Option<(String, Prec)>>) -> Option<(String, Prec)> {
+        let alts: Vec<String> = alts
+            .into_iter()
+            .map(|alt| alt.map(|(text, _)| text).unwrap_or_default())
+            .collect();
+        Some((alts.join(" | "), Prec::Alternatives))
+    }
+
+    fn sequence(&mut self, parts: Vec<(String, Prec)>) -> Option<(String, Prec)> {
+        let parts: Vec<String> = parts
+            .into_iter()
+            .map(|part| group(part, Prec::Sequence))
+            .collect();
+        match parts.len() {
+            0 => None,
+            _ => Some((parts.join(" "), Prec::Sequence)),
+        }
+    }
+
+    // `a % b` -> `a (b a)*`, `a %% b` -> `a (b a)* b?`
+    fn separated(
+        &mut self,
+        item: (String, Prec),
+        separator: Option<(String, Prec)>,
+        trailing: bool,
+    ) -> (String, Prec) {
+        let sep = match separator {
+            Some(sep) => group(sep, Prec::Primary),
+            None => return self.repeat(item, "+"),
+        };
+
+        let item = group(item, Prec::Sequence);
+        let mut text = format!("{} ({} {})*", item, sep, item);
+        if trailing {
+            write!(text, " {}?", sep).unwrap();
+        }
+        (text, Prec::Sequence)
+    }
+
+    fn rule_ref(&mut self, name: &str, args: &[String]) -> (String, Prec) {
+        let name = self.rule_name(name);
+        match args.is_empty() {
+            true => (name, Prec::Primary),
+            false => (format!("{}[{}]", name, args.join(", ")), Prec::Primary),
+        }
+    }
+
+    fn token_ref(&mut self, name: &str) -> Option<(String, Prec)> {
+        Some((name.to_string(), Prec::Primary))
+    }
+
+    // ANTLR only matches a literal in a parser rule to a token rule with exactly that literal
+    fn replaced_literal(&mut self, name: &str, literal: &str) -> Option<(String, Prec)> {
+        let value = unescape_literal(literal).ok();
+        match self.writer.token_literal(name) {
+            Some((token_lit, false)) if unescape_literal(token_lit).ok() == value => Some((
+                antlr_literal(value.as_deref().unwrap_or_default()),
+                Prec::Primary,
+            )),
+            _ => self.token_ref(name),
+        }
+    }
+
+    fn literal(&mut self, literal: &str, case_insensitive: bool) -> (String, Prec) {
+        let value = unescape_literal(literal).unwrap_or_default();
+        match case_insensitive && !self.writer.case_insensitive {
+            true => {
+                let parts = insensitive_parts(&value);
+                match parts.len() {
+                    1 => (parts.join(" "), Prec::Primary),
+                    _ => (parts.join(" "), Prec::Sequence),
+                }
+            }
+            false => (antlr_literal(&value), Prec::Primary),
+        }
+    }
+
+    fn char_class(&mut self, class: &str) -> (String, Prec) {
+        (antlr_class(class), Prec::Primary)
+    }
+}
+
+// Rules with several alternatives get one line per alternative (so labels end their line)
+fn write_rule(out: &mut String, decl: &str, alts: &[String]) {
+    match alts {
+        [alt] => writeln!(out, "{}: {};", decl, alt).unwrap(),
+        alts => {
+            writeln!(out, "{}", decl).unwrap();
+            for (idx, alt) in alts.iter().enumerate() {
+                let sep = if idx == 0 { ':' } else { '|' };
+                writeln!(out, "    {} {}", sep, alt).unwrap();
+            }
+            out.push_str("    ;\n");
+        }
+    }
+}
+
+fn is_labelable(node: &Node) -> bool {
+    match node {
+        Node::RuleRef { .. } | Node::TokenRef { .. } | Node::TokenLit { .. } => true,
+        Node::Alternatives { nodes } => nodes
+            .iter()
+            .all(|node| matches!(node, Node::TokenRef { .. } | Node::TokenLit { .. })),
+        _ => false,
+    }
+}
+
+// Token names in order of first use
+fn collect_token_names(node: &Node, names: &mut Vec<String>) {
+    match node {
+        Node::TokenRef { name, .. } => {
+            if !names.contains(name) {
+                names.push(name.to_string());
+            }
+        }
+        node => {
+            for child in node.children() {
+                collect_token_names(child, names);
+            }
+        }
+    }
+}
+
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn antlr_literal(value: &str) -> String {
+    let escaped: String = value
+        .chars()
+        .map(|ch| match ch {
+            '\'' => "\\'".to_string(),
+            '\\' => "\\\\".to_string(),
+            '\n' => "\\n".to_string(),
+            '\r' => "\\r".to_string(),
+            '\t' => "\\t".to_string(),
+            '\u{8}' => "\\b".to_string(),
+            '\u{c}' => "\\f".to_string(),
+            ch if ch.is_control() => format!("\\u{:04X}", ch as u32),
+            ch => ch.to_string(),
+        })
+        .collect();
+    format!("'{}'", escaped)
+}
+
+// ANTLR only ignores case for a whole lexer, so each letter becomes a set (ex: `'if'i` -> `[iI] [fF]`)
+fn insensitive_parts(value: &str) -> Vec<String> {
+    let mut parts = vec![];
+    let mut run = String::new();
+
+    for ch in value.chars() {
+        let upper: Vec<char> = ch.to_uppercase().collect();
+        let lower: Vec<char> = ch.to_lowercase().collect();
+        match (upper.as_slice(), lower.as_slice()) {
+            ([upper], [lower]) if upper != lower => {
+                if !run.is_empty() {
+                    parts.push(antlr_literal(&run));
+                    run.clear();
+                }
+                parts.push(format!("[{}{}]", lower, upper));
+            }
+            _ => run.push(ch),
+        }
+    }
+    if !run.is_empty() {
+        parts.push(antlr_literal(&run));
+    }
+    parts
+}
+
+// HRPG classes are the same as ANTLR sets, except for negation (`~[...]`) and the escapes ANTLR doesn't allow in a set
+// (ex: `\[`)
+fn antlr_class(class: &str) -> String {
+    let (prefix, body) = match class.strip_prefix("[^") {
+        Some(body) => ("~[", body),
+        None => match class.strip_prefix('[') {
+            Some(body) => ("[", body),
+            // A Unicode property on its own (ex: `\p{L}`)
+            None => return class.to_string(),
+        },
+    };
+    let body = body.strip_suffix(']').unwrap_or(body);
+
+    let mut out = String::new();
+    let mut chars = body.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some(ch @ ('[' | '^' | '\'' | '"')) => out.push(ch),
+                Some('0') => out.push_str("\\u0000"),
+                // `\u{...}`, `\p{...}` and `\P{...}` are written the same way
+                Some(ch @ ('u' | 'p' | 'P')) => {
+                    let rest = chars.as_str();
+                    let end = rest.find('}').map_or(rest.len(), |end| end + 1);
+                    write!(out, "\\{}{}", ch, &rest[..end]).unwrap();
+                    chars = rest[end..].chars();
+                }
+                Some(ch) => write!(out, "\\{}", ch).unwrap(),
+                None => out.push('\\'),
+            },
+            ch => out.push(ch),
+        }
+    }
+    format!("{}{}]", prefix, out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_hrpg;
+    use crate::export::hrpg::write_grammar;
+    use crate::import::antlr::import_antlr;
+    use crate::transform::Transform;
+
+    const CALC: &str = "grammar Calc;\n\n\
+                        ## A statement\n\
+                        stat\n    : name=ID '=' expr ';' @Assign\n    | expr ';' @Print\n    ;\n\n\
+                        expr: NUM (',' NUM)* [';'];\n\n\
+                        EQUALS: '=';\nSEMI: ';';\nCOMMA: ',';\nID: [a-z]+;\nNUM: [0-9]+;\n\
+                        ## @skip\nWS: [ \\t\\r\\n]+;\n";
+
+    fn export(data: &str) -> Export {
+        let (g2, transform) = Transform::process_with_vocab(&parse_hrpg(data).unwrap(), None);
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+        write_antlr(&g2, "Calc")
+    }
+
+    #[test]
+    fn small_grammar() {
+        let export = export(CALC);
+        assert_eq!(
+            export.text,
+            "grammar Calc;\n\n\
+             /** A statement */\n\
+             stat\n    : name=ID '=' expr ';' # Assign\n    | expr ';' # Print\n    ;\n\n\
+             expr: NUM (',' NUM)* ';'?;\n\n\
+             EQUALS: '=';\nSEMI: ';';\nCOMMA: ',';\nID: [a-z]+;\nNUM: [0-9]+;\n\
+             WS: [ \\t\\r\\n]+ -> skip;\n"
+        );
+        assert!(export.warnings.is_empty(), "{:?}", export.warnings);
+    }
+
+    #[test]
+    fn unmarked_tokens_are_not_skipped() {
+        let export = export("a: 'x';\nX: 'x';\nY: 'y';\n");
+        assert!(export.text.ends_with("X: 'x';\nY: 'y';\n"));
+    }
+
+    #[test]
+    fn eof_is_not_declared() {
+        let export = export("file: X* EOF;\nextern INDENT;\nX: 'x';\n");
+        assert!(export.text.contains("tokens { INDENT }\n"));
+        assert!(export.text.contains("file: X* EOF;\n"));
+    }
+
+    // ANTLR has no brackets and the importer drops doc comments, but everything else comes back
+    #[test]
+    fn round_trip() {
+        let data = CALC
+            .replace("## A statement\n", "")
+            .replace("[';']", "';'?");
+        let import = import_antlr(&export(&data).text).unwrap();
+        assert!(import.warnings.is_empty());
+        assert_eq!(
+            write_grammar(&import.grammar),
+            write_grammar(&parse_hrpg(&data).unwrap())
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::fmt::Write;
+
+use crate::ast::{Grammar, Node};
+use crate::escape::{parse_char_class, unescape_literal};
+use crate::export::{group, token_refs, Export, Format, Prec, Writer};
+
+// Writes a transformed grammar in W3C EBNF (https://www.w3.org/TR/xml/#sec-notation)
+pub fn write_ebnf(grammar: &Grammar) -> Export {
+    let mut writer = EbnfWriter {
+        writer: Writer::new(grammar),
+    };
+    let mut out = String::new();
+
+    for rule in &grammar.parser_rules {
+        write_doc(&mut out, &rule.doc);
+        writer.write_rule(&mut out, &rule.name, &rule.node);
+        out.push('\n');
+    }
+
+    if !grammar.extern_tokens.is_empty() {
+        writer
+            .writer
+            .warn("Extern tokens have no EBNF equivalent - they are only listed in a comment");
+        writeln!(
+            out,
+            "/* Matched by an external scanner: {} */\n",
+            grammar.extern_tokens.join(", ")
+        )
+        .unwrap();
+    }
+
+    // Literal tokens are written as their literal wherever parser rules use them
+    let (by_name, by_literal) = token_refs(grammar);
+    for rule in &grammar.token_rules {
+        if by_literal.contains(&rule.name) && !by_name.contains(&rule.name) {
+            continue;
+        }
+        if !rule.doc.is_empty() {
+            out.push('\n');
+        }
+        write_doc(&mut out, &rule.doc);
+        writer.write_rule(&mut out, &rule.name, &rule.node);
+    }
+
+    writer.writer.finish(out)
+}
+
+fn write_doc(out: &mut String, doc: &[String]) {
+    if !doc.is_empty() {
+        writeln!(out, "/* {} */", doc.join("\n   ").trim_end()).unwrap();
+    }
+}
+
+struct EbnfWriter<'g> {
+    writer: Writer<'g>,
+}
+
+impl<'g> EbnfWriter<'g> {
+    // Rules with several alternatives get one line per alternative, lined up under the first (an empty alternative
+    // is written as an empty string)
+    fn write_rule(&mut self, out: &mut String, name: &str, node: &Node) {
+        let alts = match node {
+            Node::Alternatives { nodes } => {
+                nodes.iter().map(|node| self.write_node(node)).collect()
+            }
+            node => vec![self.write_node(node)],
+        };
+
+        for (idx, alt) in alts.into_iter().enumerate() {
+            let text = alt
+                .map(|(text, _)| text)
+                .unwrap_or_else(|| "\"\"".to_string());
+            match idx {
+                0 => writeln!(out, "{} ::= {}", name, text).unwrap(),
+                _ => writeln!(out, "{}| {}", " ".repeat(name.len() + 3), text).unwrap(),
+            }
+        }
+    }
+}
+
+impl<'g> Format<'g> for EbnfWriter<'g> {
+    const NAME: &'static str = "EBNF";
+
+    fn writer(&mut self) -> &mut Writer<'g> {
+        &mut self.writer
+    }
+
+    fn binding(&mut self, _name: &str, _list: bool, node: &Node) -> Option<(String, Prec)> {
+        self.writer
+            .warn("Bindings have no EBNF equivalent and were dropped");
+        self.write_node(node)
+    }
+
+    // An empty alternative makes the others optional (ex: `a | b | ()` -> `(a | b)?`)
+    fn choice(&mut self, alts: Vec<Option<(String, Prec)>>) -> Option<(String, Prec)> {
+        let optional = alts.iter().any(|alt| alt.is_none());
+        let mut alts: Vec<(String, Prec)> = alts.into_iter().flatten().collect();
+
+        let alts = match alts.len() {
+            0 => return None,
+            1 => alts.remove(0),
+            _ => {
+                let texts: Vec<String> = alts
+                    .into_iter()
+                    .map(|alt| group(alt, Prec::Sequence))
+                    .collect();
+                (texts.join(" | "), Prec::Alternatives)
+            }
+        };
+        Some(match optional {
+            true => (format!("{}?", group(alts, Prec::Primary)), Prec::Suffixed),
+            false => alts,
+        })
+    }
+
+    fn labeled(&mut self, label: &str, node: &Node) -> Option<(String, Prec)> {
+        let (text, prec) = self
+            .write_node(node)
+            .unwrap_or(("\"\"".to_string(), Prec::Primary));
+        Some((format!("{} /* {} */", text, label), prec))
+    }
+
+    fn sequence(&mut self, parts: Vec<(String, Prec)>) -> Option<(String, Prec)> {
+        let parts: Vec<String> = parts
+            .into_iter()
+            .map(|part| group(part, Prec::Sequence))
+            .collect();
+        match parts.len() {
+            0 => None,
+            _ => Some((parts.join(" "), Prec::Sequence)),
+        }
+    }
+
+    // `a % b` -> `a (b a)*`, `a %% b` -> `a (b a)* b?`
+    fn separated(
+        &mut self,
+        item: (String, Prec),
+        separator: Option<(String, Prec)>,
+        trailing: bool,
+    ) -> (String, Prec) {
+        let sep = match separator {
+            Some(sep) => sep,
+            None => return self.repeat(item, "+"),
+        };
+
+        let item = group(item, Prec::Sequence);
+        let mut text = format!(
+            "{} ({} {})*",
+            item,
+            group(sep.clone(), Prec::Sequence),
+            item
+        );
+        if trailing {
+            write!(text, " {}?", group(sep, Prec::Primary)).unwrap();
+        }
+        (text, Prec::Sequence)
+    }
+
+    fn rule_ref(&mut self, name: &str, _args: &[String]) -> (String, Prec) {
+        (name.to_string(), Prec::Primary)
+    }
+
+    fn token_ref(&mut self, name: &str) -> Option<(String, Prec)> {
+        Some((name.to_string(), Prec::Primary))
+    }
+
+    // W3C strings have no escapes, so a string can't hold both kinds of quotes (ex: `"it's " '"x"'`) and invisible
+    // characters are written as character codes - ignoring case spells out each letter (ex: `[Ss] [Ee] [Ll]`)
+    fn literal(&mut self, literal: &str, case_insensitive: bool) -> (String, Prec) {
+        let case_insensitive = case_insensitive || self.writer.case_insensitive;
+        let value = match unescape_literal(literal) {
+            Ok(value) => value,
+            Err(_) => return (literal.to_string(), Prec::Primary),
+        };
+
+        let mut parts = vec![];
+        let mut run = String::new();
+        for ch in value.chars() {
+            let upper: Vec<char> = ch.to_uppercase().collect();
+            let lower: Vec<char> = ch.to_lowercase().collect();
+            let folded = case_insensitive && upper != lower && upper.len() == 1 && lower.len() == 1;
+
+            let split = folded
+                || ch.is_control()
+                || (ch == '"' && run.contains('\''))
+                || (ch == '\'' && run.contains('"'));
+            if split && !run.is_empty() {
+                parts.push(quote(&run));
+                run.clear();
+            }
+
+            if folded {
+                parts.push(format!("[{}{}]", upper[0], lower[0]));
+            } else if ch.is_control() {
+                parts.push(char_code(ch));
+            } else {
+                run.push(ch);
+            }
+        }
+        if !run.is_empty() || parts.is_empty() {
+            parts.push(quote(&run));
+        }
+
+        match parts.len() {
+            1 => (parts.remove(0), Prec::Primary),
+            _ => (parts.join(" "), Prec::Sequence),
+        }
+    }
+
+    // Unicode properties have no EBNF equivalent, so they are spelled out as the ranges they match
+    fn char_class(&mut self, class: &str) -> (String, Prec) {
+        let parsed = match parse_char_class(class) {
+            Ok(parsed) => parsed,
+            Err(_) => return (class.to_string(), Prec::Primary),
+        };
+        if class.contains("\\p") || class.contains("\\P") {
+            self.writer.warn(
+                "Unicode properties have no EBNF equivalent and were expanded to character ranges",
+            );
+        }
+
+        let items: Vec<String> = parsed
+            .ranges
+            .iter()
+            .map(|&(start, end)| match start == end {
+                true => class_char(start),
+                false => format!("{}-{}", class_char(start), class_char(end)),
+            })
+            .collect();
+        let class = format!(
+            "[{}{}]",
+            if parsed.negated { "^" } else { "" },
+            items.join("")
+        );
+        (class, Prec::Primary)
+    }
+}
+
+fn quote(value: &str) -> String {
+    match value.contains('\'') {
+        true => format!("\"{}\"", value),
+        false => format!("'{}'", value),
+    }
+}
+
+// `#x9`
+fn char_code(ch: char) -> String {
+    format!("#x{:X}", ch as u32)
+}
+
+// Only visible ASCII characters without a meaning inside of a class are written as themselves, the rest as character
+// codes (the way specifications usually write them)
+fn class_char(ch: char) -> String {
+    match ch {
+        ']' | '[' | '^' | '-' | '#' => char_code(ch),
+        ch if ch.is_ascii_graphic() => ch.to_string(),
+        ch => char_code(ch),
+    }
+}
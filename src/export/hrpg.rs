@@ -0,0 +1,253 @@
+use std::fmt::Write;
+
+use crate::ast::{Grammar, GrammarKind, Node, ParserRule, TokenRule};
+use crate::export::end_with_newline;
+
+// Writes a grammar back out in HRPG syntax (ex: after importing it from another format)
+pub fn write_grammar(grammar: &Grammar) -> String {
+    let mut out = String::new();
+
+    if let Some(name) = &grammar.name {
+        let kind = match grammar.kind {
+            GrammarKind::Combined => "",
+            GrammarKind::Lexer => "lexer ",
+            GrammarKind::Parser => "parser ",
+        };
+        writeln!(out, "{}grammar {};\n", kind, name).unwrap();
+    }
+
+    if !grammar.options.is_empty() {
+        // Sorted so the output doesn't depend on hash order
+        let mut options: Vec<_> = grammar.options.iter().collect();
+        options.sort();
+
+        out += "options {\n";
+        for (name, value) in options {
+            writeln!(out, "    {} = {};", name, option_value(value)).unwrap();
+        }
+        out += "}\n\n";
+    }
+
+    for rule in &grammar.parser_rules {
+        write_parser_rule(&mut out, rule);
+        out.push('\n');
+    }
+
+    if !grammar.extern_tokens.is_empty() {
+        writeln!(out, "extern {};\n", grammar.extern_tokens.join(", ")).unwrap();
+    }
+
+    // Token rules are usually one line each, so only multi-line ones are set apart
+    for rule in &grammar.token_rules {
+        let multi_line = matches!(rule.node, Node::Alternatives { .. });
+        let documented = !rule.doc.is_empty() || !rule.annotations.is_empty();
+        if (multi_line || documented) && !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        write_token_rule(&mut out, rule);
+        if multi_line {
+            out.push('\n');
+        }
+    }
+
+    end_with_newline(&mut out);
+    out
+}
+
+fn option_value(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+    {
+        value.to_string()
+    } else {
+        format!("'{}'", value)
+    }
+}
+
+fn write_doc(out: &mut String, doc: &[String]) {
+    for line in doc {
+        match line.is_empty() {
+            true => out.push_str("##\n"),
+            false => writeln!(out, "## {}", line).unwrap(),
+        }
+    }
+}
+
+fn write_parser_rule(out: &mut String, rule: &ParserRule) {
+    write_doc(out, &rule.doc);
+
+    let mut decl = rule.name.to_string();
+    if !rule.params.is_empty() {
+        write!(decl, "<{}>", rule.params.join(", ")).unwrap();
+    }
+    if !rule.args.is_empty() {
+        write!(decl, "[{}]", rule.args.join(", ")).unwrap();
+    }
+    if !rule.returns.is_empty() {
+        write!(decl, " returns [{}]", rule.returns.join(", ")).unwrap();
+    }
+
+    write_rule(out, &decl, &rule.node);
+}
+
+fn write_token_rule(out: &mut String, rule: &TokenRule) {
+    write_doc(out, &rule.doc);
+    for (name, value) in &rule.annotations {
+        match value.is_empty() {
+            true => writeln!(out, "## @{}", name).unwrap(),
+            false => writeln!(out, "## @{} {}", name, value).unwrap(),
+        }
+    }
+    write_rule(out, &rule.name, &rule.node);
+}
+
+// Rules with several alternatives get one line per alternative (so labels end their line)
+fn write_rule(out: &mut String, decl: &str, node: &Node) {
+    match node {
+        Node::Alternatives { nodes } => {
+            writeln!(out, "{}", decl).unwrap();
+            for (idx, node) in nodes.iter().enumerate() {
+                let sep = if idx == 0 { ':' } else { '|' };
+                writeln!(out, "    {} {}", sep, write_node(node)).unwrap();
+            }
+            out.push_str("    ;\n");
+        }
+        node => writeln!(out, "{}: {};", decl, write_node(node)).unwrap(),
+    }
+}
+
+pub fn write_node(node: &Node) -> String {
+    use Node::*;
+
+    match node {
+        Binding { name, list, node } => {
+            let op = if *list { "+=" } else { "=" };
+            match node.as_ref() {
+                // Suffixes bind tighter than the binding (ex: `x=a*`)
+                ZeroOrMore { .. } | OneOrMore { .. } | ZeroOrOne { .. } | SeparatedList { .. } => {
+                    format!("{} {} {}", name, op, write_node(node))
+                }
+                _ => format!("{} {} {}", name, op, write_elem(node)),
+            }
+        }
+
+        Alternatives { nodes } => {
+            let alts: Vec<String> = nodes.iter().map(write_node).collect();
+            alts.join(" | ")
+        }
+
+        Labeled { label, node } => format!("{} @{}", write_node(node), label),
+
+        MultipartBody { nodes } => {
+            let parts: Vec<String> = nodes
+                .iter()
+                .map(|node| match node {
+                    Alternatives { .. } | MultipartBody { .. } | Labeled { .. } => {
+                        format!("({})", write_node(node))
+                    }
+                    _ => write_node(node),
+                })
+                .collect();
+            parts.join(" ")
+        }
+
+        ZeroOrMore { node } => format!("{}*", write_elem(node)),
+        OneOrMore { node } => format!("{}+", write_elem(node)),
+        ZeroOrOne {
+            node,
+            brackets: true,
+        } => format!("[{}]", write_node(node)),
+        ZeroOrOne { node, .. } => format!("{}?", write_elem(node)),
+
+        SeparatedList {
+            node,
+            separator,
+            trailing,
+        } => {
+            let op = if *trailing { "%%" } else { "%" };
+            format!("{} {} {}", write_elem(node), op, write_elem(separator))
+        }
+
+        PositiveLookahead { node } => format!("&{}", write_elem(node)),
+        NegativeLookahead { node } => format!("!{}", write_elem(node)),
+
+        Action {
+            lang,
+            code,
+            predicate,
+        } => format!(
+            "{{{}: {}}}{}",
+            lang,
+            code,
+            if *predicate { "?" } else { "" }
+        ),
+
+        Empty => "()".to_string(),
+
+        RuleRef { name, args } if args.is_empty() => name.to_string(),
+        RuleRef { name, args } => format!("{}[{}]", name, args.join(", ")),
+
+        TemplateRef { name, args } => {
+            let args: Vec<String> = args.iter().map(write_node).collect();
+            format!("{}<{}>", name, args.join(", "))
+        }
+
+        TokenRef { name, .. } => name.to_string(),
+
+        TokenLit {
+            literal,
+            case_insensitive,
+        } => format!("{}{}", literal, if *case_insensitive { "i" } else { "" }),
+
+        CharClass { class } => class.to_string(),
+    }
+}
+
+// A node that can take a suffix or separator without changing meaning
+fn write_elem(node: &Node) -> String {
+    use Node::*;
+
+    match node {
+        Empty
+        | RuleRef { .. }
+        | TemplateRef { .. }
+        | TokenRef { .. }
+        | TokenLit { .. }
+        | CharClass { .. } => write_node(node),
+        _ => format!("({})", write_node(node)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_hrpg;
+
+    fn round_trip(data: &str) {
+        assert_eq!(write_grammar(&parse_hrpg(data).unwrap()), data);
+    }
+
+    #[test]
+    fn rules() {
+        round_trip(
+            "grammar A;\n\n\
+             options {\n    caseInsensitive = true;\n}\n\n\
+             ## A list\n\
+             list[int depth]: '[' [items += value (',' items += value)*] ']';\n\n\
+             value\n    : list @List\n    | NUM @Num\n    ;\n\n\
+             extern NUM;\n\n\
+             LBRACKET: '[';\n",
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        round_trip(
+            "a: IF;\n\n\
+             ## Keywords\n## @scope keyword.control\nIF: 'if';\n\n\
+             ## @skip\nWS: [ \\t]+;\n",
+        );
+    }
+}
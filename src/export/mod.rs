@@ -0,0 +1,273 @@
+pub mod antlr;
+pub mod ebnf;
+pub mod hrpg;
+pub mod pest;
+pub mod textmate;
+pub mod tree_sitter;
+
+use std::collections::HashSet;
+
+use crate::ast::{Grammar, Node};
+use crate::lexer_gen::{CASE_INSENSITIVE, SKIP_ANNOTATION};
+
+// A grammar written in another format, along with what that format couldn't express
+#[derive(Debug)]
+pub struct Export {
+    pub text: String,
+    pub warnings: Vec<String>,
+}
+
+// How tightly a piece of an exported expression binds, so it is only grouped when it has to be
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Prec {
+    Alternatives,
+    Sequence,
+    // Tagged or prefixed with '&' / '!' (pest)
+    Term,
+    Suffixed,
+    Primary,
+}
+
+// The state shared by the writers of every format
+pub struct Writer<'g> {
+    pub grammar: &'g Grammar,
+    // The `caseInsensitive` grammar option
+    pub case_insensitive: bool,
+    pub warnings: Vec<String>,
+}
+
+impl<'g> Writer<'g> {
+    pub fn new(grammar: &'g Grammar) -> Self {
+        Writer {
+            grammar,
+            case_insensitive: grammar.option(CASE_INSENSITIVE) == Some("true"),
+            warnings: vec![],
+        }
+    }
+
+    // Each distinct warning is only given once
+    pub fn warn(&mut self, message: impl Into<String>) {
+        let message = message.into();
+        if !self.warnings.contains(&message) {
+            self.warnings.push(message);
+        }
+    }
+
+    // The literal of a token rule that is a single literal, and whether it ignores case
+    pub fn token_literal(&self, name: &str) -> Option<(&'g str, bool)> {
+        let grammar = self.grammar;
+        let rule = grammar.token_rules.iter().find(|rule| rule.name == name)?;
+        match &rule.node {
+            Node::TokenLit {
+                literal,
+                case_insensitive,
+            } => Some((literal, *case_insensitive)),
+            _ => None,
+        }
+    }
+
+    pub fn finish(self, mut out: String) -> Export {
+        end_with_newline(&mut out);
+        Export {
+            text: out,
+            warnings: self.warnings,
+        }
+    }
+}
+
+// How a format writes each kind of node, given what the nodes inside of it were written as - `write_node` walks the
+// nodes, and writes `None` when nothing is written (ex: an action)
+pub trait Format<'g> {
+    // For warnings about what the format can't express (ex: "ANTLR")
+    const NAME: &'static str;
+    // Whether rule references can pass arguments (ex: `stmt[true]`)
+    const RULE_ARGS: bool = false;
+
+    fn writer(&mut self) -> &mut Writer<'g>;
+
+    fn binding(&mut self, name: &str, list: bool, node: &Node) -> Option<(String, Prec)>;
+
+    fn choice(&mut self, alts: Vec<Option<(String, Prec)>>) -> Option<(String, Prec)>;
+
+    fn sequence(&mut self, parts: Vec<(String, Prec)>) -> Option<(String, Prec)>;
+
+    // `*`, `+` or `?`
+    fn repeat(&mut self, item: (String, Prec), suffix: &str) -> (String, Prec) {
+        (
+            format!("{}{}", group(item, Prec::Primary), suffix),
+            Prec::Suffixed,
+        )
+    }
+
+    // `a % b` and `a %% b` (a separator that writes nothing leaves a repetition of the item)
+    fn separated(
+        &mut self,
+        item: (String, Prec),
+        separator: Option<(String, Prec)>,
+        trailing: bool,
+    ) -> (String, Prec);
+
+    // Labels of a rule's own alternatives are written with the rule
+    fn labeled(&mut self, _label: &str, node: &Node) -> Option<(String, Prec)> {
+        self.writer().warn(format!(
+            "Labels of nested alternatives have no {} equivalent and were dropped",
+            Self::NAME
+        ));
+        self.write_node(node)
+    }
+
+    fn lookahead(&mut self, _node: &Node, _negative: bool) -> Option<(String, Prec)> {
+        self.writer().warn(format!(
+            "Lookaheads have no {} equivalent and were dropped",
+            Self::NAME
+        ));
+        None
+    }
+
+    fn rule_ref(&mut self, name: &str, args: &[String]) -> (String, Prec);
+
+    fn token_ref(&mut self, name: &str) -> Option<(String, Prec)>;
+
+    // A literal of a parser rule, replaced by the token rule `name`
+    fn replaced_literal(&mut self, name: &str, literal: &str) -> Option<(String, Prec)> {
+        Some(token_rule_literal(self, name, literal))
+    }
+
+    fn literal(&mut self, literal: &str, case_insensitive: bool) -> (String, Prec);
+
+    fn char_class(&mut self, class: &str) -> (String, Prec);
+
+    fn write_node(&mut self, node: &Node) -> Option<(String, Prec)> {
+        use Node::*;
+
+        match node {
+            Binding { name, list, node } => self.binding(name, *list, node),
+            Alternatives { nodes } => {
+                let alts = nodes.iter().map(|node| self.write_node(node)).collect();
+                self.choice(alts)
+            }
+            Labeled { label, node } => self.labeled(label, node),
+            MultipartBody { nodes } => {
+                let parts = nodes
+                    .iter()
+                    .filter_map(|node| self.write_node(node))
+                    .collect();
+                self.sequence(parts)
+            }
+            ZeroOrMore { node } => self.write_repeat(node, "*"),
+            OneOrMore { node } => self.write_repeat(node, "+"),
+            ZeroOrOne { node, .. } => self.write_repeat(node, "?"),
+            SeparatedList {
+                node,
+                separator,
+                trailing,
+            } => {
+                let item = self.write_node(node)?;
+                let separator = self.write_node(separator);
+                Some(self.separated(item, separator, *trailing))
+            }
+            PositiveLookahead { node } => self.lookahead(node, false),
+            NegativeLookahead { node } => self.lookahead(node, true),
+            // Action code is written for HRPG's target languages
+            Action { .. } => {
+                self.writer().warn(format!(
+                    "Actions have no {} equivalent and were dropped",
+                    Self::NAME
+                ));
+                None
+            }
+            Empty => None,
+            RuleRef { name, args } => {
+                if !args.is_empty() && !Self::RULE_ARGS {
+                    self.writer().warn(format!(
+                        "Rule arguments have no {} equivalent and were dropped",
+                        Self::NAME
+                    ));
+                }
+                Some(self.rule_ref(name, args))
+            }
+            // Templates have been instantiated by the transform
+            TemplateRef { name, .. } => Some(self.rule_ref(name, &[])),
+            TokenRef {
+                name,
+                replaced_lit: Some(literal),
+            } => self.replaced_literal(name, literal),
+            TokenRef { name, .. } => self.token_ref(name),
+            TokenLit {
+                literal,
+                case_insensitive,
+            } => Some(self.literal(literal, *case_insensitive)),
+            CharClass { class } => Some(self.char_class(class)),
+        }
+    }
+
+    fn write_repeat(&mut self, node: &Node, suffix: &str) -> Option<(String, Prec)> {
+        let item = self.write_node(node)?;
+        Some(self.repeat(item, suffix))
+    }
+}
+
+// A literal is written as the literal of its token rule, which decides whether it ignores case
+pub fn token_rule_literal<'g, F: Format<'g> + ?Sized>(
+    format: &mut F,
+    name: &str,
+    literal: &str,
+) -> (String, Prec) {
+    match format.writer().token_literal(name) {
+        Some((literal, case_insensitive)) => format.literal(literal, case_insensitive),
+        None => format.literal(literal, false),
+    }
+}
+
+// Only a single newline at the end
+pub fn end_with_newline(out: &mut String) {
+    let len = out.trim_end().len();
+    out.truncate(len);
+    out.push('\n');
+}
+
+pub fn group((text, prec): (String, Prec), min: Prec) -> String {
+    match prec < min {
+        true => format!("({})", text),
+        false => text,
+    }
+}
+
+// The tokens parser rules refer to by name, and those they refer to by literal
+pub fn token_refs(grammar: &Grammar) -> (HashSet<String>, HashSet<String>) {
+    fn collect(node: &Node, by_name: &mut HashSet<String>, by_literal: &mut HashSet<String>) {
+        match node {
+            Node::TokenRef {
+                name,
+                replaced_lit: None,
+            } => {
+                by_name.insert(name.to_string());
+            }
+            Node::TokenRef { name, .. } => {
+                by_literal.insert(name.to_string());
+            }
+            node => {
+                for child in node.children() {
+                    collect(child, by_name, by_literal);
+                }
+            }
+        }
+    }
+
+    let mut by_name = HashSet::new();
+    let mut by_literal = HashSet::new();
+    for rule in &grammar.parser_rules {
+        collect(&rule.node, &mut by_name, &mut by_literal);
+    }
+    (by_name, by_literal)
+}
+
+// Token rules annotated `## @skip` (ex: whitespace and comments), which other formats have to be told about
+pub fn skip_tokens(grammar: &Grammar) -> HashSet<String> {
+    grammar
+        .token_rules
+        .iter()
+        .filter(|rule| rule.annotation(SKIP_ANNOTATION).is_some())
+        .map(|rule| rule.name.to_string())
+        .collect()
+}
@@ -0,0 +1,586 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use crate::ast::{Grammar, Node};
+use crate::escape::{parse_char_class, unescape_literal};
+use crate::export::{group, skip_tokens, token_refs, Export, Format, Prec, Writer};
+use crate::transform::EOF;
+
+// Pest rejects rules with these names, or gives them a meaning of their own
+const RESERVED: [&str; 11] = [
+    "ANY",
+    "DROP",
+    "EOI",
+    "PEEK",
+    "PEEK_ALL",
+    "POP",
+    "POP_ALL",
+    "PUSH",
+    "SOI",
+    "WHITESPACE",
+    "COMMENT",
+];
+
+const RUST_KEYWORDS: [&str; 51] = [
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+    "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+// Writes a transformed grammar for pest (https://pest.rs) - token rules become atomic rules, and `@skip` tokens become
+// the implicit WHITESPACE between the others
+pub fn write_pest(grammar: &Grammar) -> Export {
+    let (by_name, by_literal) = token_refs(grammar);
+    let skipped = skip_tokens(grammar);
+    // Skipped tokens are only written as a rule of their own when a parser rule refers to them
+    let unwritten: HashSet<String> = skipped.difference(&by_name).cloned().collect();
+
+    let mut writer = PestWriter {
+        writer: Writer::new(grammar),
+        names: HashMap::new(),
+        builtins: HashSet::new(),
+    };
+
+    // Builtins are found first, so rules can be renamed out of their way
+    for rule in &grammar.token_rules {
+        writer.token(&rule.node);
+    }
+    writer.rename_rules(&unwritten);
+
+    // HRPG rules only match all of the input when they end with `EOF`, the same as pest rules with `EOI`
+    let mut out = String::new();
+    for rule in &grammar.parser_rules {
+        write_doc(&mut out, &rule.doc);
+        let body = writer.write_rule(&rule.node);
+        writeln!(out, "{} = {{ {} }}\n", writer.name(&rule.name), body).unwrap();
+    }
+
+    let mut missing = grammar.extern_tokens.clone();
+    missing.extend(
+        by_name
+            .iter()
+            .chain(by_literal.iter())
+            .filter(|name| !grammar.token_rules.iter().any(|rule| &rule.name == *name))
+            .filter(|name| !grammar.extern_tokens.contains(name) && *name != EOF)
+            .cloned(),
+    );
+    if !missing.is_empty() {
+        missing.sort();
+        writer.writer.warn("Extern tokens and tokens without a rule have no pest equivalent - they are only listed in a comment");
+        let names: Vec<String> = missing.iter().map(|name| writer.name(name)).collect();
+        writeln!(out, "// Not defined here: {}\n", names.join(", ")).unwrap();
+    }
+
+    let mut whitespace = vec![];
+    for rule in &grammar.token_rules {
+        // Literal tokens are written as their literal wherever parser rules use them
+        if by_literal.contains(&rule.name) && !by_name.contains(&rule.name) {
+            continue;
+        }
+        if skipped.contains(&rule.name) {
+            whitespace.push(rule);
+            if unwritten.contains(&rule.name) {
+                continue;
+            }
+        }
+        write_doc(&mut out, &rule.doc);
+        let body = writer.token(&rule.node).0;
+        writeln!(out, "{} = @{{ {} }}\n", writer.name(&rule.name), body).unwrap();
+    }
+
+    // Skipped tokens only show up in the grammar as WHITESPACE, which pest keeps atomic
+    if !whitespace.is_empty() {
+        let alts: Vec<String> = whitespace
+            .iter()
+            .map(|rule| {
+                let alt = writer.token(&rule.node);
+                format!("{} // {}", group(alt, Prec::Sequence), rule.name)
+            })
+            .collect();
+        writeln!(out, "WHITESPACE = _{{\n    {}\n}}", alts.join("\n  | ")).unwrap();
+    }
+
+    writer.writer.finish(out)
+}
+
+fn write_doc(out: &mut String, doc: &[String]) {
+    for line in doc {
+        writeln!(out, "/// {}", line).unwrap();
+    }
+}
+
+struct PestWriter<'g> {
+    writer: Writer<'g>,
+    // Rules renamed because pest or Rust claims their name
+    names: HashMap<String, String>,
+    // Pest's builtin rules used for character classes (ex: `LETTER`)
+    builtins: HashSet<String>,
+}
+
+impl<'g> PestWriter<'g> {
+    fn rename_rules(&mut self, unwritten: &HashSet<String>) {
+        let grammar = self.writer.grammar;
+        let names = grammar
+            .parser_rules
+            .iter()
+            .map(|rule| &rule.name)
+            .chain(grammar.token_rules.iter().map(|rule| &rule.name))
+            .chain(grammar.extern_tokens.iter())
+            .filter(|name| !unwritten.contains(*name));
+
+        for name in names {
+            let clashes = |name: &str| {
+                RESERVED.contains(&name)
+                    || RUST_KEYWORDS.contains(&name)
+                    || self.builtins.contains(name)
+            };
+            if clashes(name) {
+                let mut renamed = format!("{}_", name);
+                while clashes(&renamed) {
+                    renamed.push('_');
+                }
+                self.writer.warnings.push(format!(
+                    "'{}' clashes with a pest builtin or Rust keyword and was renamed '{}'",
+                    name, renamed
+                ));
+                self.names.insert(name.to_string(), renamed);
+            }
+        }
+    }
+
+    fn name(&self, name: &str) -> String {
+        self.names
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    // An empty alternative is written as an empty string, which always matches
+    fn write_rule(&mut self, node: &Node) -> String {
+        match node {
+            Node::Alternatives { nodes } => {
+                let alts: Vec<String> = nodes
+                    .iter()
+                    .map(|node| {
+                        let node = match node {
+                            Node::Labeled { node, .. } => {
+                                self.writer
+                                    .warn("Labels have no pest equivalent and were dropped");
+                                node
+                            }
+                            node => node,
+                        };
+                        self.write_node(node)
+                            .map(|alt| group(alt, Prec::Sequence))
+                            .unwrap_or_else(|| "\"\"".to_string())
+                    })
+                    .collect();
+                alts.join(" | ")
+            }
+            node => self
+                .write_node(node)
+                .map(|(text, _)| text)
+                .unwrap_or_else(|| "\"\"".to_string()),
+        }
+    }
+
+    // Token rules are parsed the same way, pest rules being scannerless
+    fn token(&mut self, node: &Node) -> (String, Prec) {
+        self.write_node(node)
+            .unwrap_or_else(|| ("\"\"".to_string(), Prec::Primary))
+    }
+
+    // `#name = item`
+    fn tagged(&mut self, name: &str, node: &Node) -> Option<(String, Prec)> {
+        let item = self.write_node(node)?;
+        Some((
+            format!("#{} = {}", name, group(item, Prec::Suffixed)),
+            Prec::Term,
+        ))
+    }
+}
+
+impl<'g> Format<'g> for PestWriter<'g> {
+    const NAME: &'static str = "pest";
+
+    fn writer(&mut self) -> &mut Writer<'g> {
+        &mut self.writer
+    }
+
+    // Tags name a node of the parse tree - a list binding tags each of its items
+    fn binding(&mut self, name: &str, list: bool, node: &Node) -> Option<(String, Prec)> {
+        use Node::*;
+
+        self.writer.warn(
+            "Bindings were written as tags, which need pest_derive's 'grammar-extras' feature",
+        );
+        match (list, node) {
+            (true, ZeroOrMore { node }) => {
+                let item = self.tagged(name, node)?;
+                Some(self.repeat(item, "*"))
+            }
+            (true, OneOrMore { node }) => {
+                let item = self.tagged(name, node)?;
+                Some(self.repeat(item, "+"))
+            }
+            (true, ZeroOrOne { node, .. }) => {
+                let item = self.tagged(name, node)?;
+                Some(self.repeat(item, "?"))
+            }
+            (
+                true,
+                SeparatedList {
+                    node,
+                    separator,
+                    trailing,
+                },
+            ) => {
+                let item = self.tagged(name, node)?;
+                let separator = self.write_node(separator);
+                Some(self.separated(item, separator, *trailing))
+            }
+            (_, node) => self.tagged(name, node),
+        }
+    }
+
+    // An empty alternative is written as an empty string, which always matches
+    fn choice(&mut self, alts: Vec<Option<(String, Prec)>>) -> Option<(String, Prec)> {
+        let alts: Vec<String> = alts
+            .into_iter()
+            .map(|alt| {
+                alt.map(|alt| group(alt, Prec::Sequence))
+                    .unwrap_or_else(|| "\"\"".to_string())
+            })
+            .collect();
+        Some((alts.join(" | "), Prec::Alternatives))
+    }
+
+    fn labeled(&mut self, _label: &str, node: &Node) -> Option<(String, Prec)> {
+        self.writer
+            .warn("Labels have no pest equivalent and were dropped");
+        self.write_node(node)
+    }
+
+    fn sequence(&mut self, parts: Vec<(String, Prec)>) -> Option<(String, Prec)> {
+        let mut parts: Vec<String> = parts
+            .into_iter()
+            .map(|part| group(part, Prec::Term))
+            .collect();
+        match parts.len() {
+            0 => None,
+            1 => Some((parts.remove(0), Prec::Term)),
+            _ => Some((parts.join(" ~ "), Prec::Sequence)),
+        }
+    }
+
+    // `a % b` -> `a ~ (b ~ a)*`, `a %% b` -> `a ~ (b ~ a)* ~ b?`
+    fn separated(
+        &mut self,
+        item: (String, Prec),
+        separator: Option<(String, Prec)>,
+        trailing: bool,
+    ) -> (String, Prec) {
+        let sep = match separator {
+            Some(sep) => sep,
+            None => return self.repeat(item, "+"),
+        };
+
+        let item = group(item, Prec::Term);
+        let mut text = format!(
+            "{} ~ ({} ~ {})*",
+            item,
+            group(sep.clone(), Prec::Term),
+            item
+        );
+        if trailing {
+            write!(text, " ~ {}", self.repeat(sep, "?").0).unwrap();
+        }
+        (text, Prec::Sequence)
+    }
+
+    fn lookahead(&mut self, node: &Node, negative: bool) -> Option<(String, Prec)> {
+        let prefix = if negative { "!" } else { "&" };
+        let inner = self.write_node(node)?;
+        Some((
+            format!("{}{}", prefix, group(inner, Prec::Suffixed)),
+            Prec::Term,
+        ))
+    }
+
+    fn rule_ref(&mut self, name: &str, _args: &[String]) -> (String, Prec) {
+        (self.name(name), Prec::Primary)
+    }
+
+    fn token_ref(&mut self, name: &str) -> Option<(String, Prec)> {
+        match name {
+            EOF => Some(("EOI".to_string(), Prec::Primary)),
+            name => Some((self.name(name), Prec::Primary)),
+        }
+    }
+
+    // `"select"`, or `^"select"` when ignoring case
+    fn literal(&mut self, literal: &str, case_insensitive: bool) -> (String, Prec) {
+        let value = match unescape_literal(literal) {
+            Ok(value) => value,
+            Err(_) => return (literal.to_string(), Prec::Primary),
+        };
+        let text = pest_string(&value, '"');
+        match case_insensitive || self.writer.case_insensitive {
+            true => (format!("^{}", text), Prec::Primary),
+            false => (text, Prec::Primary),
+        }
+    }
+
+    // Classes become choices of ranges and builtins (ex: `[\p{L}_0-9]` -> `LETTER | "_" | '0'..'9'`), and a negated
+    // class matches any character the choice doesn't
+    fn char_class(&mut self, class: &str) -> (String, Prec) {
+        let (negated, body) = match class.strip_prefix('[') {
+            Some(body) => {
+                let body = body.strip_suffix(']').unwrap_or(body);
+                match body.strip_prefix('^') {
+                    Some(body) => (true, body),
+                    None => (false, body),
+                }
+            }
+            None => (false, class),
+        };
+
+        // Properties are taken out of the class, so what's left can be parsed for its ranges
+        let mut alts = vec![];
+        let mut rest = String::new();
+        let mut chars = body.chars();
+        while let Some(ch) = chars.next() {
+            match (ch, chars.clone().next()) {
+                ('\\', Some(kind @ ('p' | 'P'))) => {
+                    let text = chars.as_str();
+                    let end = text.find('}').map(|end| end + 1).unwrap_or(text.len());
+                    let name = text[1..end].trim_start_matches('{').trim_end_matches('}');
+                    chars = text[end..].chars();
+
+                    let builtin = pest_property(name);
+                    self.builtins.insert(builtin.to_string());
+                    alts.push(match kind {
+                        'p' => builtin,
+                        _ => format!("!{} ~ ANY", builtin),
+                    });
+                }
+                ('\\', Some(_)) => {
+                    rest.push(ch);
+                    rest.push(chars.next().unwrap());
+                }
+                (ch, _) => rest.push(ch),
+            }
+        }
+
+        if !rest.is_empty() {
+            match parse_char_class(&format!("[{}]", rest)) {
+                Ok(parsed) => {
+                    alts.extend(
+                        parsed
+                            .ranges
+                            .iter()
+                            .map(|&(start, end)| match start == end {
+                                true => pest_string(&start.to_string(), '"'),
+                                false => format!(
+                                    "{}..{}",
+                                    pest_string(&start.to_string(), '\''),
+                                    pest_string(&end.to_string(), '\'')
+                                ),
+                            }),
+                    )
+                }
+                Err(_) => alts.push(rest),
+            }
+        }
+
+        let choice = match alts.len() {
+            1 if !alts[0].contains(' ') => (alts.remove(0), Prec::Primary),
+            1 => (alts.remove(0), Prec::Sequence),
+            _ => (alts.join(" | "), Prec::Alternatives),
+        };
+        match negated {
+            true => (
+                format!("!{} ~ ANY", group(choice, Prec::Suffixed)),
+                Prec::Sequence,
+            ),
+            false => choice,
+        }
+    }
+}
+
+// A pest string (`"..."`) or character (`'.'`) - invisible and non-alphanumeric Unicode characters are escaped
+fn pest_string(value: &str, quote: char) -> String {
+    let mut text = String::new();
+    text.push(quote);
+    for ch in value.chars() {
+        match ch {
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            '\0' => text.push_str("\\0"),
+            ch if ch == quote => {
+                text.push('\\');
+                text.push(ch);
+            }
+            ch if ch.is_ascii_graphic() || ch == ' ' || ch.is_alphanumeric() => text.push(ch),
+            ch => write!(text, "\\u{{{:X}}}", ch as u32).unwrap(),
+        }
+    }
+    text.push(quote);
+    text
+}
+
+// Pest names Unicode properties in uppercase, and general categories by their long names (ex: `Lu` ->
+// `UPPERCASE_LETTER`, `XID_Start` -> `XID_START`)
+fn pest_property(name: &str) -> String {
+    let name = match name.split_once('=') {
+        Some((_, value)) => value,
+        None => name,
+    };
+    let category = match name {
+        "L" => "LETTER",
+        "LC" => "CASED_LETTER",
+        "Lu" => "UPPERCASE_LETTER",
+        "Ll" => "LOWERCASE_LETTER",
+        "Lt" => "TITLECASE_LETTER",
+        "Lm" => "MODIFIER_LETTER",
+        "Lo" => "OTHER_LETTER",
+        "M" => "MARK",
+        "Mn" => "NONSPACING_MARK",
+        "Mc" => "SPACING_MARK",
+        "Me" => "ENCLOSING_MARK",
+        "N" => "NUMBER",
+        "Nd" => "DECIMAL_NUMBER",
+        "Nl" => "LETTER_NUMBER",
+        "No" => "OTHER_NUMBER",
+        "P" => "PUNCTUATION",
+        "Pc" => "CONNECTOR_PUNCTUATION",
+        "Pd" => "DASH_PUNCTUATION",
+        "Ps" => "OPEN_PUNCTUATION",
+        "Pe" => "CLOSE_PUNCTUATION",
+        "Pi" => "INITIAL_PUNCTUATION",
+        "Pf" => "FINAL_PUNCTUATION",
+        "Po" => "OTHER_PUNCTUATION",
+        "S" => "SYMBOL",
+        "Sm" => "MATH_SYMBOL",
+        "Sc" => "CURRENCY_SYMBOL",
+        "Sk" => "MODIFIER_SYMBOL",
+        "So" => "OTHER_SYMBOL",
+        "Z" => "SEPARATOR",
+        "Zs" => "SPACE_SEPARATOR",
+        "Zl" => "LINE_SEPARATOR",
+        "Zp" => "PARAGRAPH_SEPARATOR",
+        "C" => "OTHER",
+        "Cc" => "CONTROL",
+        "Cf" => "FORMAT",
+        "Cs" => "SURROGATE",
+        "Co" => "PRIVATE_USE",
+        "Cn" => "UNASSIGNED",
+        name => return name.replace(['-', ' '], "_").to_uppercase(),
+    };
+    category.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use pest::error::ErrorVariant;
+
+    use super::*;
+    use crate::ast::parse_hrpg;
+    use crate::export::hrpg::write_grammar;
+    use crate::import::pest::import_pest;
+    use crate::transform::Transform;
+
+    const LIST: &str = "list: '[' [NUM (',' NUM)*] ']';\n\
+                        LBRACKET: '[';\nCOMMA: ',';\nRBRACKET: ']';\nNUM: [0-9]+;\n\
+                        ## @skip\nWHITESPACE: [ \\t\\r\\n]+;\n";
+
+    // Checked the way pest_derive checks a grammar
+    fn errors(text: &str) -> Vec<String> {
+        let pairs = pest_meta::parser::parse(pest_meta::parser::Rule::grammar_rules, text)
+            .unwrap_or_else(|err| panic!("{}\n{}", err, text));
+        match pest_meta::validator::validate_pairs(pairs) {
+            Ok(_) => vec![],
+            Err(errors) => errors
+                .into_iter()
+                .map(|err| match err.variant {
+                    ErrorVariant::CustomError { message } => message,
+                    variant => format!("{:?}", variant),
+                })
+                .collect(),
+        }
+    }
+
+    fn export(data: &str) -> Export {
+        let (g2, transform) = Transform::process_with_vocab(&parse_hrpg(data).unwrap(), None);
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+        write_pest(&g2)
+    }
+
+    #[test]
+    fn small_grammar() {
+        let export = export(LIST);
+        assert_eq!(
+            export.text,
+            "list = { \"[\" ~ (NUM ~ (\",\" ~ NUM)*)? ~ \"]\" }\n\n\
+             NUM = @{ '0'..'9'+ }\n\n\
+             WHITESPACE = _{\n    ('\\t'..'\\n' | \"\\r\" | \" \")+ // WHITESPACE\n}\n"
+        );
+        assert!(export.warnings.is_empty(), "{:?}", export.warnings);
+    }
+
+    #[test]
+    fn no_skip_tokens() {
+        let export = export("a: X Y;\nX: 'x';\nY: 'y';\n");
+        assert!(!export.text.contains("WHITESPACE"));
+    }
+
+    #[test]
+    fn referenced_skip_token() {
+        let export = export("a: X WS X;\nX: 'x';\n## @skip\nWS: ' '+;\n");
+        assert!(export.text.contains("WS = @{ \" \"+ }"));
+        assert!(export
+            .text
+            .ends_with("WHITESPACE = _{\n    \" \"+ // WS\n}\n"));
+    }
+
+    // Pest's `EOI` is HRPG's `EOF`
+    #[test]
+    fn eof_is_eoi() {
+        let export = export("file: stmt* EOF;\nstmt: X;\nX: 'x';\n");
+        assert!(export.text.starts_with("file = { stmt* ~ EOI }\n"));
+        assert!(!export.text.contains("Not defined here"));
+        assert_eq!(errors(&export.text), Vec::<String>::new());
+    }
+
+    // Only the extern tokens are left undefined
+    #[test]
+    fn hrpg_grammar() {
+        let export = export(include_str!("../../examples/hrpg/hrpg.hrpg"));
+        let mut errors = errors(&export.text);
+        errors.dedup();
+        assert_eq!(
+            errors,
+            [
+                "rule NL is undefined",
+                "rule ARG is undefined",
+                "rule NL is undefined",
+                "rule ACTION_CODE is undefined",
+                "rule NL is undefined"
+            ]
+        );
+    }
+
+    // Classes come back as pest ranges, so the second export is the one that has to match
+    #[test]
+    fn round_trip() {
+        let text = export(LIST).text;
+        let import = import_pest(&text).unwrap();
+        let hrpg = write_grammar(&import.grammar);
+        assert!(hrpg.ends_with("## @skip\nWHITESPACE: ([\\t-\\n] | '\\r' | ' ')+;\n"));
+        assert_eq!(export(&hrpg).text, text);
+    }
+}
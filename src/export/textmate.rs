@@ -0,0 +1,484 @@
+use std::fmt::Write;
+
+use crate::ast::{Grammar, Node, TokenRule};
+use crate::escape::{parse_char_class, unescape_literal};
+use crate::export::{skip_tokens, Export, Format, Prec, Writer};
+
+// Doc comment annotation that sets a token's scope (ex: `## @scope support.function`)
+pub const SCOPE_ANNOTATION: &str = "scope";
+
+// Literals that name a value rather than being a keyword
+const CONSTANTS: [&str; 9] = [
+    "true",
+    "false",
+    "null",
+    "nil",
+    "none",
+    "undefined",
+    "True",
+    "False",
+    "None",
+];
+
+// Writes the token rules of a grammar as a TextMate grammar (.tmLanguage.json) - scopes are guessed from the
+// tokens (keywords, strings, numbers, comments and punctuation), unless annotated
+pub fn write_textmate(grammar: &Grammar, name: &str) -> Export {
+    let mut writer = TextMateWriter {
+        writer: Writer::new(grammar),
+        suffix: name.to_lowercase(),
+    };
+
+    if grammar.token_rules.is_empty() {
+        writer
+            .writer
+            .warn("The grammar has no token rules to highlight - export its lexer grammar instead");
+    }
+    if !grammar.extern_tokens.is_empty() {
+        writer
+            .writer
+            .warn("Extern tokens have no TextMate equivalent and are not highlighted");
+    }
+
+    // Unscoped tokens are still matched, so keywords and numbers aren't found inside of them (ex: `x1`), except for
+    // skipped ones (ex: whitespace)
+    let skipped = skip_tokens(grammar);
+    let mut patterns: Vec<Pattern> = grammar
+        .token_rules
+        .iter()
+        .map(|rule| writer.pattern(rule))
+        .filter(|pattern| pattern.scope.is_some() || !skipped.contains(&pattern.name))
+        .collect();
+
+    // TextMate takes the earliest match and HRPG the longest - comments and strings go first so nothing is found
+    // inside of them, then literals from longest to shortest (ex: `<=` before `<`)
+    patterns.sort_by_key(|pattern| pattern.order);
+
+    let mut out = String::new();
+    out.push_str("{\n");
+    out.push_str("  \"$schema\": \"https://raw.githubusercontent.com/martinring/tmlanguage/master/tmlanguage.json\",\n");
+    writeln!(out, "  \"name\": {},", json_string(name)).unwrap();
+    writeln!(
+        out,
+        "  \"scopeName\": {},",
+        json_string(&format!("source.{}", writer.suffix))
+    )
+    .unwrap();
+
+    let includes: Vec<String> = patterns
+        .iter()
+        .map(|pattern| {
+            format!(
+                "    {{ \"include\": {} }}",
+                json_string(&format!("#{}", pattern.name))
+            )
+        })
+        .collect();
+    writeln!(out, "  \"patterns\": [\n{}\n  ],", includes.join(",\n")).unwrap();
+
+    let entries: Vec<String> = patterns
+        .iter()
+        .map(|pattern| {
+            let mut fields = vec![];
+            if let Some(scope) = &pattern.scope {
+                fields.push(format!("      \"name\": {}", json_string(scope)));
+            }
+            match &pattern.end {
+                Some(end) => {
+                    fields.push(format!("      \"begin\": {}", json_string(&pattern.regex)));
+                    fields.push(format!("      \"end\": {}", json_string(end)));
+                }
+                None => fields.push(format!("      \"match\": {}", json_string(&pattern.regex))),
+            }
+            format!(
+                "    {}: {{\n{}\n    }}",
+                json_string(&pattern.name),
+                fields.join(",\n")
+            )
+        })
+        .collect();
+    writeln!(out, "  \"repository\": {{\n{}\n  }}", entries.join(",\n")).unwrap();
+    out.push_str("}\n");
+
+    writer.writer.finish(out)
+}
+
+struct Pattern {
+    name: String,
+    scope: Option<String>,
+    // Where the pattern goes in the list of patterns
+    order: (u8, usize),
+    // The whole token, or where a token that can span lines begins
+    regex: String,
+    end: Option<String>,
+}
+
+struct TextMateWriter<'g> {
+    writer: Writer<'g>,
+    // Scopes end with the language's name (ex: `keyword.other.json`)
+    suffix: String,
+}
+
+impl<'g> TextMateWriter<'g> {
+    fn pattern(&mut self, rule: &TokenRule) -> Pattern {
+        let scope = self.scope(rule);
+        let mut regex = self.regex(&rule.node).0;
+        let mut end = None;
+        let mut order = (2, 0);
+
+        // Keywords only match whole words (ex: `in` doesn't match the start of `index`)
+        if let Node::TokenLit { literal, .. } = &rule.node {
+            let value = unescape_literal(literal).unwrap_or_default();
+            let word = |ch: Option<char>| ch.is_some_and(|ch| ch.is_alphanumeric() || ch == '_');
+            if word(value.chars().next()) {
+                regex = format!("\\b{}", regex);
+            }
+            if word(value.chars().last()) {
+                regex = format!("{}\\b", regex);
+            }
+            order = (1, usize::MAX - value.chars().count());
+        }
+
+        let category = scope.as_deref().and_then(|scope| scope.split('.').next());
+        if let Some("comment" | "string") = category {
+            order = (0, 0);
+        }
+
+        // Block comments can span lines, which a single match can't
+        if let (Some("comment"), Some((begin, close))) = (category, delimiters(&rule.node)) {
+            regex = self.regex(begin).0;
+            end = Some(self.regex(close).0);
+        }
+
+        if self.writer.case_insensitive {
+            regex = format!("(?i){}", regex);
+            end = end.map(|end| format!("(?i){}", end));
+        }
+
+        Pattern {
+            name: rule.name.to_string(),
+            scope,
+            order,
+            regex,
+            end,
+        }
+    }
+
+    // An annotation, or a guess from the token's name and what it matches
+    fn scope(&self, rule: &TokenRule) -> Option<String> {
+        if let Some(scope) = rule.annotation(SCOPE_ANNOTATION) {
+            return Some(scope.to_string());
+        }
+
+        let words: Vec<&str> = rule.name.split('_').collect();
+        let has_word = |options: &[&str]| words.iter().any(|word| options.contains(word));
+        // A literal starting with a quote isn't a string (ex: `'"'`)
+        let first = match &rule.node {
+            Node::TokenLit { .. } => vec![],
+            node => first_chars(node).unwrap_or_default(),
+        };
+
+        let scope = if has_word(&["COMMENT"]) && delimiters(&rule.node).is_some() {
+            "comment.block".to_string()
+        } else if has_word(&["COMMENT"]) {
+            "comment.line".to_string()
+        } else if first == ['"'] {
+            "string.quoted.double".to_string()
+        } else if first == ['\''] {
+            "string.quoted.single".to_string()
+        } else if has_word(&["STRING", "STR"]) {
+            "string.quoted.other".to_string()
+        } else if has_word(&["NUMBER", "NUM", "INT", "INTEGER", "FLOAT", "DECIMAL", "HEX"])
+            || (!first.is_empty() && first.iter().all(|ch| ch.is_ascii_digit()))
+        {
+            "constant.numeric".to_string()
+        } else {
+            let literal = match &rule.node {
+                Node::TokenLit { literal, .. } => unescape_literal(literal).ok()?,
+                _ => return None,
+            };
+            literal_scope(&literal)?.to_string()
+        };
+        Some(format!("{}.{}", scope, self.suffix))
+    }
+
+    fn regex(&mut self, node: &Node) -> (String, Prec) {
+        self.write_node(node)
+            .unwrap_or_else(|| ("(?:)".to_string(), Prec::Primary))
+    }
+
+    // Only found in parser rules
+    fn parser_only(&mut self) -> (String, Prec) {
+        self.writer
+            .warn("Token rules can only contain literals, character classes and repetitions");
+        ("(?:)".to_string(), Prec::Primary)
+    }
+}
+
+impl<'g> Format<'g> for TextMateWriter<'g> {
+    const NAME: &'static str = "TextMate";
+
+    fn writer(&mut self) -> &mut Writer<'g> {
+        &mut self.writer
+    }
+
+    fn binding(&mut self, _name: &str, _list: bool, _node: &Node) -> Option<(String, Prec)> {
+        Some(self.parser_only())
+    }
+
+    fn choice(&mut self, alts: Vec<Option<(String, Prec)>>) -> Option<(String, Prec)> {
+        let alts: Vec<String> = alts
+            .into_iter()
+            .map(|alt| alt.map_or_else(|| "(?:)".to_string(), |(text, _)| text))
+            .collect();
+        Some((alts.join("|"), Prec::Alternatives))
+    }
+
+    fn labeled(&mut self, _label: &str, _node: &Node) -> Option<(String, Prec)> {
+        Some(self.parser_only())
+    }
+
+    fn sequence(&mut self, parts: Vec<(String, Prec)>) -> Option<(String, Prec)> {
+        let parts: Vec<String> = parts
+            .into_iter()
+            .map(|part| group(part, Prec::Sequence))
+            .collect();
+        Some((parts.concat(), Prec::Sequence))
+    }
+
+    fn repeat(&mut self, item: (String, Prec), suffix: &str) -> (String, Prec) {
+        (
+            format!("{}{}", group(item, Prec::Primary), suffix),
+            Prec::Suffixed,
+        )
+    }
+
+    fn separated(
+        &mut self,
+        _item: (String, Prec),
+        _separator: Option<(String, Prec)>,
+        _trailing: bool,
+    ) -> (String, Prec) {
+        self.parser_only()
+    }
+
+    fn lookahead(&mut self, _node: &Node, _negative: bool) -> Option<(String, Prec)> {
+        Some(self.parser_only())
+    }
+
+    fn rule_ref(&mut self, _name: &str, _args: &[String]) -> (String, Prec) {
+        self.parser_only()
+    }
+
+    fn token_ref(&mut self, _name: &str) -> Option<(String, Prec)> {
+        Some(self.parser_only())
+    }
+
+    fn literal(&mut self, literal: &str, case_insensitive: bool) -> (String, Prec) {
+        let value = unescape_literal(literal).unwrap_or_default();
+        let escaped: String = value.chars().map(regex_char).collect();
+
+        // Case is ignored by the whole grammar, or by single literals (ex: `'select'i`)
+        match (
+            case_insensitive && !self.writer.case_insensitive,
+            value.chars().count(),
+        ) {
+            (true, _) => (format!("(?i:{})", escaped), Prec::Primary),
+            (false, 1) => (escaped, Prec::Primary),
+            (false, _) => (escaped, Prec::Sequence),
+        }
+    }
+
+    fn char_class(&mut self, class: &str) -> (String, Prec) {
+        (regex_class(class), Prec::Primary)
+    }
+}
+
+// Keywords, language constants and punctuation (ex: `if`, `true`, `,`, `(`, `+=`)
+fn literal_scope(literal: &str) -> Option<&'static str> {
+    let mut chars = literal.chars();
+    let first = chars.next()?;
+
+    if first.is_alphabetic() && literal.chars().all(|ch| ch.is_alphanumeric() || ch == '_') {
+        return Some(match CONSTANTS.contains(&literal) {
+            true => "constant.language",
+            false => "keyword.other",
+        });
+    }
+    if !literal.chars().all(|ch| ch.is_ascii_punctuation()) {
+        return None;
+    }
+    Some(match literal {
+        "," | ";" => "punctuation.separator",
+        "(" | ")" | "[" | "]" | "{" | "}" => "punctuation.section",
+        "." => "punctuation.accessor",
+        _ => "keyword.operator",
+    })
+}
+
+// The characters a token can start with, when they are known
+fn first_chars(node: &Node) -> Option<Vec<char>> {
+    match node {
+        Node::TokenLit { literal, .. } => {
+            let value = unescape_literal(literal).ok()?;
+            value.chars().next().map(|ch| vec![ch])
+        }
+        Node::CharClass { class } => {
+            let parsed = parse_char_class(class).ok()?;
+            let count: u32 = parsed
+                .ranges
+                .iter()
+                .map(|&(start, end)| end as u32 - start as u32 + 1)
+                .sum();
+            if parsed.negated || count > 16 {
+                return None;
+            }
+            Some(
+                parsed
+                    .ranges
+                    .iter()
+                    .flat_map(|&(start, end)| start..=end)
+                    .collect(),
+            )
+        }
+        Node::Alternatives { nodes } => {
+            let mut chars = vec![];
+            for node in nodes {
+                for ch in first_chars(node)? {
+                    if !chars.contains(&ch) {
+                        chars.push(ch);
+                    }
+                }
+            }
+            Some(chars)
+        }
+        Node::MultipartBody { nodes } => first_chars(nodes.first()?),
+        Node::OneOrMore { node } => first_chars(node),
+        _ => None,
+    }
+}
+
+// The literals a token that can span lines starts and ends with (ex: `'/*' ... '*/'`, but not `'#' ... '\n'`)
+fn delimiters(node: &Node) -> Option<(&Node, &Node)> {
+    let nodes = match node {
+        Node::MultipartBody { nodes } if nodes.len() >= 3 => nodes,
+        _ => return None,
+    };
+    match (nodes.first()?, nodes.last()?) {
+        (begin @ Node::TokenLit { .. }, end @ Node::TokenLit { literal, .. }) => {
+            match unescape_literal(literal).ok()?.as_str() {
+                "\n" | "\r\n" => None,
+                _ => Some((begin, end)),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn group((text, prec): (String, Prec), min: Prec) -> String {
+    match prec < min {
+        true => format!("(?:{})", text),
+        false => text,
+    }
+}
+
+fn regex_char(ch: char) -> String {
+    match ch {
+        '\\' | '^' | '$' | '.' | '|' | '?' | '*' | '+' | '(' | ')' | '[' | ']' | '{' | '}' => {
+            format!("\\{}", ch)
+        }
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        ch if ch.is_control() => format!("\\x{{{:x}}}", ch as u32),
+        ch => ch.to_string(),
+    }
+}
+
+// HRPG classes are Oniguruma classes, except for escaped quotes and Unicode escapes (`\u{7FFF}` -> `\x{7FFF}`)
+fn regex_class(class: &str) -> String {
+    let mut out = String::new();
+    let mut chars = class.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some(ch @ ('\'' | '"')) => out.push(ch),
+                Some('0') => out.push_str("\\x{0}"),
+                Some('u') => out.push_str("\\x"),
+                Some(ch) => write!(out, "\\{}", ch).unwrap(),
+                None => out.push('\\'),
+            },
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+fn json_string(value: &str) -> String {
+    let mut text = String::from("\"");
+    for ch in value.chars() {
+        match ch {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            ch if ch.is_control() => write!(text, "\\u{:04x}", ch as u32).unwrap(),
+            ch => text.push(ch),
+        }
+    }
+    text.push('"');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_hrpg;
+    use crate::transform::Transform;
+
+    fn export(data: &str) -> Export {
+        let (g2, transform) = Transform::process_with_vocab(&parse_hrpg(data).unwrap(), None);
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+        write_textmate(&g2, "Calc")
+    }
+
+    #[test]
+    fn small_grammar() {
+        let export = export(
+            "a: IF NUM;\n\
+             IF: 'if';\n\
+             NUM: [0-9]+;\n\
+             ## Builtin functions\n\
+             ## @scope support.function\n\
+             PRINT: 'print';\n\
+             ## @skip\n\
+             WS: [ \\t]+;\n",
+        );
+        assert!(export.text.contains(
+            "  \"patterns\": [\n    { \"include\": \"#PRINT\" },\n    { \"include\": \"#IF\" },\n    { \"include\": \"#NUM\" }\n  ],"
+        ));
+        assert!(export
+            .text
+            .contains("\"PRINT\": {\n      \"name\": \"support.function\",\n      \"match\": \"\\\\bprint\\\\b\"\n    }"));
+        assert!(export.text.contains("\"name\": \"keyword.other.calc\""));
+        assert!(export.text.contains("\"name\": \"constant.numeric.calc\""));
+        // Skipped and not scoped
+        assert!(!export.text.contains("WS"));
+        assert!(export.warnings.is_empty(), "{:?}", export.warnings);
+    }
+
+    #[test]
+    fn comments_and_strings() {
+        let export = export(
+            "a: STRING;\n\
+             STRING: '\"' [^\"]* '\"';\n\
+             ## @skip\n\
+             LINE_COMMENT: '//' [^\\n]*;\n",
+        );
+        assert!(export
+            .text
+            .contains("\"name\": \"string.quoted.double.calc\""));
+        assert!(export.text.contains("\"name\": \"comment.line.calc\""));
+    }
+}
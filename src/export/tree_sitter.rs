@@ -0,0 +1,495 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::ast::{Grammar, Node};
+use crate::escape::unescape_literal;
+use crate::export::{
+    group, skip_tokens, token_refs, token_rule_literal, Export, Format, Prec, Writer,
+};
+use crate::transform::{Transform, EOF};
+
+// Writes a transformed grammar as a tree-sitter `grammar.js` - the transform's FIRST sets show where PEG's ordered
+// choice won't carry over to tree-sitter's LR parsing
+pub fn write_tree_sitter(grammar: &Grammar, transform: &Transform, name: &str) -> Export {
+    let (by_name, by_literal) = token_refs(grammar);
+    let mut writer = TreeSitterWriter {
+        writer: Writer::new(grammar),
+        transform,
+        // Tokens only used by their literal are written as that literal
+        literal_only: grammar
+            .token_rules
+            .iter()
+            .filter(|rule| by_literal.contains(&rule.name) && !by_name.contains(&rule.name))
+            .map(|rule| rule.name.to_string())
+            .collect(),
+    };
+    let mut out = String::new();
+
+    writeln!(out, "module.exports = grammar({{\n  name: '{}',\n", name).unwrap();
+
+    // Tree-sitter skips whitespace unless told otherwise, while HRPG only skips `@skip` tokens
+    let skipped = skip_tokens(grammar);
+    let extras: Vec<String> = grammar
+        .token_rules
+        .iter()
+        .filter(|rule| skipped.contains(&rule.name))
+        .map(|rule| format!("$.{}", rule.name))
+        .collect();
+    writeln!(out, "  extras: $ => [{}],\n", extras.join(", ")).unwrap();
+
+    let externals = writer.externals();
+    if !externals.is_empty() {
+        writer.writer.warn(
+            "Extern tokens and tokens without a rule were declared as externals, which need an external scanner (src/scanner.c)",
+        );
+        let externals: Vec<String> = externals.iter().map(|name| format!("$.{}", name)).collect();
+        writeln!(out, "  externals: $ => [{}],\n", externals.join(", ")).unwrap();
+    }
+
+    out.push_str("  rules: {\n");
+    let mut rules = vec![];
+
+    // The first rule is where tree-sitter starts, so a lexer grammar starts with any of its tokens
+    if grammar.parser_rules.is_empty() {
+        let tokens: Vec<String> = grammar
+            .token_rules
+            .iter()
+            .filter(|rule| !skipped.contains(&rule.name))
+            .map(|rule| format!("$.{}", rule.name))
+            .collect();
+        rules.push(format!(
+            "    source_file: $ => repeat(choice({})),\n",
+            tokens.join(", ")
+        ));
+    }
+
+    for (idx, rule) in grammar.parser_rules.iter().enumerate() {
+        if idx > 0 && transform.nullable_rules.contains(&rule.name) {
+            writer.writer.warn(format!(
+                "Rule '{}' can match without consuming any input, which tree-sitter only allows for the first rule",
+                rule.name
+            ));
+        }
+        writer.check_choices(&rule.name, &rule.node);
+
+        let mut text = String::new();
+        write_doc(&mut text, &rule.doc);
+        text += &writer.write_rule(&rule.name, &rule.node);
+        rules.push(text);
+    }
+
+    for rule in &grammar.token_rules {
+        if writer.literal_only.contains(&rule.name) {
+            continue;
+        }
+        let mut text = String::new();
+        write_doc(&mut text, &rule.doc);
+        writeln!(
+            text,
+            "    {}: $ => {},",
+            rule.name,
+            writer.token_rule(&rule.node)
+        )
+        .unwrap();
+        rules.push(text);
+    }
+
+    out += &rules.join("\n");
+    out.push_str("  }\n});\n");
+
+    writer.writer.finish(out)
+}
+
+fn write_doc(out: &mut String, doc: &[String]) {
+    for line in doc {
+        writeln!(out, "    // {}", line).unwrap();
+    }
+}
+
+struct TreeSitterWriter<'g> {
+    writer: Writer<'g>,
+    transform: &'g Transform,
+    literal_only: BTreeSet<String>,
+}
+
+impl<'g> TreeSitterWriter<'g> {
+    // Extern tokens, and tokens used without a rule (ex: INDENT), in order of first use
+    fn externals(&self) -> Vec<String> {
+        fn collect(node: &Node, names: &mut Vec<String>) {
+            match node {
+                Node::TokenRef { name, .. } => {
+                    if !names.contains(name) {
+                        names.push(name.to_string());
+                    }
+                }
+                node => node
+                    .children()
+                    .into_iter()
+                    .for_each(|node| collect(node, names)),
+            }
+        }
+
+        let grammar = self.writer.grammar;
+        let mut names = grammar.extern_tokens.clone();
+        for rule in &grammar.parser_rules {
+            collect(&rule.node, &mut names);
+        }
+        names.retain(|name| {
+            name != EOF && !grammar.token_rules.iter().any(|rule| &rule.name == name)
+        });
+        names
+    }
+
+    // PEG tries alternatives in order and takes the first that matches, while tree-sitter considers all of them - when
+    // alternatives can start with the same token, tree-sitter may report a conflict or choose a different one
+    fn check_choices(&mut self, rule_name: &str, node: &Node) {
+        if let Node::Alternatives { nodes } = node {
+            let firsts: Vec<BTreeSet<String>> = nodes
+                .iter()
+                .map(|node| self.transform.first_set(node))
+                .collect();
+
+            let overlap = firsts.iter().enumerate().find_map(|(idx, first)| {
+                firsts[idx + 1..]
+                    .iter()
+                    .find_map(|other| first.intersection(other).next())
+            });
+            if let Some(token) = overlap {
+                self.writer.warn(format!(
+                    "Alternatives of rule '{}' can both start with {} - PEG takes the first that matches, but tree-sitter may report a conflict or choose another",
+                    rule_name, token
+                ));
+            }
+        }
+
+        for child in node.children() {
+            self.check_choices(rule_name, child);
+        }
+    }
+
+    // Rules with several alternatives get one line per alternative, with labels as comments
+    fn write_rule(&mut self, name: &str, node: &Node) -> String {
+        match node {
+            Node::Alternatives { nodes } => {
+                let mut text = format!("    {}: $ => choice(\n", name);
+                for node in nodes {
+                    match node {
+                        Node::Labeled { label, node } => {
+                            self.writer.warn(
+                                "Labels have no tree-sitter equivalent and were written as comments",
+                            );
+                            writeln!(text, "      {}, // {}", self.write_or_blank(node), label)
+                                .unwrap();
+                        }
+                        node => writeln!(text, "      {},", self.write_or_blank(node)).unwrap(),
+                    }
+                }
+                text + "    ),\n"
+            }
+            node => format!("    {}: $ => {},\n", name, self.write_or_blank(node)),
+        }
+    }
+
+    // Nodes that match nothing (ex: actions) are written as `blank()`
+    fn write_or_blank(&mut self, node: &Node) -> String {
+        self.write_node(node)
+            .map(|(text, _)| text)
+            .unwrap_or_else(|| "blank()".to_string())
+    }
+
+    // A single literal stays a string, anything else becomes one regex
+    fn token_rule(&mut self, node: &Node) -> String {
+        match node {
+            Node::TokenLit {
+                literal,
+                case_insensitive: false,
+            } if !self.writer.case_insensitive => {
+                js_string(&unescape_literal(literal).unwrap_or_default())
+            }
+            node => format!("token(/{}/)", self.regex(node).0),
+        }
+    }
+
+    fn regex(&mut self, node: &Node) -> (String, Prec) {
+        use Node::*;
+
+        match node {
+            Alternatives { nodes } => {
+                let alts: Vec<String> = nodes.iter().map(|node| self.regex(node).0).collect();
+                (alts.join("|"), Prec::Alternatives)
+            }
+            MultipartBody { nodes } => {
+                let parts: Vec<String> = nodes
+                    .iter()
+                    .map(|node| group(self.regex(node), Prec::Sequence))
+                    .collect();
+                (parts.concat(), Prec::Sequence)
+            }
+            ZeroOrMore { node } => self.regex_suffixed(node, "*"),
+            OneOrMore { node } => self.regex_suffixed(node, "+"),
+            ZeroOrOne { node, .. } => self.regex_suffixed(node, "?"),
+            TokenLit {
+                literal,
+                case_insensitive,
+            } => {
+                let value = unescape_literal(literal).unwrap_or_default();
+                let prec = match value.chars().count() {
+                    1 if !*case_insensitive => Prec::Primary,
+                    _ => Prec::Sequence,
+                };
+                (
+                    regex_literal(&value, *case_insensitive || self.writer.case_insensitive),
+                    prec,
+                )
+            }
+            CharClass { class } => (regex_class(class), Prec::Primary),
+            Empty => ("()".to_string(), Prec::Primary),
+            // Only found in parser rules
+            node => (self.write_or_blank(node), Prec::Primary),
+        }
+    }
+
+    fn regex_suffixed(&mut self, node: &Node, suffix: &str) -> (String, Prec) {
+        let inner = self.regex(node);
+        (
+            format!("{}{}", group(inner, Prec::Primary), suffix),
+            Prec::Suffixed,
+        )
+    }
+}
+
+// Every node is written as a call or a single value, so none of them need grouping
+impl<'g> Format<'g> for TreeSitterWriter<'g> {
+    const NAME: &'static str = "tree-sitter";
+
+    fn writer(&mut self) -> &mut Writer<'g> {
+        &mut self.writer
+    }
+
+    // A list binding of a separated list is a field on each item (ex: `xs += x % ','`)
+    fn binding(&mut self, name: &str, _list: bool, node: &Node) -> Option<(String, Prec)> {
+        match node {
+            Node::SeparatedList {
+                node,
+                separator,
+                trailing,
+            } => {
+                let item = self.write_node(node)?;
+                let item = (format!("field('{}', {})", name, item.0), Prec::Primary);
+                let separator = self.write_node(separator);
+                Some(self.separated(item, separator, *trailing))
+            }
+            node => {
+                let item = self.write_node(node)?;
+                Some((format!("field('{}', {})", name, item.0), Prec::Primary))
+            }
+        }
+    }
+
+    fn choice(&mut self, alts: Vec<Option<(String, Prec)>>) -> Option<(String, Prec)> {
+        let alts: Vec<String> = alts
+            .into_iter()
+            .map(|alt| alt.map_or_else(|| "blank()".to_string(), |(text, _)| text))
+            .collect();
+        Some((format!("choice({})", alts.join(", ")), Prec::Primary))
+    }
+
+    fn sequence(&mut self, mut parts: Vec<(String, Prec)>) -> Option<(String, Prec)> {
+        match parts.len() {
+            0 => None,
+            1 => parts.pop(),
+            _ => {
+                let parts: Vec<String> = parts.into_iter().map(|(text, _)| text).collect();
+                Some((format!("seq({})", parts.join(", ")), Prec::Primary))
+            }
+        }
+    }
+
+    fn repeat(&mut self, (item, _): (String, Prec), suffix: &str) -> (String, Prec) {
+        let function = match suffix {
+            "*" => "repeat",
+            "+" => "repeat1",
+            _ => "optional",
+        };
+        (format!("{}({})", function, item), Prec::Primary)
+    }
+
+    // `a % b` -> `seq(a, repeat(seq(b, a)))`, `a %% b` -> `seq(a, repeat(seq(b, a)), optional(b))`
+    fn separated(
+        &mut self,
+        item: (String, Prec),
+        separator: Option<(String, Prec)>,
+        trailing: bool,
+    ) -> (String, Prec) {
+        let (sep, _) = match separator {
+            Some(sep) => sep,
+            None => return self.repeat(item, "+"),
+        };
+
+        let item = item.0;
+        let mut parts = vec![item.to_string(), format!("repeat(seq({}, {}))", sep, item)];
+        if trailing {
+            parts.push(format!("optional({})", sep));
+        }
+        (format!("seq({})", parts.join(", ")), Prec::Primary)
+    }
+
+    fn rule_ref(&mut self, name: &str, _args: &[String]) -> (String, Prec) {
+        (format!("$.{}", name), Prec::Primary)
+    }
+
+    // Tree-sitter's root rule always matches up to the end of the input
+    fn token_ref(&mut self, name: &str) -> Option<(String, Prec)> {
+        match name {
+            EOF => None,
+            name => Some((format!("$.{}", name), Prec::Primary)),
+        }
+    }
+
+    // Only tokens that are only used by their literal are written as the literal
+    fn replaced_literal(&mut self, name: &str, literal: &str) -> Option<(String, Prec)> {
+        match self.literal_only.contains(name) {
+            true => Some(token_rule_literal(self, name, literal)),
+            false => self.token_ref(name),
+        }
+    }
+
+    // A literal that ignores case is matched by a regex, but still shows up in the tree as the literal
+    fn literal(&mut self, literal: &str, case_insensitive: bool) -> (String, Prec) {
+        let value = unescape_literal(literal).unwrap_or_default();
+        let text = match case_insensitive || self.writer.case_insensitive {
+            true => format!(
+                "alias(/{}/, {})",
+                regex_literal(&value, true),
+                js_string(&value)
+            ),
+            false => js_string(&value),
+        };
+        (text, Prec::Primary)
+    }
+
+    fn char_class(&mut self, class: &str) -> (String, Prec) {
+        (format!("/{}/", regex_class(class)), Prec::Primary)
+    }
+}
+
+fn js_string(value: &str) -> String {
+    let escaped: String = value
+        .chars()
+        .map(|ch| match ch {
+            '\'' => "\\'".to_string(),
+            '\\' => "\\\\".to_string(),
+            '\n' => "\\n".to_string(),
+            '\r' => "\\r".to_string(),
+            '\t' => "\\t".to_string(),
+            ch if ch.is_control() => format!("\\u{{{:x}}}", ch as u32),
+            ch => ch.to_string(),
+        })
+        .collect();
+    format!("'{}'", escaped)
+}
+
+// Ignoring case makes each letter a class (ex: `'if'i` -> `[iI][fF]`)
+fn regex_literal(value: &str, case_insensitive: bool) -> String {
+    value
+        .chars()
+        .map(|ch| {
+            let upper: Vec<char> = ch.to_uppercase().collect();
+            let lower: Vec<char> = ch.to_lowercase().collect();
+            match (upper.as_slice(), lower.as_slice()) {
+                ([upper], [lower]) if case_insensitive && upper != lower => {
+                    format!("[{}{}]", lower, upper)
+                }
+                _ => regex_char(ch),
+            }
+        })
+        .collect()
+}
+
+fn regex_char(ch: char) -> String {
+    match ch {
+        '\\' | '^' | '$' | '.' | '|' | '?' | '*' | '+' | '(' | ')' | '[' | ']' | '{' | '}'
+        | '/' => {
+            format!("\\{}", ch)
+        }
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        ch if ch.is_control() => format!("\\u{{{:x}}}", ch as u32),
+        ch => ch.to_string(),
+    }
+}
+
+// HRPG classes are regex classes, except for escaped quotes and `\0` - a '/' would end the regex
+fn regex_class(class: &str) -> String {
+    let mut out = String::new();
+    let mut chars = class.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some(ch @ ('\'' | '"')) => out.push(ch),
+                Some('0') => out.push_str("\\u{0}"),
+                Some(ch) => write!(out, "\\{}", ch).unwrap(),
+                None => out.push('\\'),
+            },
+            '/' => out.push_str("\\/"),
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_hrpg;
+
+    fn export(data: &str) -> Export {
+        let (g2, transform) = Transform::process_with_vocab(&parse_hrpg(data).unwrap(), None);
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+        write_tree_sitter(&g2, &transform, "calc")
+    }
+
+    #[test]
+    fn small_grammar() {
+        let export = export(
+            "stat: name=ID '=' NUM (',' NUM)* [';'];\n\
+             EQUALS: '=';\nSEMI: ';';\nCOMMA: ',';\nID: [a-z]+;\nNUM: [0-9]+;\n\
+             ## @skip\nWS: [ \\t\\r\\n]+;\n",
+        );
+        assert_eq!(
+            export.text,
+            "module.exports = grammar({\n  name: 'calc',\n\n  extras: $ => [$.WS],\n\n  rules: {\n\
+             \x20   stat: $ => seq(field('name', $.ID), '=', $.NUM, repeat(seq(',', $.NUM)), optional(';')),\n\n\
+             \x20   ID: $ => token(/[a-z]+/),\n\n\
+             \x20   NUM: $ => token(/[0-9]+/),\n\n\
+             \x20   WS: $ => token(/[ \\t\\r\\n]+/),\n  }\n});\n"
+        );
+        assert!(export.warnings.is_empty(), "{:?}", export.warnings);
+    }
+
+    // Tree-sitter's default extras would skip whitespace the grammar doesn't
+    #[test]
+    fn no_skip_tokens() {
+        let export = export("a: X Y;\nX: 'x';\nY: 'y';\n");
+        assert!(export.text.contains("  extras: $ => [],\n"));
+    }
+
+    #[test]
+    fn eof_is_dropped() {
+        let export = export("file: X* EOF;\nX: 'x';\n");
+        assert!(export.text.contains("    file: $ => repeat($.X),\n"));
+        assert!(!export.text.contains("externals"));
+        assert!(export.warnings.is_empty(), "{:?}", export.warnings);
+    }
+
+    #[test]
+    fn ordered_choice() {
+        let export = export("a: X Y | X;\nX: 'x';\nY: 'y';\n");
+        assert_eq!(
+            export.warnings,
+            vec!["Alternatives of rule 'a' can both start with X - PEG takes the first that matches, but tree-sitter may report a conflict or choose another"]
+        );
+    }
+}
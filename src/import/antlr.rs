@@ -0,0 +1,1032 @@
+use std::collections::HashMap;
+
+use convert_case::{Case, Casing};
+use pest::iterators::Pair;
+use pest::Parser;
+
+use crate::ast::{Grammar, GrammarKind, Node, ParserRule, TokenRule};
+use crate::import::{
+    add_literal_tokens, binding_name, class_char, hrpg_literal, remove_left_recursion, split_args,
+    Import, ImportWarning, LeftRecursion, ANY_CHAR,
+};
+use crate::lexer_gen::{CASE_INSENSITIVE, SKIP_ANNOTATION};
+use crate::vocab::TOKEN_VOCAB;
+
+#[derive(pest_derive::Parser)]
+#[grammar = "import/ANTLR.pest"]
+struct ANTLRParser;
+
+pub fn import_antlr(data: &str) -> Result<Import, Box<pest::error::Error<Rule>>> {
+    let pairs = ANTLRParser::parse(Rule::grammar_spec, data)
+        .map_err(Box::new)?
+        .next()
+        .unwrap()
+        .into_inner();
+
+    let mut importer = AntlrImporter::default();
+    for pair in pairs {
+        importer.process(pair);
+    }
+    Ok(importer.finish())
+}
+
+// ANTLR's default target language, which actions are written in unless the `language` option says otherwise
+const DEFAULT_LANGUAGE: &str = "java";
+
+// Lexer rules are converted once all of them are known, since they can refer to each other
+struct LexerRule<'i> {
+    name: String,
+    fragment: bool,
+    // `-> skip` or `-> channel(HIDDEN)`
+    skip: bool,
+    pair: Pair<'i, Rule>,
+}
+
+#[derive(Default)]
+struct AntlrImporter<'i> {
+    name: Option<String>,
+    kind: Option<GrammarKind>,
+    options: HashMap<String, String>,
+    // Target language of actions (ex: `python3` for `language = Python3;`)
+    language: Option<String>,
+    parser_rules: Vec<ParserRule>,
+    lexer_rules: Vec<LexerRule<'i>>,
+    extern_tokens: Vec<String>,
+    warnings: Vec<ImportWarning>,
+}
+
+impl<'i> AntlrImporter<'i> {
+    fn warn(&mut self, pair: &Pair<Rule>, message: String) {
+        self.warnings
+            .push(ImportWarning::new(&pair.as_span(), message));
+    }
+
+    fn process(&mut self, pair: Pair<'i, Rule>) {
+        match pair.as_rule() {
+            Rule::grammar_decl => {
+                for inner in pair.into_inner() {
+                    match inner.as_rule() {
+                        Rule::grammar_type => {
+                            self.kind = Some(match inner.as_str() {
+                                "lexer" => GrammarKind::Lexer,
+                                _ => GrammarKind::Parser,
+                            })
+                        }
+                        Rule::ident => self.name = Some(inner.as_str().to_string()),
+                        _ => (),
+                    }
+                }
+            }
+            Rule::options_spec => self.grammar_options(pair),
+            Rule::delegate_grammars => self.warn(
+                &pair,
+                "Grammar imports are not supported and were dropped".to_string(),
+            ),
+            Rule::tokens_spec => self.tokens_spec(pair),
+            Rule::channels_spec => self.warn(
+                &pair,
+                "Token channels are not supported and were dropped".to_string(),
+            ),
+            Rule::named_action => self.warn(
+                &pair,
+                format!("Named action '{}' was dropped", action_name(&pair)),
+            ),
+            Rule::parser_rule_spec => {
+                let rule = self.parser_rule(pair);
+                self.parser_rules.push(rule);
+            }
+            Rule::lexer_rule_spec => self.lexer_rule(pair),
+            Rule::mode_spec => {
+                let mut inner = pair.clone().into_inner();
+                let mode = inner.nth(1).unwrap().as_str().to_string();
+                self.warn(
+                    &pair,
+                    format!(
+                        "Lexer modes are not supported - the rules of mode '{}' were added to the default mode",
+                        mode
+                    ),
+                );
+                for rule in inner {
+                    self.lexer_rule(rule);
+                }
+            }
+            Rule::EOI => (),
+            _ => unreachable!(),
+        }
+    }
+
+    fn grammar_options(&mut self, pair: Pair<Rule>) {
+        for option in pair.into_inner().skip(1) {
+            let mut inner = option.clone().into_inner();
+            let name = inner.next().unwrap().as_str();
+            let value = inner.next().unwrap().as_str();
+
+            match name {
+                TOKEN_VOCAB | CASE_INSENSITIVE => {
+                    self.options
+                        .insert(name.to_string(), value.trim_matches('\'').to_string());
+                }
+                // Actions are tagged with the language instead
+                "language" => self.language = Some(value.trim_matches('\'').to_lowercase()),
+                _ => self.warn(
+                    &option,
+                    format!("Option '{}' is not supported and was dropped", name),
+                ),
+            }
+        }
+    }
+
+    // Tokens without rules are set by actions or external code, so the closest match is an extern token
+    fn tokens_spec(&mut self, pair: Pair<Rule>) {
+        let names: Vec<String> = pair
+            .clone()
+            .into_inner()
+            .skip(1)
+            .flat_map(|id_list| id_list.into_inner())
+            .map(|name| name.as_str().to_string())
+            .collect();
+
+        if self.kind == Some(GrammarKind::Parser) {
+            self.warn(
+                &pair,
+                "Tokens block of a parser grammar was dropped (tokens come from the token vocabulary)".to_string(),
+            );
+        } else {
+            self.warn(
+                &pair,
+                format!(
+                    "Tokens block was converted to extern tokens: {}",
+                    names.join(", ")
+                ),
+            );
+            self.extern_tokens.extend(names);
+        }
+    }
+
+    fn parser_rule(&mut self, pair: Pair<Rule>) -> ParserRule {
+        let span = pair.as_span();
+        let mut rule = ParserRule {
+            name: String::new(),
+            params: vec![],
+            args: vec![],
+            returns: vec![],
+            doc: vec![],
+            node: Node::Empty,
+        };
+
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::rule_modifier => self.warn(
+                    &inner,
+                    format!("Rule modifier '{}' was dropped", inner.as_str()),
+                ),
+                Rule::rule_ref => rule.name = inner.as_str().to_string(),
+                Rule::arg_action => rule.args = arg_list(&inner),
+                Rule::rule_returns => rule.returns = arg_list(&inner.into_inner().nth(1).unwrap()),
+                Rule::throws_spec => self.warn(
+                    &inner,
+                    format!("Throws clause of rule '{}' was dropped", rule.name),
+                ),
+                Rule::locals_spec => self.warn(
+                    &inner,
+                    format!("Locals of rule '{}' were dropped", rule.name),
+                ),
+                Rule::rule_prequel => {
+                    let prequel = inner.into_inner().next().unwrap();
+                    let message = match prequel.as_rule() {
+                        Rule::rule_action => format!(
+                            "Action '@{}' of rule '{}' was dropped",
+                            action_name(&prequel),
+                            rule.name
+                        ),
+                        _ => format!("Options of rule '{}' were dropped", rule.name),
+                    };
+                    self.warn(&prequel, message);
+                }
+                Rule::alt_list => rule.node = self.parser_alts(inner),
+                Rule::exception_handler => self.warn(
+                    &inner,
+                    format!("Exception handler of rule '{}' was dropped", rule.name),
+                ),
+                _ => unreachable!(),
+            }
+        }
+
+        self.left_recursion(&span, rule)
+    }
+
+    // ANTLR rewrites left recursion itself, giving earlier alternatives higher precedence - HRPG can only repeat
+    fn left_recursion(&mut self, span: &pest::Span, rule: ParserRule) -> ParserRule {
+        let alts = match rule.node {
+            Node::Alternatives { nodes } => nodes,
+            node => vec![node],
+        };
+
+        let (node, recursion) = remove_left_recursion(&rule.name, alts);
+        match &recursion {
+            LeftRecursion::None => (),
+            LeftRecursion::Converted { dropped_labels } => {
+                self.warnings.push(ImportWarning::new(
+                    span,
+                    format!(
+                        "Left recursion of rule '{}' was converted to repetition (the precedence of its alternatives is lost)",
+                        rule.name
+                    ),
+                ));
+                if !dropped_labels.is_empty() {
+                    self.warnings.push(ImportWarning::new(
+                        span,
+                        format!(
+                            "Labels of left recursive rule '{}' were dropped: {}",
+                            rule.name,
+                            dropped_labels.join(", ")
+                        ),
+                    ));
+                }
+            }
+            LeftRecursion::Unending => self.warnings.push(ImportWarning::new(
+                span,
+                format!(
+                    "Rule '{}' is left recursive with no way to end the recursion",
+                    rule.name
+                ),
+            )),
+        }
+
+        ParserRule { node, ..rule }
+    }
+
+    fn parser_alts(&mut self, pair: Pair<Rule>) -> Node {
+        let mut nodes: Vec<Node> = pair.into_inner().map(|alt| self.parser_alt(alt)).collect();
+
+        match nodes.len() {
+            1 => nodes.remove(0),
+            _ => Node::Alternatives { nodes },
+        }
+    }
+
+    fn parser_alt(&mut self, pair: Pair<Rule>) -> Node {
+        let mut nodes = vec![];
+        let mut label = None;
+
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::element_options => self.element_options(&inner),
+                Rule::element => nodes.extend(self.parser_element(inner)),
+                // HRPG labels must start with an uppercase letter
+                Rule::alt_label => {
+                    label = Some(
+                        inner
+                            .into_inner()
+                            .next()
+                            .unwrap()
+                            .as_str()
+                            .to_case(Case::UpperCamel),
+                    )
+                }
+                Rule::lexer_commands => self.warn(
+                    &inner,
+                    "Lexer commands are only allowed in lexer rules and were dropped".to_string(),
+                ),
+                _ => unreachable!(),
+            }
+        }
+
+        let node = match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.remove(0),
+            _ => Node::MultipartBody { nodes },
+        };
+
+        match label {
+            Some(label) => Node::Labeled {
+                label,
+                node: Box::new(node),
+            },
+            None => node,
+        }
+    }
+
+    fn parser_element(&mut self, pair: Pair<Rule>) -> Option<Node> {
+        let mut inner = pair.into_inner();
+        let first = inner.next().unwrap();
+
+        // The suffix is applied inside of a binding (`x+=a*` means the same in both formats)
+        let (node, binding) = match first.as_rule() {
+            Rule::labeled_element => {
+                let mut label = first.into_inner();
+                let name = binding_name(label.next().unwrap().as_str());
+                let list = label.next().unwrap().as_str() == "+=";
+                let node = self.parser_atom_or_block(label.next().unwrap())?;
+                (node, Some((name, list)))
+            }
+            Rule::action_block => return self.parser_action(&first, inner.next().is_some()),
+            _ => (self.parser_atom_or_block(first)?, None),
+        };
+
+        let node = match inner.next() {
+            Some(suffix) => self.suffix(&suffix, node),
+            None => node,
+        };
+
+        match binding {
+            Some((name, list)) => Some(Node::Binding {
+                name,
+                list,
+                node: Box::new(node),
+            }),
+            None => Some(node),
+        }
+    }
+
+    // Actions and predicates keep their code, tagged with the grammar's target language
+    fn parser_action(&mut self, pair: &Pair<Rule>, predicate: bool) -> Option<Node> {
+        let text = pair.as_str();
+        let code = text[1..text.len() - 1].trim();
+
+        // HRPG only counts braces, so braces in strings have to be balanced too
+        if !balanced_braces(code) {
+            let kind = if predicate {
+                "Semantic predicate"
+            } else {
+                "Action"
+            };
+            self.warn(
+                pair,
+                format!("{} has unbalanced braces and was dropped", kind),
+            );
+            return None;
+        }
+
+        let language = self.language.as_deref().unwrap_or(DEFAULT_LANGUAGE);
+        Some(Node::Action {
+            lang: language.to_string(),
+            code: code.to_string(),
+            predicate,
+        })
+    }
+
+    fn parser_atom_or_block(&mut self, pair: Pair<Rule>) -> Option<Node> {
+        if pair.as_rule() == Rule::block {
+            return Some(self.parser_block(pair));
+        }
+
+        let atom = pair.into_inner().next().unwrap();
+        match atom.as_rule() {
+            Rule::terminal => {
+                let mut inner = atom.into_inner();
+                let terminal = inner.next().unwrap();
+                if let Some(options) = inner.next() {
+                    self.element_options(&options);
+                }
+
+                match terminal.as_rule() {
+                    Rule::token_ref => Some(Node::TokenRef {
+                        name: terminal.as_str().to_string(),
+                        replaced_lit: None,
+                    }),
+                    _ => match unescape_antlr(terminal.as_str()) {
+                        Ok(value) => Some(hrpg_literal(&value)),
+                        Err(err) => {
+                            self.warn(&terminal, format!("{} - literal was dropped", err));
+                            None
+                        }
+                    },
+                }
+            }
+            Rule::rule_call => {
+                let mut inner = atom.into_inner();
+                let name = inner.next().unwrap().as_str().to_string();
+                let mut args = vec![];
+
+                for next in inner {
+                    match next.as_rule() {
+                        Rule::arg_action => args = arg_list(&next),
+                        _ => self.element_options(&next),
+                    }
+                }
+
+                Some(Node::RuleRef { name, args })
+            }
+            Rule::not_set => {
+                self.warn(
+                    &atom,
+                    format!(
+                        "Negated set '{}' is not supported in parser rules and was dropped",
+                        atom.as_str()
+                    ),
+                );
+                None
+            }
+            Rule::wildcard => {
+                self.warn(
+                    &atom,
+                    "Wildcard '.' is not supported in parser rules and was dropped".to_string(),
+                );
+                None
+            }
+            _ => {
+                self.warn(
+                    &atom,
+                    format!(
+                        "Character set '{}' is only allowed in lexer rules and was dropped",
+                        atom.as_str()
+                    ),
+                );
+                None
+            }
+        }
+    }
+
+    fn parser_block(&mut self, pair: Pair<Rule>) -> Node {
+        let mut node = Node::Empty;
+
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::alt_list => node = self.parser_alts(inner),
+                _ => self.warn(&inner, "Block options and actions were dropped".to_string()),
+            }
+        }
+
+        node
+    }
+
+    fn suffix(&mut self, suffix: &Pair<Rule>, node: Node) -> Node {
+        let text = suffix.as_str();
+        if text.len() > 1 {
+            self.warn(
+                suffix,
+                format!("Non-greedy '{}' was imported as greedy", text),
+            );
+        }
+
+        let node = Box::new(node);
+        match &text[..1] {
+            "*" => Node::ZeroOrMore { node },
+            "+" => Node::OneOrMore { node },
+            _ => Node::ZeroOrOne {
+                node,
+                brackets: false,
+            },
+        }
+    }
+
+    fn element_options(&mut self, pair: &Pair<Rule>) {
+        self.warn(
+            pair,
+            format!(
+                "Element options '{}' are not supported and were dropped",
+                pair.as_str()
+            ),
+        );
+    }
+
+    fn lexer_rule(&mut self, pair: Pair<'i, Rule>) {
+        let mut fragment = false;
+        let mut name = String::new();
+        let skip = pair
+            .clone()
+            .into_inner()
+            .flatten()
+            .any(|inner| inner.as_rule() == Rule::lexer_command && is_skip(&inner));
+
+        for inner in pair.clone().into_inner() {
+            match inner.as_rule() {
+                Rule::fragment => fragment = true,
+                Rule::token_ref => name = inner.as_str().to_string(),
+                Rule::options_spec => {
+                    self.warn(&inner, format!("Options of token '{}' were dropped", name))
+                }
+                _ => (),
+            }
+        }
+
+        self.lexer_rules.push(LexerRule {
+            name,
+            fragment,
+            skip,
+            pair,
+        });
+    }
+
+    fn finish(mut self) -> Import {
+        let kind = self.kind.unwrap_or(GrammarKind::Combined);
+        let mut token_rules = vec![];
+        let mut converted: HashMap<String, Result<Node, String>> = HashMap::new();
+
+        let names: Vec<String> = self
+            .lexer_rules
+            .iter()
+            .map(|rule| rule.name.to_string())
+            .collect();
+        for name in names {
+            let node = self.resolve_token(&name, &mut vec![], &mut converted);
+            let rule = self
+                .lexer_rules
+                .iter()
+                .find(|rule| rule.name == name)
+                .unwrap();
+            // Fragments only exist to be inlined into other tokens
+            if rule.fragment {
+                continue;
+            }
+
+            let annotations = match rule.skip {
+                true => vec![(SKIP_ANNOTATION.to_string(), String::new())],
+                false => vec![],
+            };
+            match node {
+                Ok(node) => token_rules.push(TokenRule {
+                    name,
+                    doc: vec![],
+                    annotations,
+                    node,
+                }),
+                Err(reason) => {
+                    let pair = rule.pair.clone();
+                    self.warn(
+                        &pair,
+                        format!(
+                            "Token '{}' can't be converted ({}) and was declared extern",
+                            name, reason
+                        ),
+                    );
+                    self.extern_tokens.push(name);
+                }
+            }
+        }
+
+        let mut grammar = Grammar {
+            name: self.name,
+            kind,
+            options: self.options,
+            parser_rules: self.parser_rules,
+            token_rules,
+            extern_tokens: self.extern_tokens,
+        };
+
+        // Only combined grammars define tokens for literals implicitly
+        if kind == GrammarKind::Combined {
+            add_literal_tokens(&mut grammar);
+        }
+
+        // Lexer rules are converted last, but their warnings belong in file order
+        self.warnings
+            .sort_by_key(|warning| (warning.line, warning.column));
+
+        Import {
+            grammar,
+            warnings: self.warnings,
+        }
+    }
+
+    // HRPG token rules can't refer to other tokens, so references (usually to fragments) are inlined
+    fn resolve_token(
+        &mut self,
+        name: &str,
+        stack: &mut Vec<String>,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        if let Some(result) = converted.get(name) {
+            return result.clone();
+        }
+        if stack.iter().any(|other| other == name) {
+            return Err(format!("'{}' is recursive", name));
+        }
+
+        let pair = match self.lexer_rules.iter().find(|rule| rule.name == name) {
+            Some(rule) => rule.pair.clone(),
+            None => return Err(format!("'{}' has no rule", name)),
+        };
+        let alt_list = pair
+            .into_inner()
+            .find(|inner| inner.as_rule() == Rule::alt_list)
+            .unwrap();
+
+        stack.push(name.to_string());
+        let result = self
+            .lexer_alts(alt_list)
+            .and_then(|node| self.inline_tokens(node, stack, converted));
+        stack.pop();
+
+        converted.insert(name.to_string(), result.clone());
+        result
+    }
+
+    fn inline_tokens(
+        &mut self,
+        node: Node,
+        stack: &mut Vec<String>,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        use Node::*;
+
+        let mut inline_all = |nodes: Vec<Node>, this: &mut Self| {
+            nodes
+                .into_iter()
+                .map(|node| this.inline_tokens(node, stack, converted))
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        Ok(match node {
+            TokenRef { name, .. } => self.resolve_token(&name, stack, converted)?,
+            Alternatives { nodes } => Alternatives {
+                nodes: inline_all(nodes, self)?,
+            },
+            MultipartBody { nodes } => MultipartBody {
+                nodes: inline_all(nodes, self)?,
+            },
+            ZeroOrMore { node } => ZeroOrMore {
+                node: Box::new(self.inline_tokens(*node, stack, converted)?),
+            },
+            OneOrMore { node } => OneOrMore {
+                node: Box::new(self.inline_tokens(*node, stack, converted)?),
+            },
+            ZeroOrOne { node, brackets } => ZeroOrOne {
+                node: Box::new(self.inline_tokens(*node, stack, converted)?),
+                brackets,
+            },
+            node => node,
+        })
+    }
+
+    fn lexer_alts(&mut self, pair: Pair<Rule>) -> Result<Node, String> {
+        let mut nodes = pair
+            .into_inner()
+            .map(|alt| self.lexer_alt(alt))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(match nodes.len() {
+            1 => nodes.remove(0),
+            _ => Node::Alternatives { nodes },
+        })
+    }
+
+    fn lexer_alt(&mut self, pair: Pair<Rule>) -> Result<Node, String> {
+        let mut nodes = vec![];
+
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::element_options => self.element_options(&inner),
+                Rule::element => nodes.extend(self.lexer_element(inner)?),
+                Rule::lexer_commands => {
+                    // Both keep the token from the parser, which is what `@skip` does
+                    for command in inner.into_inner().filter(|command| !is_skip(command)) {
+                        self.warn(
+                            &command,
+                            format!(
+                                "Lexer command '{}' is not supported and was dropped",
+                                command.as_str().trim()
+                            ),
+                        );
+                    }
+                }
+                _ => return Err("alternative labels are only allowed in parser rules".to_string()),
+            }
+        }
+
+        Ok(match nodes.len() {
+            0 => return Err("empty alternatives can't be matched".to_string()),
+            1 => nodes.remove(0),
+            _ => Node::MultipartBody { nodes },
+        })
+    }
+
+    fn lexer_element(&mut self, pair: Pair<Rule>) -> Result<Option<Node>, String> {
+        let mut inner = pair.into_inner();
+        let first = inner.next().unwrap();
+
+        let node = match first.as_rule() {
+            Rule::action_block => {
+                let message = match inner.next() {
+                    Some(_) => "Semantic predicate was dropped",
+                    None => "Action was dropped",
+                };
+                self.warn(&first, message.to_string());
+                return Ok(None);
+            }
+            Rule::block => {
+                let alt_list = first.into_inner().last().unwrap();
+                self.lexer_alts(alt_list)?
+            }
+            Rule::atom => lexer_atom(first.into_inner().next().unwrap())?,
+            _ => return Err("labels are only allowed in parser rules".to_string()),
+        };
+
+        match inner.next() {
+            Some(suffix) if suffix.as_str().len() > 1 => {
+                Err(format!("non-greedy '{}' isn't supported", suffix.as_str()))
+            }
+            Some(suffix) => Ok(Some(self.suffix(&suffix, node))),
+            None => Ok(Some(node)),
+        }
+    }
+}
+
+fn lexer_atom(pair: Pair<Rule>) -> Result<Node, String> {
+    match pair.as_rule() {
+        Rule::terminal => {
+            let terminal = pair.into_inner().next().unwrap();
+            match terminal.as_rule() {
+                Rule::token_ref => Ok(Node::TokenRef {
+                    name: terminal.as_str().to_string(),
+                    replaced_lit: None,
+                }),
+                _ => Ok(hrpg_literal(&unescape_antlr(terminal.as_str())?)),
+            }
+        }
+        Rule::char_range | Rule::char_set => Ok(Node::CharClass {
+            class: format!("[{}]", set_items(pair)?.join("")),
+        }),
+        Rule::not_set => {
+            let set = pair.into_inner().next().unwrap();
+            let items = match set.as_rule() {
+                Rule::set_block => set
+                    .into_inner()
+                    .map(|elem| set_items(elem.into_inner().next().unwrap()))
+                    .collect::<Result<Vec<_>, _>>()?
+                    .concat(),
+                _ => set_items(set.into_inner().next().unwrap())?,
+            };
+            Ok(Node::CharClass {
+                class: format!("[^{}]", items.join("")),
+            })
+        }
+        Rule::wildcard => Ok(Node::CharClass {
+            class: ANY_CHAR.to_string(),
+        }),
+        _ => Err(format!(
+            "parser rule '{}' can't be used in a token",
+            pair.as_str()
+        )),
+    }
+}
+
+// The HRPG character class items for part of a set (ex: `'a'..'z'` -> `a-z`)
+fn set_items(pair: Pair<Rule>) -> Result<Vec<String>, String> {
+    match pair.as_rule() {
+        Rule::char_range => {
+            let mut inner = pair.into_inner();
+            let start = single_char(inner.next().unwrap().as_str())?;
+            let end = single_char(inner.next().unwrap().as_str())?;
+            Ok(vec![format!("{}-{}", class_char(start), class_char(end))])
+        }
+        Rule::string_literal => Ok(vec![class_char(single_char(pair.as_str())?)]),
+        Rule::char_set => char_set_items(pair.as_str()),
+        _ => Err(format!("token '{}' can't be used in a set", pair.as_str())),
+    }
+}
+
+fn single_char(literal: &str) -> Result<char, String> {
+    let value = unescape_antlr(literal)?;
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Ok(ch),
+        _ => Err(format!("{} is not a single character", literal)),
+    }
+}
+
+// ANTLR sets only treat '-' between two characters as a range, the same as HRPG, so escaped characters are
+// escaped again and unescaped dashes are kept as is
+fn char_set_items(set: &str) -> Result<Vec<String>, String> {
+    let body = &set[1..set.len() - 1];
+    let mut chars = body.chars();
+    let mut items = vec![];
+
+    while let Some(ch) = chars.next() {
+        items.push(match ch {
+            '\\' if chars.as_str().starts_with(['p', 'P']) => {
+                let rest = chars.as_str();
+                let end = rest
+                    .find('}')
+                    .ok_or_else(|| format!("invalid Unicode property in {}", set))?;
+                chars = rest[end + 1..].chars();
+                format!("\\{}", &rest[..=end])
+            }
+            '\\' => class_char(unescape_char(&mut chars)?),
+            '-' => "-".to_string(),
+            ch => class_char(ch),
+        });
+    }
+
+    match items.is_empty() {
+        true => Err(format!("empty set {}", set)),
+        false => Ok(items),
+    }
+}
+
+fn unescape_antlr(literal: &str) -> Result<String, String> {
+    let mut chars = literal[1..literal.len() - 1].chars();
+    let mut value = String::new();
+
+    while let Some(ch) = chars.next() {
+        value.push(match ch {
+            '\\' => unescape_char(&mut chars)
+                .map_err(|err| format!("{} in literal {}", err, literal))?,
+            ch => ch,
+        });
+    }
+    Ok(value)
+}
+
+fn unescape_char(chars: &mut std::str::Chars) -> Result<char, String> {
+    match chars.next() {
+        Some('n') => Ok('\n'),
+        Some('r') => Ok('\r'),
+        Some('t') => Ok('\t'),
+        Some('b') => Ok('\u{8}'),
+        Some('f') => Ok('\u{c}'),
+        // `\uXXXX` or `\u{XXXXXX}`
+        Some('u') => {
+            let rest = chars.as_str();
+            let (hex, len) = match rest.strip_prefix('{') {
+                Some(braced) => {
+                    let end = braced.find('}').ok_or("invalid Unicode escape")?;
+                    (&braced[..end], end + 2)
+                }
+                None => (rest.get(..4).ok_or("invalid Unicode escape")?, 4),
+            };
+            let ch = u32::from_str_radix(hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("invalid Unicode escape '\\u{}'", hex))?;
+            *chars = rest[len..].chars();
+            Ok(ch)
+        }
+        Some(ch) => Ok(ch),
+        None => Err("incomplete escape sequence".to_string()),
+    }
+}
+
+fn is_skip(command: &Pair<Rule>) -> bool {
+    let command: String = command.as_str().split_whitespace().collect();
+    command == "skip" || command == "channel(HIDDEN)"
+}
+
+fn balanced_braces(code: &str) -> bool {
+    let mut depth = 0;
+    for ch in code.chars() {
+        match ch {
+            '{' => depth += 1,
+            '}' if depth == 0 => return false,
+            '}' => depth -= 1,
+            _ => (),
+        }
+    }
+    depth == 0
+}
+
+fn action_name(pair: &Pair<Rule>) -> String {
+    pair.clone()
+        .into_inner()
+        .filter(|inner| inner.as_rule() == Rule::ident)
+        .map(|inner| inner.as_str())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+fn arg_list(pair: &Pair<Rule>) -> Vec<String> {
+    let text = pair.as_str();
+    split_args(&text[1..text.len() - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_hrpg;
+    use crate::export::antlr::write_antlr;
+    use crate::export::hrpg::write_grammar;
+    use crate::transform::Transform;
+
+    fn import(data: &str) -> (String, Vec<String>) {
+        let import = import_antlr(data).unwrap();
+        let warnings = import
+            .warnings
+            .iter()
+            .map(|warning| warning.message.clone())
+            .collect();
+        (write_grammar(&import.grammar), warnings)
+    }
+
+    #[test]
+    fn small_grammar() {
+        let (hrpg, warnings) = import(
+            "grammar Calc;\n\
+             stat: ID '=' expr ';' # Assign\n    | expr ';' # Print\n    ;\n\
+             expr: NUM (',' NUM)*;\n\
+             ID: [a-z]+;\n\
+             NUM: [0-9]+;\n",
+        );
+        assert_eq!(
+            hrpg,
+            "grammar Calc;\n\n\
+             stat\n    : ID '=' expr ';' @Assign\n    | expr ';' @Print\n    ;\n\n\
+             expr: NUM (',' NUM)*;\n\n\
+             EQUALS: '=';\nSEMI: ';';\nCOMMA: ',';\nID: [a-z]+;\nNUM: [0-9]+;\n"
+        );
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    // Start rules still have to match all of the input
+    #[test]
+    fn eof_is_kept() {
+        let (hrpg, warnings) = import("grammar A;\nfile: ID* EOF;\nID: [a-z]+;\n");
+        assert!(hrpg.contains("file: ID* EOF;\n"), "{}", hrpg);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+
+        let (_, transform) = Transform::process(&parse_hrpg(&hrpg).unwrap());
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+    }
+
+    #[test]
+    fn skip_commands() {
+        let (hrpg, warnings) = import(
+            "grammar A;\na: 'x';\n\
+             WS: [ \\t]+ -> skip;\n\
+             COMMENT: '#' ~[\\n]* -> channel(HIDDEN);\n\
+             NL: '\\n' -> pushMode(X);\n",
+        );
+        assert!(hrpg
+            .ends_with("## @skip\nWS: [ \\t]+;\n\n## @skip\nCOMMENT: '#' [^\\n]*;\nNL: '\\n';\n"));
+        assert_eq!(
+            warnings,
+            vec!["Lexer command 'pushMode(X)' is not supported and was dropped"]
+        );
+    }
+
+    #[test]
+    fn left_recursion() {
+        let (hrpg, warnings) = import(
+            "grammar Expr;\n\
+             expr: expr '*' expr # Mul\n    | expr '+' expr # Add\n    | NUM # Num\n    ;\n\
+             NUM: [0-9]+;\n",
+        );
+        assert_eq!(
+            hrpg,
+            "grammar Expr;\n\n\
+             expr: NUM ('*' expr | '+' expr)*;\n\n\
+             STAR: '*';\nPLUS: '+';\nNUM: [0-9]+;\n"
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "Left recursion of rule 'expr' was converted to repetition (the precedence of its alternatives is lost)",
+                "Labels of left recursive rule 'expr' were dropped: Mul, Add, Num",
+            ]
+        );
+    }
+
+    #[test]
+    fn unending_left_recursion() {
+        let (_, warnings) = import("grammar A;\na: a 'x';\n");
+        assert_eq!(
+            warnings,
+            vec!["Rule 'a' is left recursive with no way to end the recursion"]
+        );
+    }
+
+    #[test]
+    fn actions_and_predicates() {
+        let (hrpg, warnings) = import(
+            "grammar A;\noptions { language = Python3; }\n\
+             a: {self.count += 1} 'x' {self.count < 3}? 'y';\n",
+        );
+        assert!(hrpg.contains("a: {python3: self.count += 1} 'x' {python3: self.count < 3}? 'y';"));
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn default_action_language() {
+        let (hrpg, _) = import("grammar A;\na: {count++;} 'x';\n");
+        assert!(hrpg.contains("a: {java: count++;} 'x';"));
+    }
+
+    #[test]
+    fn unbalanced_action() {
+        let (hrpg, warnings) = import("grammar A;\na: {print(\"}\")} 'x';\n");
+        assert!(hrpg.contains("a: 'x';"));
+        assert_eq!(
+            warnings,
+            vec!["Action has unbalanced braces and was dropped"]
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = "grammar Calc;\n\
+             stat: ID '=' expr ';' | expr ';';\n\
+             expr: NUM (',' NUM)*;\n\
+             ID: [a-z]+;\n\
+             NUM: [0-9]+;\n";
+        let import = import_antlr(data).unwrap();
+        let (g2, transform) = Transform::process_with_vocab(&import.grammar, None);
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+
+        let reimport = import_antlr(&write_antlr(&g2, "Calc").text).unwrap();
+        assert!(reimport.warnings.is_empty());
+        assert_eq!(
+            write_grammar(&reimport.grammar),
+            write_grammar(&import.grammar)
+        );
+    }
+}
@@ -0,0 +1,810 @@
+use std::collections::{HashMap, HashSet};
+
+use convert_case::{Case, Casing};
+use pest::iterators::Pair;
+use pest::Parser;
+
+use crate::ast::{Grammar, GrammarKind, Node, ParserRule, TokenRule};
+use crate::escape::{parse_char_class, unescape_literal};
+use crate::import::{add_literal_tokens, class_char, hrpg_literal, Import, ImportWarning};
+
+#[derive(pest_derive::Parser)]
+#[grammar = "import/EBNF.pest"]
+struct EbnfParser;
+
+pub fn import_ebnf(data: &str) -> Result<Import, Box<pest::error::Error<Rule>>> {
+    let pairs = EbnfParser::parse(Rule::ebnf_file, data)
+        .map_err(Box::new)?
+        .next()
+        .unwrap()
+        .into_inner();
+
+    let mut importer = EbnfImporter::default();
+    for pair in pairs {
+        importer.process(pair);
+    }
+    Ok(importer.finish())
+}
+
+// W3C EBNF is scannerless, so productions are only split into token and parser rules once all of them are known
+struct Production<'i> {
+    name: String,
+    pair: Pair<'i, Rule>,
+    expr: Pair<'i, Rule>,
+}
+
+#[derive(Default)]
+struct EbnfImporter<'i> {
+    productions: Vec<Production<'i>>,
+    // Productions that became (or are inlined into) token rules
+    lexical: HashSet<String>,
+    warnings: Vec<ImportWarning>,
+}
+
+impl<'i> EbnfImporter<'i> {
+    fn warn(&mut self, pair: &Pair<Rule>, message: String) {
+        self.warnings
+            .push(ImportWarning::new(&pair.as_span(), message));
+    }
+
+    fn process(&mut self, pair: Pair<'i, Rule>) {
+        match pair.as_rule() {
+            Rule::production => {
+                let mut name = String::new();
+                let mut expr = None;
+
+                for inner in pair.clone().into_inner() {
+                    match inner.as_rule() {
+                        Rule::rule_number => (),
+                        Rule::symbol => name = inner.as_str().to_string(),
+                        Rule::expression => expr = Some(inner),
+                        Rule::constraint => self.warn(
+                            &inner,
+                            format!("Constraint '{}' was dropped", inner.as_str()),
+                        ),
+                        _ => unreachable!(),
+                    }
+                }
+
+                self.productions.push(Production {
+                    name,
+                    pair,
+                    expr: expr.unwrap(),
+                });
+            }
+            Rule::EOI => (),
+            _ => unreachable!(),
+        }
+    }
+
+    fn production(&self, name: &str) -> Option<&Production<'i>> {
+        self.productions.iter().find(|prod| prod.name == name)
+    }
+
+    // The productions an expression refers to
+    fn refs(&self, pair: Pair<Rule>) -> Vec<String> {
+        let mut refs = vec![];
+        for inner in pair.into_inner().flatten() {
+            if inner.as_rule() == Rule::symbol && self.production(inner.as_str()).is_some() {
+                refs.push(inner.as_str().to_string());
+            }
+        }
+        refs
+    }
+
+    // The productions an expression refers to, directly or indirectly
+    fn reachable(&self, name: &str) -> HashSet<String> {
+        let mut pending = vec![name.to_string()];
+        let mut visited = HashSet::new();
+
+        while let Some(next) = pending.pop() {
+            let expr = self.production(&next).unwrap().expr.clone();
+            for other in self.refs(expr) {
+                if visited.insert(other.to_string()) {
+                    pending.push(other);
+                }
+            }
+        }
+        visited
+    }
+
+    // Productions built directly from characters (ex: `Digit ::= [0-9]`) or named like terminals (ex: `PNAME_NS`)
+    // become token rules, along with everything they refer to - so do productions that only combine tokens (ex:
+    // `Misc ::= Comment | S`). Recursive productions can't be tokens.
+    fn split_rules(&mut self) {
+        let reachable: HashMap<String, HashSet<String>> = self
+            .productions
+            .iter()
+            .map(|prod| (prod.name.to_string(), self.reachable(&prod.name)))
+            .collect();
+        let recursive: HashSet<&String> = reachable
+            .iter()
+            .filter(|(name, refs)| refs.contains(*name))
+            .map(|(name, _)| name)
+            .collect();
+
+        for prod in &self.productions {
+            let refs = &reachable[&prod.name];
+            if !is_nonterminal_name(&prod.name)
+                && (has_chars(prod.expr.clone()) || is_terminal_name(&prod.name))
+                && !recursive.contains(&prod.name)
+                && !refs.iter().any(|name| recursive.contains(name))
+            {
+                self.lexical.insert(prod.name.to_string());
+                self.lexical.extend(refs.iter().cloned());
+            }
+        }
+
+        loop {
+            let added: Vec<String> = self
+                .productions
+                .iter()
+                .filter(|prod| {
+                    let refs = self.refs(prod.expr.clone());
+                    !self.lexical.contains(&prod.name)
+                        && !recursive.contains(&prod.name)
+                        && !is_nonterminal_name(&prod.name)
+                        && !refs.is_empty()
+                        && !has_strings(prod.expr.clone())
+                        && refs.iter().all(|name| self.lexical.contains(name))
+                })
+                .map(|prod| prod.name.to_string())
+                .collect();
+
+            if added.is_empty() {
+                break;
+            }
+            self.lexical.extend(added);
+        }
+    }
+
+    fn finish(mut self) -> Import {
+        self.split_rules();
+
+        let mut used_by_parser = HashSet::new();
+        let mut used_by_token = HashSet::new();
+        for prod in &self.productions {
+            let refs = self.refs(prod.expr.clone());
+            match self.lexical.contains(&prod.name) {
+                true => used_by_token.extend(refs),
+                false => used_by_parser.extend(refs),
+            }
+        }
+
+        let mut parser_rules = vec![];
+        let mut token_rules = vec![];
+        let mut extern_tokens = vec![];
+        let mut converted = HashMap::new();
+
+        for idx in 0..self.productions.len() {
+            let name = self.productions[idx].name.to_string();
+            let pair = self.productions[idx].pair.clone();
+
+            // Productions only used inside of tokens are inlined there, like fragments
+            if used_by_token.contains(&name) && !used_by_parser.contains(&name) {
+                continue;
+            }
+
+            if self.lexical.contains(&name) {
+                match self.resolve_token(&name, &mut converted) {
+                    Ok(node) => token_rules.push(TokenRule {
+                        name: token_name(&name),
+                        doc: vec![],
+                        annotations: vec![],
+                        node,
+                    }),
+                    Err(reason) => {
+                        self.warn(
+                            &pair,
+                            format!(
+                                "Token '{}' can't be converted ({}) and was declared extern",
+                                token_name(&name),
+                                reason
+                            ),
+                        );
+                        extern_tokens.push(token_name(&name));
+                    }
+                }
+            } else {
+                let node = self
+                    .parser_expr(self.productions[idx].expr.clone())
+                    .unwrap_or(Node::Empty);
+                parser_rules.push(ParserRule {
+                    name: rule_name(&name),
+                    params: vec![],
+                    args: vec![],
+                    returns: vec![],
+                    doc: vec![],
+                    node,
+                });
+            }
+        }
+
+        let mut grammar = Grammar {
+            name: None,
+            kind: GrammarKind::Combined,
+            options: HashMap::new(),
+            parser_rules,
+            token_rules,
+            extern_tokens,
+        };
+        add_literal_tokens(&mut grammar);
+
+        // Tokens are converted as they are referenced, but their warnings belong in file order
+        self.warnings
+            .sort_by_key(|warning| (warning.line, warning.column));
+
+        Import {
+            grammar,
+            warnings: self.warnings,
+        }
+    }
+
+    fn parser_expr(&mut self, pair: Pair<Rule>) -> Option<Node> {
+        let mut nodes = vec![];
+        for seq in pair.into_inner() {
+            match self.parser_seq(seq).unwrap_or(Node::Empty) {
+                Node::Alternatives { nodes: inner } => nodes.extend(inner),
+                node => nodes.push(node),
+            }
+        }
+
+        match nodes.len() {
+            1 => nodes.pop(),
+            _ => Some(Node::Alternatives { nodes }),
+        }
+    }
+
+    fn parser_seq(&mut self, pair: Pair<Rule>) -> Option<Node> {
+        let nodes: Vec<Node> = pair
+            .into_inner()
+            .filter_map(|diff| self.parser_diff(diff))
+            .collect();
+        let mut nodes = merge_insensitive(nodes);
+
+        match nodes.len() {
+            0 => None,
+            1 => nodes.pop(),
+            _ => Some(Node::MultipartBody { nodes }),
+        }
+    }
+
+    fn parser_diff(&mut self, pair: Pair<Rule>) -> Option<Node> {
+        let mut items = pair.into_inner();
+        let node = self.parser_item(items.next().unwrap());
+
+        if let Some(excluded) = items.next() {
+            self.warn(
+                &excluded,
+                format!(
+                    "Exclusion '- {}' is only supported in token rules and was dropped",
+                    excluded.as_str()
+                ),
+            );
+        }
+        node
+    }
+
+    fn parser_item(&mut self, pair: Pair<Rule>) -> Option<Node> {
+        let mut inner = pair.into_inner();
+        let node = self.parser_primary(inner.next().unwrap())?;
+        Some(match inner.next() {
+            Some(suffix) => apply_suffix(suffix.as_str(), node),
+            None => node,
+        })
+    }
+
+    fn parser_primary(&mut self, pair: Pair<Rule>) -> Option<Node> {
+        let inner = pair.into_inner().next().unwrap();
+        match inner.as_rule() {
+            Rule::expression => self.parser_expr(inner),
+            Rule::symbol => {
+                let name = inner.as_str();
+                Some(match self.lexical.contains(name) {
+                    true => Node::TokenRef {
+                        name: token_name(name),
+                        replaced_lit: None,
+                    },
+                    // Undefined symbols are left for HRPG to report
+                    false => Node::RuleRef {
+                        name: rule_name(name),
+                        args: vec![],
+                    },
+                })
+            }
+            Rule::string => Some(string_node(inner.as_str())),
+            Rule::char_code => match char_code(inner.as_str()) {
+                Ok(ch) => Some(hrpg_literal(&ch.to_string())),
+                Err(err) => {
+                    self.warn(&inner, format!("{} - it was dropped", err));
+                    None
+                }
+            },
+            _ if case_letter(inner.as_str()).is_some() => case_letter(inner.as_str()),
+            _ => {
+                self.warn(
+                    &inner,
+                    format!(
+                        "Character class {} is only allowed in token rules and was dropped",
+                        inner.as_str()
+                    ),
+                );
+                None
+            }
+        }
+    }
+
+    // HRPG token rules can't refer to other tokens, so references are inlined (lexical productions aren't recursive)
+    fn resolve_token(
+        &mut self,
+        name: &str,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        if let Some(result) = converted.get(name) {
+            return result.clone();
+        }
+
+        let expr = self.production(name).unwrap().expr.clone();
+        let result = self.token_expr(expr, converted);
+
+        converted.insert(name.to_string(), result.clone());
+        result
+    }
+
+    fn token_expr(
+        &mut self,
+        pair: Pair<Rule>,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        let mut nodes = vec![];
+        for seq in pair.into_inner() {
+            match self.token_seq(seq, converted)? {
+                Node::Alternatives { nodes: inner } => nodes.extend(inner),
+                node => nodes.push(node),
+            }
+        }
+
+        Ok(match nodes.len() {
+            1 => nodes.remove(0),
+            _ => Node::Alternatives { nodes },
+        })
+    }
+
+    fn token_seq(
+        &mut self,
+        pair: Pair<Rule>,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        let mut nodes = vec![];
+        for diff in pair.into_inner() {
+            // Inlined sequences are flattened so they don't need parentheses
+            match self.token_diff(diff, converted)? {
+                Node::MultipartBody { nodes: inner } => nodes.extend(inner),
+                Node::Empty => (),
+                node => nodes.push(node),
+            }
+        }
+
+        let mut nodes = merge_insensitive(nodes);
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.remove(0),
+            _ => Node::MultipartBody { nodes },
+        })
+    }
+
+    // Only differences between sets of characters can be expressed, as a character class (ex: `Char - '-'`)
+    fn token_diff(
+        &mut self,
+        pair: Pair<Rule>,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        let text = pair.as_str().trim().to_string();
+        let mut items = pair.into_inner();
+        let node = self.token_item(items.next().unwrap(), converted)?;
+
+        let excluded = match items.next() {
+            Some(excluded) => self.token_item(excluded, converted)?,
+            None => return Ok(node),
+        };
+
+        match (char_ranges(&node), char_ranges(&excluded)) {
+            (Some(ranges), Some(excluded)) => {
+                let ranges = subtract(&ranges, &excluded);
+                match ranges.is_empty() {
+                    true => Err(format!("'{}' matches no characters", text)),
+                    false => Ok(Node::CharClass {
+                        class: class_from_ranges(false, &ranges),
+                    }),
+                }
+            }
+            _ => Err(format!("'{}' is not a difference of character sets", text)),
+        }
+    }
+
+    fn token_item(
+        &mut self,
+        pair: Pair<Rule>,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        let mut inner = pair.into_inner();
+        let node = self.token_primary(inner.next().unwrap(), converted)?;
+        Ok(match inner.next() {
+            Some(suffix) => apply_suffix(suffix.as_str(), node),
+            None => node,
+        })
+    }
+
+    fn token_primary(
+        &mut self,
+        pair: Pair<Rule>,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        let inner = pair.into_inner().next().unwrap();
+        match inner.as_rule() {
+            Rule::expression => self.token_expr(inner, converted),
+            Rule::symbol => match self.production(inner.as_str()) {
+                Some(_) => self.resolve_token(inner.as_str(), converted),
+                None => Err(format!("'{}' has no production", inner.as_str())),
+            },
+            Rule::string => Ok(string_node(inner.as_str())),
+            Rule::char_code => Ok(hrpg_literal(&char_code(inner.as_str())?.to_string())),
+            _ if case_letter(inner.as_str()).is_some() => Ok(case_letter(inner.as_str()).unwrap()),
+            _ => {
+                let (negated, ranges) = w3c_class(inner.as_str())?;
+                Ok(Node::CharClass {
+                    class: class_from_ranges(negated, &ranges),
+                })
+            }
+        }
+    }
+}
+
+// Whether an expression matches characters directly (rather than through other productions)
+fn has_chars(pair: Pair<Rule>) -> bool {
+    pair.into_inner().flatten().any(|inner| {
+        matches!(inner.as_rule(), Rule::char_class | Rule::char_code)
+            || (inner.as_rule() == Rule::difference && inner.into_inner().nth(1).is_some())
+    })
+}
+
+fn has_strings(pair: Pair<Rule>) -> bool {
+    pair.into_inner()
+        .flatten()
+        .any(|inner| inner.as_rule() == Rule::string)
+}
+
+// Specifications like SPARQL name their terminals in uppercase (ex: `IRIREF`, `PN_CHARS_BASE`)
+fn is_terminal_name(name: &str) -> bool {
+    name.chars().any(|ch| ch.is_ascii_uppercase())
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == '_')
+}
+
+// The W3C notation starts symbols with a lowercase letter unless they are the start of a regular language (ex:
+// `document`), although not every specification follows it
+fn is_nonterminal_name(name: &str) -> bool {
+    name.starts_with(|ch: char| ch.is_ascii_lowercase())
+}
+
+// A class of the uppercase and lowercase forms of a letter, which is how a literal that ignores case is spelled out
+// (ex: `[Ss] [Ee] [Ll] [Ee] [Cc] [Tt]`)
+fn case_letter(class: &str) -> Option<Node> {
+    match w3c_class(class).ok()? {
+        (false, ranges) => match ranges.as_slice() {
+            [(upper, _), (lower, _)]
+                if ranges.iter().all(|(start, end)| start == end)
+                    && upper.is_uppercase()
+                    && upper.to_lowercase().eq([*lower]) =>
+            {
+                Some(Node::TokenLit {
+                    literal: format!("'{}'", lower),
+                    case_insensitive: true,
+                })
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Adjacent literals that ignore case are joined back into one (ex: `'select'i`)
+fn merge_insensitive(nodes: Vec<Node>) -> Vec<Node> {
+    let mut merged: Vec<Node> = vec![];
+
+    for node in nodes {
+        match (merged.last_mut(), node) {
+            (
+                Some(Node::TokenLit {
+                    literal,
+                    case_insensitive: true,
+                }),
+                Node::TokenLit {
+                    literal: next,
+                    case_insensitive: true,
+                },
+            ) => {
+                literal.pop();
+                literal.push_str(&next[1..]);
+            }
+            (_, node) => merged.push(node),
+        }
+    }
+    merged
+}
+
+fn apply_suffix(suffix: &str, node: Node) -> Node {
+    match suffix {
+        "?" => Node::ZeroOrOne {
+            node: Box::new(node),
+            brackets: false,
+        },
+        "*" => Node::ZeroOrMore {
+            node: Box::new(node),
+        },
+        _ => Node::OneOrMore {
+            node: Box::new(node),
+        },
+    }
+}
+
+// W3C strings have no escapes - an empty string matches nothing
+fn string_node(string: &str) -> Node {
+    match &string[1..string.len() - 1] {
+        "" => Node::Empty,
+        value => hrpg_literal(value),
+    }
+}
+
+// `#x20`
+fn char_code(code: &str) -> Result<char, String> {
+    u32::from_str_radix(&code[2..], 16)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(|| format!("Invalid character code '{}'", code))
+}
+
+// The ranges of a W3C character class (ex: `[^#x20-#x7E<&]`)
+fn w3c_class(class: &str) -> Result<(bool, Vec<(char, char)>), String> {
+    let body = &class[1..class.len() - 1];
+    let (negated, body) = match body.strip_prefix('^') {
+        Some(body) => (true, body),
+        None => (false, body),
+    };
+
+    let mut chars = vec![];
+    let mut rest = body;
+    while let Some(ch) = rest.chars().next() {
+        let hex_len = rest
+            .strip_prefix("#x")
+            .map(|hex| {
+                hex.find(|ch: char| !ch.is_ascii_hexdigit())
+                    .unwrap_or(hex.len())
+            })
+            .unwrap_or(0);
+
+        match hex_len {
+            0 => {
+                // A '-' is only a range when it's between two characters
+                chars.push((ch, ch == '-'));
+                rest = &rest[ch.len_utf8()..];
+            }
+            _ => {
+                chars.push((char_code(&rest[..2 + hex_len])?, false));
+                rest = &rest[2 + hex_len..];
+            }
+        }
+    }
+
+    let mut ranges = vec![];
+    let mut idx = 0;
+    while idx < chars.len() {
+        let start = chars[idx].0;
+        match chars.get(idx + 1) {
+            Some((_, true)) if idx + 2 < chars.len() => {
+                let end = chars[idx + 2].0;
+                if end < start {
+                    return Err(format!("Invalid range in character class {}", class));
+                }
+                ranges.push((start, end));
+                idx += 3;
+            }
+            _ => {
+                ranges.push((start, start));
+                idx += 1;
+            }
+        }
+    }
+
+    Ok((negated, merge(ranges)))
+}
+
+// The characters a token node matches, when it only ever matches one character
+fn char_ranges(node: &Node) -> Option<Vec<(char, char)>> {
+    match node {
+        Node::CharClass { class } => {
+            let class = parse_char_class(class).ok()?;
+            Some(match class.negated {
+                true => complement(&class.ranges),
+                false => class.ranges,
+            })
+        }
+        Node::TokenLit {
+            literal,
+            case_insensitive: false,
+        } => {
+            let value = unescape_literal(literal).ok()?;
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => Some(vec![(ch, ch)]),
+                _ => None,
+            }
+        }
+        Node::Alternatives { nodes } => {
+            let mut ranges = vec![];
+            for node in nodes {
+                ranges.extend(char_ranges(node)?);
+            }
+            Some(merge(ranges))
+        }
+        _ => None,
+    }
+}
+
+fn class_from_ranges(negated: bool, ranges: &[(char, char)]) -> String {
+    let items: Vec<String> = ranges
+        .iter()
+        .map(|(start, end)| match start == end {
+            true => class_item(*start),
+            false => format!("{}-{}", class_item(*start), class_item(*end)),
+        })
+        .collect();
+    format!("[{}{}]", if negated { "^" } else { "" }, items.join(""))
+}
+
+// Characters outside of ASCII are written as code points, the same as the `#xN` they usually come from
+fn class_item(ch: char) -> String {
+    match ch.is_ascii() {
+        true => class_char(ch),
+        false => format!("\\u{{{:x}}}", ch as u32),
+    }
+}
+
+fn merge(mut ranges: Vec<(char, char)>) -> Vec<(char, char)> {
+    ranges.sort();
+    let mut merged: Vec<(char, char)> = vec![];
+
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if next_char(last.1).is_none_or(|next| start <= next) => {
+                last.1 = last.1.max(end)
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn complement(ranges: &[(char, char)]) -> Vec<(char, char)> {
+    subtract(&[('\0', char::MAX)], ranges)
+}
+
+// Both sets of ranges are sorted and don't overlap
+fn subtract(ranges: &[(char, char)], excluded: &[(char, char)]) -> Vec<(char, char)> {
+    let mut result = vec![];
+
+    for &(start, end) in ranges {
+        let mut start = Some(start);
+        for &(ex_start, ex_end) in excluded {
+            let current = match start {
+                Some(current) if current <= end => current,
+                _ => break,
+            };
+            if ex_end < current || ex_start > end {
+                continue;
+            }
+            if ex_start > current {
+                result.push((current, prev_char(ex_start).unwrap()));
+            }
+            start = next_char(ex_end);
+        }
+
+        match start {
+            Some(start) if start <= end => result.push((start, end)),
+            _ => (),
+        }
+    }
+    result
+}
+
+// Surrogates aren't characters, so they are skipped
+fn next_char(ch: char) -> Option<char> {
+    match ch {
+        '\u{d7ff}' => Some('\u{e000}'),
+        ch => char::from_u32(ch as u32 + 1),
+    }
+}
+
+fn prev_char(ch: char) -> Option<char> {
+    match ch {
+        '\u{e000}' => Some('\u{d7ff}'),
+        ch => (ch as u32).checked_sub(1).and_then(char::from_u32),
+    }
+}
+
+fn token_name(name: &str) -> String {
+    name.to_case(Case::UpperSnake)
+}
+
+fn rule_name(name: &str) -> String {
+    name.to_case(Case::Snake)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ebnf::write_ebnf;
+    use crate::export::hrpg::write_grammar;
+    use crate::transform::Transform;
+
+    const XML: &str = "document ::= XMLDecl? element\n\
+                       XMLDecl ::= '<?xml' S 'version' Eq '\"1.0\"' S? '?>'\n\
+                       element ::= '<' Name S? '/>' | '<' Name S? '>' content '</' Name S? '>'\n\
+                       content ::= (element | CharData)*\n\
+                       Eq ::= S? '=' S?\n\
+                       S ::= (#x20 | #x9 | #xD | #xA)+\n\
+                       Name ::= [a-zA-Z_:] [a-zA-Z0-9_:.-]*\n\
+                       CharData ::= [^<&]*\n";
+
+    fn import(data: &str) -> (String, Vec<String>) {
+        let import = import_ebnf(data).unwrap();
+        let warnings = import
+            .warnings
+            .iter()
+            .map(|warning| warning.message.clone())
+            .collect();
+        (write_grammar(&import.grammar), warnings)
+    }
+
+    #[test]
+    fn small_grammar() {
+        let (hrpg, warnings) = import(XML);
+        assert_eq!(
+            hrpg,
+            "document: xml_decl? element;\n\n\
+             xml_decl: '<?xml' S 'version' eq '\"1.0\"' S? '?>';\n\n\
+             element\n    : '<' NAME S? '/>'\n    | '<' NAME S? '>' content '</' NAME S? '>'\n    ;\n\n\
+             content: (element | CHAR_DATA)*;\n\n\
+             eq: S? '=' S?;\n\n\
+             LANGLE_QUEST_MARK_XML: '<?xml';\n\
+             VERSION: 'version';\n\
+             DOUBLE_QUOTE_1_DOT_0_DOUBLE_QUOTE: '\"1.0\"';\n\
+             QUEST_MARK_RANGLE: '?>';\n\
+             LANGLE: '<';\n\
+             SLASH_RANGLE: '/>';\n\
+             RANGLE: '>';\n\
+             LANGLE_SLASH: '</';\n\
+             EQUALS: '=';\n\
+             S: (' ' | '\\t' | '\\r' | '\\n')+;\n\
+             NAME: [:A-Z_a-z] [\\--.0-:A-Z_a-z]*;\n\
+             CHAR_DATA: [^&<]*;\n"
+        );
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn round_trip() {
+        let import = import_ebnf(XML).unwrap();
+        let (g2, transform) = Transform::process_with_vocab(&import.grammar, None);
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+
+        let export = write_ebnf(&g2);
+        assert!(export.warnings.is_empty(), "{:?}", export.warnings);
+        let reimport = import_ebnf(&export.text).unwrap();
+        assert_eq!(
+            write_grammar(&reimport.grammar),
+            write_grammar(&import.grammar)
+        );
+    }
+}
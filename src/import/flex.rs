@@ -0,0 +1,616 @@
+use std::collections::HashMap;
+
+use convert_case::{Case, Casing};
+use pest::iterators::Pair;
+use pest::Parser;
+
+use crate::ast::{Grammar, GrammarKind, Node, TokenRule};
+use crate::import::{
+    class_char, hrpg_literal, repeat_node, unescape_c, unescape_c_char, Import, ImportWarning,
+};
+use crate::lexer_gen::SKIP_ANNOTATION;
+
+#[derive(pest_derive::Parser)]
+#[grammar = "import/FLEX.pest"]
+struct FlexParser;
+
+// Flex's `.` matches anything except a newline
+const DOT_CHAR: &str = "[^\\n]";
+
+// Name of the token matching the input the scanner skips
+const SKIP_TOKEN: &str = "SKIP";
+
+// Converts the rules of a flex scanner that return a token into the token rules of a lexer grammar
+pub fn import_flex(data: &str) -> Result<Import, Box<pest::error::Error<Rule>>> {
+    let pairs = FlexParser::parse(Rule::flex_file, data)
+        .map_err(Box::new)?
+        .next()
+        .unwrap()
+        .into_inner();
+
+    let mut importer = FlexImporter::default();
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::definitions => {
+                for definition in pair.into_inner() {
+                    importer.definition(definition.into_inner().next().unwrap());
+                }
+            }
+            Rule::flex_rules => {
+                let rules: Vec<Pair<Rule>> = pair
+                    .into_inner()
+                    .map(|line| line.into_inner().next().unwrap())
+                    .filter(|line| line.as_rule() == Rule::flex_rule)
+                    .collect();
+                importer.flex_rules(rules);
+            }
+            Rule::user_code if !pair.as_str().trim().is_empty() => {
+                importer.warn(&pair, "User code section was dropped".to_string())
+            }
+            _ => (),
+        }
+    }
+    Ok(importer.finish())
+}
+
+// What the action of a rule returns
+enum Returned {
+    Token(String),
+    // `return yytext[0];` or `return '+';`
+    Char,
+    Nothing,
+}
+
+#[derive(Default)]
+struct FlexImporter<'i> {
+    definitions: HashMap<String, Pair<'i, Rule>>,
+    // Token name -> its patterns, in the order first returned
+    tokens: Vec<(String, Vec<Node>)>,
+    // Patterns that don't return a token
+    skipped: Vec<Node>,
+    warnings: Vec<ImportWarning>,
+}
+
+impl<'i> FlexImporter<'i> {
+    fn warn(&mut self, pair: &Pair<Rule>, message: String) {
+        self.warnings
+            .push(ImportWarning::new(&pair.as_span(), message));
+    }
+
+    fn definition(&mut self, pair: Pair<'i, Rule>) {
+        match pair.as_rule() {
+            Rule::code_block => self.warn(&pair, "C code block was dropped".to_string()),
+            Rule::directive => {
+                let text = pair.as_str().trim();
+                self.warn(&pair, format!("Directive '{}' was dropped", text));
+            }
+            Rule::name_def => {
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap().as_str().to_string();
+                self.definitions.insert(name, inner.next().unwrap());
+            }
+            _ => (),
+        }
+    }
+
+    fn flex_rules(&mut self, rules: Vec<Pair<'i, Rule>>) {
+        for (idx, rule) in rules.iter().enumerate() {
+            let mut start_conds = None;
+            let mut pattern = None;
+            for inner in rule.clone().into_inner() {
+                match inner.as_rule() {
+                    Rule::start_conds => start_conds = Some(inner),
+                    Rule::pattern => pattern = Some(inner),
+                    _ => (),
+                }
+            }
+            let pattern = pattern.unwrap();
+            let text = pattern.as_str();
+
+            // `|` shares the action of the next rule that has one
+            let action = rules[idx..]
+                .iter()
+                .map(|rule| rule.clone().into_inner().last().unwrap())
+                .map(|action| action.into_inner().next().unwrap())
+                .find(|action| action.as_rule() != Rule::same_action)
+                .map_or("", |action| action.as_str());
+
+            let name = match self.returned(rule, action) {
+                Returned::Token(name) => name,
+                Returned::Char => {
+                    self.warn(
+                        rule,
+                        format!(
+                            "Pattern '{}' returns the character it matched - literals of the parser are used instead",
+                            text
+                        ),
+                    );
+                    continue;
+                }
+                // Skipped input (ex: whitespace) - skipping it everywhere would be wrong for a start condition
+                Returned::Nothing => {
+                    match start_conds.filter(|start_conds| start_conds.as_str() != "<INITIAL>") {
+                        Some(start_conds) => self.warn(
+                            &start_conds,
+                            format!(
+                                "Start conditions are not supported - pattern '{}' doesn't return a token and was dropped",
+                                text
+                            ),
+                        ),
+                        None => match self.pattern(pattern.clone()) {
+                            Ok(node) => self.skipped.push(node),
+                            Err(reason) => self.warn(
+                                rule,
+                                format!(
+                                    "Skipped pattern '{}' can't be converted ({}) and was dropped",
+                                    text, reason
+                                ),
+                            ),
+                        },
+                    }
+                    continue;
+                }
+            };
+
+            if let Some(start_conds) = start_conds {
+                if start_conds.as_str() != "<INITIAL>" {
+                    self.warn(
+                        &start_conds,
+                        format!(
+                            "Start conditions are not supported - pattern '{}' was added to the default state",
+                            text
+                        ),
+                    );
+                }
+            }
+
+            // Tokens without any pattern that could be converted end up extern
+            let idx = match self.tokens.iter().position(|(other, _)| *other == name) {
+                Some(idx) => idx,
+                None => {
+                    self.tokens.push((name.to_string(), vec![]));
+                    self.tokens.len() - 1
+                }
+            };
+
+            match self.pattern(pattern.clone()) {
+                Ok(node) => self.tokens[idx].1.push(node),
+                Err(reason) => self.warn(
+                    rule,
+                    format!(
+                        "Pattern '{}' of token '{}' can't be converted ({}) and was dropped",
+                        text, name, reason
+                    ),
+                ),
+            }
+        }
+    }
+
+    // Finds the token returned by an action (ex: `{ yylval = atoi(yytext); return NUM; }`)
+    fn returned(&mut self, rule: &Pair<Rule>, action: &str) -> Returned {
+        let mut returned = vec![];
+        let mut rest = action;
+
+        while let Some(idx) = rest.find("return") {
+            let before = rest[..idx].chars().last();
+            rest = &rest[idx + "return".len()..];
+            if before.is_some_and(|ch| ch.is_alphanumeric() || ch == '_')
+                || rest.starts_with(|ch: char| ch.is_alphanumeric() || ch == '_')
+            {
+                continue;
+            }
+
+            let value = rest.trim_start().trim_start_matches('(').trim_start();
+            if value.starts_with('\'')
+                || value.starts_with("yytext")
+                || value.starts_with("*yytext")
+            {
+                returned.push(None);
+                continue;
+            }
+
+            // C++ scanners qualify token names (ex: `return yy::parser::token::NUM;`)
+            let path: String = value
+                .chars()
+                .take_while(|ch| ch.is_alphanumeric() || *ch == '_' || *ch == ':')
+                .collect();
+            if let Some(name) = path.rsplit("::").next().filter(|name| !name.is_empty()) {
+                let name = token_name(name);
+                if !returned.contains(&Some(name.clone())) {
+                    returned.push(Some(name));
+                }
+            }
+        }
+
+        let mut tokens = returned.iter().flatten();
+        match (returned.first(), tokens.next(), tokens.next()) {
+            (None, _, _) => Returned::Nothing,
+            (_, None, _) => Returned::Char,
+            (_, Some(first), Some(_)) => {
+                self.warn(
+                    rule,
+                    format!(
+                        "Action returns several tokens - pattern was only added to '{}'",
+                        first
+                    ),
+                );
+                Returned::Token(first.to_string())
+            }
+            (_, Some(first), None) => Returned::Token(first.to_string()),
+        }
+    }
+
+    fn pattern(&self, pair: Pair<Rule>) -> Result<Node, String> {
+        let mut node = None;
+
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::regex => node = Some(self.regex(inner, &mut vec![])?),
+                Rule::eof_pattern => return Err("'<<EOF>>' isn't a pattern".to_string()),
+                Rule::bol => return Err("'^' anchors aren't supported".to_string()),
+                Rule::eol => return Err("'$' anchors aren't supported".to_string()),
+                _ => return Err("trailing context isn't supported".to_string()),
+            }
+        }
+
+        Ok(node.unwrap())
+    }
+
+    fn regex(&self, pair: Pair<Rule>, stack: &mut Vec<String>) -> Result<Node, String> {
+        let mut nodes = vec![];
+        for branch in pair.into_inner() {
+            match self.branch(branch, stack)? {
+                Node::Alternatives { nodes: inner } => nodes.extend(inner),
+                node => nodes.push(node),
+            }
+        }
+
+        Ok(match nodes.len() {
+            1 => nodes.remove(0),
+            _ => Node::Alternatives { nodes },
+        })
+    }
+
+    // Consecutive characters are joined into one literal (ex: `if` -> `'if'`)
+    fn branch(&self, pair: Pair<Rule>, stack: &mut Vec<String>) -> Result<Node, String> {
+        let mut nodes = vec![];
+        let mut literal = String::new();
+
+        for piece in pair.into_inner() {
+            let mut inner = piece.into_inner();
+            let atom = inner.next().unwrap().into_inner().next().unwrap();
+            let quantifiers: Vec<&str> = inner.map(|quantifier| quantifier.as_str()).collect();
+
+            if quantifiers.is_empty() {
+                if let Some(value) = atom_value(&atom)? {
+                    literal += &value;
+                    continue;
+                }
+            }
+
+            if !literal.is_empty() {
+                nodes.push(hrpg_literal(&literal));
+                literal.clear();
+            }
+
+            let mut node = self.atom(atom, stack)?;
+            for quantifier in quantifiers {
+                node = quantify(node, quantifier);
+            }
+            match node {
+                Node::MultipartBody { nodes: inner } => nodes.extend(inner),
+                node => nodes.push(node),
+            }
+        }
+
+        if !literal.is_empty() {
+            nodes.push(hrpg_literal(&literal));
+        }
+
+        Ok(match nodes.len() {
+            0 => return Err("empty pattern".to_string()),
+            1 => nodes.remove(0),
+            _ => Node::MultipartBody { nodes },
+        })
+    }
+
+    fn atom(&self, pair: Pair<Rule>, stack: &mut Vec<String>) -> Result<Node, String> {
+        if let Some(value) = atom_value(&pair)? {
+            return Ok(hrpg_literal(&value));
+        }
+
+        match pair.as_rule() {
+            Rule::regex => self.regex(pair, stack),
+            Rule::re_class => Ok(Node::CharClass {
+                class: flex_class(pair.as_str())?,
+            }),
+            Rule::name_ref => {
+                let name = pair.into_inner().next().unwrap().as_str();
+                if stack.iter().any(|other| other == name) {
+                    return Err(format!("'{{{}}}' is recursive", name));
+                }
+                let definition = self
+                    .definitions
+                    .get(name)
+                    .ok_or_else(|| format!("'{{{}}}' has no definition", name))?
+                    .clone();
+
+                stack.push(name.to_string());
+                let result = self.regex(definition, stack);
+                stack.pop();
+                result
+            }
+            _ => Ok(Node::CharClass {
+                class: DOT_CHAR.to_string(),
+            }),
+        }
+    }
+
+    fn finish(mut self) -> Import {
+        let mut token_rules = vec![];
+        let mut extern_tokens = vec![];
+
+        for (name, mut nodes) in self.tokens {
+            match nodes.len() {
+                0 => extern_tokens.push(name),
+                1 => token_rules.push(TokenRule {
+                    name,
+                    doc: vec![],
+                    annotations: vec![],
+                    node: nodes.remove(0),
+                }),
+                _ => token_rules.push(TokenRule {
+                    name,
+                    doc: vec![],
+                    annotations: vec![],
+                    node: Node::Alternatives { nodes },
+                }),
+            }
+        }
+
+        // Skipped patterns become a single `@skip` token
+        if !self.skipped.is_empty() {
+            let mut name = SKIP_TOKEN.to_string();
+            while token_rules.iter().any(|rule| rule.name == name) || extern_tokens.contains(&name)
+            {
+                name.push('_');
+            }
+            let mut nodes = self.skipped;
+            token_rules.push(TokenRule {
+                name,
+                doc: vec![],
+                annotations: vec![(SKIP_ANNOTATION.to_string(), String::new())],
+                node: match nodes.len() {
+                    1 => nodes.remove(0),
+                    _ => Node::Alternatives { nodes },
+                },
+            });
+        }
+
+        self.warnings
+            .sort_by_key(|warning| (warning.line, warning.column));
+
+        Import {
+            grammar: Grammar {
+                name: None,
+                kind: GrammarKind::Lexer,
+                options: HashMap::new(),
+                parser_rules: vec![],
+                token_rules,
+                extern_tokens,
+            },
+            warnings: self.warnings,
+        }
+    }
+}
+
+// The text matched by an atom that only matches one string
+fn atom_value(pair: &Pair<Rule>) -> Result<Option<String>, String> {
+    let text = pair.as_str();
+    Ok(match pair.as_rule() {
+        Rule::re_string => {
+            let value = unescape_c(&text[1..text.len() - 1])
+                .map_err(|err| format!("{} in {}", err, text))?;
+            match value.is_empty() {
+                true => return Err("empty string".to_string()),
+                false => Some(value),
+            }
+        }
+        Rule::re_escape => Some(unescape_c(text).map_err(|err| format!("{} in {}", err, text))?),
+        Rule::re_char => Some(text.to_string()),
+        _ => None,
+    })
+}
+
+fn quantify(node: Node, quantifier: &str) -> Node {
+    match quantifier {
+        "*" => Node::ZeroOrMore {
+            node: Box::new(node),
+        },
+        "+" => Node::OneOrMore {
+            node: Box::new(node),
+        },
+        "?" => Node::ZeroOrOne {
+            node: Box::new(node),
+            brackets: false,
+        },
+        // `{n}`, `{n,}` or `{n,m}`
+        _ => {
+            let counts = &quantifier[1..quantifier.len() - 1];
+            let (min, max) = match counts.split_once(',') {
+                Some((min, max)) => (min, max.parse().ok()),
+                None => (counts, counts.parse().ok()),
+            };
+            repeat_node(node, min.parse().unwrap_or(0), max)
+        }
+    }
+}
+
+// Converts a flex character class to an HRPG one (ex: `[^"\\\n]`, `[[:alpha:]_]`)
+fn flex_class(class: &str) -> Result<String, String> {
+    let body = &class[1..class.len() - 1];
+    let (negated, body) = match body.strip_prefix('^') {
+        Some(body) => (true, body),
+        None => (false, body),
+    };
+
+    // Some(ch) for characters, None for an unescaped '-'
+    let mut items: Vec<Option<char>> = vec![];
+    let mut posix = vec![];
+    let mut chars = body.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '[' if chars.as_str().starts_with(':') => {
+                let rest = chars.as_str();
+                let end = rest.find(":]").unwrap();
+                posix.push(posix_class(&rest[1..end])?);
+                chars = rest[end + 2..].chars();
+            }
+            '\\' => items.push(Some(unescape_c_char(&mut chars)?)),
+            '-' => items.push(None),
+            ch => items.push(Some(ch)),
+        }
+    }
+
+    let mut result = String::new();
+    let mut idx = 0;
+    while idx < items.len() {
+        // A '-' is only a range when it is between two characters
+        match &items[idx..] {
+            [Some(start), None, Some(end), ..] => {
+                result += &format!("{}-{}", class_char(*start), class_char(*end));
+                idx += 3;
+                continue;
+            }
+            [Some(ch), ..] => result += &class_char(*ch),
+            _ => result += "\\-",
+        }
+        idx += 1;
+    }
+    result.extend(posix);
+
+    match result.is_empty() {
+        true => Err(format!("empty class {}", class)),
+        false => Ok(format!("[{}{}]", if negated { "^" } else { "" }, result)),
+    }
+}
+
+fn posix_class(name: &str) -> Result<&'static str, String> {
+    Ok(match name {
+        "alnum" => "a-zA-Z0-9",
+        "alpha" => "a-zA-Z",
+        "blank" => " \\t",
+        "cntrl" => "\\u{0}-\\u{1f}\\u{7f}",
+        "digit" => "0-9",
+        "graph" => "!-~",
+        "lower" => "a-z",
+        "print" => " -~",
+        "punct" => "!-/:-@\\[-`{-~",
+        "space" => " \\t\\n\\r\\u{b}\\u{c}",
+        "upper" => "A-Z",
+        "xdigit" => "0-9a-fA-F",
+        _ => return Err(format!("unknown class '[:{}:]'", name)),
+    })
+}
+
+// Token names are shared with the parser, so they are named the same way
+pub fn token_name(name: &str) -> String {
+    name.replace('.', "_").to_case(Case::UpperSnake)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::hrpg::write_grammar;
+
+    fn import(data: &str) -> (String, Vec<String>) {
+        let import = import_flex(data).unwrap();
+        let warnings = import
+            .warnings
+            .iter()
+            .map(|warning| warning.message.clone())
+            .collect();
+        (write_grammar(&import.grammar), warnings)
+    }
+
+    #[test]
+    fn small_scanner() {
+        let (hrpg, warnings) = import(
+            "DIGIT [0-9]\n\
+             ID    [a-zA-Z_][a-zA-Z0-9_]*\n\
+             %%\n\
+             \"if\"                    { return IF; }\n\
+             {DIGIT}+(\".\"{DIGIT}+)?  { yylval = atof(yytext); return NUMBER; }\n\
+             {ID}                    return yy::parser::token::ID;\n\
+             \\\"([^\"\\\\]|\\\\.)*\\\"       { return STRING; }\n\
+             a{2,3}                  { return AS; }\n\
+             [[:digit:]]x            { return DX; }\n",
+        );
+        assert_eq!(
+            hrpg,
+            "IF: 'if';\n\
+             NUMBER: [0-9]+ ('.' [0-9]+)?;\n\
+             ID: [a-zA-Z_] [a-zA-Z0-9_]*;\n\
+             STRING: '\"' ([^\"\\\\] | '\\\\' [^\\n])* '\"';\n\
+             AS: 'a' 'a' 'a'?;\n\
+             DX: [0-9] 'x';\n"
+        );
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn unsupported_patterns() {
+        let (hrpg, warnings) = import(
+            "%option noyywrap\n\
+             %%\n\
+             <COMMENT>\"*/\" { BEGIN(INITIAL); }\n\
+             ^#.*          { return DIRECTIVE; }\n\
+             .             { return yytext[0]; }\n",
+        );
+        assert_eq!(hrpg, "extern DIRECTIVE;\n");
+        assert_eq!(
+            warnings,
+            vec![
+                "Directive '%option noyywrap' was dropped",
+                "Start conditions are not supported - pattern '\"*/\"' doesn't return a token and was dropped",
+                "Pattern '^#.*' of token 'DIRECTIVE' can't be converted ('^' anchors aren't supported) and was dropped",
+                "Pattern '.' returns the character it matched - literals of the parser are used instead",
+            ]
+        );
+    }
+
+    #[test]
+    fn skipped_patterns() {
+        let (hrpg, warnings) = import(
+            "%%\n\
+             [ \\t]+     ;\n\
+             \\n         { line++; }\n\
+             \"//\".*     /* comment */\n\
+             [0-9]+     { return NUM; }\n",
+        );
+        assert_eq!(
+            hrpg,
+            "NUM: [0-9]+;\n\n\
+             ## @skip\n\
+             SKIP\n    : [ \\t]+\n    | '\\n'\n    | '//' [^\\n]*\n    ;\n"
+        );
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn shared_action() {
+        let (hrpg, _) = import("%%\n\"+\" |\n\"-\" { return OP; }\n");
+        assert_eq!(hrpg, "OP\n    : '+'\n    | '-'\n    ;\n");
+    }
+
+    #[test]
+    fn several_returned_tokens() {
+        let (hrpg, warnings) = import("%%\n\"x\" { if (a) return A; return B; }\n");
+        assert_eq!(hrpg, "A: 'x';\n");
+        assert_eq!(
+            warnings,
+            vec!["Action returns several tokens - pattern was only added to 'A'"]
+        );
+    }
+}
@@ -0,0 +1,537 @@
+pub mod antlr;
+pub mod ebnf;
+pub mod flex;
+pub mod pest;
+pub mod yacc;
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::ast::{Grammar, Node, TokenRule};
+use crate::escape::{escape_literal, unescape_literal};
+
+// Something in the imported grammar that HRPG can't express, so it was dropped or changed
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportWarning {
+    // Only set when the warning is about a file other than the one being imported (ex: a yacc grammar's lexer)
+    pub file: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ImportWarning {
+    pub fn new(span: &::pest::Span, message: String) -> Self {
+        let (line, column) = span.start_pos().line_col();
+        ImportWarning {
+            file: None,
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ImportWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub struct Import {
+    pub grammar: Grammar,
+    pub warnings: Vec<ImportWarning>,
+}
+
+// Matches any character (ex: ANTLR's `.` in lexer rules)
+pub const ANY_CHAR: &str = "[\\u{0}-\\u{10ffff}]";
+
+// Quotes a literal value as an HRPG literal (ex: `it's` -> `'it\'s'`)
+pub fn hrpg_literal(value: &str) -> Node {
+    Node::TokenLit {
+        literal: format!("'{}'", escape_literal(value)),
+        case_insensitive: false,
+    }
+}
+
+// HRPG has no repetition counts, so they are spelled out (ex: `a{2,4}` -> `a a (a a?)?`, `a{2,}` -> `a a a*`)
+pub fn repeat_node(node: Node, min: usize, max: Option<usize>) -> Node {
+    let mut nodes = vec![node.clone(); min];
+    match max {
+        Some(max) => {
+            let mut optional = None;
+            for _ in min..max {
+                let inner = match optional {
+                    Some(optional) => Node::MultipartBody {
+                        nodes: vec![node.clone(), optional],
+                    },
+                    None => node.clone(),
+                };
+                optional = Some(Node::ZeroOrOne {
+                    node: Box::new(inner),
+                    brackets: false,
+                });
+            }
+            nodes.extend(optional);
+        }
+        None => nodes.push(Node::ZeroOrMore {
+            node: Box::new(node),
+        }),
+    }
+
+    match nodes.len() {
+        0 => Node::Empty,
+        1 => nodes.remove(0),
+        _ => Node::MultipartBody { nodes },
+    }
+}
+
+// Escapes a character for use inside of an HRPG character class
+pub fn class_char(ch: char) -> String {
+    match ch {
+        '\\' | ']' | '[' | '-' | '^' => format!("\\{}", ch),
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        ch if ch.is_control() => format!("\\u{{{:x}}}", ch as u32),
+        ch => ch.to_string(),
+    }
+}
+
+// Names a literal token after its words and symbols (ex: `'while'` -> `WHILE`, `'+='` -> `PLUS_EQUALS`,
+// `'=='` -> `DOUBLE_EQUALS`, `'<?xml'` -> `LANGLE_QUEST_MARK_XML`)
+pub fn literal_token_name(value: &str) -> Option<String> {
+    let is_word_char = |ch: &char| ch.is_ascii_alphanumeric() || *ch == '_';
+    let mut parts = vec![];
+    let mut chars = value.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if is_word_char(&ch) {
+            let mut word = ch.to_string();
+            while let Some(ch) = chars.next_if(is_word_char) {
+                word.push(ch);
+            }
+            let word = word.trim_start_matches('_').to_uppercase();
+            if !word.is_empty() {
+                parts.push(word);
+            }
+        } else {
+            parts.push(punct_name(ch)?.to_string());
+        }
+    }
+
+    let name = match parts.as_slice() {
+        [name, other] if name == other => format!("DOUBLE_{}", name),
+        parts => parts.join("_"),
+    };
+    // Token names have to start with an uppercase letter (ex: not `'1st'`)
+    name.starts_with(|ch: char| ch.is_ascii_uppercase())
+        .then_some(name)
+}
+
+fn punct_name(ch: char) -> Option<&'static str> {
+    Some(match ch {
+        '+' => "PLUS",
+        '-' => "MINUS",
+        '*' => "STAR",
+        '/' => "SLASH",
+        '\\' => "BACKSLASH",
+        '%' => "PERCENT",
+        '=' => "EQUALS",
+        '<' => "LANGLE",
+        '>' => "RANGLE",
+        '!' => "BANG",
+        '&' => "AMPERSAND",
+        '|' => "PIPE",
+        '^' => "CARET",
+        '~' => "TILDE",
+        '?' => "QUEST_MARK",
+        ':' => "COLON",
+        ';' => "SEMI",
+        ',' => "COMMA",
+        '.' => "DOT",
+        '(' => "LPAREN",
+        ')' => "RPAREN",
+        '[' => "LBRACKET",
+        ']' => "RBRACKET",
+        '{' => "LBRACE",
+        '}' => "RBRACE",
+        '@' => "AT",
+        '#' => "HASH",
+        '$' => "DOLLAR",
+        '\'' => "QUOTE",
+        '"' => "DOUBLE_QUOTE",
+        '`' => "BACKTICK",
+        _ => return None,
+    })
+}
+
+// Other formats let parser rules use literals that no token rule defines - HRPG needs a token rule for each, so
+// they are added ahead of the existing token rules (where the other formats usually give them priority)
+pub fn add_literal_tokens(grammar: &mut Grammar) {
+    let mut defined: Vec<String> = grammar
+        .token_rules
+        .iter()
+        .filter_map(|rule| literal_value(&rule.node).map(|(value, _)| value))
+        .collect();
+    let mut names: Vec<String> = grammar
+        .token_rules
+        .iter()
+        .map(|rule| rule.name.to_string())
+        .chain(grammar.extern_tokens.iter().cloned())
+        .collect();
+    let mut added = vec![];
+
+    let mut literals = vec![];
+    for rule in &grammar.parser_rules {
+        collect_literals(&rule.node, &mut literals);
+    }
+
+    for (value, case_insensitive) in literals {
+        if defined.contains(&value) {
+            continue;
+        }
+
+        // Literals that can't be named (ex: `' '`) are numbered instead
+        let (base, mut name) = match literal_token_name(&value) {
+            Some(base) => (base.clone(), base),
+            None => ("LIT".to_string(), "LIT_1".to_string()),
+        };
+        let mut count = 1;
+        while names.contains(&name) {
+            count += 1;
+            name = format!("{}_{}", base, count);
+        }
+
+        names.push(name.clone());
+        defined.push(value.clone());
+        added.push(TokenRule {
+            name,
+            doc: vec![],
+            annotations: vec![],
+            node: Node::TokenLit {
+                literal: format!("'{}'", escape_literal(&value)),
+                case_insensitive,
+            },
+        });
+    }
+
+    added.append(&mut grammar.token_rules);
+    grammar.token_rules = added;
+}
+
+fn literal_value(node: &Node) -> Option<(String, bool)> {
+    match node {
+        Node::TokenLit {
+            literal,
+            case_insensitive,
+        } => unescape_literal(literal)
+            .ok()
+            .map(|value| (value, *case_insensitive)),
+        _ => None,
+    }
+}
+
+fn collect_literals(node: &Node, literals: &mut Vec<(String, bool)>) {
+    match literal_value(node) {
+        Some((value, case_insensitive)) => {
+            if !literals.iter().any(|(other, _)| *other == value) {
+                literals.push((value, case_insensitive));
+            }
+        }
+        None => {
+            for child in node.children() {
+                collect_literals(child, literals);
+            }
+        }
+    }
+}
+
+// How `remove_left_recursion` changed a rule
+#[derive(Debug, PartialEq)]
+pub enum LeftRecursion {
+    None,
+    // Labels can't stay on alternatives that became part of the repetition, so all of them are dropped
+    Converted { dropped_labels: Vec<String> },
+    // Every alternative is left recursive, so the alternatives were kept as is
+    Unending,
+}
+
+// HRPG parsers can't be left recursive, so direct left recursion becomes repetition (ex: `list: list ',' item | item`
+// -> `list: item (',' item)*`)
+pub fn remove_left_recursion(name: &str, alts: Vec<Node>) -> (Node, LeftRecursion) {
+    if !alts.iter().any(|alt| recursive_tail(name, alt).is_some()) {
+        return (alternatives(alts), LeftRecursion::None);
+    }
+    if alts.iter().all(|alt| recursive_tail(name, alt).is_some()) {
+        return (alternatives(alts), LeftRecursion::Unending);
+    }
+
+    let mut dropped_labels = vec![];
+    let mut base = vec![];
+    let mut tails = vec![];
+
+    for alt in alts {
+        let alt = match alt {
+            Node::Labeled { label, node } => {
+                dropped_labels.push(label);
+                *node
+            }
+            alt => alt,
+        };
+        match recursive_tail(name, &alt) {
+            Some(tail) => tails.push(tail),
+            None => base.push(alt),
+        }
+    }
+
+    let tail = Node::ZeroOrMore {
+        node: Box::new(alternatives(tails)),
+    };
+    let node = match alternatives(base) {
+        Node::Empty => tail,
+        base => Node::MultipartBody {
+            nodes: vec![base, tail],
+        },
+    };
+    (node, LeftRecursion::Converted { dropped_labels })
+}
+
+// What follows the rule's reference to itself at the start of an alternative
+fn recursive_tail(name: &str, alt: &Node) -> Option<Node> {
+    let is_self = |node: &Node| matches!(node, Node::RuleRef { name: other, args } if other == name && args.is_empty());
+
+    match alt {
+        Node::Labeled { node, .. } => recursive_tail(name, node),
+        node if is_self(node) => Some(Node::Empty),
+        Node::MultipartBody { nodes } if is_self(&nodes[0]) => Some(match nodes.len() {
+            2 => nodes[1].clone(),
+            _ => Node::MultipartBody {
+                nodes: nodes[1..].to_vec(),
+            },
+        }),
+        _ => None,
+    }
+}
+
+fn alternatives(mut nodes: Vec<Node>) -> Node {
+    match nodes.len() {
+        0 => Node::Empty,
+        1 => nodes.remove(0),
+        _ => Node::Alternatives { nodes },
+    }
+}
+
+// Splits target language arguments on commas outside of brackets/parentheses, the same as HRPG does (ex:
+// `int a, int[] b`)
+pub fn split_args(args: &str) -> Vec<String> {
+    let mut result = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (idx, ch) in args.char_indices() {
+        match ch {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                result.push(args[start..idx].trim().to_string());
+                start = idx + 1;
+            }
+            _ => (),
+        }
+    }
+    result.push(args[start..].trim().to_string());
+
+    result.retain(|arg| !arg.is_empty());
+    result
+}
+
+// HRPG binding names must start with a lowercase letter
+pub fn binding_name(label: &str) -> String {
+    let label = label.trim_start_matches('_');
+    let mut chars = label.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => "value".to_string(),
+    }
+}
+
+// Unescapes the text of a C string or character literal, without its quotes (ex: `a\tb\x41\101`)
+pub fn unescape_c(text: &str) -> Result<String, String> {
+    let mut chars = text.chars();
+    let mut value = String::new();
+
+    while let Some(ch) = chars.next() {
+        value.push(match ch {
+            '\\' => unescape_c_char(&mut chars)?,
+            ch => ch,
+        });
+    }
+    Ok(value)
+}
+
+// Unescapes a C escape sequence - the backslash has been consumed
+pub fn unescape_c_char(chars: &mut std::str::Chars) -> Result<char, String> {
+    let ch = match chars.next() {
+        Some('n') => '\n',
+        Some('t') => '\t',
+        Some('r') => '\r',
+        Some('f') => '\u{c}',
+        Some('v') => '\u{b}',
+        Some('a') => '\u{7}',
+        Some('b') => '\u{8}',
+        Some('x') => {
+            let rest = chars.as_str();
+            let len = rest
+                .chars()
+                .take(2)
+                .take_while(|ch| ch.is_ascii_hexdigit())
+                .count();
+            let code = u32::from_str_radix(&rest[..len], 16)
+                .map_err(|_| "invalid escape '\\x'".to_string())?;
+            *chars = rest[len..].chars();
+            char::from_u32(code).unwrap()
+        }
+        Some(digit @ '0'..='7') => {
+            let rest = chars.as_str();
+            let len = rest
+                .chars()
+                .take(2)
+                .take_while(|ch| ('0'..='7').contains(ch))
+                .count();
+            let code = u32::from_str_radix(&format!("{}{}", digit, &rest[..len]), 8).unwrap();
+            *chars = rest[len..].chars();
+            char::from_u32(code).unwrap()
+        }
+        Some(ch) => ch,
+        None => return Err("incomplete escape sequence".to_string()),
+    };
+    Ok(ch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{GrammarKind, ParserRule};
+    use crate::export::hrpg::write_node;
+
+    fn names(parser_rule: Node) -> Vec<String> {
+        let mut grammar = Grammar {
+            name: None,
+            kind: GrammarKind::Combined,
+            options: Default::default(),
+            parser_rules: vec![ParserRule {
+                name: "a".to_string(),
+                params: vec![],
+                args: vec![],
+                returns: vec![],
+                doc: vec![],
+                node: parser_rule,
+            }],
+            token_rules: vec![],
+            extern_tokens: vec![],
+        };
+        add_literal_tokens(&mut grammar);
+        grammar
+            .token_rules
+            .into_iter()
+            .map(|rule| rule.name)
+            .collect()
+    }
+
+    fn rule_ref(name: &str) -> Node {
+        Node::RuleRef {
+            name: name.to_string(),
+            args: vec![],
+        }
+    }
+
+    #[test]
+    fn literal_names() {
+        let name = |value| literal_token_name(value);
+        assert_eq!(name("while").as_deref(), Some("WHILE"));
+        assert_eq!(name("_if").as_deref(), Some("IF"));
+        assert_eq!(name("+=").as_deref(), Some("PLUS_EQUALS"));
+        assert_eq!(name("==").as_deref(), Some("DOUBLE_EQUALS"));
+        assert_eq!(name("?>").as_deref(), Some("QUEST_MARK_RANGLE"));
+        assert_eq!(name("<?xml").as_deref(), Some("LANGLE_QUEST_MARK_XML"));
+        assert_eq!(name("1st"), None);
+        assert_eq!(name(" "), None);
+        assert_eq!(name("é"), None);
+        assert_eq!(name(""), None);
+    }
+
+    #[test]
+    fn unnamed_literals_are_numbered() {
+        let node = Node::MultipartBody {
+            nodes: vec![
+                hrpg_literal(" "),
+                hrpg_literal("\t"),
+                hrpg_literal("+"),
+                hrpg_literal(" "),
+            ],
+        };
+        assert_eq!(names(node), vec!["LIT_1", "LIT_2", "PLUS"]);
+    }
+
+    #[test]
+    fn literal_names_are_unique() {
+        let node = Node::Alternatives {
+            nodes: vec![hrpg_literal("if"), hrpg_literal("IF"), hrpg_literal("_if")],
+        };
+        assert_eq!(names(node), vec!["IF", "IF_2", "IF_3"]);
+    }
+
+    #[test]
+    fn left_recursion() {
+        let alts = vec![
+            Node::MultipartBody {
+                nodes: vec![rule_ref("a"), hrpg_literal("+"), rule_ref("b")],
+            },
+            Node::Labeled {
+                node: Box::new(rule_ref("b")),
+                label: "B".to_string(),
+            },
+        ];
+        let (node, recursion) = remove_left_recursion("a", alts);
+        assert_eq!(
+            recursion,
+            LeftRecursion::Converted {
+                dropped_labels: vec!["B".to_string()]
+            }
+        );
+        assert_eq!(write_node(&node), "b ('+' b)*");
+    }
+
+    #[test]
+    fn no_left_recursion() {
+        let alts = vec![rule_ref("b"), rule_ref("c")];
+        let (_, recursion) = remove_left_recursion("a", alts);
+        assert_eq!(recursion, LeftRecursion::None);
+
+        let (_, recursion) = remove_left_recursion("a", vec![rule_ref("a")]);
+        assert_eq!(recursion, LeftRecursion::Unending);
+    }
+
+    #[test]
+    fn args() {
+        assert_eq!(split_args("int a, int[] b"), vec!["int a", "int[] b"]);
+        assert_eq!(split_args("f(a, b), c"), vec!["f(a, b)", "c"]);
+        assert!(split_args(" ").is_empty());
+    }
+
+    #[test]
+    fn c_escapes() {
+        assert_eq!(unescape_c("a\\tb\\x41\\101\\0").unwrap(), "a\tbAA\0");
+        assert!(unescape_c("a\\").is_err());
+    }
+
+    #[test]
+    fn repeat_counts() {
+        let x = || hrpg_literal("x");
+        assert_eq!(write_node(&repeat_node(x(), 2, Some(2))), "'x' 'x'");
+        assert_eq!(write_node(&repeat_node(x(), 1, Some(3))), "'x' ('x' 'x'?)?");
+        assert_eq!(write_node(&repeat_node(x(), 1, None)), "'x' 'x'*");
+    }
+}
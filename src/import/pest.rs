@@ -0,0 +1,1002 @@
+use std::collections::{HashMap, HashSet};
+
+use convert_case::{Case, Casing};
+use pest::iterators::Pair;
+use pest::Parser;
+
+use crate::ast::{Grammar, GrammarKind, Node, ParserRule, TokenRule};
+use crate::escape::parse_char_class;
+use crate::import::{
+    add_literal_tokens, binding_name, class_char, hrpg_literal, repeat_node, Import, ImportWarning,
+    ANY_CHAR,
+};
+use crate::lexer_gen::SKIP_ANNOTATION;
+use crate::transform::EOF;
+
+#[derive(pest_derive::Parser)]
+#[grammar = "import/PEST.pest"]
+struct PestParser;
+
+// Pest inserts these between the elements of non-atomic rules
+const IMPLICIT_RULES: [&str; 2] = ["WHITESPACE", "COMMENT"];
+
+const STACK_RULES: [&str; 5] = ["PEEK", "PEEK_ALL", "POP", "POP_ALL", "DROP"];
+
+pub fn import_pest(data: &str) -> Result<Import, Box<pest::error::Error<Rule>>> {
+    let pairs = PestParser::parse(Rule::grammar_rules, data)
+        .map_err(Box::new)?
+        .next()
+        .unwrap()
+        .into_inner();
+
+    let mut importer = PestImporter::default();
+    for pair in pairs {
+        importer.process(pair);
+    }
+    Ok(importer.finish())
+}
+
+// Pest is scannerless, so rules are only split into token and parser rules once all of them are known
+struct PestRule<'i> {
+    name: String,
+    modifier: Option<&'i str>,
+    doc: Vec<String>,
+    pair: Pair<'i, Rule>,
+    expr: Pair<'i, Rule>,
+}
+
+#[derive(Default)]
+struct PestImporter<'i> {
+    rules: Vec<PestRule<'i>>,
+    // Rules that became (or are inlined into) token rules
+    lexical: HashSet<String>,
+    warnings: Vec<ImportWarning>,
+}
+
+impl<'i> PestImporter<'i> {
+    fn warn(&mut self, pair: &Pair<Rule>, message: String) {
+        self.warnings
+            .push(ImportWarning::new(&pair.as_span(), message));
+    }
+
+    fn process(&mut self, pair: Pair<'i, Rule>) {
+        match pair.as_rule() {
+            Rule::grammar_doc => self.warn(&pair, "Grammar doc comment was dropped".to_string()),
+            Rule::grammar_rule => {
+                let mut doc = vec![];
+                let mut name = String::new();
+                let mut modifier = None;
+                let mut expr = None;
+
+                for inner in pair.clone().into_inner() {
+                    match inner.as_rule() {
+                        Rule::rule_doc => {
+                            let text = inner.into_inner().next().unwrap().as_str().trim_end();
+                            doc.push(text.strip_prefix(' ').unwrap_or(text).to_string());
+                        }
+                        Rule::identifier => name = inner.as_str().to_string(),
+                        Rule::modifier => modifier = Some(inner.as_str()),
+                        Rule::expression => expr = Some(inner),
+                        _ => unreachable!(),
+                    }
+                }
+
+                self.rules.push(PestRule {
+                    name,
+                    modifier,
+                    doc,
+                    pair,
+                    expr: expr.unwrap(),
+                });
+            }
+            Rule::EOI => (),
+            _ => unreachable!(),
+        }
+    }
+
+    fn rule(&self, name: &str) -> Option<&PestRule<'i>> {
+        self.rules.iter().find(|rule| rule.name == name)
+    }
+
+    // Atomic rules and rules built directly from characters (ex: `digit = { '0'..'9' }`) become token rules -
+    // everything else stays a parser rule
+    fn split_rules(&mut self) {
+        for rule in &self.rules {
+            if matches!(rule.modifier, Some("@" | "$"))
+                || IMPLICIT_RULES.contains(&rule.name.as_str())
+            {
+                self.lexical.insert(rule.name.to_string());
+            }
+        }
+
+        loop {
+            let added: Vec<String> = self
+                .rules
+                .iter()
+                .filter(|rule| {
+                    !self.lexical.contains(&rule.name)
+                        && self.has_class(rule.expr.clone())
+                        && self
+                            .refs(rule.expr.clone())
+                            .iter()
+                            .all(|name| self.lexical.contains(name))
+                })
+                .map(|rule| rule.name.to_string())
+                .collect();
+
+            if added.is_empty() {
+                break;
+            }
+            self.lexical.extend(added);
+        }
+    }
+
+    // Whether an expression matches characters directly (rather than through other rules - which take precedence
+    // over builtins with the same name, ex: `NUMBER`)
+    fn has_class(&self, pair: Pair<Rule>) -> bool {
+        pair.into_inner()
+            .flatten()
+            .any(|inner| match inner.as_rule() {
+                Rule::range => true,
+                Rule::identifier => {
+                    let name = inner.as_str();
+                    self.rule(name).is_none() && (name == "ANY" || builtin_class(name).is_some())
+                }
+                _ => false,
+            })
+    }
+
+    // The rules an expression refers to (builtins aren't rules)
+    fn refs(&self, pair: Pair<Rule>) -> Vec<String> {
+        let mut refs = vec![];
+        for inner in pair.into_inner().flatten() {
+            if inner.as_rule() == Rule::identifier && self.rule(inner.as_str()).is_some() {
+                refs.push(inner.as_str().to_string());
+            }
+        }
+        refs
+    }
+
+    fn has_implicit_rules(&self) -> bool {
+        IMPLICIT_RULES.iter().any(|name| self.rule(name).is_some())
+    }
+
+    fn finish(mut self) -> Import {
+        self.split_rules();
+
+        let mut used_by_parser = HashSet::new();
+        let mut used_by_token = HashSet::new();
+        for rule in &self.rules {
+            let refs = self.refs(rule.expr.clone());
+            match self.lexical.contains(&rule.name) {
+                true => used_by_token.extend(refs),
+                false => used_by_parser.extend(refs),
+            }
+        }
+
+        let mut parser_rules = vec![];
+        let mut token_rules = vec![];
+        let mut extern_tokens = vec![];
+        let mut converted = HashMap::new();
+
+        for idx in 0..self.rules.len() {
+            let name = self.rules[idx].name.to_string();
+            let pair = self.rules[idx].pair.clone();
+
+            // Rules only used inside of tokens are inlined there, like fragments
+            if used_by_token.contains(&name)
+                && !used_by_parser.contains(&name)
+                && !IMPLICIT_RULES.contains(&name.as_str())
+            {
+                continue;
+            }
+
+            if self.rules[idx].modifier == Some("_") && !IMPLICIT_RULES.contains(&name.as_str()) {
+                self.warn(
+                    &pair,
+                    format!("Silent rule '{}' was imported as a regular rule", name),
+                );
+            }
+
+            // HRPG skips `@skip` tokens between the others, which is close to what pest does with these between
+            // the elements of non-atomic rules
+            let doc = self.rules[idx].doc.clone();
+            let annotations = match IMPLICIT_RULES.contains(&name.as_str()) {
+                true => vec![(SKIP_ANNOTATION.to_string(), String::new())],
+                false => vec![],
+            };
+            if self.lexical.contains(&name) {
+                match self.resolve_token(&name, &mut vec![], &mut converted) {
+                    Ok(node) => {
+                        // Characters of a token can't be separated like the elements of a non-atomic rule
+                        let atomic = matches!(self.rules[idx].modifier, Some("@" | "$"));
+                        if !atomic
+                            && !IMPLICIT_RULES.contains(&name.as_str())
+                            && self.has_implicit_rules()
+                            && has_sequence(self.rules[idx].expr.clone())
+                        {
+                            self.warn(
+                                &pair,
+                                format!(
+                                    "Rule '{}' was imported as a token, so implicit whitespace is no longer allowed inside of it",
+                                    name
+                                ),
+                            );
+                        }
+
+                        token_rules.push(TokenRule {
+                            name: token_name(&name),
+                            doc,
+                            annotations,
+                            node,
+                        });
+                    }
+                    Err(reason) => {
+                        self.warn(
+                            &pair,
+                            format!(
+                                "Token '{}' can't be converted ({}) and was declared extern",
+                                token_name(&name),
+                                reason
+                            ),
+                        );
+                        extern_tokens.push(token_name(&name));
+                    }
+                }
+            } else {
+                let node = self
+                    .parser_expr(self.rules[idx].expr.clone())
+                    .unwrap_or(Node::Empty);
+                parser_rules.push(ParserRule {
+                    name: rule_name(&name),
+                    params: vec![],
+                    args: vec![],
+                    returns: vec![],
+                    doc,
+                    node,
+                });
+            }
+        }
+
+        let mut grammar = Grammar {
+            name: None,
+            kind: GrammarKind::Combined,
+            options: HashMap::new(),
+            parser_rules,
+            token_rules,
+            extern_tokens,
+        };
+        add_literal_tokens(&mut grammar);
+
+        // Tokens are converted as they are referenced, but their warnings belong in file order
+        self.warnings
+            .sort_by_key(|warning| (warning.line, warning.column));
+
+        Import {
+            grammar,
+            warnings: self.warnings,
+        }
+    }
+
+    fn parser_expr(&mut self, pair: Pair<Rule>) -> Option<Node> {
+        let mut nodes = vec![];
+        for seq in pair.into_inner() {
+            match self.parser_seq(seq) {
+                Some(Node::Alternatives { nodes: inner }) => nodes.extend(inner),
+                Some(node) => nodes.push(node),
+                None => (),
+            }
+        }
+
+        match nodes.len() {
+            0 => None,
+            1 => nodes.pop(),
+            _ => Some(Node::Alternatives { nodes }),
+        }
+    }
+
+    // `!x ~ ANY` matches a single character, which a parser rule can't do - keeping only the lookahead would
+    // match nothing at all, so the pair is dropped together (along with the alternative if nothing else is left)
+    fn parser_seq(&mut self, pair: Pair<Rule>) -> Option<Node> {
+        let mut nodes = vec![];
+        let mut dropped_any = false;
+        let mut terms = pair.into_inner().peekable();
+
+        while let Some(term) = terms.next() {
+            if negated_node(&term).is_some() && terms.peek().is_some_and(is_any) {
+                let any = terms.next().unwrap();
+                self.warn(
+                    &term,
+                    format!(
+                        "'{} ~ {}' matches single characters, which is only possible in token rules - it was dropped",
+                        term.as_str().trim(),
+                        any.as_str().trim()
+                    ),
+                );
+                dropped_any = true;
+                continue;
+            }
+
+            nodes.extend(self.parser_term(term));
+        }
+
+        match nodes.len() {
+            0 if dropped_any => None,
+            0 => Some(Node::Empty),
+            1 => nodes.pop(),
+            _ => Some(Node::MultipartBody { nodes }),
+        }
+    }
+
+    fn parser_term(&mut self, pair: Pair<Rule>) -> Option<Node> {
+        let mut tag = None;
+        let mut prefixes = vec![];
+        let mut node = None;
+
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::tag => tag = Some(binding_name(inner.into_inner().next().unwrap().as_str())),
+                Rule::prefix => prefixes.push(inner.as_str()),
+                Rule::node => node = Some(self.parser_node(inner)?),
+                Rule::postfix => node = Some(postfix(inner, node.unwrap())),
+                _ => unreachable!(),
+            }
+        }
+
+        let mut node = node.unwrap();
+        for prefix in prefixes.into_iter().rev() {
+            let inner = Box::new(node);
+            node = match prefix {
+                "&" => Node::PositiveLookahead { node: inner },
+                _ => Node::NegativeLookahead { node: inner },
+            };
+        }
+
+        // Tags name a node of the parse tree, which is what bindings do
+        Some(match tag {
+            Some(name) => Node::Binding {
+                name,
+                list: false,
+                node: Box::new(node),
+            },
+            None => node,
+        })
+    }
+
+    fn parser_node(&mut self, pair: Pair<Rule>) -> Option<Node> {
+        let inner = pair.into_inner().next().unwrap();
+        if inner.as_rule() == Rule::expression {
+            return self.parser_expr(inner);
+        }
+
+        let terminal = inner.into_inner().next().unwrap();
+        match terminal.as_rule() {
+            Rule::push => {
+                self.warn(
+                    &terminal,
+                    "Stack operation 'PUSH' is not supported - only its expression was kept"
+                        .to_string(),
+                );
+                self.parser_expr(terminal.into_inner().next().unwrap())
+            }
+            Rule::peek_slice => {
+                self.warn(
+                    &terminal,
+                    format!(
+                        "Stack operation '{}' is not supported and was dropped",
+                        terminal.as_str()
+                    ),
+                );
+                None
+            }
+            Rule::identifier => self.parser_ident(&terminal),
+            Rule::string => match unescape_pest(terminal.as_str()) {
+                Ok(value) => Some(hrpg_literal(&value)),
+                Err(err) => {
+                    self.warn(&terminal, format!("{} - literal was dropped", err));
+                    None
+                }
+            },
+            Rule::insensitive_string => {
+                let string = terminal.clone().into_inner().next().unwrap();
+                match unescape_pest(string.as_str()) {
+                    Ok(value) => Some(insensitive_literal(&value)),
+                    Err(err) => {
+                        self.warn(&terminal, format!("{} - literal was dropped", err));
+                        None
+                    }
+                }
+            }
+            _ => {
+                self.warn(
+                    &terminal,
+                    format!(
+                        "Character range {} is only allowed in token rules and was dropped",
+                        terminal.as_str()
+                    ),
+                );
+                None
+            }
+        }
+    }
+
+    fn parser_ident(&mut self, pair: &Pair<Rule>) -> Option<Node> {
+        let name = pair.as_str();
+
+        if self.rule(name).is_some() {
+            return Some(match self.lexical.contains(name) {
+                true => Node::TokenRef {
+                    name: token_name(name),
+                    replaced_lit: None,
+                },
+                false => Node::RuleRef {
+                    name: rule_name(name),
+                    args: vec![],
+                },
+            });
+        }
+
+        let message = match name {
+            // HRPG parser rules always start at the beginning of the input
+            "SOI" => return None,
+            "EOI" => {
+                return Some(Node::TokenRef {
+                    name: EOF.to_string(),
+                    replaced_lit: None,
+                })
+            }
+            "NEWLINE" => return Some(newline()),
+            name if STACK_RULES.contains(&name) => {
+                format!(
+                    "Stack operation '{}' is not supported and was dropped",
+                    name
+                )
+            }
+            name if builtin_class(name).is_some() => format!(
+                "Character class '{}' is only allowed in token rules and was dropped",
+                name
+            ),
+            // Left for HRPG to report
+            name => {
+                return Some(Node::RuleRef {
+                    name: rule_name(name),
+                    args: vec![],
+                })
+            }
+        };
+
+        self.warn(pair, message);
+        None
+    }
+
+    // HRPG token rules can't refer to other tokens, so references are inlined
+    fn resolve_token(
+        &mut self,
+        name: &str,
+        stack: &mut Vec<String>,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        if let Some(result) = converted.get(name) {
+            return result.clone();
+        }
+        if stack.iter().any(|other| other == name) {
+            return Err(format!("'{}' is recursive", name));
+        }
+
+        let expr = self.rule(name).unwrap().expr.clone();
+        stack.push(name.to_string());
+        let result = self.token_expr(expr, stack, converted);
+        stack.pop();
+
+        converted.insert(name.to_string(), result.clone());
+        result
+    }
+
+    fn token_expr(
+        &mut self,
+        pair: Pair<Rule>,
+        stack: &mut Vec<String>,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        let mut nodes = vec![];
+        for seq in pair.into_inner() {
+            match self.token_seq(seq, stack, converted)? {
+                Node::Alternatives { nodes: inner } => nodes.extend(inner),
+                node => nodes.push(node),
+            }
+        }
+
+        Ok(match nodes.len() {
+            1 => nodes.remove(0),
+            _ => Node::Alternatives { nodes },
+        })
+    }
+
+    // Negative lookaheads are only supported as the usual "any character except" idiom (ex: `!("\"" | "\\") ~ ANY`),
+    // or at the end of a token where longest match usually has the same effect (ex: `"if" ~ !ASCII_ALPHA`)
+    fn token_seq(
+        &mut self,
+        pair: Pair<Rule>,
+        stack: &mut Vec<String>,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        let mut nodes = vec![];
+        let mut excluded: Vec<Pair<Rule>> = vec![];
+
+        for term in pair.into_inner() {
+            if let Some(node) = negated_node(&term) {
+                excluded.push(node);
+                continue;
+            }
+
+            if !excluded.is_empty() {
+                if !is_any(&term) {
+                    return Err(format!(
+                        "'!{}' is only supported before ANY",
+                        excluded[0].as_str()
+                    ));
+                }
+
+                let mut items = vec![];
+                for node in excluded.drain(..) {
+                    items.extend(self.set_items(node, stack)?);
+                }
+                nodes.push(Node::CharClass {
+                    class: format!("[^{}]", items.join("")),
+                });
+                continue;
+            }
+
+            // Inlined sequences are flattened so they don't need parentheses
+            match self.token_term(term, stack, converted)? {
+                Node::MultipartBody { nodes: inner } => nodes.extend(inner),
+                node => nodes.push(node),
+            }
+        }
+
+        if nodes.is_empty() && !excluded.is_empty() {
+            return Err(format!(
+                "'!{}' is only supported before ANY",
+                excluded[0].as_str()
+            ));
+        }
+        for node in excluded {
+            self.warn(
+                &node,
+                format!(
+                    "Lookahead '!{}' at the end of a token was dropped",
+                    node.as_str()
+                ),
+            );
+        }
+
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.remove(0),
+            _ => Node::MultipartBody { nodes },
+        })
+    }
+
+    // Tags are dropped, since tokens have no parse tree to name
+    fn token_term(
+        &mut self,
+        pair: Pair<Rule>,
+        stack: &mut Vec<String>,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        let mut node = None;
+
+        for inner in pair.into_inner() {
+            match inner.as_rule() {
+                Rule::tag => (),
+                Rule::prefix => {
+                    return Err(format!("lookahead '{}' isn't supported", inner.as_str()))
+                }
+                Rule::node => node = Some(self.token_node(inner, stack, converted)?),
+                Rule::postfix => node = Some(postfix(inner, node.unwrap())),
+                _ => unreachable!(),
+            }
+        }
+
+        Ok(node.unwrap())
+    }
+
+    fn token_node(
+        &mut self,
+        pair: Pair<Rule>,
+        stack: &mut Vec<String>,
+        converted: &mut HashMap<String, Result<Node, String>>,
+    ) -> Result<Node, String> {
+        let inner = pair.into_inner().next().unwrap();
+        if inner.as_rule() == Rule::expression {
+            return self.token_expr(inner, stack, converted);
+        }
+
+        let terminal = inner.into_inner().next().unwrap();
+        match terminal.as_rule() {
+            Rule::identifier => {
+                let name = terminal.as_str();
+                match name {
+                    name if self.rule(name).is_some() => self.resolve_token(name, stack, converted),
+                    "ANY" => Ok(Node::CharClass {
+                        class: ANY_CHAR.to_string(),
+                    }),
+                    "NEWLINE" => Ok(newline()),
+                    name => match builtin_class(name) {
+                        Some(class) => Ok(Node::CharClass { class }),
+                        // Only parser rules can match the end of the input, as `EOF`
+                        None if name == "EOI" => {
+                            Err("'EOI' is only supported in parser rules".to_string())
+                        }
+                        None if STACK_RULES.contains(&name) || name == "SOI" => {
+                            Err(format!("'{}' isn't supported", name))
+                        }
+                        None => Err(format!("'{}' has no rule", name)),
+                    },
+                }
+            }
+            Rule::string => Ok(hrpg_literal(&unescape_pest(terminal.as_str())?)),
+            Rule::insensitive_string => {
+                let string = terminal.into_inner().next().unwrap();
+                Ok(insensitive_literal(&unescape_pest(string.as_str())?))
+            }
+            Rule::range => Ok(Node::CharClass {
+                class: format!("[{}]", range_item(terminal)?),
+            }),
+            _ => Err(format!("'{}' isn't supported", terminal.as_str())),
+        }
+    }
+
+    // The HRPG character class items for a set of single characters (ex: `"a" | 'x'..'z'` -> `ax-z`)
+    fn set_items(&self, pair: Pair<Rule>, stack: &mut Vec<String>) -> Result<Vec<String>, String> {
+        let inner = pair.clone().into_inner().next().unwrap();
+        if inner.as_rule() == Rule::expression {
+            return self.expr_set_items(inner, stack);
+        }
+
+        let terminal = inner.into_inner().next().unwrap();
+        match terminal.as_rule() {
+            Rule::string => {
+                let value = unescape_pest(terminal.as_str())?;
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => Ok(vec![class_char(ch)]),
+                    _ => Err(format!(
+                        "'!{}' is more than one character",
+                        terminal.as_str()
+                    )),
+                }
+            }
+            Rule::range => Ok(vec![range_item(terminal)?]),
+            Rule::identifier => {
+                let name = terminal.as_str();
+                if let Some(rule) = self.rule(name) {
+                    if stack.iter().any(|other| other == name) {
+                        return Err(format!("'{}' is recursive", name));
+                    }
+                    stack.push(name.to_string());
+                    let items = self.expr_set_items(rule.expr.clone(), stack);
+                    stack.pop();
+                    return items;
+                }
+
+                match (name, builtin_class(name)) {
+                    // Excluding the first character of "\r\n" is the same as excluding "\r"
+                    ("NEWLINE", _) => Ok(vec!["\\n".to_string(), "\\r".to_string()]),
+                    (_, Some(class)) => Ok(vec![class
+                        .strip_prefix('[')
+                        .and_then(|class| class.strip_suffix(']'))
+                        .unwrap_or(&class)
+                        .to_string()]),
+                    _ => Err(format!("'!{}' is not a set of characters", name)),
+                }
+            }
+            _ => Err(format!(
+                "'!{}' is not a set of characters",
+                terminal.as_str()
+            )),
+        }
+    }
+
+    fn expr_set_items(
+        &self,
+        pair: Pair<Rule>,
+        stack: &mut Vec<String>,
+    ) -> Result<Vec<String>, String> {
+        let mut items = vec![];
+
+        for seq in pair.clone().into_inner() {
+            let mut terms = seq.into_inner();
+            let node = match (terms.next(), terms.next()) {
+                (Some(term), None) => plain_node(&term),
+                _ => None,
+            };
+            match node {
+                Some(node) => items.extend(self.set_items(node, stack)?),
+                None => return Err(format!("'{}' is not a set of characters", pair.as_str())),
+            }
+        }
+
+        Ok(items)
+    }
+}
+
+// Whether an expression matches several elements in a row (where pest allows implicit whitespace between them)
+fn has_sequence(pair: Pair<Rule>) -> bool {
+    pair.into_inner()
+        .flatten()
+        .any(|inner| match inner.as_rule() {
+            Rule::sequence => inner.into_inner().nth(1).is_some(),
+            Rule::postfix => inner.as_str() != "?",
+            _ => false,
+        })
+}
+
+// The node of a term without a tag, prefix or suffix
+fn plain_node<'i>(term: &Pair<'i, Rule>) -> Option<Pair<'i, Rule>> {
+    let mut inner = term.clone().into_inner();
+    match (inner.next(), inner.next()) {
+        (Some(node), None) if node.as_rule() == Rule::node => Some(node),
+        _ => None,
+    }
+}
+
+// The node of a `!node` term
+fn negated_node<'i>(term: &Pair<'i, Rule>) -> Option<Pair<'i, Rule>> {
+    let mut inner = term.clone().into_inner();
+    match (inner.next(), inner.next(), inner.next()) {
+        (Some(prefix), Some(node), None) if prefix.as_str() == "!" => Some(node),
+        _ => None,
+    }
+}
+
+fn is_any(term: &Pair<Rule>) -> bool {
+    plain_node(term).is_some_and(|node| node.as_str() == "ANY")
+}
+
+fn postfix(pair: Pair<Rule>, node: Node) -> Node {
+    match pair.as_str() {
+        "?" => Node::ZeroOrOne {
+            node: Box::new(node),
+            brackets: false,
+        },
+        "*" => Node::ZeroOrMore {
+            node: Box::new(node),
+        },
+        "+" => Node::OneOrMore {
+            node: Box::new(node),
+        },
+        _ => repeat(pair.into_inner().next().unwrap(), node),
+    }
+}
+
+// Pest's repetition counts (ex: `{2,4}`, `{,4}`, `{2,}`)
+fn repeat(pair: Pair<Rule>, node: Node) -> Node {
+    let mut min = None;
+    let mut max = None;
+    let mut comma = false;
+
+    for inner in pair.into_inner() {
+        match inner.as_rule() {
+            Rule::repeat_comma => comma = true,
+            _ if comma => max = inner.as_str().parse::<usize>().ok(),
+            _ => min = inner.as_str().parse::<usize>().ok(),
+        }
+    }
+
+    let min = min.unwrap_or(0);
+    let max = match comma {
+        true => max,
+        false => Some(min),
+    };
+    repeat_node(node, min, max)
+}
+
+fn newline() -> Node {
+    Node::Alternatives {
+        nodes: vec![hrpg_literal("\n"), hrpg_literal("\r\n"), hrpg_literal("\r")],
+    }
+}
+
+fn insensitive_literal(value: &str) -> Node {
+    match hrpg_literal(value) {
+        Node::TokenLit { literal, .. } => Node::TokenLit {
+            literal,
+            case_insensitive: true,
+        },
+        _ => unreachable!(),
+    }
+}
+
+// The HRPG character class for one of pest's builtin rules (ex: `ASCII_DIGIT` -> `[0-9]`, `LETTER` -> `\p{LETTER}`)
+fn builtin_class(name: &str) -> Option<String> {
+    let class = match name {
+        "ASCII_DIGIT" => "[0-9]",
+        "ASCII_NONZERO_DIGIT" => "[1-9]",
+        "ASCII_BIN_DIGIT" => "[01]",
+        "ASCII_OCT_DIGIT" => "[0-7]",
+        "ASCII_HEX_DIGIT" => "[0-9a-fA-F]",
+        "ASCII_ALPHA_LOWER" => "[a-z]",
+        "ASCII_ALPHA_UPPER" => "[A-Z]",
+        "ASCII_ALPHA" => "[a-zA-Z]",
+        "ASCII_ALPHANUMERIC" => "[a-zA-Z0-9]",
+        "ASCII" => "[\\u{0}-\\u{7f}]",
+        // Pest names Unicode properties in uppercase, which Unicode's loose matching allows
+        name if name.chars().all(|ch| ch.is_ascii_uppercase() || ch == '_') => {
+            let class = format!("\\p{{{}}}", name);
+            return parse_char_class(&class).ok().map(|_| class);
+        }
+        _ => return None,
+    };
+    Some(class.to_string())
+}
+
+fn range_item(pair: Pair<Rule>) -> Result<String, String> {
+    let mut inner = pair.into_inner();
+    let start = single_char(inner.next().unwrap().as_str())?;
+    let end = single_char(inner.next().unwrap().as_str())?;
+    Ok(format!("{}-{}", class_char(start), class_char(end)))
+}
+
+fn single_char(literal: &str) -> Result<char, String> {
+    let value = unescape_pest(literal)?;
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), None) => Ok(ch),
+        _ => Err(format!("{} is not a single character", literal)),
+    }
+}
+
+// Unescapes a pest string or character (ex: `"\x41\u{1F600}"`)
+fn unescape_pest(literal: &str) -> Result<String, String> {
+    let mut chars = literal[1..literal.len() - 1].chars();
+    let mut value = String::new();
+
+    while let Some(ch) = chars.next() {
+        value.push(match ch {
+            '\\' => unescape_char(&mut chars)
+                .map_err(|err| format!("{} in literal {}", err, literal))?,
+            ch => ch,
+        });
+    }
+    Ok(value)
+}
+
+fn unescape_char(chars: &mut std::str::Chars) -> Result<char, String> {
+    match chars.next() {
+        Some('n') => Ok('\n'),
+        Some('r') => Ok('\r'),
+        Some('t') => Ok('\t'),
+        Some('0') => Ok('\0'),
+        Some('x') => {
+            let rest = chars.as_str();
+            let hex = rest.get(..2).ok_or("invalid escape '\\x'")?;
+            let ch = u8::from_str_radix(hex, 16)
+                .map(char::from)
+                .map_err(|_| format!("invalid escape '\\x{}'", hex))?;
+            *chars = rest[2..].chars();
+            Ok(ch)
+        }
+        Some('u') => {
+            let rest = chars.as_str();
+            let end = rest.find('}').ok_or("invalid Unicode escape")?;
+            let hex = rest[..end].trim_start_matches('{');
+            let ch = u32::from_str_radix(hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("invalid Unicode escape '\\u{{{}}}'", hex))?;
+            *chars = rest[end + 1..].chars();
+            Ok(ch)
+        }
+        Some(ch) => Ok(ch),
+        None => Err("incomplete escape sequence".to_string()),
+    }
+}
+
+fn token_name(name: &str) -> String {
+    name.to_case(Case::UpperSnake)
+}
+
+fn rule_name(name: &str) -> String {
+    name.to_case(Case::Snake)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::hrpg::write_grammar;
+    use crate::export::pest::write_pest;
+    use crate::transform::Transform;
+
+    const LIST: &str = "list = { \"[\" ~ (number ~ (\",\" ~ number)*)? ~ \"]\" }\n\
+                        number = @{ ASCII_DIGIT+ }\n";
+
+    fn import(data: &str) -> (String, Vec<String>) {
+        let import = import_pest(data).unwrap();
+        let warnings = import
+            .warnings
+            .iter()
+            .map(|warning| warning.message.clone())
+            .collect();
+        (write_grammar(&import.grammar), warnings)
+    }
+
+    #[test]
+    fn small_grammar() {
+        let (hrpg, warnings) = import(&format!(
+            "{}string = @{{ \"\\\"\" ~ (!(\"\\\"\" | \"\\\\\") ~ ANY)* ~ \"\\\"\" }}\n",
+            LIST
+        ));
+        assert_eq!(
+            hrpg,
+            "list: '[' (NUMBER (',' NUMBER)*)? ']';\n\n\
+             LBRACKET: '[';\nCOMMA: ',';\nRBRACKET: ']';\nNUMBER: [0-9]+;\nSTRING: '\"' [^\"\\\\]* '\"';\n"
+        );
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn implicit_whitespace() {
+        let (hrpg, warnings) = import(&format!(
+            "{}WHITESPACE = _{{ \" \" | \"\\t\" }}\nCOMMENT = _{{ \"#\" ~ (!\"\\n\" ~ ANY)* }}\n",
+            LIST
+        ));
+        assert!(hrpg.contains("## @skip\nWHITESPACE\n    : ' '\n    | '\\t'\n    ;\n"));
+        assert!(hrpg.ends_with("## @skip\nCOMMENT: '#' [^\\n]*;\n"));
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn rule_named_like_builtin() {
+        let (hrpg, _) = import("sum = { NUMBER ~ \"+\" ~ NUMBER }\nNUMBER = @{ ASCII_DIGIT+ }\n");
+        assert!(hrpg.starts_with("sum: NUMBER '+' NUMBER;"));
+    }
+
+    #[test]
+    fn any_after_lookahead_in_parser_rule() {
+        let (hrpg, warnings) =
+            import("comment = { \"/*\" ~ (comment | !\"*/\" ~ ANY)* ~ \"*/\" }\n");
+        assert!(hrpg.starts_with("comment: '/*' comment* '*/';"));
+        assert_eq!(
+            warnings,
+            vec!["'!\"*/\" ~ ANY' matches single characters, which is only possible in token rules - it was dropped"]
+        );
+    }
+
+    #[test]
+    fn lookahead_not_before_any_in_token() {
+        let (hrpg, warnings) = import("word = @{ !\"if\" ~ ASCII_ALPHA+ }\n");
+        assert_eq!(hrpg, "extern WORD;\n");
+        assert_eq!(
+            warnings,
+            vec!["Token 'WORD' can't be converted ('!\"if\"' is only supported before ANY) and was declared extern"]
+        );
+    }
+
+    #[test]
+    fn eoi_is_eof() {
+        let (hrpg, warnings) = import(&format!("file = {{ SOI ~ list ~ EOI }}\n{}", LIST));
+        assert!(hrpg.starts_with("file: list EOF;\n"), "{}", hrpg);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn round_trip() {
+        let import = import_pest(LIST).unwrap();
+        let (g2, transform) = Transform::process_with_vocab(&import.grammar, None);
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+
+        let reimport = import_pest(&write_pest(&g2).text).unwrap();
+        assert_eq!(
+            write_grammar(&reimport.grammar),
+            write_grammar(&import.grammar)
+        );
+    }
+
+    #[test]
+    fn hrpg_grammar() {
+        let import = import_pest(include_str!("../HRPG.pest")).unwrap();
+        let (_, transform) = Transform::process_with_vocab(&import.grammar, None);
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+    }
+}
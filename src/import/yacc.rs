@@ -0,0 +1,698 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use convert_case::{Case, Casing};
+use pest::iterators::Pair;
+use pest::Parser;
+
+use crate::ast::{Grammar, GrammarKind, Node, ParserRule};
+use crate::import::flex::token_name;
+use crate::import::{
+    add_literal_tokens, binding_name, hrpg_literal, remove_left_recursion, unescape_c, Import,
+    ImportWarning, LeftRecursion,
+};
+
+#[derive(pest_derive::Parser)]
+#[grammar = "import/YACC.pest"]
+struct YaccParser;
+
+// Converts a yacc/bison grammar, with token rules from its flex scanner (if there is one). Actions are dropped, or
+// kept as doc comments of their rule.
+pub fn import_yacc(
+    data: &str,
+    lexer: Option<Import>,
+    keep_actions: bool,
+) -> Result<Import, Box<pest::error::Error<Rule>>> {
+    let pairs = YaccParser::parse(Rule::yacc_file, data)
+        .map_err(Box::new)?
+        .next()
+        .unwrap()
+        .into_inner();
+
+    let mut importer = YaccImporter {
+        keep_actions,
+        ..YaccImporter::default()
+    };
+    for pair in pairs {
+        importer.process(pair);
+    }
+    Ok(importer.finish(lexer))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Associativity {
+    Left,
+    Right,
+    NonAssociative,
+}
+
+// Literals are unescaped
+#[derive(Clone, PartialEq)]
+enum Symbol {
+    Name(String),
+    Char(String),
+    Str(String),
+}
+
+struct Item<'i> {
+    symbol: Symbol,
+    named_ref: Option<String>,
+    pair: Pair<'i, Rule>,
+}
+
+struct Alternative<'i> {
+    items: Vec<Item<'i>>,
+    prec: Option<Symbol>,
+    has_action: bool,
+    pair: Pair<'i, Rule>,
+}
+
+struct YaccRule<'i> {
+    name: String,
+    pair: Pair<'i, Rule>,
+    alts: Vec<Alternative<'i>>,
+}
+
+#[derive(Default)]
+struct YaccImporter<'i> {
+    keep_actions: bool,
+    // Tokens in the order they are declared or used
+    tokens: Vec<(String, Pair<'i, Rule>)>,
+    // String aliases of tokens (ex: `%token ARROW "->"`)
+    aliases: HashMap<String, String>,
+    // Lowest precedence first
+    precedence: Vec<(Associativity, Vec<String>)>,
+    start: Option<String>,
+    rules: Vec<YaccRule<'i>>,
+    nonterminals: HashSet<String>,
+    warnings: Vec<ImportWarning>,
+}
+
+impl<'i> YaccImporter<'i> {
+    fn warn(&mut self, pair: &Pair<Rule>, message: String) {
+        self.warnings
+            .push(ImportWarning::new(&pair.as_span(), message));
+    }
+
+    fn process(&mut self, pair: Pair<'i, Rule>) {
+        match pair.as_rule() {
+            Rule::declaration => {
+                let decl = pair.into_inner().next().unwrap();
+                match decl.as_rule() {
+                    Rule::prologue => self.warn(&decl, "C prologue was dropped".to_string()),
+                    _ => self.directive(decl),
+                }
+            }
+            Rule::yacc_rule => self.yacc_rule(pair),
+            Rule::epilogue if !pair.as_str()[2..].trim().is_empty() => {
+                self.warn(&pair, "C epilogue was dropped".to_string())
+            }
+            _ => (),
+        }
+    }
+
+    fn directive(&mut self, pair: Pair<'i, Rule>) {
+        let mut inner = pair.clone().into_inner();
+        let name = inner.next().unwrap().as_str();
+        let args: Vec<Pair<Rule>> = inner.filter_map(|arg| arg.into_inner().next()).collect();
+
+        match name {
+            "%token" => {
+                for (idx, arg) in args.iter().enumerate() {
+                    if arg.as_rule() != Rule::symbol {
+                        continue;
+                    }
+                    self.add_token(arg.clone());
+
+                    // `%token NAME [number] ["alias"]`
+                    let alias = args[idx + 1..]
+                        .iter()
+                        .take(2)
+                        .take_while(|next| next.as_rule() != Rule::symbol)
+                        .find(|next| next.as_rule() == Rule::string);
+                    if let Some(alias) = alias {
+                        if let Ok(value) = unescape_string(alias.as_str()) {
+                            self.aliases.insert(value, arg.as_str().to_string());
+                        }
+                    }
+                }
+            }
+            "%left" | "%right" | "%nonassoc" | "%precedence" => {
+                let assoc = match name {
+                    "%left" => Associativity::Left,
+                    "%right" => Associativity::Right,
+                    _ => Associativity::NonAssociative,
+                };
+                let keys = args
+                    .iter()
+                    .filter_map(|arg| self.parse_symbol(arg).ok().flatten())
+                    .map(|symbol| self.key(&symbol))
+                    .collect();
+                self.precedence.push((assoc, keys));
+            }
+            "%start" => match args.first() {
+                Some(arg) => self.start = Some(arg.as_str().to_string()),
+                None => self.warn(&pair, "Declaration '%start' has no symbol".to_string()),
+            },
+            _ => self.warn(&pair, format!("Declaration '{}' was dropped", name)),
+        }
+    }
+
+    fn add_token(&mut self, pair: Pair<'i, Rule>) {
+        let name = pair.as_str();
+        if !self.tokens.iter().any(|(other, _)| other == name) {
+            self.tokens.push((name.to_string(), pair));
+        }
+    }
+
+    // Only symbols and literals are symbols (ex: not a `<type>` tag)
+    fn parse_symbol(&self, pair: &Pair<Rule>) -> Result<Option<Symbol>, String> {
+        let text = pair.as_str();
+        Ok(Some(match pair.as_rule() {
+            Rule::symbol => Symbol::Name(text.to_string()),
+            Rule::char_lit => Symbol::Char(unescape_c(&text[1..text.len() - 1])?),
+            Rule::string => Symbol::Str(unescape_string(text)?),
+            _ => return Ok(None),
+        }))
+    }
+
+    // How a symbol is found in the precedence declarations
+    fn key(&self, symbol: &Symbol) -> String {
+        match symbol {
+            Symbol::Name(name) => name.to_string(),
+            Symbol::Char(value) => format!("'{}'", value),
+            Symbol::Str(value) => match self.aliases.get(value) {
+                Some(name) => name.to_string(),
+                None => format!("\"{}\"", value),
+            },
+        }
+    }
+
+    fn yacc_rule(&mut self, pair: Pair<'i, Rule>) {
+        let mut inner = pair.clone().into_inner();
+        let lhs = inner.next().unwrap();
+        let name = lhs.into_inner().next().unwrap().as_str().to_string();
+
+        let mut alts = vec![];
+        for alt in inner {
+            alts.push(self.alternative(alt));
+        }
+
+        // A rule can be given in several parts (ex: `a: b; a: c;`)
+        match self.rules.iter_mut().find(|rule| rule.name == name) {
+            Some(rule) => rule.alts.extend(alts),
+            None => {
+                self.nonterminals.insert(name.to_string());
+                self.rules.push(YaccRule { name, pair, alts });
+            }
+        }
+    }
+
+    fn alternative(&mut self, pair: Pair<'i, Rule>) -> Alternative<'i> {
+        let mut alt = Alternative {
+            items: vec![],
+            prec: None,
+            has_action: false,
+            pair: pair.clone(),
+        };
+
+        for item in pair.into_inner() {
+            let item = item.into_inner().next().unwrap();
+            match item.as_rule() {
+                Rule::rhs_symbol => {
+                    let mut inner = item.clone().into_inner();
+                    let symbol = inner.next().unwrap();
+                    let named_ref = inner.next().map(|named_ref| {
+                        named_ref.into_inner().next().unwrap().as_str().to_string()
+                    });
+
+                    match self.parse_symbol(&symbol) {
+                        Ok(Some(symbol)) => alt.items.push(Item {
+                            symbol,
+                            named_ref,
+                            pair: item,
+                        }),
+                        Ok(None) => unreachable!(),
+                        Err(err) => self.warn(&item, format!("{} - symbol was dropped", err)),
+                    }
+                }
+                Rule::prec => {
+                    let symbol = item.clone().into_inner().next().unwrap();
+                    match self.parse_symbol(&symbol) {
+                        Ok(symbol) => alt.prec = symbol,
+                        Err(err) => self.warn(&item, format!("{} - '%prec' was dropped", err)),
+                    }
+                }
+                Rule::action => alt.has_action = true,
+                Rule::empty => (),
+                _ => self.warn(
+                    &item,
+                    format!("'{}' is not supported and was dropped", item.as_str()),
+                ),
+            }
+        }
+
+        alt
+    }
+
+    fn finish(mut self, lexer: Option<Import>) -> Import {
+        let mut parser_rules = vec![];
+        let rules = std::mem::take(&mut self.rules);
+        for rule in &rules {
+            parser_rules.extend(self.convert_rule(rule));
+        }
+
+        // The start symbol is the first rule in HRPG
+        if let Some(start) = &self.start {
+            let start = rule_name(start);
+            if let Some(idx) = parser_rules.iter().position(|rule| rule.name == start) {
+                let rule = parser_rules.remove(idx);
+                parser_rules.insert(0, rule);
+            }
+        }
+
+        let mut grammar = Grammar {
+            name: None,
+            kind: GrammarKind::Combined,
+            options: HashMap::new(),
+            parser_rules,
+            token_rules: vec![],
+            extern_tokens: vec![],
+        };
+
+        let mut lexer_warnings = vec![];
+        let has_lexer = lexer.is_some();
+        if let Some(lexer) = lexer {
+            grammar.token_rules = lexer.grammar.token_rules;
+            grammar.extern_tokens = lexer.grammar.extern_tokens;
+            lexer_warnings = lexer.warnings;
+        }
+
+        // Tokens the lexer doesn't define are left to an external scanner
+        for (name, pair) in std::mem::take(&mut self.tokens) {
+            let name = token_name(&name);
+            let defined = grammar.token_rules.iter().any(|rule| rule.name == name)
+                || grammar.extern_tokens.contains(&name);
+            if defined {
+                continue;
+            }
+
+            if has_lexer {
+                self.warn(
+                    &pair,
+                    format!(
+                        "Token '{}' has no rule in the lexer and was declared extern",
+                        name
+                    ),
+                );
+            }
+            grammar.extern_tokens.push(name);
+        }
+
+        add_literal_tokens(&mut grammar);
+
+        self.warnings
+            .sort_by_key(|warning| (warning.line, warning.column));
+        self.warnings.extend(lexer_warnings);
+
+        Import {
+            grammar,
+            warnings: self.warnings,
+        }
+    }
+
+    fn convert_rule(&mut self, rule: &YaccRule<'i>) -> Vec<ParserRule> {
+        let doc = match self.keep_actions {
+            true => rule
+                .alts
+                .iter()
+                .filter(|alt| alt.has_action)
+                .map(|alt| {
+                    alt.pair
+                        .as_str()
+                        .split_whitespace()
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect(),
+            false => vec![],
+        };
+
+        let mut binary = vec![];
+        let mut prefix = vec![];
+        let mut others = vec![];
+        for alt in &rule.alts {
+            let level = self.level(alt);
+            match (alt.items.as_slice(), level) {
+                ([left, op, right], Some(level))
+                    if self.is_rule(left, &rule.name)
+                        && self.is_terminal(op)
+                        && self.is_rule(right, &rule.name) =>
+                {
+                    binary.push((level, op))
+                }
+                ([op, operand], Some(level))
+                    if self.is_terminal(op) && self.is_rule(operand, &rule.name) =>
+                {
+                    prefix.push((level, op))
+                }
+                _ => others.push(alt),
+            }
+        }
+
+        let name = rule_name(&rule.name);
+        if binary.is_empty() && prefix.is_empty() {
+            let node = self.left_recursion(rule, &others);
+            return vec![parser_rule(name, doc, node)];
+        }
+
+        // HRPG has no precedence declarations, so each precedence level becomes a rule that refers to the next
+        // higher one (ex: `exp: exp_2 (('+' | '-') exp_2)*; exp_2: exp_primary (('*' | '/') exp_primary)*;`)
+        self.warn(
+            &rule.pair,
+            format!(
+                "Operator precedence of rule '{}' was converted to one rule per precedence level",
+                rule.name
+            ),
+        );
+
+        let levels: BTreeSet<usize> = binary
+            .iter()
+            .chain(prefix.iter())
+            .map(|(level, _)| *level)
+            .collect();
+        let mut taken: Vec<String> = self.rules_names();
+        let mut names = vec![name.to_string()];
+        for idx in 1..levels.len() {
+            names.push(unique_name(format!("{}_{}", name, idx + 1), &mut taken));
+        }
+        names.push(unique_name(format!("{}_primary", name), &mut taken));
+
+        let mut result = vec![];
+        for (idx, level) in levels.iter().enumerate() {
+            let this = rule_ref(&names[idx]);
+            let next = rule_ref(&names[idx + 1]);
+
+            let prefix_ops: Vec<Node> = prefix
+                .iter()
+                .filter(|(other, _)| other == level)
+                .filter_map(|(_, op)| self.item_node(op))
+                .collect();
+            let binary_ops: Vec<Node> = binary
+                .iter()
+                .filter(|(other, _)| other == level)
+                .filter_map(|(_, op)| self.item_node(op))
+                .collect();
+
+            let mut parts = vec![];
+            if !prefix_ops.is_empty() {
+                parts.push(Node::MultipartBody {
+                    nodes: vec![alternatives(prefix_ops), this.clone()],
+                });
+            }
+            parts.push(match binary_ops.is_empty() {
+                true => next,
+                false => {
+                    let (right, repeat) = match self.precedence[*level].0 {
+                        Associativity::Left => (next.clone(), true),
+                        Associativity::Right => (this, false),
+                        Associativity::NonAssociative => (next.clone(), false),
+                    };
+                    let tail = Box::new(Node::MultipartBody {
+                        nodes: vec![alternatives(binary_ops), right],
+                    });
+                    let tail = match repeat {
+                        true => Node::ZeroOrMore { node: tail },
+                        false => Node::ZeroOrOne {
+                            node: tail,
+                            brackets: false,
+                        },
+                    };
+                    Node::MultipartBody {
+                        nodes: vec![next, tail],
+                    }
+                }
+            });
+
+            let doc = match idx {
+                0 => doc.clone(),
+                _ => vec![],
+            };
+            result.push(parser_rule(
+                names[idx].to_string(),
+                doc,
+                alternatives(parts),
+            ));
+        }
+
+        let node = self.left_recursion(rule, &others);
+        result.push(parser_rule(names.last().unwrap().to_string(), vec![], node));
+        result
+    }
+
+    fn rules_names(&self) -> Vec<String> {
+        self.nonterminals
+            .iter()
+            .map(|name| rule_name(name))
+            .collect()
+    }
+
+    fn left_recursion(&mut self, rule: &YaccRule<'i>, alts: &[&Alternative<'i>]) -> Node {
+        let nodes = alts.iter().map(|alt| self.items_node(&alt.items)).collect();
+
+        let (node, recursion) = remove_left_recursion(&rule_name(&rule.name), nodes);
+        match recursion {
+            LeftRecursion::None => (),
+            LeftRecursion::Converted { .. } => self.warn(
+                &rule.pair,
+                format!(
+                    "Left recursion of rule '{}' was converted to repetition",
+                    rule.name
+                ),
+            ),
+            LeftRecursion::Unending => self.warn(
+                &rule.pair,
+                format!(
+                    "Rule '{}' is left recursive with no way to end the recursion",
+                    rule.name
+                ),
+            ),
+        }
+        node
+    }
+
+    // Like yacc, the precedence of an alternative is from `%prec` or its last terminal
+    fn level(&self, alt: &Alternative) -> Option<usize> {
+        let symbol = match &alt.prec {
+            Some(symbol) => symbol,
+            None => {
+                &alt.items
+                    .iter()
+                    .rev()
+                    .find(|item| self.is_terminal(item))?
+                    .symbol
+            }
+        };
+        let key = self.key(symbol);
+        self.precedence
+            .iter()
+            .position(|(_, keys)| keys.contains(&key))
+    }
+
+    fn is_rule(&self, item: &Item, name: &str) -> bool {
+        item.symbol == Symbol::Name(name.to_string())
+    }
+
+    fn is_terminal(&self, item: &Item) -> bool {
+        match &item.symbol {
+            Symbol::Name(name) => !self.nonterminals.contains(name),
+            _ => true,
+        }
+    }
+
+    fn items_node(&mut self, items: &[Item<'i>]) -> Node {
+        let mut nodes: Vec<Node> = items
+            .iter()
+            .filter_map(|item| self.item_node(item))
+            .collect();
+        match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.remove(0),
+            _ => Node::MultipartBody { nodes },
+        }
+    }
+
+    fn item_node(&mut self, item: &Item<'i>) -> Option<Node> {
+        let node = match &item.symbol {
+            Symbol::Name(name) if self.nonterminals.contains(name) => rule_ref(&rule_name(name)),
+            Symbol::Name(name) if name == "error" => {
+                self.warn(
+                    &item.pair,
+                    "Error recovery token 'error' is not supported and was dropped".to_string(),
+                );
+                return None;
+            }
+            Symbol::Name(name) => {
+                self.add_token(item.pair.clone().into_inner().next().unwrap());
+                Node::TokenRef {
+                    name: token_name(name),
+                    replaced_lit: None,
+                }
+            }
+            Symbol::Char(value) => hrpg_literal(value),
+            Symbol::Str(value) => match self.aliases.get(value) {
+                Some(name) => Node::TokenRef {
+                    name: token_name(name),
+                    replaced_lit: None,
+                },
+                None => hrpg_literal(value),
+            },
+        };
+
+        Some(match &item.named_ref {
+            Some(name) => Node::Binding {
+                name: binding_name(name),
+                list: false,
+                node: Box::new(node),
+            },
+            None => node,
+        })
+    }
+}
+
+fn parser_rule(name: String, doc: Vec<String>, node: Node) -> ParserRule {
+    ParserRule {
+        name,
+        params: vec![],
+        args: vec![],
+        returns: vec![],
+        doc,
+        node,
+    }
+}
+
+fn rule_ref(name: &str) -> Node {
+    Node::RuleRef {
+        name: name.to_string(),
+        args: vec![],
+    }
+}
+
+fn alternatives(mut nodes: Vec<Node>) -> Node {
+    match nodes.len() {
+        0 => Node::Empty,
+        1 => nodes.remove(0),
+        _ => Node::Alternatives { nodes },
+    }
+}
+
+fn unique_name(base: String, taken: &mut Vec<String>) -> String {
+    let mut name = base.to_string();
+    let mut count = 1;
+    while taken.contains(&name) {
+        count += 1;
+        name = format!("{}_{}", base, count);
+    }
+    taken.push(name.to_string());
+    name
+}
+
+fn unescape_string(literal: &str) -> Result<String, String> {
+    unescape_c(&literal[1..literal.len() - 1])
+}
+
+// Bison names can contain '.' and '-' (ex: `exp.list`)
+fn rule_name(name: &str) -> String {
+    name.replace('.', "_").to_case(Case::Snake)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::hrpg::write_grammar;
+    use crate::import::flex::import_flex;
+    use crate::transform::Transform;
+
+    const CALC: &str = "%token NUM\n\
+                        %left '+' '-'\n\
+                        %left '*'\n\
+                        %%\n\
+                        input: %empty | input line ;\n\
+                        line: expr ';' { printf(\"%d\\n\", $1); } ;\n\
+                        expr: NUM\n    | expr '+' expr\n    | expr '-' expr\n    | expr '*' expr\n    | '(' expr ')'\n    ;\n";
+
+    const CALC_LEXER: &str = "DIGIT [0-9]\n\
+                              %%\n\
+                              {DIGIT}+ { return NUM; }\n\
+                              \"+\" { return '+'; }\n\
+                              [ \\t\\n]+ ;\n";
+
+    fn import(data: &str, lexer: Option<&str>, keep_actions: bool) -> (String, Vec<String>) {
+        let lexer = lexer.map(|lexer| import_flex(lexer).unwrap());
+        let import = import_yacc(data, lexer, keep_actions).unwrap();
+        let warnings = import
+            .warnings
+            .iter()
+            .map(|warning| warning.message.clone())
+            .collect();
+        (write_grammar(&import.grammar), warnings)
+    }
+
+    #[test]
+    fn small_grammar() {
+        let (hrpg, warnings) = import(CALC, None, false);
+        assert_eq!(
+            hrpg,
+            "input: line*;\n\n\
+             line: expr ';';\n\n\
+             expr: expr_2 (('+' | '-') expr_2)*;\n\n\
+             expr_2: expr_primary ('*' expr_primary)*;\n\n\
+             expr_primary\n    : NUM\n    | '(' expr ')'\n    ;\n\n\
+             extern NUM;\n\n\
+             SEMI: ';';\nPLUS: '+';\nMINUS: '-';\nSTAR: '*';\nLPAREN: '(';\nRPAREN: ')';\n"
+        );
+        assert_eq!(
+            warnings,
+            vec![
+                "Left recursion of rule 'input' was converted to repetition",
+                "Operator precedence of rule 'expr' was converted to one rule per precedence level",
+            ]
+        );
+
+        let import = import_yacc(CALC, None, false).unwrap();
+        let (_, transform) = Transform::process_with_vocab(&import.grammar, None);
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+    }
+
+    #[test]
+    fn keep_actions() {
+        let (hrpg, _) = import(CALC, None, true);
+        assert!(hrpg.contains("## expr ';' { printf(\"%d\\n\", $1); }\nline: expr ';';"));
+    }
+
+    #[test]
+    fn flex_tokens() {
+        let (hrpg, warnings) = import(CALC, Some(CALC_LEXER), false);
+        assert!(!hrpg.contains("extern"));
+        assert!(hrpg.ends_with("NUM: [0-9]+;\n\n## @skip\nSKIP: [ \\t\\n]+;\n"));
+        assert_eq!(
+            warnings[2..],
+            ["Pattern '\"+\"' returns the character it matched - literals of the parser are used instead"]
+        );
+    }
+
+    #[test]
+    fn right_recursion_is_kept() {
+        let (hrpg, warnings) = import("%%\nlist: 'x' | 'x' list ;\n", None, false);
+        assert!(hrpg.starts_with("list\n    : 'x'\n    | 'x' list\n    ;\n"));
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn unending_left_recursion() {
+        let (_, warnings) = import("%%\nlist: list 'x' ;\n", None, false);
+        assert_eq!(
+            warnings,
+            vec!["Rule 'list' is left recursive with no way to end the recursion"]
+        );
+    }
+}
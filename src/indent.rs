@@ -0,0 +1,54 @@
+use crate::ast::Grammar;
+
+// Grammar option that makes the lexer synthesize `INDENT`, `DEDENT` and `NEWLINE` tokens
+pub const INDENT_OPTION: &str = "indent";
+
+// Grammar option giving the tab stop used for indentation - 0 means tabs are not allowed in indentation
+pub const TAB_WIDTH: &str = "tabWidth";
+
+const DEFAULT_TAB_WIDTH: u32 = 8;
+
+pub const INDENT: &str = "INDENT";
+pub const DEDENT: &str = "DEDENT";
+pub const NEWLINE: &str = "NEWLINE";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IndentConfig {
+    pub tab_width: u32,
+}
+
+impl IndentConfig {
+    // `None` if the grammar isn't indentation sensitive
+    pub fn from_grammar(grammar: &Grammar) -> Result<Option<IndentConfig>, String> {
+        match grammar.option(INDENT_OPTION) {
+            Some("true") => (),
+            Some("false") | None => {
+                return match grammar.option(TAB_WIDTH) {
+                    Some(_) => Err(format!(
+                        "Option '{}' requires option '{}'",
+                        TAB_WIDTH, INDENT_OPTION
+                    )),
+                    None => Ok(None),
+                }
+            }
+            Some(value) => {
+                return Err(format!(
+                    "Option '{}' must be 'true' or 'false', not '{}'",
+                    INDENT_OPTION, value
+                ))
+            }
+        }
+
+        let tab_width = match grammar.option(TAB_WIDTH) {
+            Some(value) => value.parse().map_err(|_| {
+                format!(
+                    "Option '{}' must be a number of columns, not '{}'",
+                    TAB_WIDTH, value
+                )
+            })?,
+            None => DEFAULT_TAB_WIDTH,
+        };
+
+        Ok(Some(IndentConfig { tab_width }))
+    }
+}
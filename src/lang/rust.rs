@@ -1,5 +1,8 @@
-use crate::parser_gen::LangConfig;
-use convert_case::Case;
+use crate::lexer_gen::{ExternHook, LexerSpec, Pattern};
+use crate::parser_gen::{Function, LangCodeGen, LangConfig, MatchKind, MatchRule, ParserSpec};
+use crate::transform::EOF;
+use crate::vocab::TokenVocab;
+use convert_case::{Case, Casing};
 use std::collections::HashSet;
 
 pub struct RustConfig;
@@ -20,4 +23,1681 @@ impl LangConfig for RustConfig {
     fn keywords(&self) -> HashSet<String> {
         todo!()
     }
+
+    fn name(&self) -> &str {
+        "rust"
+    }
+
+    // Declarations are `name: Type` (ex: `in_loop: bool`, `mut depth: usize`)
+    fn param_name<'d>(&self, decl: &'d str) -> Option<&'d str> {
+        let (name, ty) = decl.split_once(':')?;
+        let name = name.trim();
+        let name = name.strip_prefix("mut ").map_or(name, str::trim_start);
+
+        let mut chars = name.chars();
+        let valid_name = matches!(chars.next(), Some(ch) if ch.is_alphabetic() || ch == '_')
+            && chars.all(|ch| ch.is_alphanumeric() || ch == '_');
+
+        (valid_name && !ty.trim().is_empty()).then_some(name)
+    }
+}
+
+// Writes a lexer and parser with no dependencies - parsing is PEG style (the first alternative that matches is
+// taken, repetition is greedy) and the lexer matches whichever token the parser expects next. Each rule matched
+// becomes a `Tree::Rule` of the rules and tokens matched within it
+pub fn write_rust(
+    lexer: &LexerSpec,
+    parser: &ParserSpec,
+    vocab: &TokenVocab,
+    source: &str,
+) -> Result<String, String> {
+    let errors = parser_errors(parser);
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    let gen = RustCodeGen {
+        lexer: LexerSource::Spec(lexer),
+        parser,
+        vocab,
+        source,
+    };
+    Ok(gen.write())
+}
+
+// Writes the lexer of a lexer grammar on its own, for the parsers of parser grammars to import
+pub fn write_rust_lexer(lexer: &LexerSpec, vocab: &TokenVocab, source: &str) -> String {
+    let mut out = String::new();
+    write_header(&mut out, source);
+    RustLexerGen { lexer, vocab }.write(&mut out);
+    out
+}
+
+// Writes the parser of a parser grammar, which imports its lexer from the sibling module `lexer_module` (the lexer
+// grammar written by `write_rust_lexer`)
+pub fn write_rust_parser(
+    lexer_module: &str,
+    parser: &ParserSpec,
+    vocab: &TokenVocab,
+    source: &str,
+) -> Result<String, String> {
+    let errors = parser_errors(parser);
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    let gen = RustCodeGen {
+        lexer: LexerSource::Module(lexer_module),
+        parser,
+        vocab,
+        source,
+    };
+    Ok(gen.write())
+}
+
+fn parser_errors(parser: &ParserSpec) -> Vec<String> {
+    let mut errors = parser.errors.clone();
+
+    match parser
+        .functions
+        .iter()
+        .find(|func| Some(&func.name) == parser.start.as_ref())
+    {
+        Some(func) if !func.params.is_empty() => errors.push(format!(
+            "ERROR: Rule '{}' can't take arguments, since parsing starts with it",
+            func.rule
+        )),
+        Some(_) => (),
+        None => errors.push("ERROR: Grammar has no parser rules".to_string()),
+    }
+    errors
+}
+
+fn write_header(w: &mut String, source: &str) {
+    push_lines(
+        w,
+        0,
+        &format!(
+            "// Generated by hrpg from {} - do not edit\n\n#![allow(dead_code, unused_variables)]\n",
+            source
+        ),
+    );
+}
+
+// Everything in the generated file that doesn't depend on the grammar
+const TOKEN_TYPE: &str = r#"#[derive(Clone, Debug, PartialEq)]
+pub struct Token<'i> {
+    pub kind: TokenKind,
+    pub text: &'i str,
+    // Byte offset of the token in the input
+    pub start: usize,
+}
+"#;
+
+const PARSER_TYPES: &str = r#"#[derive(Clone, Debug, PartialEq)]
+pub enum Tree<'i> {
+    Rule { kind: RuleKind, children: Vec<Tree<'i>> },
+    Token(Token<'i>),
+}
+
+// Where parsing got the furthest before failing, and the tokens it expected there
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub expected: Vec<TokenKind>,
+    // Why the lexer stopped matching tokens there, if it did (ex: an inconsistent dedent)
+    pub lexer_error: Option<String>,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(error) = &self.lexer_error {
+            return write!(f, "{}:{}: {}", self.line, self.column, error);
+        }
+        let expected: Vec<String> = self.expected.iter().map(|kind| format!("{:?}", kind)).collect();
+        write!(f, "{}:{}: expected {}", self.line, self.column, expected.join(" or "))
+    }
+}
+
+impl std::error::Error for ParseError {}
+"#;
+
+const LEXER_HELPERS: &str = r#"fn literal(input: &str, pos: usize, text: &str) -> Option<usize> {
+    input[pos..].starts_with(text).then_some(pos + text.len())
+}
+
+fn literal_ignore_case(input: &str, pos: usize, text: &str) -> Option<usize> {
+    let mut end = pos;
+    let mut chars = input[pos..].chars();
+    for expected in text.chars() {
+        let ch = chars.next()?;
+        if !ch.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+        end += ch.len_utf8();
+    }
+    Some(end)
+}
+
+// `ranges` are sorted and don't overlap
+fn class(input: &str, pos: usize, ranges: &[(char, char)], negated: bool) -> Option<usize> {
+    let ch = input[pos..].chars().next()?;
+    let found = ranges
+        .binary_search_by(|&(start, end)| {
+            if end < ch {
+                std::cmp::Ordering::Less
+            } else if start > ch {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok();
+    (found != negated).then_some(pos + ch.len_utf8())
+}
+
+fn repeat(mut pos: usize, pattern: impl Fn(usize) -> Option<usize>) -> usize {
+    while let Some(end) = pattern(pos) {
+        if end == pos {
+            break;
+        }
+        pos = end;
+    }
+    pos
+}
+
+// Keywords can't be followed by anything that would continue them as an identifier
+fn word_end(input: &str, end: usize) -> bool {
+    !input[end..].starts_with(|ch: char| ch.is_alphanumeric() || ch == '_')
+}
+"#;
+
+const PARSER_HELPERS: &str = r#"pub fn new(input: &'i str, scanner: S) -> Self {
+    Parser {
+        input,
+        pos: Pos::default(),
+        lexer: Lexer::new(input, scanner),
+        silent: 0,
+        fail_pos: 0,
+        expected: vec![],
+    }
+}
+
+// Parsing starts with the first rule, which has to end with `EOF` to match all of the input
+pub fn parse(mut self) -> Result<Tree<'i>, ParseError> {
+    match {start} {
+        Some(mut trees) => Ok(trees.remove(0)),
+        None => Err(self.error()),
+    }
+}
+
+fn token(&mut self, kind: TokenKind) -> Option<Vec<Tree<'i>>> {
+    match self.lexer.token(kind, self.pos) {
+        Ok((token, end)) => {
+            self.pos = end;
+            Some(vec![Tree::Token(token)])
+        }
+        Err(start) => {
+            self.fail(start, kind);
+            None
+        }
+    }
+}
+
+fn fail(&mut self, pos: usize, kind: TokenKind) {
+    if self.silent > 0 || pos < self.fail_pos {
+        return;
+    }
+    if pos > self.fail_pos {
+        self.fail_pos = pos;
+        self.expected.clear();
+    }
+    if !self.expected.contains(&kind) {
+        self.expected.push(kind);
+    }
+}
+
+fn error(&self) -> ParseError {
+    let before = &self.input[..self.fail_pos];
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    ParseError {
+        line: before.matches('\n').count() + 1,
+        column: before[line_start..].chars().count() + 1,
+        expected: self.expected.clone(),
+        lexer_error: self.lexer.error(self.fail_pos).map(str::to_string),
+    }
+}
+"#;
+
+// Positions of lexers with indentation tokens, which don't take up any input
+const INDENT_TYPES: &str = r#"// Where the parser is in the input
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Pos {
+    // Byte offset
+    pub offset: usize,
+    // Indentation tokens matched at `offset`
+    pub indent: usize,
+}
+"#;
+
+const INDENT_FIELDS: &str = r#"    // Indentation tokens before the first token of a line (or the end of the input), by its offset
+    indents: Vec<(usize, Vec<TokenKind>)>,
+    // Where indentation is inconsistent and why - no tokens match from there on
+    error: Option<(usize, String)>,
+"#;
+
+const INDENT_NEW: &str = r#"let mut lexer = Lexer {
+    input,
+    scanner,
+    indents: vec![],
+    error: None,
+};
+lexer.track_indents();
+lexer"#;
+
+// The rest of `Lexer::token` for lexers with indentation tokens
+const INDENT_TOKEN: &str = r#"if matches!(self.error, Some((offset, _)) if start >= offset) {
+    return Err(start);
+}
+
+// Indentation tokens have to be matched before the first token of their line
+let matched = if start == pos.offset { pos.indent } else { 0 };
+let next = match self.indents.binary_search_by_key(&start, |(offset, _)| *offset) {
+    Ok(idx) => self.indents[idx].1.get(matched).copied(),
+    Err(_) => None,
+};
+let (end, indent) = match next {
+    Some(next) if next == kind => (start, matched + 1),
+    Some(_) => return Err(start),
+    None => (self.token_end(kind, start).ok_or(start)?, 0),
+};
+Ok((
+    Token {
+        kind,
+        text: &self.input[start..end],
+        start,
+    },
+    Pos { offset: end, indent },
+))"#;
+
+const INDENT_ERROR: &str = r#"pub fn error(&self, pos: usize) -> Option<&str> {
+    match &self.error {
+        Some((offset, error)) if pos >= *offset => Some(error),
+        _ => None,
+    }
+}
+"#;
+
+const INDENT_HELPERS: &str = r#"// Finds the indentation tokens before the first token of each line (outside brackets) and at the end of the input,
+// by matching the longest token at each position
+fn track_indents(&mut self) {
+    let input = self.input;
+    let mut levels = vec![0];
+    let mut depth = 0;
+    let mut line = None;
+    let mut pos = 0;
+
+    loop {
+        let start = self.skip(pos);
+        if start == input.len() {
+            break;
+        }
+
+        let line_start = input[..start].rfind('\n').map_or(0, |idx| idx + 1);
+        if line != Some(line_start) && depth == 0 {
+            match Self::indent_tokens(&mut levels, line.is_some(), &input[line_start..start]) {
+                Ok(kinds) if kinds.is_empty() => (),
+                Ok(kinds) => self.indents.push((start, kinds)),
+                Err(error) => {
+                    self.error = Some((start, error));
+                    return;
+                }
+            }
+        }
+        line = Some(line_start);
+
+        pos = match self.longest_token(start) {
+            Some((end, kind)) => {
+                depth = Self::bracket_depth(kind, depth);
+                end
+            }
+            // Characters no token matches are passed over
+            None => start + input[start..].chars().next().map_or(1, char::len_utf8),
+        };
+    }
+
+    // The end of the input ends the last line and closes any open indentation
+    let mut kinds = vec![];
+    if line.is_some() {
+        kinds.push({newline});
+    }
+    kinds.extend(levels[1..].iter().map(|_| {dedent}));
+    if !kinds.is_empty() {
+        self.indents.push((input.len(), kinds));
+    }
+}
+
+// The tokens before the first token on a line, given the text of the line before it - tabs advance to the next
+// tab stop ({tab_width} columns, where 0 means tabs aren't allowed)
+fn indent_tokens(levels: &mut Vec<usize>, started: bool, leading: &str) -> Result<Vec<TokenKind>, String> {
+    let tab_width = {tab_width};
+    let mut column = 0;
+    for ch in leading.chars().take_while(|&ch| ch == ' ' || ch == '\t') {
+        column = match ch {
+            '\t' if tab_width == 0 => return Err("tabs are not allowed in indentation".to_string()),
+            '\t' => (column / tab_width + 1) * tab_width,
+            _ => column + 1,
+        };
+    }
+
+    // The first line has no previous line to end
+    let mut kinds = vec![];
+    if started {
+        kinds.push({newline});
+    }
+    let current = *levels.last().unwrap();
+    if column > current {
+        levels.push(column);
+        kinds.push({indent});
+    } else if column < current {
+        if !levels.contains(&column) {
+            let levels: Vec<String> = levels.iter().map(|level| level.to_string()).collect();
+            return Err(format!(
+                "dedent to column {} does not match any enclosing indentation level (expected one of: {})",
+                column,
+                levels.join(", ")
+            ));
+        }
+        while *levels.last().unwrap() > column {
+            levels.pop();
+            kinds.push({dedent});
+        }
+    }
+    Ok(kinds)
+}
+
+// The longest token at `start` that isn't skipped
+fn longest_token(&mut self, start: usize) -> Option<(usize, TokenKind)> {
+    let mut longest: Option<(usize, TokenKind)> = None;
+    for kind in [{kinds}] {
+        if let Some(end) = self.token_end(kind, start) {
+            if end > longest.map_or(start, |(end, _)| end) {
+                longest = Some((end, kind));
+            }
+        }
+    }
+    longest
+}
+"#;
+
+struct RustLexerGen<'s> {
+    lexer: &'s LexerSpec,
+    vocab: &'s TokenVocab,
+}
+
+impl<'s> RustLexerGen<'s> {
+    // The token kinds, the scanner trait and the `Lexer` that matches tokens for the parser
+    fn write(&self, w: &mut String) {
+        let kinds: Vec<String> = self
+            .vocab
+            .tokens
+            .iter()
+            .map(|(name, id)| format!("    {} = {},", name.to_case(Case::UpperCamel), id))
+            .collect();
+        push_lines(
+            w,
+            0,
+            &format!(
+                "#[derive(Clone, Copy, Debug, PartialEq, Eq)]\npub enum TokenKind {{\n{}\n}}\n",
+                kinds.join("\n")
+            ),
+        );
+        push_lines(w, 0, TOKEN_TYPE);
+        if self.lexer.indent.is_some() {
+            push_lines(w, 0, INDENT_TYPES);
+        } else {
+            push_lines(
+                w,
+                0,
+                "// Where the parser is in the input - a byte offset\npub type Pos = usize;\n",
+            );
+        }
+
+        let trait_name = scanner_trait(self.lexer);
+        let mut methods: Vec<String> = self
+            .lexer
+            .scanner
+            .iter()
+            .flat_map(|scanner| &scanner.hooks)
+            .map(|hook| {
+                format!(
+                    "// {}\nfn {}(&mut self, input: &str, pos: usize) -> Option<usize>;\n",
+                    hook.name, hook.method
+                )
+            })
+            .collect();
+        methods.push("// Skips what `@skip` tokens can't match (ex: nested comments)\nfn skip(&mut self, _input: &str, pos: usize) -> usize {\n    pos\n}".to_string());
+        push_lines(
+            w,
+            0,
+            &format!(
+                "// Matches `extern` tokens and anything else the generated lexer can't\npub trait {} {}\n",
+                trait_name,
+                block(&methods)
+            ),
+        );
+        if self.extern_tokens().is_empty() {
+            push_lines(w, 0, &format!("impl {} for () {{}}\n", trait_name));
+        }
+
+        self.write_match_funcs(w);
+
+        let (fields, new) = if self.lexer.indent.is_some() {
+            (INDENT_FIELDS, INDENT_NEW)
+        } else {
+            ("", "Lexer { input, scanner }")
+        };
+        push_lines(
+            w,
+            0,
+            &format!(
+                "pub struct Lexer<'i, S> {{\n    input: &'i str,\n    scanner: S,\n{}}}\n",
+                fields
+            ),
+        );
+        push_lines(
+            w,
+            0,
+            &format!("impl<'i, S: {}> Lexer<'i, S> {{", trait_name),
+        );
+        push_lines(
+            w,
+            1,
+            &format!(
+                "pub fn new(input: &'i str, scanner: S) -> Self {}\n",
+                block(&[new.to_string()])
+            ),
+        );
+        self.write_skip_func(w);
+        self.write_token_funcs(w);
+        // Functions end with an empty line
+        w.pop();
+        w.push_str("}\n");
+    }
+
+    fn extern_tokens(&self) -> Vec<&str> {
+        match &self.lexer.scanner {
+            Some(scanner) => scanner
+                .hooks
+                .iter()
+                .map(|hook| hook.name.as_str())
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    fn write_match_funcs(&self, w: &mut String) {
+        for table in &self.lexer.tables {
+            let ranges: Vec<String> = table
+                .ranges
+                .iter()
+                .map(|(start, end)| format!("({:?}, {:?})", start, end))
+                .collect();
+            push_lines(w, 0, &format!("// {}", table.comment));
+            push_lines(
+                w,
+                0,
+                &format!(
+                    "const {}: &[(char, char)] = &[{}];\n",
+                    table.name.to_case(Case::UpperSnake),
+                    ranges.join(", ")
+                ),
+            );
+        }
+
+        for token in &self.lexer.tokens {
+            let keyword = self.lexer.keywords.iter().any(|kw| kw.id == token.id);
+            let mut body = option_expr(self.pattern_expr(&token.pattern));
+            if keyword {
+                body += ".filter(|&end| word_end(input, end))";
+            }
+
+            for line in &token.doc {
+                push_lines(w, 0, format!("/// {}", line).trim_end());
+            }
+            push_lines(w, 0, &format!("// {}", token.comment));
+            push_lines(
+                w,
+                0,
+                &format!(
+                    "fn {}(input: &str, pos: usize) -> Option<usize> {{\n    {}\n}}\n",
+                    match_func(&token.name),
+                    body
+                ),
+            );
+        }
+
+        push_lines(w, 0, LEXER_HELPERS);
+    }
+
+    // An `Option<usize>` expression of where the pattern ends when it matches at `pos` - `Err` for patterns that
+    // always match (a `usize` expression)
+    fn pattern_expr(&self, pattern: &Pattern) -> Result<String, String> {
+        match pattern {
+            Pattern::Literal {
+                value,
+                case_insensitive: false,
+            } => Ok(format!("literal(input, pos, {:?})", value)),
+            Pattern::Literal { value, .. } => Ok(format!(
+                "literal_ignore_case(input, pos, {:?})",
+                value.to_lowercase()
+            )),
+            Pattern::Class(class) => {
+                let ranges: Vec<String> = class
+                    .ranges
+                    .iter()
+                    .map(|(start, end)| format!("({:?}, {:?})", start, end))
+                    .collect();
+                Ok(format!(
+                    "class(input, pos, &[{}], {})",
+                    ranges.join(", "),
+                    class.negated
+                ))
+            }
+            Pattern::Table { index, negated } => Ok(format!(
+                "class(input, pos, {}, {})",
+                self.lexer.tables[*index].name.to_case(Case::UpperSnake),
+                negated
+            )),
+            Pattern::Sequence(patterns) => {
+                let mut exprs = patterns.iter().map(|pattern| self.pattern_expr(pattern));
+                let mut expr = option_expr(exprs.next().unwrap());
+                for next in exprs {
+                    expr = match next {
+                        Ok(next) => format!("{}.and_then(|pos| {})", expr, next),
+                        Err(next) => format!("{}.map(|pos| {})", expr, next),
+                    };
+                }
+                Ok(expr)
+            }
+            Pattern::Choice(patterns) => {
+                let exprs: Vec<String> = patterns
+                    .iter()
+                    .map(|pattern| option_expr(self.pattern_expr(pattern)))
+                    .collect();
+                Ok(exprs.join(".or_else(|| ") + &")".repeat(exprs.len() - 1))
+            }
+            Pattern::ZeroOrMore(pattern) => Err(format!(
+                "repeat(pos, |pos| {})",
+                option_expr(self.pattern_expr(pattern))
+            )),
+            Pattern::OneOrMore(pattern) => {
+                let expr = option_expr(self.pattern_expr(pattern));
+                Ok(format!("{}.map(|pos| repeat(pos, |pos| {}))", expr, expr))
+            }
+            Pattern::ZeroOrOne(pattern) => Err(format!(
+                "{}.unwrap_or(pos)",
+                option_expr(self.pattern_expr(pattern))
+            )),
+        }
+    }
+
+    fn write_skip_func(&self, w: &mut String) {
+        let mut lines = vec![
+            "let start = pos;".to_string(),
+            "pos = self.scanner.skip(self.input, pos);".to_string(),
+        ];
+        for token in self.lexer.tokens.iter().filter(|token| token.skip) {
+            lines.push(format!(
+                "if let Some(end) = {}(self.input, pos) {{\n    pos = end;\n}}",
+                match_func(&token.name)
+            ));
+        }
+        lines.push("if pos == start {\n    return pos;\n}".to_string());
+
+        push_lines(
+            w,
+            1,
+            &format!(
+                "// Skips `@skip` tokens (and whatever the scanner skips) until neither match\nfn skip(&mut self, mut pos: usize) -> usize {}\n",
+                block(&[format!("loop {}", block(&lines))])
+            ),
+        );
+    }
+
+    fn write_token_funcs(&self, w: &mut String) {
+        let externs = self.extern_tokens();
+        let offset = if self.lexer.indent.is_some() {
+            "pos.offset"
+        } else {
+            "pos"
+        };
+
+        let mut lines = vec![];
+        if externs.is_empty() {
+            lines.push(format!("let start = self.skip({});", offset));
+        } else {
+            let kinds: Vec<String> = externs.iter().map(|name| token_kind(name)).collect();
+            lines.push("// Extern tokens are matched where the last token ended, so their hooks can match what would otherwise be skipped".to_string());
+            lines.push(format!(
+                "let start = match kind {{\n    {} => {},\n    _ => self.skip({}),\n}};",
+                kinds.join(" | "),
+                offset,
+                offset
+            ));
+        }
+
+        let mut indent_helpers = None;
+        let error = match &self.lexer.indent {
+            Some(indent) => {
+                let kind = |id: u32| token_kind(self.vocab.token_name(id).unwrap_or_default());
+                lines.push(INDENT_TOKEN.to_string());
+                let mut helpers = INDENT_HELPERS
+                    .replace("{tab_width}", &indent.config.tab_width.to_string())
+                    .replace("{newline}", &kind(indent.newline))
+                    .replace("{indent}", &kind(indent.indent))
+                    .replace("{dedent}", &kind(indent.dedent));
+
+                // Newlines inside brackets don't end the line
+                let depth = if indent.brackets.is_empty() {
+                    "depth".to_string()
+                } else {
+                    let opens: Vec<String> = indent
+                        .brackets
+                        .iter()
+                        .map(|(open, _)| kind(*open))
+                        .collect();
+                    let closes: Vec<String> = indent
+                        .brackets
+                        .iter()
+                        .map(|(_, close)| kind(*close))
+                        .collect();
+                    format!(
+                        "match kind {{\n    {} => depth + 1,\n    {} => depth.saturating_sub(1),\n    _ => depth,\n}}",
+                        opens.join(" | "),
+                        closes.join(" | ")
+                    )
+                };
+                helpers += &format!(
+                    "\n// How many brackets are open after a `kind` token\nfn bracket_depth(kind: TokenKind, depth: usize) -> usize {}\n",
+                    block(&[depth])
+                );
+
+                // Tokens that aren't skipped, for the longest match
+                let kinds: Vec<String> = self
+                    .vocab
+                    .tokens
+                    .iter()
+                    .filter(|(_, id)| {
+                        let skip = self
+                            .lexer
+                            .tokens
+                            .iter()
+                            .any(|token| token.id == *id && token.skip);
+                        self.matchable(*id) && !skip
+                    })
+                    .map(|(name, _)| token_kind(name))
+                    .collect();
+                indent_helpers = Some(helpers.replace("{kinds}", &kinds.join(", ")));
+                INDENT_ERROR
+            }
+            None => {
+                lines.push(
+                    "let end = self.token_end(kind, start).ok_or(start)?;\nOk((\n    Token {\n        kind,\n        text: &self.input[start..end],\n        start,\n    },\n    end,\n))".to_string(),
+                );
+                "pub fn error(&self, pos: usize) -> Option<&str> {\n    None\n}\n"
+            }
+        };
+
+        push_lines(
+            w,
+            1,
+            &format!(
+                "// Matches a `kind` token at `pos`, after any skipped tokens - or returns where it was expected\npub fn token(&mut self, kind: TokenKind, pos: Pos) -> Result<(Token<'i>, Pos), usize> {}\n",
+                block(&lines)
+            ),
+        );
+        push_lines(
+            w,
+            1,
+            &format!(
+                "// Why no token matches at `pos`, when it isn't just the wrong token there (ex: an inconsistent dedent)\n{}",
+                error
+            ),
+        );
+
+        let mut arms = vec![];
+        let mut unmatched = vec![];
+        for (name, id) in &self.vocab.tokens {
+            let expr = if name == EOF {
+                "(start == input.len()).then_some(start)".to_string()
+            } else if let Some(hook) = self.hook(*id) {
+                format!("self.scanner.{}(input, start)", hook.method)
+            } else if self.matchable(*id) {
+                format!("{}(input, start)", match_func(name))
+            } else {
+                unmatched.push(token_kind(name));
+                continue;
+            };
+            arms.push(format!("    {} => {},", token_kind(name), expr));
+        }
+        // Tokens without a rule never match (ex: `ILLEGAL`)
+        if !unmatched.is_empty() {
+            arms.push(format!("    {} => None,", unmatched.join(" | ")));
+        }
+
+        push_lines(
+            w,
+            1,
+            &format!(
+                "// Where a `kind` token at `start` ends, if it matches there\nfn token_end(&mut self, kind: TokenKind, start: usize) -> Option<usize> {}\n",
+                block(&[
+                    "let input = self.input;".to_string(),
+                    format!("match kind {{\n{}\n}}", arms.join("\n")),
+                ])
+            ),
+        );
+        if let Some(helpers) = indent_helpers {
+            push_lines(w, 1, &helpers);
+        }
+    }
+
+    fn hook(&self, id: u32) -> Option<&'s ExternHook> {
+        self.lexer
+            .scanner
+            .iter()
+            .flat_map(|scanner| &scanner.hooks)
+            .find(|hook| hook.id == id)
+    }
+
+    // Tokens matched by a rule or an extern hook - `EOF` and the others (ex: `ILLEGAL`) aren't
+    fn matchable(&self, id: u32) -> bool {
+        self.hook(id).is_some() || self.lexer.tokens.iter().any(|token| token.id == id)
+    }
+}
+
+// Where the parser's lexer comes from
+enum LexerSource<'s> {
+    // Written into the same file, before the parser
+    Spec(&'s LexerSpec),
+    // Imported from the sibling module of a lexer grammar
+    Module(&'s str),
+}
+
+struct RustCodeGen<'s> {
+    lexer: LexerSource<'s>,
+    parser: &'s ParserSpec,
+    vocab: &'s TokenVocab,
+    // Name of the grammar file
+    source: &'s str,
+}
+
+impl<'s> RustCodeGen<'s> {
+    fn write(&self) -> String {
+        let mut out = String::new();
+
+        self.file_start(&mut out);
+        self.class_start(&mut out);
+        for func in &self.parser.functions {
+            self.func_start(&mut out, func, self.parser);
+            for action in &func.actions {
+                self.action(&mut out, action, func);
+            }
+            self.func_end(&mut out, func, self.parser);
+        }
+        self.class_end(&mut out);
+
+        out
+    }
+
+    // Names and types of the return values of the rule the function belongs to
+    fn returns(&self, func: &Function) -> Vec<(&'s str, &'s str)> {
+        let rule = self
+            .parser
+            .functions
+            .iter()
+            .find(|rule| rule.rule == func.rule && rule.node_kind.is_some());
+        rule.iter()
+            .flat_map(|rule| &rule.returns)
+            .filter_map(|decl| {
+                let name = RustConfig.param_name(decl)?;
+                let (_, ty) = decl.split_once(':')?;
+                Some((name, ty.trim()))
+            })
+            .collect()
+    }
+
+    // An `Option<Vec<Tree>>` expression calling the function - sub-functions are passed the return values of their
+    // rule to set, while the return values of a called rule are dropped
+    fn call_expr(&self, func_name: &str, args: &[String]) -> String {
+        let Some(func) = self
+            .parser
+            .functions
+            .iter()
+            .find(|func| func.name == func_name)
+        else {
+            return format!("self.{}({})", func_name, args.join(", "));
+        };
+        let returns = self.returns(func);
+
+        let mut args = args.to_vec();
+        if func.node_kind.is_none() {
+            args.extend(returns.iter().map(|(name, _)| name.to_string()));
+        }
+        let call = format!("self.{}({})", func_name, args.join(", "));
+        if func.node_kind.is_some() && !returns.is_empty() {
+            format!("{}.map(|(trees, _)| trees)", call)
+        } else {
+            call
+        }
+    }
+
+    // An `Option<Vec<Tree>>` expression of what the rule matches, ignoring its `MatchKind`
+    fn base_expr(&self, rule: &MatchRule) -> String {
+        match rule {
+            MatchRule::Token { token_name, .. } => {
+                format!(
+                    "self.token(TokenKind::{})",
+                    token_name.to_case(Case::UpperCamel)
+                )
+            }
+            MatchRule::Parser {
+                func_name, args, ..
+            } => self.call_expr(func_name, args),
+            MatchRule::SeparatedList {
+                item,
+                separator,
+                trailing,
+                ..
+            } => {
+                let item = self.kind_expr(item);
+                let separator = self.kind_expr(separator);
+
+                let mut loop_lines = vec![
+                    "let start = self.pos;".to_string(),
+                    format!(
+                        "let Some(separator) = {} else {}",
+                        separator,
+                        block(&[
+                            "self.pos = start;".to_string(),
+                            "break Some(items);".to_string()
+                        ])
+                    ) + ";",
+                ];
+                let no_item = if *trailing {
+                    loop_lines.push("let end = self.pos;".to_string());
+                    vec![
+                        "self.pos = end;".to_string(),
+                        "items.extend(separator);".to_string(),
+                        "break Some(items);".to_string(),
+                    ]
+                } else {
+                    vec![
+                        "self.pos = start;".to_string(),
+                        "break Some(items);".to_string(),
+                    ]
+                };
+                loop_lines.push(format!(
+                    "match {} {}",
+                    item,
+                    block(&[
+                        format!(
+                            "Some(item) => {}",
+                            block(&[
+                                "items.extend(separator);".to_string(),
+                                "items.extend(item);".to_string()
+                            ])
+                        ),
+                        format!("None => {}", block(&no_item)),
+                    ])
+                ));
+
+                format!(
+                    "match {} {}",
+                    item,
+                    block(&[
+                        format!("Some(mut items) => loop {},", block(&loop_lines)),
+                        "None => None,".to_string(),
+                    ])
+                )
+            }
+            MatchRule::Lookahead { rule, negative, .. } => {
+                let matched = if *negative { "(!matched)" } else { "matched" };
+                block(&[
+                    "let start = self.pos;".to_string(),
+                    "self.silent += 1;".to_string(),
+                    format!("let matched = {}.is_some();", self.kind_expr(rule)),
+                    "self.silent -= 1;".to_string(),
+                    "self.pos = start;".to_string(),
+                    format!("{}.then(Vec::new)", matched),
+                ])
+            }
+            MatchRule::Empty { .. } => "Some(Vec::new())".to_string(),
+            MatchRule::Code {
+                code,
+                predicate: false,
+                ..
+            } => block(&[statement(code), "Some(Vec::new())".to_string()]),
+            MatchRule::Code { code, .. } => format!("({}).then(Vec::new)", code.trim()),
+        }
+    }
+
+    // Same as `base_expr`, but matched as often as its `MatchKind` says
+    fn kind_expr(&self, rule: &MatchRule) -> String {
+        let base = self.base_expr(rule);
+        let repeat = |items: &str| {
+            format!(
+                "loop {}",
+                block(&[
+                    "let start = self.pos;".to_string(),
+                    format!(
+                        "match {} {}",
+                        base,
+                        block(&[
+                            format!(
+                                "Some(matched) if self.pos > start => {}.extend(matched),",
+                                items
+                            ),
+                            format!(
+                                "_ => {}",
+                                block(&[
+                                    "self.pos = start;".to_string(),
+                                    format!("break Some({});", items),
+                                ])
+                            ),
+                        ])
+                    ),
+                ])
+            )
+        };
+
+        match match_kind(rule) {
+            MatchKind::Once => base,
+            MatchKind::ZeroOrOnce => block(&[
+                "let start = self.pos;".to_string(),
+                format!(
+                    "match {} {}",
+                    base,
+                    block(&[
+                        "Some(matched) => Some(matched),".to_string(),
+                        format!(
+                            "None => {}",
+                            block(&[
+                                "self.pos = start;".to_string(),
+                                "Some(Vec::new())".to_string(),
+                            ])
+                        ),
+                    ])
+                ),
+            ]),
+            MatchKind::ZeroOrMore => {
+                block(&["let mut items = Vec::new();".to_string(), repeat("items")])
+            }
+            MatchKind::OnceOrMore => format!(
+                "match {} {}",
+                base,
+                block(&[
+                    format!("Some(mut items) => {},", repeat("items")),
+                    "None => None,".to_string(),
+                ])
+            ),
+        }
+    }
+
+    // Matches of a function without `ret_on_match` are added to `children`, and it fails as soon as one doesn't match
+    fn write_sequence_match(&self, w: &mut String, rule: &MatchRule) {
+        let base = self.base_expr(rule);
+        let once = if base.contains('\n') {
+            format!("let matched = {};\nchildren.extend(matched?);", base)
+        } else {
+            format!("children.extend({}?);", base)
+        };
+        let repeat = format!(
+            "loop {}",
+            block(&[
+                "let start = self.pos;".to_string(),
+                format!(
+                    "match {} {}",
+                    base,
+                    block(&[
+                        "Some(matched) if self.pos > start => children.extend(matched),"
+                            .to_string(),
+                        format!(
+                            "_ => {}",
+                            block(&["self.pos = start;".to_string(), "break;".to_string()])
+                        ),
+                    ])
+                ),
+            ])
+        );
+
+        let code = match (rule, match_kind(rule)) {
+            (MatchRule::Empty { .. }, _) => return,
+            (
+                MatchRule::Code {
+                    code,
+                    predicate: false,
+                    ..
+                },
+                _,
+            ) => statement(code),
+            (MatchRule::Code { code, .. }, _) => {
+                format!(
+                    "if !({}) {}",
+                    code.trim(),
+                    block(&["return None;".to_string()])
+                )
+            }
+            (_, MatchKind::Once) => once,
+            (_, MatchKind::ZeroOrOnce) => format!(
+                "let start = self.pos;\nmatch {} {}",
+                base,
+                block(&[
+                    "Some(matched) => children.extend(matched),".to_string(),
+                    "None => self.pos = start,".to_string(),
+                ])
+            ),
+            (_, MatchKind::ZeroOrMore) => repeat,
+            (_, MatchKind::OnceOrMore) => format!("{}\n{}", once, repeat),
+        };
+        push_lines(w, 2, &code);
+    }
+
+    // Alternatives of a function with `ret_on_match` are tried from where the function started, until one matches
+    fn write_alternative_match(&self, w: &mut String, rule: &MatchRule, func: &Function) {
+        let expr = self.kind_expr(rule);
+        let ret = block(&[format!("return Some({});", ret_value(func))]);
+
+        let code = if expr.contains('\n') {
+            format!(
+                "let matched = {};\nif let Some(children) = matched {}\nself.pos = start;",
+                expr, ret
+            )
+        } else {
+            format!(
+                "if let Some(children) = {} {}\nself.pos = start;",
+                expr, ret
+            )
+        };
+        push_lines(w, 2, &code);
+    }
+
+    fn write_match(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        push_lines(w, 2, &format!("// {}", comment(action)));
+        if func.ret_on_match {
+            self.write_alternative_match(w, action, func);
+        } else {
+            self.write_sequence_match(w, action);
+        }
+    }
+}
+
+impl<'s> LangCodeGen<String> for RustCodeGen<'s> {
+    fn file_start(&self, w: &mut String) {
+        write_header(w, self.source);
+        match self.lexer {
+            LexerSource::Spec(lexer) => {
+                push_lines(w, 0, "use std::fmt;\n");
+                RustLexerGen {
+                    lexer,
+                    vocab: self.vocab,
+                }
+                .write(w);
+                w.push('\n');
+            }
+            LexerSource::Module(module) => push_lines(
+                w,
+                0,
+                &format!(
+                    "use std::fmt;\n\nuse super::{}::{{{}, Lexer, Pos, Token, TokenKind}};\n",
+                    module.to_case(Case::Snake),
+                    SCANNER_TRAIT
+                ),
+            ),
+        }
+
+        let kinds: Vec<String> = self
+            .parser
+            .functions
+            .iter()
+            .filter_map(|func| Some(format!("    {},", func.node_kind.as_ref()?)))
+            .collect();
+        push_lines(
+            w,
+            0,
+            &format!(
+                "#[derive(Clone, Copy, Debug, PartialEq, Eq)]\npub enum RuleKind {{\n{}\n}}\n",
+                kinds.join("\n")
+            ),
+        );
+
+        push_lines(w, 0, PARSER_TYPES);
+    }
+
+    fn class_start(&self, w: &mut String) {
+        let trait_name = match self.lexer {
+            LexerSource::Spec(lexer) => scanner_trait(lexer),
+            LexerSource::Module(_) => SCANNER_TRAIT,
+        };
+
+        push_lines(
+            w,
+            0,
+            r#"pub struct Parser<'i, S> {
+    input: &'i str,
+    pos: Pos,
+    lexer: Lexer<'i, S>,
+    // Lookahead nesting - tokens that don't match within lookahead aren't expected
+    silent: usize,
+    // The furthest position a token didn't match at, and the tokens expected there
+    fail_pos: usize,
+    expected: Vec<TokenKind>,
+}
+"#,
+        );
+        push_lines(
+            w,
+            0,
+            &format!("impl<'i, S: {}> Parser<'i, S> {{", trait_name),
+        );
+        push_lines(
+            w,
+            1,
+            &PARSER_HELPERS.replace(
+                "{start}",
+                &self.call_expr(self.parser.start.as_deref().unwrap_or_default(), &[]),
+            ),
+        );
+    }
+
+    fn class_end(&self, w: &mut String) {
+        // Functions end with an empty line
+        w.pop();
+        w.push_str("}\n");
+    }
+
+    fn func_start(&self, w: &mut String, func: &Function, _spec: &ParserSpec) {
+        for line in &func.doc {
+            push_lines(w, 1, format!("/// {}", line).trim_end());
+        }
+        push_lines(w, 1, &format!("// {}", func.comment));
+
+        let visibility = if func.node_kind.is_some() { "pub " } else { "" };
+        let returns = self.returns(func);
+        let mut params = vec!["&mut self".to_string()];
+        params.extend(func.params.iter().cloned());
+        // Sub-functions set the return values of their rule
+        if func.node_kind.is_none() {
+            params.extend(
+                returns
+                    .iter()
+                    .map(|(name, ty)| format!("{}: &mut {}", name, ty)),
+            );
+        }
+        let ret_type = match (&func.node_kind, returns_type(&returns)) {
+            (Some(_), Some(ty)) => format!("(Vec<Tree<'i>>, {})", ty),
+            _ => "Vec<Tree<'i>>".to_string(),
+        };
+        push_lines(
+            w,
+            1,
+            &format!(
+                "{}fn {}({}) -> Option<{}> {{",
+                visibility,
+                func.name,
+                params.join(", "),
+                ret_type
+            ),
+        );
+
+        if let (Some(_), Some(ty)) = (&func.node_kind, returns_type(&returns)) {
+            let names: Vec<&str> = returns.iter().map(|(name, _)| *name).collect();
+            let pattern = match names.as_slice() {
+                [name] => name.to_string(),
+                names => format!("({})", names.join(", ")),
+            };
+            push_lines(
+                w,
+                2,
+                &format!(
+                    "// Return values start out as their `Default`, and actions set them through `&mut` references\nlet mut returns: {} = Default::default();\nlet {} = &mut returns;",
+                    ty, pattern
+                ),
+            );
+        }
+
+        if func.ret_on_match {
+            push_lines(w, 2, "let start = self.pos;");
+        } else {
+            // Only actions and empty matches add no children
+            let adds = func
+                .actions
+                .iter()
+                .any(|action| !matches!(action, MatchRule::Empty { .. } | MatchRule::Code { .. }));
+            let var = if adds { "mut children" } else { "children" };
+            push_lines(w, 2, &format!("let {} = Vec::new();", var));
+        }
+    }
+
+    fn func_end(&self, w: &mut String, func: &Function, _spec: &ParserSpec) {
+        if func.ret_on_match {
+            push_lines(w, 2, "None");
+        } else {
+            push_lines(w, 2, &format!("Some({})", ret_value(func)));
+        }
+        push_lines(w, 1, "}\n");
+    }
+
+    fn match_token_once(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+
+    fn match_token_zero_or_once(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+
+    fn match_token_zero_or_more(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+
+    fn match_token_once_or_more(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+
+    fn match_rule_once(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+
+    fn match_rule_zero_or_once(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+
+    fn match_rule_zero_or_more(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+
+    fn match_rule_once_or_more(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+
+    fn match_separated_list(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+
+    fn match_lookahead(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+
+    fn match_empty(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+
+    fn embedded_code(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+
+    fn embedded_predicate(&self, w: &mut String, action: &MatchRule, func: &Function) {
+        self.write_match(w, action, func);
+    }
+}
+
+// The trait of lexers without a scanner, and of lexers imported from a module
+const SCANNER_TRAIT: &str = "ExternalScanner";
+
+fn scanner_trait(lexer: &LexerSpec) -> &str {
+    lexer
+        .scanner
+        .as_ref()
+        .map_or(SCANNER_TRAIT, |scanner| scanner.trait_name.as_str())
+}
+
+fn match_kind(rule: &MatchRule) -> &MatchKind {
+    match rule {
+        MatchRule::Token { match_kind, .. }
+        | MatchRule::Parser { match_kind, .. }
+        | MatchRule::SeparatedList { match_kind, .. }
+        | MatchRule::Lookahead { match_kind, .. } => match_kind,
+        MatchRule::Empty { .. } | MatchRule::Code { .. } => &MatchKind::Once,
+    }
+}
+
+fn comment(rule: &MatchRule) -> &str {
+    match rule {
+        MatchRule::Token { comment, .. }
+        | MatchRule::Parser { comment, .. }
+        | MatchRule::SeparatedList { comment, .. }
+        | MatchRule::Lookahead { comment, .. }
+        | MatchRule::Empty { comment }
+        | MatchRule::Code { comment, .. } => comment,
+    }
+}
+
+// Rules wrap their matches in a tree node (next to their return values), sub-functions leave that to their rule
+fn ret_value(func: &Function) -> String {
+    match &func.node_kind {
+        Some(kind) => {
+            let tree = format!(
+                "vec![Tree::Rule {{\n    kind: RuleKind::{},\n    children,\n}}]",
+                kind
+            );
+            if func.returns.is_empty() {
+                tree
+            } else {
+                format!("({}, returns)", tree)
+            }
+        }
+        None => "children".to_string(),
+    }
+}
+
+// The type of a rule's return values - a tuple when there is more than one
+fn returns_type(returns: &[(&str, &str)]) -> Option<String> {
+    let types: Vec<&str> = returns.iter().map(|(_, ty)| *ty).collect();
+    match types.as_slice() {
+        [] => None,
+        [ty] => Some(ty.to_string()),
+        types => Some(format!("({})", types.join(", "))),
+    }
+}
+
+fn token_kind(name: &str) -> String {
+    format!("TokenKind::{}", name.to_case(Case::UpperCamel))
+}
+
+fn match_func(token: &str) -> String {
+    format!("match_{}", token).to_case(Case::Snake)
+}
+
+// Patterns that always match are made to match `Some` end
+fn option_expr(expr: Result<String, String>) -> String {
+    match expr {
+        Ok(expr) => expr,
+        Err(expr) => format!("Some({})", expr),
+    }
+}
+
+// Action code is a statement, which needs a ';' unless it is a block
+fn statement(code: &str) -> String {
+    let code = code.trim();
+    if code.ends_with(';') || code.ends_with('}') {
+        code.to_string()
+    } else {
+        format!("{};", code)
+    }
+}
+
+fn block(lines: &[String]) -> String {
+    let mut out = "{\n".to_string();
+    for line in lines {
+        for line in line.lines() {
+            if !line.is_empty() {
+                out += "    ";
+            }
+            out += line;
+            out += "\n";
+        }
+    }
+    out + "}"
+}
+
+fn push_lines(w: &mut String, indent: usize, text: &str) {
+    for line in text.lines() {
+        if !line.is_empty() {
+            w.push_str(&"    ".repeat(indent));
+        }
+        w.push_str(line);
+        w.push('\n');
+    }
+    // A trailing newline ends with an empty line
+    if text.ends_with('\n') {
+        w.push('\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_hrpg;
+    use crate::lexer_gen::LexerGen;
+    use crate::parser_gen::ParserGen;
+    use crate::transform::Transform;
+    use std::process::Command;
+    use std::{env, fs, process};
+
+    fn write(data: &str) -> Result<String, String> {
+        let (grammar, transform) = Transform::process(&parse_hrpg(data).unwrap());
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+
+        let vocab = transform.vocab();
+        let lexer = LexerGen::new(RustConfig).generate(&grammar, &vocab);
+        let parser = ParserGen::new(RustConfig).generate(&grammar);
+        write_rust(&lexer, &parser, &vocab, "test.hrpg")
+    }
+
+    fn write_lexer(data: &str) -> (String, TokenVocab) {
+        let (grammar, transform) = Transform::process(&parse_hrpg(data).unwrap());
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+
+        let vocab = transform.vocab();
+        let lexer = LexerGen::new(RustConfig).generate(&grammar, &vocab);
+        (write_rust_lexer(&lexer, &vocab, "lexer.hrpg"), vocab)
+    }
+
+    fn write_parser(data: &str, vocab: &TokenVocab) -> String {
+        let (grammar, transform) =
+            Transform::process_with_vocab(&parse_hrpg(data).unwrap(), Some(vocab));
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+
+        let parser = ParserGen::new(RustConfig).generate(&grammar);
+        write_rust_parser("lexer", &parser, &transform.vocab(), "parser.hrpg").unwrap()
+    }
+
+    // Compiles the generated modules along with `main`, and returns what running it prints
+    fn run(test: &str, modules: &[(&str, &str)], main: &str) -> String {
+        let dir = env::temp_dir().join(format!("hrpg_{}_{}", test, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut source = String::new();
+        for (name, code) in modules {
+            fs::write(dir.join(format!("{}.rs", name)), code).unwrap();
+            source += &format!("mod {};\n", name);
+        }
+        fs::write(dir.join("main.rs"), source + main).unwrap();
+
+        let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let output = Command::new(rustc)
+            .args(["--edition", "2021", "-o"])
+            .arg(dir.join("main"))
+            .arg(dir.join("main.rs"))
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+
+        let output = Command::new(dir.join("main")).output().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[test]
+    fn rule_returns() {
+        let code = write(
+            "list returns [count: usize, end: usize]: (num {rust: *count += 1})+ {rust: *end = self.pos} EOF;
+            num returns [digits: usize]: NUM {rust: *digits = 1} | '-' NUM {rust: *digits = 2};
+            NUM: [0-9]+; MINUS: '-';\n## @skip\nWS: ' '+;",
+        )
+        .unwrap();
+        assert!(code
+            .contains("pub fn parse_list(&mut self) -> Option<(Vec<Tree<'i>>, (usize, usize))>"));
+        assert!(code.contains("fn parse_num_sub_1(&mut self, digits: &mut usize)"));
+
+        let out = run(
+            "rule_returns",
+            &[("parser", &code)],
+            r#"fn main() {
+    println!("{:?}", parser::Parser::new("1 22 -333", ()).parse_list().map(|(_, returns)| returns));
+    println!("{:?}", parser::Parser::new("-1", ()).parse_num().map(|(_, returns)| returns));
+    println!("{}", parser::Parser::new("1 2", ()).parse().is_ok());
+}"#,
+        );
+        assert_eq!(out, "Some((3, 9))\nSome(2)\ntrue\n");
+    }
+
+    #[test]
+    fn start_without_args() {
+        let err = write("a[x: bool]: 'q'; Q: 'q';").unwrap_err();
+        assert!(err.contains("Rule 'a' can't take arguments"), "{}", err);
+        assert!(write("lexer grammar L; Q: 'q';").is_err());
+    }
+
+    #[test]
+    fn keywords_end_words() {
+        let code = write("a: 'if' ID; ID: [a-z]+; IF: 'if';").unwrap();
+        assert!(code.contains("word_end(input, end)"), "{}", code);
+        assert!(code.contains("pub fn parse(mut self) -> Result<Tree<'i>, ParseError>"));
+    }
+
+    #[test]
+    fn extern_scanner() {
+        let code = write("extern HEREDOC\na: HEREDOC\n").unwrap();
+        assert!(
+            code.contains("fn scan_heredoc(&mut self, input: &str, pos: usize) -> Option<usize>;")
+        );
+        assert!(!code.contains("impl ExternalScanner for () {}"));
+
+        let code = write("a: 'q'; Q: 'q';").unwrap();
+        assert!(code.contains("impl ExternalScanner for () {}"));
+    }
+
+    #[test]
+    fn lexer_grammar() {
+        let (code, _) = write_lexer("lexer grammar L;\nNUM: [0-9]+;\n## @skip\nWS: ' '+;");
+        assert!(!code.contains("Parser"));
+
+        let out = run(
+            "lexer_grammar",
+            &[("lexer", &code)],
+            r#"fn main() {
+    let mut lexer = lexer::Lexer::new("  12+", ());
+    println!("{:?}", lexer.token(lexer::TokenKind::Num, 0));
+    println!("{:?}", lexer.token(lexer::TokenKind::Num, 4));
+}"#,
+        );
+        assert_eq!(
+            out,
+            "Ok((Token { kind: Num, text: \"12\", start: 2 }, 4))\nErr(4)\n"
+        );
+    }
+
+    #[test]
+    fn parser_grammar() {
+        let (lexer, vocab) =
+            write_lexer("lexer grammar L;\nNUM: [0-9]+;\nPLUS: '+';\n## @skip\nWS: ' '+;");
+        let parser = write_parser(
+            "parser grammar P;\noptions { tokenVocab = L; }\nsum: NUM ('+' NUM)* EOF;",
+            &vocab,
+        );
+        assert!(
+            parser.contains("use super::lexer::{ExternalScanner, Lexer, Pos, Token, TokenKind};")
+        );
+
+        let out = run(
+            "parser_grammar",
+            &[("lexer", &lexer), ("parser", &parser)],
+            r#"fn main() {
+    let tree = parser::Parser::new("1 + 2", ()).parse().unwrap();
+    let parser::Tree::Rule { children, .. } = tree else { panic!() };
+    println!("{}", children.len());
+    println!("{}", parser::Parser::new("1 +", ()).parse().unwrap_err());
+}"#,
+        );
+        assert_eq!(out, "4\n1:4: expected Num\n");
+    }
+
+    const BLOCKS: &str = "options { indent = true; tabWidth = 4; }
+        block: stmt* EOF;
+        stmt: ID ('(' ID* ')')? (':' NEWLINE INDENT stmt+ DEDENT | NEWLINE);
+        ID: [a-z]+; LPAREN: '('; RPAREN: ')'; COLON: ':';\n## @skip\nWS: [ \\t\\n]+;";
+
+    // Parses each input and prints the tokens or the error
+    const PRINT_TOKENS: &str = r#"fn tokens(tree: &parser::Tree, out: &mut Vec<String>) {
+    match tree {
+        parser::Tree::Rule { children, .. } => children.iter().for_each(|child| tokens(child, out)),
+        parser::Tree::Token(token) if token.text.is_empty() => out.push(format!("{:?}", token.kind)),
+        parser::Tree::Token(token) => out.push(token.text.to_string()),
+    }
+}
+
+fn main() {
+    for input in INPUTS {
+        match parser::Parser::new(input, ()).parse() {
+            Ok(tree) => {
+                let mut out = vec![];
+                tokens(&tree, &mut out);
+                println!("{}", out.join(" "));
+            }
+            Err(err) => println!("{}", err),
+        }
+    }
+}"#;
+
+    #[test]
+    fn indentation() {
+        let code = write(BLOCKS).unwrap();
+        let main = r#"const INPUTS: &[&str] = &[
+    "a:\n  b\n  c:\n    d\ne",
+    "a (\n  b\n c\n   ):\n    d",
+    "a:\n\tb\n    c\n",
+    "\n  a\n",
+];
+"#;
+        let out = run(
+            "indentation",
+            &[("parser", &code)],
+            &(main.to_string() + PRINT_TOKENS),
+        );
+        assert_eq!(
+            out.lines().collect::<Vec<_>>(),
+            [
+                "a : Newline Indent b Newline c : Newline Indent d Newline Dedent Dedent e Newline Eof",
+                // Newlines inside brackets don't end the line
+                "a ( b c ) : Newline Indent d Newline Dedent Eof",
+                // A tab is 4 columns
+                "a : Newline Indent b Newline c Newline Dedent Eof",
+                // An indented first line starts with `INDENT`
+                "2:3: expected Id or Eof",
+            ]
+        );
+    }
+
+    #[test]
+    fn indentation_errors() {
+        let code = write(BLOCKS).unwrap();
+        let main = r#"const INPUTS: &[&str] = &["a:\n    b\n  c", "a:\n  b\n   c"];
+"#;
+        let out = run(
+            "indentation_errors",
+            &[("parser", &code)],
+            &(main.to_string() + PRINT_TOKENS),
+        );
+        assert_eq!(
+            out,
+            "3:3: dedent to column 2 does not match any enclosing indentation level (expected one of: 0, 4)\n\
+             3:4: expected Lparen or Colon or Id or Dedent\n"
+        );
+
+        let code = write(&BLOCKS.replace("tabWidth = 4", "tabWidth = 0")).unwrap();
+        let main = r#"const INPUTS: &[&str] = &["a:\n\tb"];
+"#;
+        let out = run(
+            "tabs_not_allowed",
+            &[("parser", &code)],
+            &(main.to_string() + PRINT_TOKENS),
+        );
+        assert_eq!(out, "2:2: tabs are not allowed in indentation\n");
+    }
 }
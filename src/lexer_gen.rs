@@ -0,0 +1,312 @@
+use crate::ast::{Comment, Grammar, Node, TokenRule};
+use crate::escape::{parse_char_class, unescape_literal, CharClass};
+use crate::indent::{IndentConfig, DEDENT, INDENT, NEWLINE};
+use crate::parser_gen::LangConfig;
+use crate::vocab::TokenVocab;
+use convert_case::Casing;
+
+// Grammar option that makes every literal in token rules ignore case
+pub const CASE_INSENSITIVE: &str = "caseInsensitive";
+
+// Token rule annotation for tokens skipped between the tokens of parser rules (ex: `## @skip` on whitespace)
+pub const SKIP_ANNOTATION: &str = "skip";
+
+// Classes with more ranges than this are matched via a range table instead of inline comparisons
+const MAX_INLINE_RANGES: usize = 4;
+
+// The language agnostic description of what a token matches
+#[derive(Debug)]
+pub enum Pattern {
+    Literal {
+        value: String,
+        case_insensitive: bool,
+    },
+    Class(CharClass),
+    // Index into `LexerSpec::tables`
+    Table {
+        index: usize,
+        negated: bool,
+    },
+    Sequence(Vec<Pattern>),
+    Choice(Vec<Pattern>),
+    ZeroOrMore(Box<Pattern>),
+    OneOrMore(Box<Pattern>),
+    ZeroOrOne(Box<Pattern>),
+}
+
+#[derive(Debug)]
+pub struct LexToken {
+    pub name: String,
+    pub id: u32,
+    pub doc: Vec<String>,
+    pub comment: String,
+    pub pattern: Pattern,
+    // Matched and dropped before the tokens the parser expects
+    pub skip: bool,
+}
+
+// A literal token that would also match as an identifier - these are best matched as an identifier and then
+// looked up in a table
+#[derive(Debug)]
+pub struct Keyword {
+    pub name: String,
+    pub id: u32,
+    // Lowercased when `case_insensitive` is set
+    pub text: String,
+    pub case_insensitive: bool,
+}
+
+// Sorted ranges to binary search, shared by all classes with the same ranges (ex: `\p{XID_Start}`)
+#[derive(Debug)]
+pub struct RangeTable {
+    pub name: String,
+    pub comment: String,
+    pub ranges: Vec<(char, char)>,
+}
+
+// Token ids for indentation sensitive lexing - the generated lexer synthesizes them
+#[derive(Debug)]
+pub struct IndentSpec {
+    pub config: IndentConfig,
+    pub indent: u32,
+    pub dedent: u32,
+    pub newline: u32,
+    // Open and close token ids of brackets - newlines inside them are ignored
+    pub brackets: Vec<(u32, u32)>,
+}
+
+// A token declared `extern` - matched by a user supplied method instead of a generated pattern
+#[derive(Debug)]
+pub struct ExternHook {
+    pub name: String,
+    pub id: u32,
+    pub method: String,
+}
+
+// The trait users implement to match `extern` tokens. At the start of each token the generated lexer calls the
+// hooks in declaration order, before trying any generated patterns, and takes the first token a hook accepts
+#[derive(Debug)]
+pub struct ScannerSpec {
+    pub trait_name: String,
+    pub hooks: Vec<ExternHook>,
+}
+
+#[derive(Debug)]
+pub struct LexerSpec {
+    pub tokens: Vec<LexToken>,
+    pub keywords: Vec<Keyword>,
+    pub tables: Vec<RangeTable>,
+    pub indent: Option<IndentSpec>,
+    pub scanner: Option<ScannerSpec>,
+}
+
+pub struct LexerGen<L> {
+    tokens: Vec<LexToken>,
+    keywords: Vec<Keyword>,
+    tables: Vec<RangeTable>,
+    case_insensitive: bool,
+    lang_config: L,
+}
+
+impl<L: LangConfig> LexerGen<L> {
+    pub fn new(config: L) -> Self {
+        LexerGen {
+            tokens: vec![],
+            keywords: vec![],
+            tables: vec![],
+            case_insensitive: false,
+            lang_config: config,
+        }
+    }
+
+    // Token ids come from the vocabulary so the lexer and parser agree on them
+    pub fn generate(mut self, grammar: &Grammar, vocab: &TokenVocab) -> LexerSpec {
+        self.case_insensitive = grammar.option(CASE_INSENSITIVE) == Some("true");
+
+        for rule in &grammar.token_rules {
+            log::trace!("Starting token rule: {}", &rule.name);
+            self.make_token(rule, vocab);
+            log::trace!("Ending token rule: {}", &rule.name);
+        }
+
+        // Option errors were already reported by `Transform`
+        let indent = match IndentConfig::from_grammar(grammar) {
+            Ok(Some(config)) => make_indent_spec(config, vocab),
+            _ => None,
+        };
+        let scanner = self.make_scanner(grammar, vocab);
+
+        LexerSpec {
+            tokens: self.tokens,
+            keywords: self.keywords,
+            tables: self.tables,
+            indent,
+            scanner,
+        }
+    }
+
+    fn make_scanner(&self, grammar: &Grammar, vocab: &TokenVocab) -> Option<ScannerSpec> {
+        if grammar.extern_tokens.is_empty() {
+            return None;
+        }
+
+        let hooks = grammar
+            .extern_tokens
+            .iter()
+            .filter_map(|name| {
+                let id = token_id(vocab, name)?;
+                Some(ExternHook {
+                    name: name.to_string(),
+                    id,
+                    method: format!("scan_{}", name).to_case(self.lang_config.function_case()),
+                })
+            })
+            .collect();
+
+        Some(ScannerSpec {
+            trait_name: "external_scanner".to_case(self.lang_config.class_case()),
+            hooks,
+        })
+    }
+
+    fn make_token(&mut self, rule: &TokenRule, vocab: &TokenVocab) {
+        let id = token_id(vocab, &rule.name);
+
+        // Invalid escapes and classes were already reported by `Transform`
+        if let (Some(id), Some(pattern)) = (id, self.make_pattern(&rule.name, &rule.node)) {
+            if let Pattern::Literal {
+                value,
+                case_insensitive,
+            } = &pattern
+            {
+                if is_keyword(value) {
+                    self.keywords.push(Keyword {
+                        name: rule.name.to_string(),
+                        id,
+                        text: if *case_insensitive {
+                            value.to_lowercase()
+                        } else {
+                            value.to_string()
+                        },
+                        case_insensitive: *case_insensitive,
+                    });
+                }
+            }
+
+            self.tokens.push(LexToken {
+                name: rule.name.to_string(),
+                id,
+                doc: rule.doc.clone(),
+                comment: rule.comment(),
+                pattern,
+                skip: rule.annotation(SKIP_ANNOTATION).is_some(),
+            });
+        }
+    }
+
+    fn make_pattern(&mut self, token: &str, node: &Node) -> Option<Pattern> {
+        let mut make_all = |nodes: &[Node]| {
+            nodes
+                .iter()
+                .map(|node| self.make_pattern(token, node))
+                .collect::<Option<Vec<_>>>()
+        };
+
+        Some(match node {
+            Node::TokenLit {
+                literal,
+                case_insensitive,
+            } => Pattern::Literal {
+                value: unescape_literal(literal).ok()?,
+                case_insensitive: *case_insensitive || self.case_insensitive,
+            },
+            Node::CharClass { class } => {
+                let char_class = parse_char_class(class).ok()?;
+                if char_class.ranges.len() > MAX_INLINE_RANGES {
+                    Pattern::Table {
+                        index: self.add_table(token, class, char_class.ranges),
+                        negated: char_class.negated,
+                    }
+                } else {
+                    Pattern::Class(char_class)
+                }
+            }
+            Node::MultipartBody { nodes } => Pattern::Sequence(make_all(nodes)?),
+            Node::Alternatives { nodes } => Pattern::Choice(make_all(nodes)?),
+            Node::ZeroOrMore { node } => {
+                Pattern::ZeroOrMore(Box::new(self.make_pattern(token, node)?))
+            }
+            Node::OneOrMore { node } => {
+                Pattern::OneOrMore(Box::new(self.make_pattern(token, node)?))
+            }
+            Node::ZeroOrOne { node, .. } => {
+                Pattern::ZeroOrOne(Box::new(self.make_pattern(token, node)?))
+            }
+            _ => {
+                unreachable!("Token rules only contain literals, character classes and repetition")
+            }
+        })
+    }
+
+    fn add_table(&mut self, token: &str, class: &str, ranges: Vec<(char, char)>) -> usize {
+        if let Some(index) = self.tables.iter().position(|table| table.ranges == ranges) {
+            return index;
+        }
+
+        // Named after the first token that uses it
+        let base_name = format!("{}_ranges", token.to_lowercase());
+        let count = self
+            .tables
+            .iter()
+            .filter(|table| table.name.starts_with(&base_name))
+            .count();
+        let name = match count {
+            0 => base_name,
+            _ => format!("{}_{}", base_name, count + 1),
+        };
+
+        self.tables.push(RangeTable {
+            name,
+            comment: class.to_string(),
+            ranges,
+        });
+        self.tables.len() - 1
+    }
+}
+
+fn token_id(vocab: &TokenVocab, name: &str) -> Option<u32> {
+    vocab
+        .tokens
+        .iter()
+        .find(|(token, _)| token == name)
+        .map(|(_, id)| *id)
+}
+
+fn make_indent_spec(config: IndentConfig, vocab: &TokenVocab) -> Option<IndentSpec> {
+    let literal_id = |literal: &str| {
+        vocab
+            .literals
+            .iter()
+            .find(|(lit, _)| lit == literal)
+            .map(|(_, id)| *id)
+    };
+
+    let brackets = [("(", ")"), ("[", "]"), ("{", "}")]
+        .iter()
+        .filter_map(|(open, close)| Some((literal_id(open)?, literal_id(close)?)))
+        .collect();
+
+    Some(IndentSpec {
+        config,
+        indent: token_id(vocab, INDENT)?,
+        dedent: token_id(vocab, DEDENT)?,
+        newline: token_id(vocab, NEWLINE)?,
+        brackets,
+    })
+}
+
+fn is_keyword(value: &str) -> bool {
+    let mut chars = value.chars();
+    matches!(chars.next(), Some(ch) if ch.is_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_alphanumeric() || ch == '_')
+}
@@ -1,5 +1,13 @@
 pub mod ast;
+pub mod ast_gen;
+pub mod bootstrap;
 pub mod diagram;
+pub mod escape;
+pub mod export;
+pub mod import;
+pub mod indent;
 pub mod lang;
+pub mod lexer_gen;
 pub mod parser_gen;
 pub mod transform;
+pub mod vocab;
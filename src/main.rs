@@ -1,15 +1,30 @@
 use std::error::Error;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use clap::Parser;
 
-use hrpg::ast::parse_hrpg;
+use hrpg::ast::{parse_hrpg, Grammar, GrammarKind};
 use hrpg::diagram::draw_diagram;
-use hrpg::lang::rust::RustConfig;
-use hrpg::parser_gen::ParserGen;
+use hrpg::export::antlr::write_antlr;
+use hrpg::export::ebnf::write_ebnf;
+use hrpg::export::hrpg::write_grammar;
+use hrpg::export::pest::write_pest;
+use hrpg::export::textmate::write_textmate;
+use hrpg::export::tree_sitter::write_tree_sitter;
+use hrpg::export::Export;
+use hrpg::import::antlr::import_antlr;
+use hrpg::import::ebnf::import_ebnf;
+use hrpg::import::flex::import_flex;
+use hrpg::import::pest::import_pest;
+use hrpg::import::yacc::import_yacc;
+use hrpg::import::Import;
+use hrpg::lang::rust::{write_rust, write_rust_lexer, write_rust_parser, RustConfig};
+use hrpg::lexer_gen::LexerGen;
+use hrpg::parser_gen::{ParserGen, ParserSpec};
 use hrpg::transform::Transform;
+use hrpg::vocab::{TokenVocab, TOKEN_VOCAB, VOCAB_EXT};
 
 /// Human Readable Parser Generator
 #[derive(clap::Parser)]
@@ -23,6 +38,11 @@ struct Args {
 enum SubCommands {
     Build(Build),
     Draw(Draw),
+    Export(ExportGrammar),
+    ImportAntlr(ImportAntlr),
+    ImportEbnf(ImportEbnf),
+    ImportPest(ImportPest),
+    ImportYacc(ImportYacc),
 }
 
 /// Build lexer/parser from a grammar
@@ -34,6 +54,10 @@ struct Build {
 
     /// The grammar file to build
     input_file: PathBuf,
+
+    /// The Rust lexer/parser to write (otherwise the specs are printed)
+    #[clap(short, long)]
+    output_file: Option<PathBuf>,
 }
 
 /// Draw railroad/syntax diagram from a grammar
@@ -43,6 +67,87 @@ struct Draw {
     input_file: PathBuf,
 }
 
+/// Convert a grammar to another grammar format
+#[derive(clap::Parser)]
+struct ExportGrammar {
+    /// The grammar file to convert
+    input_file: PathBuf,
+
+    /// The format to write
+    #[clap(short, long, arg_enum)]
+    format: ExportFormat,
+
+    /// The file to write (otherwise printed)
+    #[clap(short, long)]
+    output_file: Option<PathBuf>,
+}
+
+#[derive(clap::ArgEnum, Clone)]
+enum ExportFormat {
+    /// ANTLR4 (.g4)
+    Antlr,
+    /// W3C EBNF
+    Ebnf,
+    /// pest (.pest)
+    Pest,
+    /// TextMate syntax highlighting (.tmLanguage.json)
+    Textmate,
+    /// tree-sitter (grammar.js)
+    TreeSitter,
+}
+
+/// Convert an ANTLR4 grammar (.g4) to an HRPG grammar
+#[derive(clap::Parser)]
+struct ImportAntlr {
+    /// The ANTLR4 grammar file to convert
+    input_file: PathBuf,
+
+    /// The HRPG grammar file to write (otherwise printed)
+    #[clap(short, long)]
+    output_file: Option<PathBuf>,
+}
+
+/// Convert a W3C EBNF grammar to an HRPG grammar
+#[derive(clap::Parser)]
+struct ImportEbnf {
+    /// The EBNF grammar file to convert
+    input_file: PathBuf,
+
+    /// The HRPG grammar file to write (otherwise printed)
+    #[clap(short, long)]
+    output_file: Option<PathBuf>,
+}
+
+/// Convert a pest grammar (.pest) to an HRPG grammar
+#[derive(clap::Parser)]
+struct ImportPest {
+    /// The pest grammar file to convert
+    input_file: PathBuf,
+
+    /// The HRPG grammar file to write (otherwise printed)
+    #[clap(short, long)]
+    output_file: Option<PathBuf>,
+}
+
+/// Convert a yacc/bison grammar (.y) to an HRPG grammar
+#[derive(clap::Parser)]
+struct ImportYacc {
+    /// The yacc/bison grammar file to convert
+    input_file: PathBuf,
+
+    /// The flex scanner (.l) to convert into token rules (otherwise tokens are extern)
+    #[clap(short, long)]
+    lexer_file: Option<PathBuf>,
+
+    /// Keep the actions of each rule as doc comments
+    #[clap(short, long)]
+    keep_actions: bool,
+
+    /// The HRPG grammar file to write (otherwise printed)
+    #[clap(short, long)]
+    output_file: Option<PathBuf>,
+}
+
 fn main() {
     env_logger::init();
 
@@ -51,6 +156,31 @@ fn main() {
     let result = match options.sub_cmd {
         SubCommands::Build(build) => process_build(&build),
         SubCommands::Draw(draw) => process_draw(&draw),
+        SubCommands::Export(export) => process_export(&export),
+        SubCommands::ImportAntlr(import) => {
+            process_import(&import.input_file, &import.output_file, |data| {
+                Ok(import_antlr(data)?)
+            })
+        }
+        SubCommands::ImportEbnf(import) => {
+            process_import(&import.input_file, &import.output_file, |data| {
+                Ok(import_ebnf(data)?)
+            })
+        }
+        SubCommands::ImportPest(import) => {
+            process_import(&import.input_file, &import.output_file, |data| {
+                Ok(import_pest(data)?)
+            })
+        }
+        SubCommands::ImportYacc(import) => {
+            process_import(&import.input_file, &import.output_file, |data| {
+                let lexer = match &import.lexer_file {
+                    Some(lexer_file) => Some(read_lexer(lexer_file)?),
+                    None => None,
+                };
+                Ok(import_yacc(data, lexer, import.keep_actions)?)
+            })
+        }
     };
     match result {
         Ok(Some(str)) => println!("{}", str),
@@ -60,6 +190,10 @@ fn main() {
 }
 
 fn process_build(build: &Build) -> Result<Option<String>, Box<dyn Error>> {
+    if let Some(output_file) = &build.output_file {
+        return write_build(build, output_file);
+    }
+
     println!("Grammar: {:?}", &build.input_file);
     println!(
         "Config: {:?}\n",
@@ -74,12 +208,26 @@ fn process_build(build: &Build) -> Result<Option<String>, Box<dyn Error>> {
 
     println!("Original AST: {:#?}\n", g);
 
-    let (g2, transform) = Transform::process(&g);
+    let vocab = read_vocab(&build.input_file, &g)?;
+    let (g2, transform) = Transform::process_with_vocab(&g, vocab.as_ref());
     println!("Transformed AST: {:#?}\n", g2);
 
     println!("Tokens: {:#?}", &transform.token_names);
     println!("Errors: {:#?}", &transform.errors);
 
+    // Lexer grammars publish their tokens so parser grammars can share them
+    if g2.kind == GrammarKind::Lexer && transform.errors.is_empty() {
+        let vocab_file = vocab_path(&build.input_file, &g2);
+        fs::write(&vocab_file, transform.vocab().to_string())?;
+        println!("Token vocabulary: {:?}", vocab_file);
+    }
+
+    // Parser grammars get their tokens from a separate lexer grammar
+    if g2.kind != GrammarKind::Parser {
+        let lexer_spec = LexerGen::new(RustConfig).generate(&g2, &transform.vocab());
+        println!("Lexer spec: {:#?}", lexer_spec);
+    }
+
     let gen = ParserGen::new(RustConfig);
     let spec = gen.generate(&g2);
     println!("Spec: {:#?}", spec);
@@ -87,11 +235,61 @@ fn process_build(build: &Build) -> Result<Option<String>, Box<dyn Error>> {
     Ok(None)
 }
 
+fn write_build(build: &Build, output_file: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    let data = fs::read_to_string(&build.input_file)?;
+    let g = parse_hrpg(&data)?;
+    let vocab = read_vocab(&build.input_file, &g)?;
+    let (g2, transform) = Transform::process_with_vocab(&g, vocab.as_ref());
+
+    if !transform.errors.is_empty() {
+        return Err(format!("{:?}", &transform.errors).into());
+    }
+
+    let source = build
+        .input_file
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let vocab = transform.vocab();
+
+    let code = match g2.kind {
+        // Lexer grammars publish their tokens so parser grammars can share them
+        GrammarKind::Lexer => {
+            fs::write(vocab_path(&build.input_file, &g2), vocab.to_string())?;
+            let lexer_spec = LexerGen::new(RustConfig).generate(&g2, &vocab);
+            write_rust_lexer(&lexer_spec, &vocab, source)
+        }
+        // Parser grammars import the lexer built from their token vocabulary's lexer grammar
+        GrammarKind::Parser => {
+            let spec = parser_spec(build, &g2);
+            let lexer_module = g.option(TOKEN_VOCAB).unwrap_or_default();
+            write_rust_parser(lexer_module, &spec, &vocab, source)?
+        }
+        GrammarKind::Combined => {
+            let lexer_spec = LexerGen::new(RustConfig).generate(&g2, &vocab);
+            let spec = parser_spec(build, &g2);
+            write_rust(&lexer_spec, &spec, &vocab, source)?
+        }
+    };
+
+    fs::write(output_file, code)?;
+    Ok(None)
+}
+
+fn parser_spec(build: &Build, grammar: &Grammar) -> ParserSpec {
+    let spec = ParserGen::new(RustConfig).generate(grammar);
+    for warning in &spec.warnings {
+        eprintln!("warning: {}: {}", build.input_file.display(), warning);
+    }
+    spec
+}
+
 fn process_draw(draw: &Draw) -> Result<Option<String>, Box<dyn Error>> {
     eprintln!("Grammar: {:?}", &draw.input_file);
     let data = fs::read_to_string(&draw.input_file)?;
     let g = parse_hrpg(&data)?;
-    let (g2, transform) = Transform::process(&g);
+    let vocab = read_vocab(&draw.input_file, &g)?;
+    let (g2, transform) = Transform::process_with_vocab(&g, vocab.as_ref());
 
     if transform.errors.is_empty() {
         Ok(Some(format!("{}", draw_diagram(&g2))))
@@ -99,3 +297,103 @@ fn process_draw(draw: &Draw) -> Result<Option<String>, Box<dyn Error>> {
         Err(format!("{:?}", &transform.errors).into())
     }
 }
+
+// Warnings go to stderr so the exported grammar can be printed to stdout
+fn process_export(export: &ExportGrammar) -> Result<Option<String>, Box<dyn Error>> {
+    let data = fs::read_to_string(&export.input_file)?;
+    let g = parse_hrpg(&data)?;
+    let vocab = read_vocab(&export.input_file, &g)?;
+    let (g2, transform) = Transform::process_with_vocab(&g, vocab.as_ref());
+
+    if !transform.errors.is_empty() {
+        return Err(format!("{:?}", &transform.errors).into());
+    }
+
+    let Export { text, warnings } = match export.format {
+        ExportFormat::Antlr => write_antlr(&g2, grammar_name(&export.input_file, &g2)),
+        ExportFormat::Ebnf => write_ebnf(&g2),
+        ExportFormat::Pest => write_pest(&g2),
+        ExportFormat::Textmate => write_textmate(&g2, grammar_name(&export.input_file, &g2)),
+        ExportFormat::TreeSitter => {
+            write_tree_sitter(&g2, &transform, grammar_name(&export.input_file, &g2))
+        }
+    };
+
+    for warning in &warnings {
+        eprintln!("warning: {}: {}", export.input_file.display(), warning);
+    }
+
+    match &export.output_file {
+        Some(output_file) => {
+            fs::write(output_file, text)?;
+            Ok(None)
+        }
+        None => Ok(Some(text)),
+    }
+}
+
+// Warnings go to stderr so the grammar can be printed to stdout
+fn process_import(
+    input_file: &Path,
+    output_file: &Option<PathBuf>,
+    import: impl Fn(&str) -> Result<Import, Box<dyn Error>>,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let data = fs::read_to_string(input_file)?;
+    let Import { grammar, warnings } = import(&data)?;
+
+    for warning in &warnings {
+        let file = warning.file.as_deref().unwrap_or(input_file);
+        eprintln!("warning: {}:{}", file.display(), warning);
+    }
+
+    let hrpg = write_grammar(&grammar);
+    match output_file {
+        Some(output_file) => {
+            fs::write(output_file, hrpg)?;
+            Ok(None)
+        }
+        None => Ok(Some(hrpg)),
+    }
+}
+
+// A flex scanner's warnings are reported against its own file
+fn read_lexer(lexer_file: &Path) -> Result<Import, Box<dyn Error>> {
+    let data = fs::read_to_string(lexer_file)?;
+    let mut lexer =
+        import_flex(&data).map_err(|err| err.with_path(&lexer_file.display().to_string()))?;
+
+    for warning in &mut lexer.warnings {
+        warning.file = Some(lexer_file.to_path_buf());
+    }
+    Ok(lexer)
+}
+
+// Grammars without a declaration are named after their file
+fn grammar_name<'a>(input_file: &'a Path, grammar: &'a Grammar) -> &'a str {
+    match &grammar.name {
+        Some(name) => name.as_str(),
+        None => input_file
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default(),
+    }
+}
+
+// The vocabulary file for a grammar sits next to the grammar file and is named after the grammar
+fn vocab_path(input_file: &Path, grammar: &Grammar) -> PathBuf {
+    let name = grammar_name(input_file, grammar);
+    input_file.with_file_name(format!("{}.{}", name, VOCAB_EXT))
+}
+
+fn read_vocab(input_file: &Path, grammar: &Grammar) -> Result<Option<TokenVocab>, Box<dyn Error>> {
+    match grammar.option(TOKEN_VOCAB) {
+        Some(vocab_name) => {
+            let vocab_file = input_file.with_file_name(format!("{}.{}", vocab_name, VOCAB_EXT));
+            let data = fs::read_to_string(&vocab_file).map_err(|err| {
+                format!("Unable to read token vocabulary {:?}: {}", vocab_file, err)
+            })?;
+            Ok(Some(TokenVocab::parse(&data)?))
+        }
+        None => Ok(None),
+    }
+}
@@ -1,4 +1,5 @@
 use crate::ast::{Comment, Grammar, Node};
+use crate::ast_gen::{AstGen, AstType};
 use std::cell::Cell;
 use std::collections::HashSet;
 use std::fmt;
@@ -25,23 +26,70 @@ pub enum MatchRule {
     Parser {
         match_kind: MatchKind,
         func_name: String,
+        // Target language expressions passed to the function
+        args: Vec<String>,
         var_name: String,
+        // Append to `var_name` instead of assigning it (`+=` bindings)
+        list: bool,
+        comment: String,
+    },
+    // One or more `item` separated by `separator` (both always `MatchKind::Once`) - an optional trailing
+    // separator is only consumed if `trailing` is set
+    SeparatedList {
+        match_kind: MatchKind,
+        item: Box<MatchRule>,
+        separator: Box<MatchRule>,
+        trailing: bool,
+        comment: String,
+    },
+    // Try and match `rule` (always `MatchKind::Once`), then rewind - succeeds if `rule` matched (or didn't when `negative`)
+    Lookahead {
+        match_kind: MatchKind,
+        rule: Box<MatchRule>,
+        negative: bool,
+        comment: String,
+    },
+    // Always matches without consuming anything (an empty alternative)
+    Empty {
+        comment: String,
+    },
+    // Target language code from a grammar action, emitted verbatim - when `predicate` is set it is an expression
+    // that must be true for the match to continue
+    Code {
+        code: String,
+        predicate: bool,
         comment: String,
     },
 }
 
 #[derive(Debug)]
 pub struct Function {
-    name: String,
-    comment: String,
-    ret_on_match: bool,
-    actions: Vec<MatchRule>,
+    pub name: String,
+    // Grammar rule the function matches (part of)
+    pub rule: String,
+    // Target language declarations - sub-functions take the same parameters as their rule, but return no values
+    pub params: Vec<String>,
+    pub returns: Vec<String>,
+    // Doc comment lines of the rule - sub-functions have none
+    pub doc: Vec<String>,
+    pub comment: String,
+    // Kind of tree node the function's matches are wrapped in - only rules have one, sub-functions add their
+    // matches to those of the function calling them
+    pub node_kind: Option<String>,
+    // The actions are alternatives and the function returns on the first that matches, otherwise it needs all of
+    // them to match in order
+    pub ret_on_match: bool,
+    pub actions: Vec<MatchRule>,
 }
 
 #[derive(Debug)]
 pub struct ParserSpec {
-    ret_type: String,
-    functions: Vec<Function>,
+    pub functions: Vec<Function>,
+    // Function of the first rule, where parsing starts
+    pub start: Option<String>,
+    pub types: Vec<AstType>,
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
 }
 
 pub trait LangConfig {
@@ -52,6 +100,13 @@ pub trait LangConfig {
     fn function_case(&self) -> convert_case::Case;
 
     fn keywords(&self) -> HashSet<String>;
+
+    // Name used to tag actions for this language (ex: `{rust: ...}`)
+    fn name(&self) -> &str;
+
+    // The name of the parameter in an argument or return value declaration (ex: `in_loop` in `in_loop: bool`) -
+    // `None` if the declaration isn't valid in the language
+    fn param_name<'d>(&self, decl: &'d str) -> Option<&'d str>;
 }
 
 pub trait LangCodeGen<W: fmt::Write> {
@@ -83,6 +138,13 @@ pub trait LangCodeGen<W: fmt::Write> {
                 MatchKind::ZeroOrMore => self.match_rule_zero_or_more(w, action, func),
                 MatchKind::OnceOrMore => self.match_rule_once_or_more(w, action, func),
             },
+            MatchRule::SeparatedList { .. } => self.match_separated_list(w, action, func),
+            MatchRule::Lookahead { .. } => self.match_lookahead(w, action, func),
+            MatchRule::Empty { .. } => self.match_empty(w, action, func),
+            MatchRule::Code {
+                predicate: false, ..
+            } => self.embedded_code(w, action, func),
+            MatchRule::Code { .. } => self.embedded_predicate(w, action, func),
         }
     }
 
@@ -101,6 +163,16 @@ pub trait LangCodeGen<W: fmt::Write> {
     fn match_rule_zero_or_more(&self, w: &mut W, action: &MatchRule, func: &Function);
 
     fn match_rule_once_or_more(&self, w: &mut W, action: &MatchRule, func: &Function);
+
+    fn match_separated_list(&self, w: &mut W, action: &MatchRule, func: &Function);
+
+    fn match_lookahead(&self, w: &mut W, action: &MatchRule, func: &Function);
+
+    fn match_empty(&self, w: &mut W, action: &MatchRule, func: &Function);
+
+    fn embedded_code(&self, w: &mut W, action: &MatchRule, func: &Function);
+
+    fn embedded_predicate(&self, w: &mut W, action: &MatchRule, func: &Function);
 }
 
 struct FuncData<'n> {
@@ -133,15 +205,20 @@ impl<'n> FuncData<'n> {
         }
     }
 
+    // Bindings and labels are named (a reused binding name also gets a number, see `to_num_sub`)
     pub fn to_named_sub(&self, sub_name: &'n str) -> Self {
         FuncData {
             base: self.base,
             sub_name: Some(sub_name),
-            sub_num: self.sub_num,
+            sub_num: None,
             next_sub_num: self.next_sub_num.clone(),
         }
     }
 
+    pub fn is_sub(&self) -> bool {
+        self.sub_name.is_some() || self.sub_num.is_some()
+    }
+
     pub fn name(&self, case: convert_case::Case) -> String {
         match (self.sub_name, self.sub_num) {
             (Some(sub_name), Some(sub_num)) => {
@@ -156,13 +233,20 @@ impl<'n> FuncData<'n> {
         .to_case(case)
     }
 
-    pub fn base_name(&self) -> &str {
-        self.base
+    // Name the result of this function is stored under
+    pub fn var_base_name(&self) -> &str {
+        self.sub_name.unwrap_or(self.base)
     }
 }
 
 pub struct ParserGen<L> {
     functions: Vec<Function>,
+    // Argument and return value declarations of the rule being generated
+    rule_args: Vec<String>,
+    rule_returns: Vec<String>,
+    rule_doc: Vec<String>,
+    errors: Vec<String>,
+    warnings: Vec<String>,
     lang_config: L,
 }
 
@@ -170,6 +254,11 @@ impl<L: LangConfig> ParserGen<L> {
     pub fn new(config: L) -> Self {
         ParserGen {
             functions: vec![],
+            rule_args: vec![],
+            rule_returns: vec![],
+            rule_doc: vec![],
+            errors: vec![],
+            warnings: vec![],
             lang_config: config,
         }
     }
@@ -183,14 +272,27 @@ impl<L: LangConfig> ParserGen<L> {
 
         for rule in &grammar.parser_rules {
             log::trace!("Starting parser rule: {}", &rule.name);
+            self.rule_args = rule.args.clone();
+            self.rule_returns = rule.returns.clone();
+            self.rule_doc = rule.doc.clone();
+            self.check_decls(&rule.name);
             self.make_func(&FuncData::new(&rule.name), &rule.node, &rule.comment());
             log::trace!("Ending parser rule: {}", &rule.name);
         }
 
+        let types = AstGen::new(&self.lang_config).generate(grammar);
+
+        let start = grammar
+            .parser_rules
+            .first()
+            .map(|rule| FuncData::new(&rule.name).name(self.lang_config.function_case()));
+
         ParserSpec {
-            // TODO: Need this obviously - from LangConfig?
-            ret_type: "<todo>".to_string(),
             functions: self.functions,
+            start,
+            types,
+            errors: self.errors,
+            warnings: self.warnings,
         }
     }
 
@@ -204,26 +306,78 @@ impl<L: LangConfig> ParserGen<L> {
         let actions = self.process_node(node, func_data, comment, MatchKind::Once, true);
         log::trace!("Ending function: {}", &name);
 
+        let (returns, doc, node_kind) = if func_data.is_sub() {
+            (vec![], vec![], None)
+        } else {
+            (
+                self.rule_returns.clone(),
+                self.rule_doc.clone(),
+                Some(func_data.base.to_case(self.lang_config.class_case())),
+            )
+        };
+
         self.functions.push(Function {
             name,
+            rule: func_data.base.to_string(),
+            params: self.rule_args.clone(),
+            returns,
+            doc,
             comment: comment.to_string(),
+            node_kind,
             ret_on_match,
             actions,
         })
     }
 
+    fn has_func(&self, func_data: &FuncData) -> bool {
+        let name = func_data.name(self.lang_config.function_case());
+        self.functions.iter().any(|func| func.name == name)
+    }
+
+    fn check_decls(&mut self, rule_name: &str) {
+        for decl in self.rule_args.iter().chain(&self.rule_returns) {
+            if self.lang_config.param_name(decl).is_none() {
+                self.errors.push(format!(
+                    "ERROR: Declaration '{}' in rule '{}' is not a valid '{}' declaration",
+                    decl,
+                    rule_name,
+                    self.lang_config.name()
+                ));
+            }
+        }
+    }
+
+    // Sub-functions are passed the arguments of the rule they belong to
+    fn forward_args(&self) -> Vec<String> {
+        self.rule_args
+            .iter()
+            .map(|decl| {
+                self.lang_config
+                    .param_name(decl)
+                    .unwrap_or(decl)
+                    .to_string()
+            })
+            .collect()
+    }
+
     fn process_rule_ref(
         &self,
         func_data: &FuncData,
+        args: Vec<String>,
         comment: &str,
         kind: MatchKind,
+        list: bool,
     ) -> Vec<MatchRule> {
-        let var_name = func_data.base_name().to_case(self.lang_config.var_case());
+        let var_name = func_data
+            .var_base_name()
+            .to_case(self.lang_config.var_case());
 
         vec![MatchRule::Parser {
             match_kind: kind,
             func_name: func_data.name(self.lang_config.function_case()),
+            args,
             var_name,
+            list,
             comment: comment.to_string(),
         }]
     }
@@ -238,28 +392,43 @@ impl<L: LangConfig> ParserGen<L> {
     ) -> Vec<MatchRule> {
         match node {
             // Binding - use the name of binding as function name, NOT `curr_func` as base like `Alternatives/MultipartBody`
-            Node::Binding { name, node: next_node } => {
-                let func_name = &curr_func.to_named_sub(name);
+            Node::Binding { name, list, node: next_node } => {
+                let named_func = curr_func.to_named_sub(name);
+                // A binding can be used more than once (ex: `xs+=A (',' xs+=B)*`), so later uses get numbered functions
+                let func_name = &if self.has_func(&named_func) {
+                    named_func.to_num_sub()
+                } else {
+                    named_func
+                };
                 // Function gets comment from the binding itself
                 self.make_func(func_name, next_node, &node.comment());
                 // The rule to process gets the inherited comment
-                self.process_rule_ref(func_name, comment, match_kind)
+                self.process_rule_ref(func_name, self.forward_args(), comment, match_kind, *list)
             },
             // If top level of function, we simply process each node and flatten
-            // (only this and `MultipartBody` truly return more than one entry)
+            // (only this and `MultipartBody` truly return more than one entry) - each alternative has to match once,
+            // unless it is itself optional or repeated (ex: `a: b? | c`)
             Node::Alternatives { nodes } if top_level => nodes
                 .iter()
                 .flat_map(|node| {
-                    self.process_node(node, curr_func, &node.comment(), MatchKind::ZeroOrOnce, false)
+                    self.process_node(node, curr_func, &node.comment(), MatchKind::Once, false)
                 })
                 .collect(),
+            // Labeled alternative - like `Binding`, the label is used as the function name
+            Node::Labeled { label, node: next_node } => {
+                let func_name = &curr_func.to_named_sub(label);
+                // Function gets comment from the labeled alternative itself
+                self.make_func(func_name, next_node, &node.comment());
+                // The rule to process gets the inherited comment
+                self.process_rule_ref(func_name, self.forward_args(), comment, match_kind, false)
+            },
             // If not top level, then we need to force a sub-function to handle it
             Node::Alternatives { .. } => {
                 let func_name = &curr_func.to_num_sub();
                 // Function gets comment from the binding itself
                 self.make_func(func_name, node, &node.comment());
                 // The rule to process gets the inherited comment
-                self.process_rule_ref(func_name, comment, match_kind)
+                self.process_rule_ref(func_name, self.forward_args(), comment, match_kind, false)
             },
             // If top level of function, we simply process each node and flatten
             // (only this and `Alternatives` truly return more than one entry)
@@ -273,7 +442,7 @@ impl<L: LangConfig> ParserGen<L> {
                 // Function gets comment from the binding itself
                 self.make_func(func_name, node, &node.comment());
                 // The rule to process gets the inherited comment
-                self.process_rule_ref(func_name, comment, match_kind)
+                self.process_rule_ref(func_name, self.forward_args(), comment, match_kind, false)
             },
             Node::ZeroOrMore { node } => {
                 self.process_node(node, curr_func, comment, MatchKind::ZeroOrMore, false)
@@ -284,7 +453,54 @@ impl<L: LangConfig> ParserGen<L> {
             Node::ZeroOrOne { node, .. } => {
                 self.process_node(node, curr_func, comment, MatchKind::ZeroOrOnce, false)
             }
-            Node::RuleRef { name } =>  self.process_rule_ref(&FuncData::new(name), comment, match_kind),
+            Node::SeparatedList { node, separator, trailing } => {
+                let item = self.process_node(node, curr_func, &node.comment(), MatchKind::Once, false);
+                let separator = self.process_node(separator, curr_func, &separator.comment(), MatchKind::Once, false);
+
+                vec![
+                    MatchRule::SeparatedList {
+                        match_kind,
+                        // Anything that isn't a single match was forced into a sub-function
+                        item: Box::new(item.into_iter().next().unwrap()),
+                        separator: Box::new(separator.into_iter().next().unwrap()),
+                        trailing: *trailing,
+                        comment: comment.to_string(),
+                    }
+                ]
+            }
+            Node::PositiveLookahead { node: next_node } | Node::NegativeLookahead { node: next_node } => {
+                let rule = self.process_node(next_node, curr_func, &next_node.comment(), MatchKind::Once, false);
+
+                vec![
+                    MatchRule::Lookahead {
+                        match_kind,
+                        rule: Box::new(rule.into_iter().next().unwrap()),
+                        negative: matches!(node, Node::NegativeLookahead { .. }),
+                        comment: comment.to_string(),
+                    }
+                ]
+            }
+            Node::Action { lang, code, predicate } => {
+                if lang == self.lang_config.name() {
+                    vec![
+                        MatchRule::Code {
+                            code: code.to_string(),
+                            predicate: *predicate,
+                            comment: comment.to_string(),
+                        }
+                    ]
+                } else {
+                    // Still matches (as a no-op) so anything wrapping the action has something to match
+                    self.warnings.push(format!(
+                        "Action '{}' in rule '{}' is not '{}' code and is ignored",
+                        node.comment(), curr_func.base, self.lang_config.name()
+                    ));
+                    vec![MatchRule::Empty { comment: comment.to_string() }]
+                }
+            }
+            // Makes the function succeed even when no other alternative matches
+            Node::Empty => vec![MatchRule::Empty { comment: comment.to_string() }],
+            Node::RuleRef { name, args } =>  self.process_rule_ref(&FuncData::new(name), args.clone(), comment, match_kind, false),
             Node::TokenRef { name,.. } => {
                 let var_name = name.to_case(self.lang_config.var_case());
 
@@ -298,7 +514,169 @@ impl<L: LangConfig> ParserGen<L> {
                     }
                 ]
             }
-            Node::TokenLit { literal } => panic!("Found token literal '{}' - this should have been replaced during AST transformation", literal),
+            Node::TemplateRef { name, .. } => panic!("Found rule template reference '{}' - this should have been instantiated during AST transformation", name),
+            Node::CharClass { class } => panic!("Found character class '{}' - these are only allowed in token rules", class),
+            Node::TokenLit { literal, .. } => panic!("Found token literal '{}' - this should have been replaced during AST transformation", literal),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_hrpg;
+    use crate::lang::rust::RustConfig;
+    use crate::transform::Transform;
+
+    fn generate(data: &str) -> ParserSpec {
+        let (grammar, transform) = Transform::process(&parse_hrpg(data).unwrap());
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+        ParserGen::new(RustConfig).generate(&grammar)
+    }
+
+    #[test]
+    fn action_for_other_lang() {
+        let spec = generate("a: &({python: x}?) 'q'; Q: 'q';");
+        assert!(spec.errors.is_empty(), "{:?}", spec.errors);
+        assert_eq!(
+            spec.warnings,
+            ["Action '{python: x}?' in rule 'a' is not 'rust' code and is ignored"]
+        );
+
+        let actions = &spec.functions[0].actions;
+        assert!(matches!(
+            &actions[0],
+            MatchRule::Lookahead { rule, .. } if matches!(**rule, MatchRule::Empty { .. })
+        ));
+    }
+
+    #[test]
+    fn rule_decls() {
+        let spec = generate("a[in_loop: bool, mut depth: usize] returns [node: Node]: b[in_loop]; b[x: bool]: 'q'; Q: 'q';");
+        assert!(spec.errors.is_empty(), "{:?}", spec.errors);
+        assert_eq!(
+            spec.functions[0].params,
+            ["in_loop: bool", "mut depth: usize"]
+        );
+        assert_eq!(spec.functions[0].returns, ["node: Node"]);
+    }
+
+    #[test]
+    fn forwarded_args() {
+        let spec = generate("a[in_loop: bool, mut depth: usize]: 'q' ('q' | 'q' 'q'); Q: 'q';");
+        // Sub-functions are generated before the function using them
+        let func = spec
+            .functions
+            .iter()
+            .find(|func| func.name == "parse_a")
+            .unwrap();
+        assert!(matches!(
+            &func.actions[1],
+            MatchRule::Parser { args, .. } if args == &["in_loop", "depth"]
+        ));
+    }
+
+    #[test]
+    fn invalid_rule_decls() {
+        let spec = generate("a[bool in_loop] returns [Node]: 'q'; Q: 'q';");
+        assert_eq!(spec.errors.len(), 2, "{:?}", spec.errors);
+    }
+
+    #[test]
+    fn action_with_lang_block() {
+        let spec = generate("a: {python: x}? {rust: self.x()}? 'q'; Q: 'q';");
+        assert!(spec.errors.is_empty());
+        assert!(matches!(
+            &spec.functions[0].actions[1],
+            MatchRule::Code { code, predicate: true, .. } if code.trim() == "self.x()"
+        ));
+    }
+
+    // Each block is its own action or predicate
+    #[test]
+    fn action_then_predicate() {
+        let spec = generate("a: {rust: self.a()} {rust: self.b()}? 'q'; Q: 'q';");
+        assert!(spec.errors.is_empty(), "{:?}", spec.errors);
+        assert!(spec.warnings.is_empty(), "{:?}", spec.warnings);
+
+        let actions = &spec.functions[0].actions;
+        assert!(matches!(
+            &actions[0],
+            MatchRule::Code { code, predicate: false, .. } if code == "self.a()"
+        ));
+        assert!(matches!(
+            &actions[1],
+            MatchRule::Code { code, predicate: true, .. } if code == "self.b()"
+        ));
+    }
+
+    #[test]
+    fn start_and_node_kinds() {
+        let spec = generate("stmt_list: stmt (';' stmt)*; stmt: 'q'; Q: 'q'; SEMI: ';';");
+        assert_eq!(spec.start.as_deref(), Some("parse_stmt_list"));
+
+        let node_kind = |name: &str| {
+            let func = spec
+                .functions
+                .iter()
+                .find(|func| func.name == name)
+                .unwrap();
+            func.node_kind.clone()
+        };
+        assert_eq!(node_kind("parse_stmt_list"), Some("StmtList".to_string()));
+        assert_eq!(node_kind("parse_stmt"), Some("Stmt".to_string()));
+        assert!(spec.functions.iter().any(|func| func.node_kind.is_none()));
+    }
+
+    // Alternatives are only optional when they are themselves optional
+    #[test]
+    fn alternatives_match_once() {
+        let spec = generate("a: b? | 'q'; b: 'q'; Q: 'q';");
+        let func = &spec.functions[0];
+        assert!(func.ret_on_match);
+        assert!(matches!(
+            &func.actions[0],
+            MatchRule::Parser {
+                match_kind: MatchKind::ZeroOrOnce,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &func.actions[1],
+            MatchRule::Token {
+                match_kind: MatchKind::Once,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn reused_binding() {
+        let spec = generate("a: xs+=A (',' xs+=B)*; A: 'a'; B: 'b'; COMMA: ',';");
+        let token_name = |name: &str| {
+            let func = spec
+                .functions
+                .iter()
+                .find(|func| func.name == name)
+                .unwrap();
+            match &func.actions[..] {
+                [MatchRule::Token { token_name, .. }] => token_name.clone(),
+                actions => panic!("{:?}", actions),
+            }
+        };
+        assert_eq!(token_name("parse_a_xs"), "A");
+        assert_eq!(token_name("parse_a_xs_sub_2"), "B");
+
+        // Both uses store their result under the binding's name
+        let func = spec
+            .functions
+            .iter()
+            .find(|func| func.name == "parse_a_sub_1")
+            .unwrap();
+        assert!(matches!(
+            &func.actions[1],
+            MatchRule::Parser { func_name, var_name, list: true, .. }
+                if func_name == "parse_a_xs_sub_2" && var_name == "xs"
+        ));
+    }
+}
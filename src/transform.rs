@@ -1,93 +1,727 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
 
 use crate::ast::Node::*;
-use crate::ast::{Grammar, Node, ParserRule, TokenRule};
+use crate::ast::{Comment, Grammar, GrammarKind, Node, ParserRule, TokenRule};
+use crate::escape::{parse_char_class, unescape_literal};
+use crate::indent::{IndentConfig, DEDENT, INDENT, INDENT_OPTION, NEWLINE};
+use crate::lexer_gen::CASE_INSENSITIVE;
+use crate::vocab::{TokenVocab, TOKEN_VOCAB};
 
-const EOF: &str = "EOF";
+// Matched at the end of the input, after anything skipped
+pub const EOF: &str = "EOF";
 const ILLEGAL: &str = "ILLEGAL";
 
+// Nesting depth at which template expansion is assumed to never terminate
+const MAX_TEMPLATE_DEPTH: usize = 32;
+
 pub struct Transform {
     literals: HashMap<String, (String, Option<Node>)>,
+    // Lowercased literal -> its token rule and whether that rule ignores case
+    folded_literals: HashMap<String, (String, bool)>,
+    case_insensitive: bool,
+    token_ids: HashMap<String, u32>,
+    next_token_id: u32,
+    imported_vocab: bool,
+
+    templates: HashMap<String, ParserRule>,
+    rule_names: HashSet<String>,
+    // Rule name -> number of arguments it takes
+    rule_arity: HashMap<String, usize>,
+    // Instance name -> the arguments it was instantiated with
+    instance_args: HashMap<String, String>,
+    // Template name -> its instances
+    instances: HashMap<String, Vec<ParserRule>>,
+    // Templates currently being expanded with the size of their arguments
+    expanding: Vec<(String, usize)>,
 
     pub token_names: HashSet<String>,
+    // Rules that can match without consuming any tokens
+    pub nullable_rules: HashSet<String>,
+    // Rule name -> tokens that can start a match of the rule
+    pub first_sets: HashMap<String, BTreeSet<String>>,
     pub errors: Vec<String>,
 }
 
-fn strip_quotes(str: &str) -> String {
-    let mut chars = str.chars();
-    chars.next();
-    chars.next_back();
-    chars.as_str().to_string()
-}
-
 impl Transform {
-    fn new() -> Transform {
-        let mut token_names = HashSet::new();
-        token_names.insert(EOF.to_string());
-        token_names.insert(ILLEGAL.to_string());
-
-        Transform {
-            token_names,
+    fn new(vocab: Option<&TokenVocab>) -> Transform {
+        let mut transform = Transform {
+            token_names: HashSet::new(),
             literals: HashMap::new(),
+            folded_literals: HashMap::new(),
+            case_insensitive: false,
+            token_ids: HashMap::new(),
+            next_token_id: 0,
+            imported_vocab: vocab.is_some(),
+            templates: HashMap::new(),
+            rule_names: HashSet::new(),
+            rule_arity: HashMap::new(),
+            instance_args: HashMap::new(),
+            instances: HashMap::new(),
+            expanding: vec![],
+            nullable_rules: HashSet::new(),
+            first_sets: HashMap::new(),
             errors: vec![],
+        };
+        transform.add_token(EOF);
+        transform.add_token(ILLEGAL);
+
+        if let Some(vocab) = vocab {
+            for (name, id) in &vocab.tokens {
+                transform.token_names.insert(name.to_string());
+                transform.token_ids.insert(name.to_string(), *id);
+                transform.next_token_id = transform.next_token_id.max(id + 1);
+            }
+
+            for (literal, id) in &vocab.literals {
+                match vocab.token_name(*id) {
+                    Some(name) => {
+                        transform
+                            .literals
+                            .insert(literal.to_string(), (name.to_string(), None));
+                    }
+                    None => transform.log_error(&format!(
+                        "Literal '{}' in token vocabulary has unknown token id {}",
+                        literal, id
+                    )),
+                }
+            }
         }
+
+        transform
     }
 
     pub fn process(grammar: &Grammar) -> (Grammar, Transform) {
+        Self::process_with_vocab(grammar, None)
+    }
+
+    // Same as `process`, but tokens are resolved against a token vocabulary (typically written by a lexer grammar)
+    pub fn process_with_vocab(
+        grammar: &Grammar,
+        vocab: Option<&TokenVocab>,
+    ) -> (Grammar, Transform) {
         let token_rules = &grammar.token_rules;
         let parser_rules = &grammar.parser_rules;
 
-        let mut transform = Transform::new();
+        let mut transform = Transform::new(vocab);
+        transform.check_grammar_kind(grammar);
+
+        if let (Some(vocab_name), None) = (grammar.option(TOKEN_VOCAB), vocab) {
+            transform.log_error(&format!(
+                "Grammar uses token vocabulary '{}', but it was not provided",
+                vocab_name
+            ));
+        }
+
+        match grammar.option(CASE_INSENSITIVE) {
+            Some("true") => transform.case_insensitive = true,
+            Some("false") | None => (),
+            Some(value) => transform.log_error(&format!(
+                "Option '{}' must be 'true' or 'false', not '{}'",
+                CASE_INSENSITIVE, value
+            )),
+        }
+
+        match IndentConfig::from_grammar(grammar) {
+            Ok(Some(_)) => transform.add_indent_tokens(grammar),
+            Ok(None) => (),
+            Err(err) => transform.log_error(&err),
+        }
+        transform.add_extern_tokens(grammar);
 
         let token_rules = token_rules
             .iter()
             .map(|rule| transform.process_token_rule(rule))
             .collect();
-        let parser_rules = parser_rules
+        transform.collect_templates(parser_rules);
+        let processed_rules: Vec<Option<ParserRule>> = parser_rules
             .iter()
-            .map(|rule| transform.process_parser_rule(rule))
+            .map(|rule| {
+                if rule.params.is_empty() {
+                    Some(transform.process_parser_rule(rule))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        // Templates are replaced by their instances
+        let parser_rules: Vec<ParserRule> = parser_rules
+            .iter()
+            .zip(processed_rules)
+            .flat_map(|(rule, processed)| match processed {
+                Some(processed) => vec![processed],
+                None => transform.instances.remove(&rule.name).unwrap_or_default(),
+            })
             .collect();
+
+        transform.analyze(&parser_rules);
         (
             Grammar {
+                name: grammar.name.clone(),
+                kind: grammar.kind,
+                options: grammar.options.clone(),
                 parser_rules,
                 token_rules,
+                extern_tokens: grammar.extern_tokens.clone(),
             },
             transform,
         )
     }
 
+    // The token vocabulary of the processed grammar (imported tokens included), ordered by token id
+    pub fn vocab(&self) -> TokenVocab {
+        let mut tokens: Vec<(String, u32)> = self
+            .token_ids
+            .iter()
+            .map(|(name, id)| (name.to_string(), *id))
+            .collect();
+        tokens.sort_by_key(|(_, id)| *id);
+
+        let mut literals: Vec<(String, u32)> = self
+            .literals
+            .iter()
+            .map(|(literal, (name, _))| (literal.to_string(), self.token_ids[name]))
+            .collect();
+        literals.sort_by_key(|(_, id)| *id);
+
+        TokenVocab { tokens, literals }
+    }
+
+    pub fn is_nullable(&self, node: &Node) -> bool {
+        match node {
+            Alternatives { nodes } => nodes.iter().any(|node| self.is_nullable(node)),
+            MultipartBody { nodes } => nodes.iter().all(|node| self.is_nullable(node)),
+            // Lookahead and actions never consume anything
+            ZeroOrMore { .. }
+            | ZeroOrOne { .. }
+            | PositiveLookahead { .. }
+            | NegativeLookahead { .. }
+            | Action { .. }
+            | Empty => true,
+            Binding { node, .. }
+            | Labeled { node, .. }
+            | OneOrMore { node }
+            | SeparatedList { node, .. } => self.is_nullable(node),
+            RuleRef { name, .. } => self.nullable_rules.contains(name),
+            TemplateRef { .. } | TokenRef { .. } | TokenLit { .. } | CharClass { .. } => false,
+        }
+    }
+
+    pub fn first_set(&self, node: &Node) -> BTreeSet<String> {
+        match node {
+            Alternatives { nodes } => nodes.iter().flat_map(|node| self.first_set(node)).collect(),
+            MultipartBody { nodes } => {
+                let mut first = BTreeSet::new();
+                for node in nodes {
+                    first.extend(self.first_set(node));
+                    if !self.is_nullable(node) {
+                        break;
+                    }
+                }
+                first
+            }
+            SeparatedList {
+                node, separator, ..
+            } => {
+                let mut first = self.first_set(node);
+                // The separator can only come first if the item before it matched nothing
+                if self.is_nullable(node) {
+                    first.extend(self.first_set(separator));
+                }
+                first
+            }
+            // Lookahead only checks what is next, it doesn't start a match of anything
+            PositiveLookahead { .. } | NegativeLookahead { .. } | Action { .. } | Empty => {
+                BTreeSet::new()
+            }
+            Binding { node, .. }
+            | Labeled { node, .. }
+            | ZeroOrMore { node }
+            | OneOrMore { node }
+            | ZeroOrOne { node, .. } => self.first_set(node),
+            RuleRef { name, .. } => self.first_sets.get(name).cloned().unwrap_or_default(),
+            TokenRef { name, .. } => BTreeSet::from([name.to_string()]),
+            TemplateRef { .. } | TokenLit { .. } | CharClass { .. } => BTreeSet::new(),
+        }
+    }
+
+    // Nullable rules and FIRST sets depend on each other, so they are computed together until nothing changes
+    fn analyze(&mut self, rules: &[ParserRule]) {
+        loop {
+            let mut changed = false;
+
+            for rule in rules {
+                if !self.nullable_rules.contains(&rule.name) && self.is_nullable(&rule.node) {
+                    self.nullable_rules.insert(rule.name.to_string());
+                    changed = true;
+                }
+
+                // Sets only ever grow, so a change in size is a change
+                let first = self.first_set(&rule.node);
+                let old_len = self.first_sets.get(&rule.name).map_or(0, |set| set.len());
+                if first.len() != old_len {
+                    self.first_sets.insert(rule.name.to_string(), first);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        for rule in rules {
+            self.check_nullable(&rule.name, &rule.node);
+        }
+    }
+
+    // Repeating something that matches nothing never ends, and not matching something that matches nothing never succeeds
+    fn check_nullable(&mut self, rule_name: &str, node: &Node) {
+        match node {
+            ZeroOrMore { node: repeated } | OneOrMore { node: repeated }
+                if self.is_nullable(repeated) =>
+            {
+                self.log_error(&format!(
+                    "Repetition '{}' in rule '{}' can match without consuming any input and would never end",
+                    node.comment(),
+                    rule_name
+                ));
+            }
+            SeparatedList {
+                node: item,
+                separator,
+                ..
+            } if self.is_nullable(item) && self.is_nullable(separator) => {
+                self.log_error(&format!(
+                    "Separated list '{}' in rule '{}' can match without consuming any input and would never end",
+                    node.comment(),
+                    rule_name
+                ));
+            }
+            NegativeLookahead { node: next_node } if self.is_nullable(next_node) => {
+                self.log_error(&format!(
+                    "Negative lookahead '{}' in rule '{}' can never succeed, since '{}' always matches",
+                    node.comment(),
+                    rule_name,
+                    next_node.comment()
+                ));
+            }
+            _ => (),
+        }
+
+        for child in node.children() {
+            self.check_nullable(rule_name, child);
+        }
+    }
+
     fn log_error(&mut self, msg: &str) {
         self.errors.push(format!("ERROR: {}", msg));
     }
 
+    fn check_grammar_kind(&mut self, grammar: &Grammar) {
+        match grammar.kind {
+            GrammarKind::Lexer => {
+                for rule in &grammar.parser_rules {
+                    self.log_error(&format!(
+                        "Lexer grammar cannot contain parser rule '{}'",
+                        rule.name
+                    ));
+                }
+                if grammar.option(TOKEN_VOCAB).is_some() {
+                    self.log_error("Lexer grammar cannot use a token vocabulary");
+                }
+            }
+            GrammarKind::Parser => {
+                for rule in &grammar.token_rules {
+                    self.log_error(&format!(
+                        "Parser grammar cannot contain token rule '{}'",
+                        rule.name
+                    ));
+                }
+            }
+            GrammarKind::Combined => (),
+        }
+    }
+
+    // The lexer synthesizes these, so they can't also be defined by a rule
+    fn add_indent_tokens(&mut self, grammar: &Grammar) {
+        if grammar.kind == GrammarKind::Parser {
+            self.log_error(&format!(
+                "Parser grammar cannot use option '{}' (it belongs in the lexer grammar)",
+                INDENT_OPTION
+            ));
+            return;
+        }
+
+        for name in [INDENT, DEDENT, NEWLINE] {
+            if grammar.token_rules.iter().any(|rule| rule.name == name) {
+                self.log_error(&format!(
+                    "Token rule '{}' conflicts with the token synthesized by option '{}'",
+                    name, INDENT_OPTION
+                ));
+            }
+            self.add_token(name);
+        }
+    }
+
+    fn add_extern_tokens(&mut self, grammar: &Grammar) {
+        if grammar.kind == GrammarKind::Parser && !grammar.extern_tokens.is_empty() {
+            self.log_error(
+                "Parser grammar cannot declare extern tokens (they belong in the lexer grammar)",
+            );
+            return;
+        }
+
+        for (idx, name) in grammar.extern_tokens.iter().enumerate() {
+            if grammar.extern_tokens[..idx].contains(name) {
+                self.log_error(&format!(
+                    "Extern token '{}' is declared more than once",
+                    name
+                ));
+            } else if self.token_names.contains(name)
+                || grammar.token_rules.iter().any(|rule| &rule.name == name)
+            {
+                self.log_error(&format!(
+                    "Extern token '{}' conflicts with another token of the same name",
+                    name
+                ));
+            }
+            self.add_token(name);
+        }
+    }
+
+    fn add_token(&mut self, name: &str) {
+        if self.token_names.insert(name.to_string()) {
+            self.token_ids.insert(name.to_string(), self.next_token_id);
+            self.next_token_id += 1;
+        }
+    }
+
     fn process_token_rule(&mut self, rule: &TokenRule) -> TokenRule {
-        let TokenRule { name, literal } = rule;
-        let lit = match literal {
-            TokenLit { literal } => strip_quotes(literal),
-            _ => unreachable!(),
-        };
-        self.literals.insert(lit, (name.to_string(), None));
-        self.token_names.insert(name.to_string());
+        let TokenRule { name, node, .. } = rule;
+
+        if self.imported_vocab && self.token_names.contains(name) {
+            self.log_error(&format!(
+                "Token rule '{}' redefines a token from the token vocabulary",
+                name
+            ));
+        }
+
+        // Only single literal tokens can be referred to by their literal in parser rules
+        match node {
+            TokenLit {
+                literal,
+                case_insensitive,
+            } => {
+                if let Some(lit) = self.unescape(literal) {
+                    let case_insensitive = *case_insensitive || self.case_insensitive;
+                    self.add_folded_literal(name, &lit, case_insensitive);
+                    self.literals.insert(lit, (name.to_string(), None));
+                }
+            }
+            _ => self.check_pattern(name, node),
+        }
+        self.add_token(name);
 
         rule.clone()
     }
 
+    // Literals that only differ by case can't be told apart once either of them ignores case
+    fn add_folded_literal(&mut self, name: &str, lit: &str, case_insensitive: bool) {
+        let folded = lit.to_lowercase();
+
+        match self.folded_literals.get(&folded) {
+            Some((other, other_insensitive)) if other != name => {
+                if case_insensitive || *other_insensitive {
+                    self.log_error(&format!(
+                        "Literal '{}' of token rule '{}' collides with token rule '{}' when ignoring case",
+                        lit, name, other
+                    ));
+                }
+            }
+            _ => {
+                self.folded_literals
+                    .insert(folded, (name.to_string(), case_insensitive));
+            }
+        }
+    }
+
+    fn check_pattern(&mut self, rule_name: &str, node: &Node) {
+        match node {
+            TokenLit { literal, .. } => {
+                self.unescape(literal);
+            }
+            CharClass { class } => {
+                if let Err(err) = parse_char_class(class) {
+                    self.log_error(&format!("{} in token rule '{}'", err, rule_name));
+                }
+            }
+            _ => node
+                .children()
+                .into_iter()
+                .for_each(|node| self.check_pattern(rule_name, node)),
+        }
+    }
+
+    fn unescape(&mut self, literal: &str) -> Option<String> {
+        match unescape_literal(literal) {
+            Ok(lit) => Some(lit),
+            Err(err) => {
+                self.log_error(&err);
+                None
+            }
+        }
+    }
+
+    fn collect_templates(&mut self, rules: &[ParserRule]) {
+        for rule in rules {
+            self.rule_arity
+                .insert(rule.name.to_string(), rule.args.len());
+
+            if rule.params.is_empty() {
+                self.rule_names.insert(rule.name.to_string());
+                continue;
+            }
+
+            // Template references have no way to pass arguments
+            if !rule.args.is_empty() {
+                self.log_error(&format!(
+                    "Rule template '{}' can't take arguments",
+                    rule.name
+                ));
+            }
+
+            for (idx, param) in rule.params.iter().enumerate() {
+                if rule.params[..idx].contains(param) {
+                    self.log_error(&format!(
+                        "Parameter '{}' is used more than once in rule template '{}'",
+                        param, rule.name
+                    ));
+                }
+            }
+            self.templates.insert(rule.name.to_string(), rule.clone());
+        }
+    }
+
     fn process_parser_rule(&mut self, rule: &ParserRule) -> ParserRule {
-        ParserRule {
+        self.check_labels(rule);
+
+        let rule = ParserRule {
             name: rule.name.to_string(),
+            params: vec![],
+            args: rule.args.clone(),
+            returns: rule.returns.clone(),
+            doc: rule.doc.clone(),
             node: self.process_node(&rule.node),
+        };
+
+        // Literals have been replaced by now, so `x += ','` and `x += COMMA` are seen as the same type
+        self.check_bindings(&rule);
+        rule
+    }
+
+    // A binding name is either a single value (`=`) or a list (`+=`) and always holds the same type of value
+    fn check_bindings(&mut self, rule: &ParserRule) {
+        let mut bindings: Vec<(&str, bool, BindingType)> = vec![];
+        find_bindings(&rule.node, &mut bindings);
+
+        let mut checked: Vec<&str> = vec![];
+
+        for (name, list, bound_type) in &bindings {
+            if checked.contains(name) {
+                continue;
+            }
+            checked.push(name);
+
+            let others = bindings.iter().filter(|(other, ..)| other == name);
+
+            if others.clone().any(|(_, other_list, _)| other_list != list) {
+                self.log_error(&format!(
+                    "Binding '{}' in rule '{}' is used with both '=' and '+='",
+                    name, rule.name
+                ));
+            }
+            if let Some((.., other_type)) = others
+                .clone()
+                .find(|(.., other_type)| !other_type.matches(bound_type))
+            {
+                self.log_error(&format!(
+                    "Binding '{}' in rule '{}' is used for both {} and {}",
+                    name, rule.name, bound_type, other_type
+                ));
+            }
+        }
+    }
+
+    // Labels are only valid on a rule's top level alternatives and, like ANTLR, it is all or none
+    fn check_labels(&mut self, rule: &ParserRule) {
+        let mut nested_labels = vec![];
+
+        match &rule.node {
+            Alternatives { nodes } => {
+                let mut labels: Vec<&str> = vec![];
+
+                for node in nodes {
+                    match node {
+                        Labeled { label, node } => {
+                            if labels.contains(&label.as_str()) {
+                                self.log_error(&format!(
+                                    "Label '{}' is used more than once in rule '{}'",
+                                    label, rule.name
+                                ));
+                            }
+                            labels.push(label);
+                            find_labels(node, &mut nested_labels);
+                        }
+                        _ => find_labels(node, &mut nested_labels),
+                    }
+                }
+
+                if !labels.is_empty() && labels.len() != nodes.len() {
+                    self.log_error(&format!(
+                        "Rule '{}' labels some of its alternatives, but not all of them",
+                        rule.name
+                    ));
+                }
+            }
+            Labeled { label, node } => {
+                self.log_error(&format!(
+                    "Label '{}' in rule '{}' labels the only alternative",
+                    label, rule.name
+                ));
+                find_labels(node, &mut nested_labels);
+            }
+            node => find_labels(node, &mut nested_labels),
+        }
+
+        for label in nested_labels {
+            self.log_error(&format!(
+                "Label '{}' in rule '{}' is not on one of the rule's top level alternatives",
+                label, rule.name
+            ));
+        }
+    }
+
+    // Instantiates the template (if not already done for these arguments) and returns a reference to the instance
+    fn instantiate(&mut self, name: &str, args: Vec<Node>) -> Node {
+        let template = match self.templates.get(name) {
+            Some(template) => template.clone(),
+            None => {
+                self.log_error(&format!("Rule '{}' is not a rule template", name));
+                return RuleRef {
+                    name: name.to_string(),
+                    args: vec![],
+                };
+            }
+        };
+
+        if template.params.len() != args.len() {
+            self.log_error(&format!(
+                "Rule template '{}' expects {} argument(s), but {} were given",
+                name,
+                template.params.len(),
+                args.len()
+            ));
+            return RuleRef {
+                name: name.to_string(),
+                args: vec![],
+            };
+        }
+
+        let args_key = args
+            .iter()
+            .map(|arg| arg.comment())
+            .collect::<Vec<String>>()
+            .join(", ");
+        let instance_name = self.instance_name(name, &args, &args_key);
+
+        if !self.instance_args.contains_key(&instance_name) {
+            // A template that instantiates itself with larger arguments would do so forever (ex: `x<a>: a | x<(a a)>;`)
+            let args_size: usize = args.iter().map(node_size).sum();
+            let growing = self
+                .expanding
+                .iter()
+                .any(|(template, size)| template == name && *size < args_size);
+
+            if growing || self.expanding.len() >= MAX_TEMPLATE_DEPTH {
+                self.log_error(&format!(
+                    "Expansion of rule template '{}' does not terminate",
+                    name
+                ));
+                return RuleRef {
+                    name: instance_name,
+                    args: vec![],
+                };
+            }
+
+            // Registered before processing so recursive uses with the same arguments refer to this instance
+            self.instance_args.insert(instance_name.clone(), args_key);
+            let instance = ParserRule {
+                name: instance_name.clone(),
+                params: vec![],
+                args: vec![],
+                returns: template.returns.clone(),
+                doc: template.doc.clone(),
+                node: substitute(&template.node, &template.params, &args),
+            };
+
+            // Keep instances in the order they were first used (nested instances are completed first)
+            let idx = self.instances.entry(name.to_string()).or_default().len();
+
+            self.expanding.push((name.to_string(), args_size));
+            let instance = self.process_parser_rule(&instance);
+            self.expanding.pop();
+
+            self.instances.get_mut(name).unwrap().insert(idx, instance);
+        }
+
+        RuleRef {
+            name: instance_name,
+            args: vec![],
+        }
+    }
+
+    // Instances are named after their template and arguments (ex: `comma_list<value>` -> `comma_list_value`)
+    fn instance_name(&self, template: &str, args: &[Node], args_key: &str) -> String {
+        let parts: Vec<String> = args
+            .iter()
+            .map(|arg| match arg {
+                RuleRef { name, .. } => name.to_string(),
+                TokenRef { name, .. } => name.to_lowercase(),
+                _ => "group".to_string(),
+            })
+            .collect();
+        let base_name = format!("{}_{}", template, parts.join("_"));
+
+        // Number any name already taken by a user rule or an instance with different arguments
+        let mut name = base_name.clone();
+        let mut num = 2;
+        loop {
+            match self.instance_args.get(&name) {
+                Some(key) if key == args_key => return name,
+                None if !self.rule_names.contains(&name) => return name,
+                _ => {
+                    name = format!("{}_{}", base_name, num);
+                    num += 1;
+                }
+            }
         }
     }
 
     fn process_node(&mut self, node: &Node) -> Node {
         match node {
-            Binding { name, node } => Binding {
+            Binding { name, list, node } => Binding {
                 name: name.to_string(),
+                list: *list,
                 node: Box::new(self.process_node(node)),
             },
             Alternatives { nodes } => Alternatives {
                 nodes: nodes.iter().map(|node| self.process_node(node)).collect(),
             },
+            Labeled { label, node } => Labeled {
+                label: label.to_string(),
+                node: Box::new(self.process_node(node)),
+            },
             MultipartBody { nodes } => MultipartBody {
                 nodes: nodes.iter().map(|node| self.process_node(node)).collect(),
             },
@@ -101,17 +735,70 @@ impl Transform {
                 node: Box::new(self.process_node(node)),
                 brackets: *brackets,
             },
-            RuleRef { .. } => node.clone(),
+            SeparatedList {
+                node,
+                separator,
+                trailing,
+            } => SeparatedList {
+                node: Box::new(self.process_node(node)),
+                separator: Box::new(self.process_node(separator)),
+                trailing: *trailing,
+            },
+            PositiveLookahead { node } => PositiveLookahead {
+                node: Box::new(self.process_node(node)),
+            },
+            NegativeLookahead { node } => NegativeLookahead {
+                node: Box::new(self.process_node(node)),
+            },
+            Action { .. } => node.clone(),
+            Empty => Empty,
+            RuleRef { name, args } => {
+                if self.templates.contains_key(name) {
+                    self.log_error(&format!(
+                        "Rule template '{}' is used without arguments",
+                        name
+                    ));
+                } else if let Some(arity) = self.rule_arity.get(name) {
+                    if *arity != args.len() {
+                        self.log_error(&format!(
+                            "Rule '{}' expects {} argument(s), but {} were given",
+                            name,
+                            arity,
+                            args.len()
+                        ));
+                    }
+                }
+                node.clone()
+            }
+            TemplateRef { name, args } => {
+                let args = args.iter().map(|arg| self.process_node(arg)).collect();
+                self.instantiate(name, args)
+            }
             TokenRef {
                 name,
                 replaced_lit: _replaced_lit,
             } => {
-                self.token_names.insert(name.to_string());
+                // Tokens can only be implicitly defined when there is no vocabulary to resolve them against
+                if self.imported_vocab && !self.token_names.contains(name) {
+                    self.log_error(&format!(
+                        "Token '{}' is not defined in the token vocabulary",
+                        name
+                    ));
+                }
+                self.add_token(name);
                 node.clone()
             }
-            TokenLit { literal } => {
-                // Strip quotes and use as lookup key
-                let lit = strip_quotes(literal);
+            // Only found in token rules
+            CharClass { .. } => node.clone(),
+            TokenLit {
+                literal,
+                case_insensitive,
+            } => {
+                // Unescape and use as lookup key (so `'\''` and `'\u{27}'` are the same literal)
+                let lit = match self.unescape(literal) {
+                    Some(lit) => lit,
+                    None => return node.clone(),
+                };
 
                 // Try and find the literal to ensure it has a corresponding rule
                 match self.literals.get(&lit).cloned() {
@@ -125,15 +812,310 @@ impl Transform {
                         self.literals.insert(lit, (name, Some(token_ref)));
                         ref_copy
                     }
-                    None => {
-                        self.log_error(&format!(
-                            "Literal {} does not have corresponding rule",
-                            literal
-                        ));
-                        node.clone()
-                    }
+                    // Otherwise it might match a rule that ignores case (or ignore case itself)
+                    None => match self.folded_literals.get(&lit.to_lowercase()) {
+                        Some((name, rule_insensitive))
+                            if *rule_insensitive || *case_insensitive =>
+                        {
+                            TokenRef {
+                                name: name.to_string(),
+                                replaced_lit: Some(literal.to_string()),
+                            }
+                        }
+                        _ => {
+                            self.log_error(&format!(
+                                "Literal {} does not have corresponding rule",
+                                literal
+                            ));
+                            node.clone()
+                        }
+                    },
                 }
             }
         }
     }
 }
+
+fn find_labels(node: &Node, labels: &mut Vec<String>) {
+    if let Labeled { label, .. } = node {
+        labels.push(label.to_string());
+    }
+    node.children()
+        .into_iter()
+        .for_each(|node| find_labels(node, labels));
+}
+
+fn find_bindings<'n>(node: &'n Node, bindings: &mut Vec<(&'n str, bool, BindingType)>) {
+    if let Binding { name, list, node } = node {
+        let bound_type = match binding_type(node) {
+            // A list can collect different tokens (ex: `xs+=A (',' xs+=B)*`)
+            BindingType::Token(_) if *list => BindingType::Token(None),
+            bound_type => bound_type,
+        };
+        bindings.push((name, *list, bound_type));
+    }
+    node.children()
+        .into_iter()
+        .for_each(|node| find_bindings(node, bindings));
+}
+
+// The type of value a binding holds (repetition only changes how many values there are)
+#[derive(Debug, PartialEq)]
+enum BindingType {
+    Rule(String),
+    // `None` when it can be one of several tokens (ex: `op=('+' | '-')`)
+    Token(Option<String>),
+    Group(String),
+}
+
+impl BindingType {
+    fn matches(&self, other: &BindingType) -> bool {
+        match (self, other) {
+            (BindingType::Token(None), BindingType::Token(_))
+            | (BindingType::Token(_), BindingType::Token(None)) => true,
+            _ => self == other,
+        }
+    }
+}
+
+impl fmt::Display for BindingType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingType::Rule(name) => write!(f, "rule '{}'", name),
+            BindingType::Token(Some(name)) => write!(f, "token '{}'", name),
+            BindingType::Token(None) => write!(f, "a token"),
+            BindingType::Group(comment) => write!(f, "'{}'", comment),
+        }
+    }
+}
+
+fn binding_type(node: &Node) -> BindingType {
+    match node {
+        ZeroOrMore { node }
+        | OneOrMore { node }
+        | ZeroOrOne { node, .. }
+        | SeparatedList { node, .. } => binding_type(node),
+        RuleRef { name, .. } => BindingType::Rule(name.clone()),
+        TokenRef { name, .. } => BindingType::Token(Some(name.clone())),
+        TokenLit { .. } | CharClass { .. } => BindingType::Token(None),
+        // A choice between tokens still holds a token
+        Alternatives { nodes }
+            if nodes
+                .iter()
+                .all(|node| matches!(binding_type(node), BindingType::Token(_))) =>
+        {
+            BindingType::Token(None)
+        }
+        _ => BindingType::Group(node.comment()),
+    }
+}
+
+fn node_size(node: &Node) -> usize {
+    1 + node.children().into_iter().map(node_size).sum::<usize>()
+}
+
+// Replaces references to template parameters with the arguments of an instantiation
+fn substitute(node: &Node, params: &[String], args: &[Node]) -> Node {
+    let sub = |node: &Node| Box::new(substitute(node, params, args));
+
+    match node {
+        Binding { name, list, node } => Binding {
+            name: name.to_string(),
+            list: *list,
+            node: sub(node),
+        },
+        Alternatives { nodes } => Alternatives {
+            nodes: nodes
+                .iter()
+                .map(|node| substitute(node, params, args))
+                .collect(),
+        },
+        Labeled { label, node } => Labeled {
+            label: label.to_string(),
+            node: sub(node),
+        },
+        MultipartBody { nodes } => MultipartBody {
+            nodes: nodes
+                .iter()
+                .map(|node| substitute(node, params, args))
+                .collect(),
+        },
+        ZeroOrMore { node } => ZeroOrMore { node: sub(node) },
+        OneOrMore { node } => OneOrMore { node: sub(node) },
+        ZeroOrOne { node, brackets } => ZeroOrOne {
+            node: sub(node),
+            brackets: *brackets,
+        },
+        SeparatedList {
+            node,
+            separator,
+            trailing,
+        } => SeparatedList {
+            node: sub(node),
+            separator: sub(separator),
+            trailing: *trailing,
+        },
+        PositiveLookahead { node } => PositiveLookahead { node: sub(node) },
+        NegativeLookahead { node } => NegativeLookahead { node: sub(node) },
+        RuleRef { name, .. } => match params.iter().position(|param| param == name) {
+            Some(idx) => args[idx].clone(),
+            None => node.clone(),
+        },
+        TemplateRef {
+            name,
+            args: ref_args,
+        } => TemplateRef {
+            name: name.to_string(),
+            args: ref_args
+                .iter()
+                .map(|arg| substitute(arg, params, args))
+                .collect(),
+        },
+        Action { .. } | Empty | TokenRef { .. } | TokenLit { .. } | CharClass { .. } => {
+            node.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_hrpg;
+
+    fn errors(data: &str) -> Vec<String> {
+        Transform::process(&parse_hrpg(data).unwrap()).1.errors
+    }
+
+    #[test]
+    fn template_instances() {
+        let (grammar, transform) = Transform::process(
+            &parse_hrpg("a: list<B> list<B> list<c>;\nc: B;\nlist<item>: item (',' item)*;\nB: 'b';\nCOMMA: ',';\n")
+                .unwrap(),
+        );
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+
+        let names: Vec<&str> = grammar
+            .parser_rules
+            .iter()
+            .map(|rule| rule.name.as_str())
+            .collect();
+        assert_eq!(names, ["a", "c", "list_b", "list_c"]);
+    }
+
+    #[test]
+    fn template_arity() {
+        assert_eq!(
+            errors(
+                "a: list<B, C>;\nlist<item>: item (',' item)*;\nB: 'b';\nC: 'c';\nCOMMA: ',';\n"
+            ),
+            ["ERROR: Rule template 'list' expects 1 argument(s), but 2 were given"]
+        );
+        assert_eq!(
+            errors("a: list;\nlist<item>: item*;\n"),
+            ["ERROR: Rule template 'list' is used without arguments"]
+        );
+    }
+
+    // Each expansion instantiates the template with a larger argument
+    #[test]
+    fn template_expansion_does_not_terminate() {
+        let errors = errors("a: x<B>;\nx<item>: item | x<(item item)>;\nB: 'b';\n");
+        assert!(
+            errors
+                .contains(&"ERROR: Expansion of rule template 'x' does not terminate".to_string()),
+            "{:?}",
+            errors
+        );
+    }
+
+    // Recursion with the same arguments refers back to the same instance
+    #[test]
+    fn recursive_template() {
+        assert!(errors("a: nested<B>;\nnested<item>: item | '(' nested<item> ')';\nB: 'b';\nLPAREN: '(';\nRPAREN: ')';\n").is_empty());
+    }
+
+    #[test]
+    fn case_fold_collisions() {
+        assert_eq!(
+            errors("a: A B;\nA: 'select'i;\nB: 'SELECT';\n"),
+            ["ERROR: Literal 'SELECT' of token rule 'B' collides with token rule 'A' when ignoring case"]
+        );
+        assert_eq!(
+            errors("options { caseInsensitive = true; }\na: A B;\nA: 'x';\nB: 'X';\n"),
+            ["ERROR: Literal 'X' of token rule 'B' collides with token rule 'A' when ignoring case"]
+        );
+        // Without ignoring case they are different literals
+        assert!(errors("a: 'select' 'SELECT';\nA: 'select';\nB: 'SELECT';\n").is_empty());
+    }
+
+    #[test]
+    fn case_insensitive_literal_refs() {
+        let (grammar, transform) = Transform::process(
+            &parse_hrpg("a: 'SELECT' 'from'i;\nA: 'select'i;\nB: 'FROM';\n").unwrap(),
+        );
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+
+        let refs: Vec<&str> = match &grammar.parser_rules[0].node {
+            MultipartBody { nodes } => nodes
+                .iter()
+                .map(|node| match node {
+                    TokenRef { name, .. } => name.as_str(),
+                    node => panic!("{:?}", node),
+                })
+                .collect(),
+            node => panic!("{:?}", node),
+        };
+        assert_eq!(refs, ["A", "B"]);
+    }
+
+    #[test]
+    fn extern_tokens() {
+        let (_, transform) =
+            Transform::process(&parse_hrpg("a: HEREDOC B;\nextern HEREDOC;\nB: 'b';\n").unwrap());
+        assert!(transform.errors.is_empty(), "{:?}", transform.errors);
+        assert_eq!(transform.vocab().token_name(2), Some("HEREDOC"));
+
+        assert_eq!(
+            errors("a: A;\nextern A, A;\n"),
+            ["ERROR: Extern token 'A' is declared more than once"]
+        );
+        assert_eq!(
+            errors("a: A;\nextern A;\nA: 'a';\n"),
+            ["ERROR: Extern token 'A' conflicts with another token of the same name"]
+        );
+        assert_eq!(
+            errors("a: EOF;\nextern EOF;\n"),
+            ["ERROR: Extern token 'EOF' conflicts with another token of the same name"]
+        );
+        assert_eq!(
+            errors("parser grammar P;\na: A;\nextern A;\n"),
+            ["ERROR: Parser grammar cannot declare extern tokens (they belong in the lexer grammar)"]
+        );
+    }
+
+    #[test]
+    fn binding_types() {
+        assert!(errors("a: xs+=A (',' xs+=B)*;\nA: 'a';\nB: 'b';\nCOMMA: ',';\n").is_empty());
+        assert_eq!(
+            errors("s: x=A x=B;\nA: 'a';\nB: 'b';\n"),
+            ["ERROR: Binding 'x' in rule 's' is used for both token 'A' and token 'B'"]
+        );
+        assert_eq!(
+            errors("s: x=A x=b;\nb: A;\nA: 'a';\n"),
+            ["ERROR: Binding 'x' in rule 's' is used for both token 'A' and rule 'b'"]
+        );
+        assert_eq!(
+            errors("s: xs+=A xs=A;\nA: 'a';\n"),
+            ["ERROR: Binding 'xs' in rule 's' is used with both '=' and '+='"]
+        );
+    }
+
+    // Each side is a choice between tokens, so both hold a token
+    #[test]
+    fn binding_token_choices() {
+        assert!(errors(
+            "e: a op=('*' | '/') a | a op=('+' | '-') a | a op='%' a;\na: A;\nA: 'a';\nSTAR: '*';\nSLASH: '/';\nPLUS: '+';\nMINUS: '-';\nPERCENT: '%';\n"
+        )
+        .is_empty());
+    }
+}
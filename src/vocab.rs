@@ -0,0 +1,141 @@
+use std::fmt;
+
+use crate::escape::{escape_literal, unescape_literal};
+
+// Grammar option naming the lexer grammar whose vocabulary a parser grammar uses
+pub const TOKEN_VOCAB: &str = "tokenVocab";
+
+// File extension of a written token vocabulary
+pub const VOCAB_EXT: &str = "tokens";
+
+// Token names and literals with their token ids, in the same format as ANTLR's `.tokens` files:
+//
+// TRUE=2
+// 'true'=2
+#[derive(Clone, Debug, Default)]
+pub struct TokenVocab {
+    pub tokens: Vec<(String, u32)>,
+    pub literals: Vec<(String, u32)>,
+}
+
+impl TokenVocab {
+    pub fn parse(data: &str) -> Result<TokenVocab, String> {
+        let mut vocab = TokenVocab::default();
+
+        for (line_num, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            // Split on the last '=' since a literal may itself contain one
+            let (name, id) = line.rsplit_once('=').ok_or_else(|| {
+                format!("Line {}: expected NAME=id, found '{}'", line_num + 1, line)
+            })?;
+            let id: u32 = id
+                .parse()
+                .map_err(|_| format!("Line {}: invalid token id '{}'", line_num + 1, id))?;
+
+            if name.len() >= 2 && name.starts_with('\'') && name.ends_with('\'') {
+                let literal = unescape_literal(name)
+                    .map_err(|err| format!("Line {}: {}", line_num + 1, err))?;
+                vocab.literals.push((literal, id));
+            } else {
+                vocab.tokens.push((name.to_string(), id));
+            }
+        }
+
+        Ok(vocab)
+    }
+
+    pub fn token_name(&self, id: u32) -> Option<&str> {
+        self.tokens
+            .iter()
+            .find(|(_, token_id)| *token_id == id)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+impl fmt::Display for TokenVocab {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, id) in &self.tokens {
+            writeln!(f, "{}={}", name, id)?;
+        }
+        for (literal, id) in &self.literals {
+            writeln!(f, "'{}'={}", escape_literal(literal), id)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::parse_hrpg;
+    use crate::transform::Transform;
+
+    const CALC: &str = "EOF=0\nILLEGAL=1\nNUM=2\nPLUS=3\nQUOTE=4\n'+'=3\n'\\''=4\n";
+
+    fn errors(data: &str, vocab: &str) -> Vec<String> {
+        let vocab = TokenVocab::parse(vocab).unwrap();
+        let (_, transform) =
+            Transform::process_with_vocab(&parse_hrpg(data).unwrap(), Some(&vocab));
+        transform.errors
+    }
+
+    #[test]
+    fn round_trip() {
+        let vocab = TokenVocab::parse(CALC).unwrap();
+        assert_eq!(vocab.token_name(3), Some("PLUS"));
+        assert_eq!(
+            vocab.literals,
+            vec![("+".to_string(), 3), ("'".to_string(), 4)]
+        );
+        assert_eq!(vocab.to_string(), CALC);
+
+        // Literals split on their last '='
+        let vocab = TokenVocab::parse("EQ=2\n'='=2\n").unwrap();
+        assert_eq!(vocab.literals, vec![("=".to_string(), 2)]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            TokenVocab::parse("EOF=0\nNUM\n").unwrap_err(),
+            "Line 2: expected NAME=id, found 'NUM'"
+        );
+        assert_eq!(
+            TokenVocab::parse("NUM=two").unwrap_err(),
+            "Line 1: invalid token id 'two'"
+        );
+    }
+
+    #[test]
+    fn vocab_mismatch() {
+        let data = "parser grammar P;\noptions { tokenVocab = Calc; }\n";
+
+        assert!(errors(&format!("{}a: NUM '+' NUM EOF;", data), CALC).is_empty());
+        assert_eq!(
+            errors(&format!("{}a: NUM MINUS NUM EOF;", data), CALC),
+            vec!["ERROR: Token 'MINUS' is not defined in the token vocabulary"]
+        );
+        assert_eq!(
+            errors(&format!("{}a: NUM EOF;", data), "NUM=2\n'-'=3\n"),
+            vec!["ERROR: Literal '-' in token vocabulary has unknown token id 3"]
+        );
+        assert_eq!(
+            errors(
+                "lexer grammar L;\noptions { tokenVocab = Calc; }\nSPACE: ' ';",
+                CALC
+            ),
+            vec!["ERROR: Lexer grammar cannot use a token vocabulary"]
+        );
+        assert!(errors(
+            "grammar G;\noptions { tokenVocab = Calc; }\na: NUM;\nNUM: [0-9]+;",
+            CALC
+        )
+        .contains(
+            &"ERROR: Token rule 'NUM' redefines a token from the token vocabulary".to_string()
+        ));
+    }
+}